@@ -1,7 +1,8 @@
 use divan::AllocProfiler;
 use divan::{Bencher, black_box};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use treesearch::conllu::TreeIterator;
+use treesearch::{Treebank, compile_query};
 
 #[global_allocator]
 static ALLOC: AllocProfiler = AllocProfiler::system();
@@ -34,3 +35,138 @@ fn parse_gz(bencher: Bencher) {
         }
     });
 }
+
+/// Small in-memory corpus (repeated sentence) for pattern-shape benchmarks
+fn synthetic_corpus() -> String {
+    let sentence = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                    2\tcat\tcat\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                    3\tgave\tgive\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                    4\tthe\tthe\tDET\tDT\t_\t5\tdet\t_\t_\n\
+                    5\tdog\tdog\tNOUN\tNN\t_\t3\tiobj\t_\t_\n\
+                    6\ta\ta\tDET\tDT\t_\t7\tdet\t_\t_\n\
+                    7\tbone\tbone\tNOUN\tNN\t_\t3\tobj\t_\t_\n\
+                    8\tfrom\tfrom\tADP\tIN\t_\t10\tcase\t_\t_\n\
+                    9\tthe\tthe\tDET\tDT\t_\t10\tdet\t_\t_\n\
+                    10\tyard\tyard\tNOUN\tNN\t_\t7\tnmod\t_\t_\n\n";
+    sentence.repeat(2000)
+}
+
+/// Benchmark a 3-variable chain pattern (middle node is the best anchor)
+#[divan::bench]
+fn match_chain3(bencher: Bencher) {
+    let text = synthetic_corpus();
+    let pattern =
+        compile_query("MATCH { V []; O []; N []; V -[obj]-> O; O -[nmod]-> N; }").unwrap();
+    bencher.bench_local(|| {
+        let count = Treebank::from_string(&text)
+            .match_iter(pattern.clone(), true)
+            .count();
+        black_box(count);
+    });
+}
+
+/// Benchmark a 4-variable star pattern (hub node is the best anchor)
+#[divan::bench]
+fn match_star4(bencher: Bencher) {
+    let text = synthetic_corpus();
+    let pattern = compile_query(
+        "MATCH { V []; S []; I []; O []; V -[nsubj]-> S; V -[iobj]-> I; V -[obj]-> O; }",
+    )
+    .unwrap();
+    bencher.bench_local(|| {
+        let count = Treebank::from_string(&text)
+            .match_iter(pattern.clone(), true)
+            .count();
+        black_box(count);
+    });
+}
+
+/// Benchmark a 2-variable pattern whose domains come straight from the UPOS/lemma indices
+#[divan::bench]
+fn match_indexed2(bencher: Bencher) {
+    let text = synthetic_corpus();
+    let pattern =
+        compile_query("MATCH { V [upos=\"VERB\" & lemma=\"give\"]; N [upos=\"NOUN\"]; V -> N; }")
+            .unwrap();
+    bencher.bench_local(|| {
+        let count = Treebank::from_string(&text)
+            .match_iter(pattern.clone(), true)
+            .count();
+        black_box(count);
+    });
+}
+
+/// Benchmark a pattern of unlabeled structural and word-order relations, where the
+/// domains only shrink once neighbours are assigned
+#[divan::bench]
+fn match_relations4(bencher: Bencher) {
+    let text = synthetic_corpus();
+    let pattern =
+        compile_query("MATCH { A []; B []; C []; D []; A >> B; B -> C; A << D; D < C; }").unwrap();
+    bencher.bench_local(|| {
+        let count = Treebank::from_string(&text)
+            .match_iter(pattern.clone(), true)
+            .count();
+        black_box(count);
+    });
+}
+
+/// Benchmark match throughput with different (match_batch, channel_batches) settings
+#[divan::bench(args = [(100, 20), (500, 100), (2000, 400)])]
+fn match_buffer_sizes(bencher: Bencher, (match_batch, channel_batches): (usize, usize)) {
+    let text = synthetic_corpus();
+    let pattern = compile_query("MATCH { N [upos=\"NOUN\"]; }").unwrap();
+    bencher.bench_local(|| {
+        let count = Treebank::from_string(&text)
+            .with_buffer_size(match_batch, channel_batches)
+            .match_iter(pattern.clone(), true)
+            .count();
+        black_box(count);
+    });
+}
+
+/// Write a 1M-sentence corpus to disk (100 files of 10k sentences)
+///
+/// The ordered path holds every match of a chunk of files in memory, so the
+/// files are kept small enough for that to fit.
+fn write_large_corpus(dir: &Path) -> Vec<PathBuf> {
+    let text = synthetic_corpus().repeat(10_000 / 2000);
+    (0..100)
+        .map(|i| {
+            let path = dir.join(format!("part{}.conllu", i));
+            std::fs::write(&path, &text).unwrap();
+            path
+        })
+        .collect()
+}
+
+/// Match throughput over the 1M-sentence corpus with the given buffer sizes
+fn bench_buffer_sizes_1m(
+    bencher: Bencher,
+    (match_batch, channel_batches): (usize, usize),
+    ordered: bool,
+) {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = write_large_corpus(dir.path());
+    let pattern = compile_query("MATCH { N [upos=\"NOUN\"]; }").unwrap();
+    bencher.bench_local(|| {
+        let count = Treebank::from_paths(paths.clone())
+            .with_buffer_size(match_batch, channel_batches)
+            .match_iter(pattern.clone(), ordered)
+            .count();
+        black_box(count);
+    });
+}
+
+/// Unordered file source, where the batch and channel sizes govern contention
+/// between the file readers and the consumer
+#[divan::bench(args = [(100, 20), (500, 100), (2000, 400), (5000, 1000)], sample_count = 3)]
+fn match_buffer_sizes_1m(bencher: Bencher, sizes: (usize, usize)) {
+    bench_buffer_sizes_1m(bencher, sizes, false);
+}
+
+/// Ordered file source, where files are searched in chunks and sent in order
+#[divan::bench(args = [(100, 20), (500, 100), (2000, 400), (5000, 1000)], sample_count = 3)]
+fn match_buffer_sizes_1m_ordered(bencher: Bencher, sizes: (usize, usize)) {
+    bench_buffer_sizes_1m(bencher, sizes, true);
+}
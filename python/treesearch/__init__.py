@@ -11,12 +11,17 @@ __version__ = version("treesearch-ud")
 
 try:
     from .treesearch import (
+        FilteredMatchIterator,
+        FilteredTreebank,
+        FilteredTreeIterator,
+        IndexedTreeIterator,
         MatchIterator,
         Pattern,
         Tree,
         Treebank,
         TreeIterator,
         Word,
+        WordIterator,
         compile_query,
         py_search_trees,
     )
@@ -36,13 +41,19 @@ __all__ = [
     "Word",
     "Pattern",
     "Treebank",
+    "FilteredTreebank",
     "TreeIterator",
+    "FilteredTreeIterator",
+    "FilteredMatchIterator",
+    "IndexedTreeIterator",
     "MatchIterator",
+    "WordIterator",
     "compile_query",
     "search",
     "load",
     "from_string",
     "trees",
+    "read_trees",
     "search",
     "search_trees",
     "to_displacy",
@@ -122,10 +133,53 @@ def trees(source: str | Path | Iterable[str | Path], ordered: bool = True) -> Tr
     return treebank.trees(ordered=ordered)
 
 
+def read_trees(
+    source: str | Path | Iterable[str | Path],
+    ordered: bool = True,
+) -> TreeIterator:
+    """Read trees from a file, glob pattern, list of files, or CoNLL-U string.
+
+    The source type is detected automatically:
+
+    - a list (or other iterable) of paths is read as multiple files
+    - a string starting with "#" or a digit that contains a tab or newline
+      is parsed as CoNLL-U text
+    - a string containing "*" or "?" is expanded as a glob pattern
+    - anything else is read as a single file
+
+    Args:
+        source: File path, glob pattern, list of paths, or CoNLL-U text
+        ordered: If True (default), return trees in deterministic order
+
+    Returns:
+        Iterator over Tree objects
+
+    Example:
+        >>> for tree in treesearch.read_trees("data/*.conllu"):
+        ...     print(tree.sentence_text)
+    """
+    if isinstance(source, Path):
+        treebank = Treebank.from_file(str(source))
+    elif isinstance(source, str):
+        stripped = source.lstrip()
+        if stripped[:1] in ("#", *"0123456789") and ("\t" in source or "\n" in source):
+            treebank = Treebank.from_string(source)
+        elif "*" in source or "?" in source:
+            treebank = Treebank.from_files(sorted(glob.glob(source, recursive=True)))
+        else:
+            treebank = Treebank.from_file(source)
+    elif isinstance(source, Iterable):
+        treebank = Treebank.from_files([str(path) for path in source])
+    else:
+        raise ValueError("source must be str, Path, or Iterable[str | Path]")
+    return treebank.trees(ordered=ordered)
+
+
 def search(
     source: str | Path | Iterable[str | Path],
     query: str | Pattern,
     ordered: bool = True,
+    max_matches: int | None = None,
 ) -> MatchIterator:
     """Search one or more files for pattern matches.
 
@@ -133,12 +187,13 @@ def search(
         source: Path to a single file or glob pattern
         query: Query string or compiled Pattern
         ordered: If True (default), return matches in deterministic order
+        max_matches: If given, return at most this many matches from each tree
 
     Returns:
         Iterator over (Tree, match_dict) tuples
     """
     treebank = load(source)
-    return treebank.search(query, ordered=ordered)
+    return treebank.search(query, ordered=ordered, max_matches=max_matches)
 
 
 def search_trees(
@@ -2,7 +2,7 @@
 
 from __future__ import annotations
 
-from typing import Iterator, Optional
+from typing import Any, Callable, Iterator, Optional, overload
 
 class Tree:
     """Represents a dependency tree."""
@@ -17,6 +17,36 @@ class Tree:
         """Tree metadata from CoNLL-U comment lines."""
         ...
 
+    @property
+    def is_new_doc(self) -> bool:
+        """Whether the sentence starts a document ('# newdoc')."""
+        ...
+
+    @property
+    def is_new_par(self) -> bool:
+        """Whether the sentence starts a paragraph ('# newpar')."""
+        ...
+
+    @property
+    def doc_id(self) -> Optional[str]:
+        """Document id from '# newdoc id = ...' or '# doc_id = ...', if any."""
+        ...
+
+    @property
+    def raw_comments(self) -> list[str]:
+        """Every comment line as read, in order, including free-form ones like '# newpar'."""
+        ...
+
+    @property
+    def root(self) -> Optional[Word]:
+        """Root word of the tree, or None for an empty tree."""
+        ...
+
+    @property
+    def roots(self) -> list[Word]:
+        """All words without a head (more than one only in malformed trees)."""
+        ...
+
     def word(self, id: int) -> Word:
         """Get word by ID (0-based index).
 
@@ -49,6 +79,175 @@ class Tree:
         """Number of words in tree."""
         ...
 
+    def __iter__(self) -> WordIterator:
+        """Iterate over the words in linear order."""
+        ...
+
+    @property
+    def multiword_tokens(self) -> list[dict]:
+        """Multiword tokens as dicts with 'start' and 'end' (token IDs), 'form' and 'misc'."""
+        ...
+
+    def has_feature(self, id: int, key: str, value: str) -> bool:
+        """Check whether word `id` has morphological feature key=value.
+
+        Raises:
+            IndexError: If ID is out of bounds
+        """
+        ...
+
+    def feature_value(self, id: int, key: str) -> Optional[str]:
+        """Value of feature `key` on word `id`, or None if absent.
+
+        Raises:
+            IndexError: If ID is out of bounds
+        """
+        ...
+
+    def is_projective(self) -> bool:
+        """True if no dependency arc crosses another."""
+        ...
+
+    def non_projective_arcs(self) -> list[tuple[int, int]]:
+        """Non-projective arcs as (head, dependent) pairs."""
+        ...
+
+    def height(self) -> int:
+        """Greatest depth of any word (0 for a single word or an empty tree)."""
+        ...
+
+    def subtree_size(self, id: int) -> int:
+        """Number of words in the subtree rooted at word `id`, including itself.
+
+        Raises:
+            IndexError: If ID is out of bounds
+        """
+        ...
+
+    def subtree_text(self, id: int) -> str:
+        """Surface text of the subtree rooted at word `id`, respecting SpaceAfter=No.
+
+        Raises:
+            IndexError: If ID is out of bounds
+        """
+        ...
+
+    def subtree_span(self, id: int) -> tuple[int, int]:
+        """Leftmost and rightmost word IDs of the subtree rooted at word `id`.
+
+        Raises:
+            IndexError: If ID is out of bounds
+        """
+        ...
+
+    def mean_branching_factor(self) -> float:
+        """Mean number of children over the words that have any (0.0 if none do)."""
+        ...
+
+    def lca(self, word_a: int, word_b: int) -> Optional[int]:
+        """Lowest common ancestor of two words (a word counts as its own ancestor).
+
+        Returns None if the words have no common ancestor (only in malformed trees).
+
+        Raises:
+            IndexError: If either ID is out of bounds
+        """
+        ...
+
+    def subtree(self, word_id: int) -> Tree:
+        """Copy the subtree rooted at `word_id` into a new tree.
+
+        Words keep their order and are renumbered from 0. Enhanced dependencies
+        and multiword tokens are kept when they lie inside the subtree.
+
+        Raises:
+            IndexError: If ID is out of bounds
+        """
+        ...
+
+    def to_json(self) -> str:
+        """Serialize the tree (text, metadata, comments, multiword tokens, words) as JSON."""
+        ...
+
+    @staticmethod
+    def from_json(json: str) -> Tree:
+        """Rebuild a tree saved with Tree.to_json().
+
+        Raises:
+            ValueError: If the JSON is malformed or a head is not in the tree
+        """
+        ...
+
+    def validate(self) -> list[str]:
+        """Check the tree against the UD guidelines (heads, root, cycles, UPOS, feature order).
+
+        Returns one message per problem; an empty list means the tree is valid.
+        """
+        ...
+
+    def to_conllu(self) -> str:
+        """Serialize the tree as a CoNLL-U sentence, including the trailing blank line."""
+        ...
+
+    def to_dot(
+        self, show_lemma: bool = False, show_xpos: bool = False, direction: str = "TB"
+    ) -> str:
+        """Render the tree as a GraphViz DOT graph.
+
+        Nodes are labelled with form and UPOS (plus lemma/XPOS if requested), arcs
+        with the relation, and the root has a double border.
+
+        Args:
+            show_lemma: Add lemmas to the node labels
+            show_xpos: Add XPOS tags to the node labels
+            direction: "TB" (top to bottom) or "LR" (left to right)
+        """
+        ...
+
+    def save_dot(
+        self,
+        path: str,
+        show_lemma: bool = False,
+        show_xpos: bool = False,
+        direction: str = "TB",
+    ) -> None:
+        """Write the tree as a GraphViz DOT file (same options as to_dot)."""
+        ...
+
+    def to_ascii(self, show_features: bool = False) -> str:
+        """Draw the tree as text, one word per line as form/UPOS/deprel.
+
+        Dependents are indented under their heads with box-drawing characters:
+
+            runs/VERB/root
+            ├── dog/NOUN/nsubj
+            └── quickly/ADV/advmod
+
+        Args:
+            show_features: Add each word's features in brackets
+        """
+        ...
+
+    def display(self, show_features: bool = False) -> None:
+        """Print the tree as drawn by to_ascii()."""
+        ...
+
+    def to_networkx(self) -> Any:
+        """Convert the tree to a networkx DiGraph (requires networkx).
+
+        Nodes are word ids with form, lemma, upos, xpos, deprel and feats
+        attributes; edges run from head to dependent with a deprel attribute.
+        """
+        ...
+
+    def to_networkx_undirected(self) -> Any:
+        """Like to_networkx(), but returns an undirected networkx Graph."""
+        ...
+
+    def format_match(self, match: dict[str, int]) -> str:
+        """Format a match from this tree as '{V=2 (runs/VERB), ...}'."""
+        ...
+
     def __repr__(self) -> str: ...
 
 class Word:
@@ -64,6 +263,11 @@ class Word:
         """Token ID from CoNLL-U (1-based)."""
         ...
 
+    @property
+    def depth(self) -> int:
+        """Distance from the root (the root has depth 0)."""
+        ...
+
     @property
     def form(self) -> str:
         """Word form (surface text)."""
@@ -109,10 +313,19 @@ class Word:
         """Miscellaneous annotations as key-value pairs."""
         ...
 
+    @property
+    def enhanced_deps(self) -> list[tuple[Optional[int], str]]:
+        """Enhanced dependencies (DEPS column) as (head, deprel) pairs; head is None for the root."""
+        ...
+
     def parent(self) -> Optional[Word]:
         """Get parent word, None for root."""
         ...
 
+    def path_to_root(self) -> list[Word]:
+        """This word followed by its ancestors, ending at the root."""
+        ...
+
     def children(self) -> list[Word]:
         """Get all children words."""
         ...
@@ -128,10 +341,89 @@ class Word:
         """
         ...
 
+    @property
+    def left_siblings(self) -> list[Word]:
+        """Words with the same parent that precede this word."""
+        ...
+
+    @property
+    def right_siblings(self) -> list[Word]:
+        """Words with the same parent that follow this word."""
+        ...
+
     def __repr__(self) -> str: ...
+    def __eq__(self, other: object) -> bool:
+        """Same word (same id) in the same tree object."""
+        ...
+
+    def __hash__(self) -> int: ...
+    def __lt__(self, other: Word) -> bool:
+        """Compare by linear position (word id)."""
+        ...
+
+    def __le__(self, other: Word) -> bool: ...
+    def __gt__(self, other: Word) -> bool: ...
+    def __ge__(self, other: Word) -> bool: ...
 
 class Pattern:
-    """Compiled query pattern."""
+    """Compiled query pattern.
+
+    Patterns can be pickled (e.g. for multiprocessing); they are recompiled from
+    the original query string when unpickled.
+    """
+
+    @property
+    def order_by(self) -> list[tuple[str, str, bool]]:
+        """ORDER BY keys as (variable, field, ascending) tuples."""
+        ...
+
+    def matches(self, tree: Tree) -> bool:
+        """Check whether the pattern has at least one match in a tree."""
+        ...
+
+    def search(self, tree: Tree, order: str = "unsorted") -> list[dict[str, int]]:
+        """Find the matches of the pattern in one tree.
+
+        Args:
+            tree: Tree to search
+            order: "unsorted" (solver order), "leftmost" (by the leftmost bound
+                word), "rightmost" (by the rightmost bound word, from the right)
+                or a variable name (by that variable's word)
+
+        Returns:
+            List of matches, each a dict from variable name to word ID
+        """
+        ...
+
+    def explain(self, tree: Tree) -> str:
+        """Describe how the pattern would be searched in a tree.
+
+        Lists each block's variables in search order with their candidate counts,
+        and when each edge constraint is checked.
+        """
+        ...
+
+    def complexity(self, treebank: Treebank | None = None) -> dict[str, Any]:
+        """Estimate how expensive the pattern is to search.
+
+        Returns a dict with n_vars, domain_size_product (candidate combinations
+        per sentence), n_edge_checks and warning (a message or None). With a
+        treebank, its tag and relation frequencies replace the fixed guesses.
+        """
+        ...
+
+    def to_json(self) -> str:
+        """Serialize the pattern as JSON: {"query": "..."}."""
+        ...
+
+    @staticmethod
+    def from_json(json: str) -> Pattern:
+        """Recompile a pattern saved with Pattern.to_json().
+
+        Raises:
+            ValueError: If the JSON is malformed or the query doesn't compile
+        """
+        ...
 
     def __repr__(self) -> str: ...
 
@@ -162,6 +454,22 @@ class Treebank:
         """
         ...
 
+    @classmethod
+    def from_binary(cls, path: str) -> Treebank:
+        """Open a binary treebank file written by save_binary().
+
+        Raises:
+            OSError: If the file is not a binary treebank or has an
+                unsupported format version
+        """
+        ...
+
+    @classmethod
+    def load_or_build(cls, conllu_path: str, bin_path: str) -> Treebank:
+        """Open the binary cache bin_path of conllu_path, rebuilding it if it is
+        missing or older than conllu_path."""
+        ...
+
     @classmethod
     def from_files(cls, file_paths: list[str]) -> Treebank:
         """Create treebank from multiple CoNLL-U files.
@@ -174,6 +482,40 @@ class Treebank:
         """
         ...
 
+    @classmethod
+    def from_glob(cls, pattern: str) -> Treebank:
+        """Create treebank from the files matching a glob pattern, in sorted order.
+
+        Args:
+            pattern: Glob pattern (e.g., "data/*.conllu")
+
+        Returns:
+            Treebank object (empty, with a UserWarning, if no files match)
+
+        Raises:
+            ValueError: If the glob pattern is invalid
+        """
+        ...
+
+    @classmethod
+    def from_directory(
+        cls, dir: str, recursive: bool = False, pattern: str = "*.conllu"
+    ) -> Treebank:
+        """Create treebank from the files in a directory.
+
+        Args:
+            dir: Directory to search
+            recursive: Also search subdirectories
+            pattern: Glob pattern for file names
+
+        Returns:
+            Treebank object (empty, with a UserWarning, if no files match)
+
+        Raises:
+            ValueError: If the file name pattern is invalid
+        """
+        ...
+
     def trees(self, ordered: bool = True) -> TreeIterator:
         """Iterate over trees in treebank.
 
@@ -186,32 +528,364 @@ class Treebank:
         """
         ...
 
-    def search(self, pattern: Pattern | str, ordered: bool = True) -> MatchIterator:
+    def trees_with_index(self, ordered: bool = True) -> IndexedTreeIterator:
+        """Iterate over trees along with their 0-based sentence index.
+
+        Args:
+            ordered: If True (default), return trees in deterministic order.
+                    If False, indices reflect the order trees arrive in.
+
+        Returns:
+            Iterator over (index, Tree) tuples
+        """
+        ...
+
+    def search(
+        self, pattern: Pattern | str, ordered: bool = True, max_matches: Optional[int] = None
+    ) -> MatchIterator:
         """Search for pattern matches across all trees.
 
         Args:
             pattern: Compiled Pattern or query string
             ordered: If True (default), return matches in deterministic order.
                     If False, matches may arrive in any order for better performance.
+            max_matches: If given, return at most this many matches from each tree
 
         Returns:
             Iterator over (Tree, match_dict) tuples
         """
         ...
 
+    @overload
+    def filter(self, pattern: Pattern | str, ordered: bool = True) -> TreeIterator: ...
+    @overload
+    def filter(self, pattern: Callable[[Tree], bool], ordered: bool = True) -> FilteredTreebank: ...
+    def filter(self, pattern, ordered=True):
+        """Filter trees by a pattern or a predicate.
+
+        With a Pattern or query string, iterate over the trees that have at least
+        one match. With a callable, return a FilteredTreebank of the trees for
+        which it returns True.
+        """
+        ...
+
+    def filter_by_length(self, min: int = 0, max: Optional[int] = None) -> FilteredTreebank:
+        """Keep the trees with between min and max words (inclusive)."""
+        ...
+
+    def filter_by_metadata(self, key: str, value: str) -> FilteredTreebank:
+        """Keep the trees whose metadata has key set to value."""
+        ...
+
+    def count(self) -> int:
+        """Count the trees in the treebank without keeping them.
+
+        Raises:
+            OSError: If a file cannot be read
+            ValueError: If a tree cannot be parsed
+        """
+        ...
+
+    def count_matching_sentences(self, pattern: Pattern | str) -> int:
+        """Count the trees with at least one match (each tree counted once).
+
+        Args:
+            pattern: Compiled Pattern or query string
+
+        Raises:
+            OSError: If a file cannot be read
+            ValueError: If a tree cannot be parsed
+        """
+        ...
+
+    def kwic(
+        self,
+        pattern: Pattern | str,
+        anchor: str,
+        context: int = 5,
+        ordered: bool = True,
+        sort: Optional[str] = None,
+    ) -> list[str]:
+        """Concordance (KWIC) lines for the matches of a pattern.
+
+        Each line shows the anchor word with `context` words on either side, with
+        the keywords lined up and matched words in brackets.
+
+        Args:
+            pattern: Compiled Pattern or query string
+            anchor: Variable whose word is the keyword
+            context: Number of words on each side
+            ordered: Keep treebank order (ignored when sorting)
+            sort: "keyword", "left"/"right" (adjacent word) or "leftN"/"rightN"
+
+        Returns:
+            List of concordance lines
+        """
+        ...
+
+    def multi_search(
+        self, patterns: dict[str, Pattern | str]
+    ) -> dict[str, list[tuple[Tree, dict[str, int]]]]:
+        """Search for several named patterns in a single pass over the treebank.
+
+        Args:
+            patterns: Dict from names to compiled Patterns or query strings
+
+        Returns:
+            Dict from each name to its (tree, match_dict) tuples, in treebank order
+        """
+        ...
+
+    def frequency_per_million(self, pattern: Pattern | str) -> float:
+        """Matches of a pattern per million words of the treebank.
+
+        Args:
+            pattern: Compiled Pattern or query string
+
+        Returns:
+            Number of matches per million words (0.0 for an empty treebank)
+        """
+        ...
+
+    def count_by(
+        self, pattern: Pattern | str, group_by: str, field: str = "lemma"
+    ) -> dict[str, int]:
+        """Count matches grouped by a field of one variable's word.
+
+        Args:
+            pattern: Compiled Pattern or query string
+            group_by: Name of the variable to group by
+            field: form, lemma, upos, xpos, deprel or token_id
+
+        Returns:
+            Dict from field value to number of matches
+        """
+        ...
+
+    def stats(self) -> dict[str, Any]:
+        """Summarize the treebank in a single pass.
+
+        Returns:
+            Dict with n_sentences, n_words, mean_sentence_length, max_sentence_length,
+            upos_frequencies and deprel_frequencies (dicts of counts),
+            mean_dependency_distance, n_nonprojective, vocabulary_size and n_unique_lemmas
+        """
+        ...
+
+    def sample(self, n: int, seed: Optional[int] = None) -> Treebank:
+        """Draw a random sample of n trees (reservoir sampling), kept in treebank order.
+
+        Args:
+            n: Number of trees (all trees if there are fewer)
+            seed: Random seed for a reproducible sample
+
+        Returns:
+            In-memory Treebank holding the sample
+        """
+        ...
+
+    def sample_matching(
+        self, pattern: Pattern | str, n: int, seed: Optional[int] = None
+    ) -> list[Tree]:
+        """Draw a random sample of n trees from those that match a pattern.
+
+        Args:
+            pattern: Compiled Pattern or query string
+            n: Number of trees (all matching trees if there are fewer)
+            seed: Random seed for a reproducible sample
+
+        Returns:
+            List of trees, in treebank order
+        """
+        ...
+
+    def write_conllu(self, path: str) -> int:
+        """Write all trees to a CoNLL-U file, in treebank order.
+
+        The file is gzip-compressed if the path ends in ".gz".
+
+        Args:
+            path: Output file path (overwritten if it exists)
+
+        Returns:
+            Number of trees written
+
+        Raises:
+            OSError: If a file cannot be read or written
+            ValueError: If a tree cannot be parsed
+        """
+        ...
+
+    def save_binary(self, path: str) -> int:
+        """Write all trees to a binary treebank file, which loads without parsing.
+
+        Args:
+            path: Output file path (overwritten if it exists)
+
+        Returns:
+            Number of trees written
+        """
+        ...
+
+    def any_match(self, pattern: Pattern | str) -> bool:
+        """Check whether any tree matches a pattern (stops at the first match).
+
+        Args:
+            pattern: Compiled Pattern or query string
+
+        Returns:
+            True if some tree has a match
+
+        Raises:
+            OSError: If a file cannot be read before a match is found
+        """
+        ...
+
+    def with_buffer_size(self, match_batch: int = 500, channel_batches: int = 100) -> Treebank:
+        """Return a copy of this treebank with different batch and channel sizes.
+
+        Args:
+            match_batch: Number of results sent from worker threads at a time
+            channel_batches: Number of batches buffered before workers block
+
+        Returns:
+            Treebank with the new buffer sizes
+        """
+        ...
+
+    def to_memory(self) -> Treebank:
+        """Load all files into memory and return an in-memory Treebank.
+
+        Returns:
+            Treebank backed by memory
+
+        Raises:
+            OSError: If a file cannot be read
+        """
+        ...
+
     def __repr__(self) -> str: ...
 
+class FilteredTreebank:
+    """A treebank restricted to the trees that pass a set of filters."""
+
+    def trees(self, ordered: bool = True) -> FilteredTreeIterator:
+        """Iterate over the trees that pass the filters."""
+        ...
+
+    def __iter__(self) -> FilteredTreeIterator: ...
+    def search(
+        self, pattern: Pattern | str, ordered: bool = True, max_matches: Optional[int] = None
+    ) -> FilteredMatchIterator:
+        """Search the trees that pass the filters."""
+        ...
+
+    def filter(self, predicate: Callable[[Tree], bool]) -> FilteredTreebank:
+        """Add a predicate filter."""
+        ...
+
+    def filter_by_length(self, min: int = 0, max: Optional[int] = None) -> FilteredTreebank:
+        """Add a length filter."""
+        ...
+
+    def filter_by_metadata(self, key: str, value: str) -> FilteredTreebank:
+        """Add a metadata filter."""
+        ...
+
+    def __repr__(self) -> str: ...
+
+class FilteredTreeIterator(Iterator[Tree]):
+    """Iterator over the trees of a FilteredTreebank."""
+
+    def __iter__(self) -> FilteredTreeIterator: ...
+    def __next__(self) -> Tree: ...
+
+class FilteredMatchIterator(Iterator[tuple[Tree, dict[str, int]]]):
+    """Iterator over (Tree, match_dict) tuples from a FilteredTreebank."""
+
+    def __iter__(self) -> FilteredMatchIterator: ...
+    def __next__(self) -> tuple[Tree, dict[str, int]]: ...
+
 class TreeIterator(Iterator[Tree]):
     """Iterator over Tree objects."""
 
     def __iter__(self) -> TreeIterator: ...
     def __next__(self) -> Tree: ...
 
+class WordIterator(Iterator[Word]):
+    """Iterator over the words of a tree."""
+
+    def __iter__(self) -> WordIterator: ...
+    def __next__(self) -> Word: ...
+
+class IndexedTreeIterator(Iterator[tuple[int, Tree]]):
+    """Iterator over (index, Tree) tuples."""
+
+    def __iter__(self) -> IndexedTreeIterator: ...
+    def __next__(self) -> tuple[int, Tree]: ...
+
 class MatchIterator(Iterator[tuple[Tree, dict[str, int]]]):
     """Iterator over (Tree, match_dict) tuples."""
 
     def __iter__(self) -> MatchIterator: ...
     def __next__(self) -> tuple[Tree, dict[str, int]]: ...
+    def to_jsonl(self, path: str, fields: Optional[list[str]] = None) -> int:
+        """Write the remaining matches to a JSON Lines file, one object per match.
+
+        Each line has the tree's sent_id and sentence_text, plus an object per
+        variable with the chosen fields of the bound word.
+
+        Args:
+            path: Output file
+            fields: Any of form, lemma, upos, xpos, deprel, token_id
+                (default: form, lemma, upos)
+
+        Returns:
+            Number of matches written
+        """
+        ...
+
+    def to_csv(
+        self, path: str, columns: list[tuple[str, str, str]], sep: str = ","
+    ) -> int:
+        """Write the remaining matches to a CSV (or TSV) file, one row per match.
+
+        Args:
+            path: Output file
+            columns: (column_name, variable, field) triples, e.g.
+                [("V.lemma", "V", "lemma"), ("N.form", "N", "form")]; the names
+                form the header row, and unbound variables give empty cells
+            sep: Single-character delimiter ("\t" for TSV)
+
+        Returns:
+            Number of matches written
+        """
+        ...
+
+    def to_arrow(
+        self, schema: Optional[list[str]] = None, batch_size: Optional[int] = None
+    ) -> Any:
+        """Collect the remaining matches into a pyarrow RecordBatch (requires pyarrow).
+
+        Args:
+            schema: Columns as "variable.field" names, e.g. ["V.lemma", "N.form"]
+                (default: lemma and upos of every MATCH and OPTIONAL variable); token_id
+                columns are int64, unbound variables give nulls
+            batch_size: Take at most this many matches (default: all remaining)
+
+        Returns:
+            pyarrow.RecordBatch with one row per match
+
+        Raises:
+            ValueError: If a schema column is malformed or names a variable not in the pattern
+        """
+        ...
+
+    def to_polars(
+        self, schema: Optional[list[str]] = None, batch_size: Optional[int] = None
+    ) -> Any:
+        """Like to_arrow(), but returns a polars DataFrame (requires polars and pyarrow)."""
+        ...
 
 def compile_query(query: str) -> Pattern:
     """Compile query string into Pattern object.
@@ -0,0 +1,491 @@
+//! Binary treebank cache format
+//!
+//! Parsing CoNLL-U and interning its strings dominates load time for large
+//! treebanks. The binary format stores already parsed trees so they can be
+//! reloaded without that work.
+//!
+//! A file starts with the magic bytes `TSBT` and a little-endian `u32` format
+//! version, followed by one record per tree. Word fields (form, lemma, UPOS,
+//! XPOS, relations, feature names and values) are numbered in a string table
+//! that grows as the file is written: each record starts with the strings it
+//! introduces, and its words refer to strings by number. All integers are
+//! LEB128 varints. Comments and metadata are stored as plain strings.
+//!
+//! Files in this format are recognized by their magic bytes wherever the
+//! treebank reads files, so a binary file can be used like a CoNLL-U file.
+
+use crate::bytes::{BytestringPool, Sym};
+use crate::conllu::{ParseError, TreeIterator, open_conllu};
+use crate::tree::{Dep, Features, MultiwordToken, Tree, Word};
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// First bytes of every binary treebank file
+pub const MAGIC: &[u8; 4] = b"TSBT";
+
+/// Version of the record layout, bumped on incompatible changes
+pub const FORMAT_VERSION: u32 = 1;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn write_optional_string(out: &mut Vec<u8>, value: Option<&String>) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            write_bytes(out, value.as_bytes());
+        }
+        None => out.push(0),
+    }
+}
+
+/// Encode an optional word id as 0 for `None` and `id + 1` otherwise
+fn encode_head(head: Option<usize>) -> u64 {
+    head.map_or(0, |head| head as u64 + 1)
+}
+
+/// Streams trees to a writer in the binary format
+pub struct BinaryWriter<W: Write> {
+    writer: W,
+    /// Number of each string written so far
+    strings: FxHashMap<Box<[u8]>, u64>,
+}
+
+impl BinaryWriter<BufWriter<File>> {
+    /// Create (or overwrite) a binary treebank file
+    pub fn to_file(path: &Path) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> BinaryWriter<W> {
+    /// Start a binary treebank, writing the header
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            strings: FxHashMap::default(),
+        })
+    }
+
+    /// Number the string behind `sym`, noting it in `new_strings` if it is new
+    fn string_id(&mut self, pool: &BytestringPool, sym: Sym, new_strings: &mut Vec<u8>) -> u64 {
+        let bytes = pool.resolve(sym);
+        if let Some(&id) = self.strings.get(&*bytes) {
+            return id;
+        }
+        let id = self.strings.len() as u64;
+        write_bytes(new_strings, &bytes);
+        self.strings.insert(Box::from(&*bytes), id);
+        id
+    }
+
+    fn write_features(
+        &mut self,
+        out: &mut Vec<u8>,
+        pool: &BytestringPool,
+        features: &Features,
+        new_strings: &mut Vec<u8>,
+    ) {
+        write_varint(out, features.len() as u64);
+        for &(key, value) in features {
+            let key = self.string_id(pool, key, new_strings);
+            write_varint(out, key);
+            let value = self.string_id(pool, value, new_strings);
+            write_varint(out, value);
+        }
+    }
+
+    /// Write one tree record
+    pub fn write_tree(&mut self, tree: &Tree) -> io::Result<()> {
+        let pool = &tree.string_pool;
+        let known_strings = self.strings.len();
+        let mut new_strings = Vec::new();
+        let mut body = Vec::new();
+
+        write_varint(&mut body, tree.words.len() as u64);
+        for word in &tree.words {
+            write_varint(&mut body, word.token_id as u64);
+            for sym in [word.form, word.lemma, word.upos, word.xpos, word.deprel] {
+                let id = self.string_id(pool, sym, &mut new_strings);
+                write_varint(&mut body, id);
+            }
+            write_varint(&mut body, encode_head(word.head));
+            self.write_features(&mut body, pool, &word.feats, &mut new_strings);
+            self.write_features(&mut body, pool, &word.misc, &mut new_strings);
+            write_varint(&mut body, word.enhanced_deps.len() as u64);
+            for dep in &word.enhanced_deps {
+                write_varint(&mut body, encode_head(dep.head));
+                let deprel = self.string_id(pool, dep.deprel, &mut new_strings);
+                write_varint(&mut body, deprel);
+            }
+        }
+
+        write_varint(&mut body, tree.multiword_tokens.len() as u64);
+        for mwt in &tree.multiword_tokens {
+            write_varint(&mut body, mwt.start as u64);
+            write_varint(&mut body, mwt.end as u64);
+            let form = self.string_id(pool, mwt.form, &mut new_strings);
+            write_varint(&mut body, form);
+            self.write_features(&mut body, pool, &mwt.misc, &mut new_strings);
+        }
+
+        write_optional_string(&mut body, tree.sentence_text.as_ref());
+        // Sorted so that a tree always gives the same bytes
+        let mut metadata: Vec<_> = tree.metadata.iter().collect();
+        metadata.sort();
+        write_varint(&mut body, metadata.len() as u64);
+        for (key, value) in metadata {
+            write_bytes(&mut body, key.as_bytes());
+            write_bytes(&mut body, value.as_bytes());
+        }
+        for lines in [&tree.comment_order, &tree.raw_comments] {
+            write_varint(&mut body, lines.len() as u64);
+            for line in lines {
+                write_bytes(&mut body, line.as_bytes());
+            }
+        }
+        body.push(u8::from(tree.is_new_doc) | (u8::from(tree.is_new_par) << 1));
+        write_optional_string(&mut body, tree.doc_id.as_ref());
+
+        let mut header = Vec::new();
+        write_varint(&mut header, (self.strings.len() - known_strings) as u64);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(&new_strings)?;
+        self.writer.write_all(&body)
+    }
+
+    /// Flush the output after the last tree
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Read a varint, or `None` at a clean end of input
+fn read_varint(reader: &mut impl Read) -> io::Result<Option<u64>> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0u8];
+        if reader.read(&mut byte)? == 0 {
+            return if shift == 0 {
+                Ok(None)
+            } else {
+                Err(io::ErrorKind::UnexpectedEof.into())
+            };
+        }
+        if shift > 63 {
+            return Err(invalid_data("varint is too long"));
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+        shift += 7;
+    }
+}
+
+/// Reads trees written by [`BinaryWriter`]
+pub struct BinaryTreeReader<R: Read> {
+    reader: R,
+    string_pool: BytestringPool,
+    /// Interned string for each string number
+    syms: Vec<Sym>,
+}
+
+impl BinaryTreeReader<BufReader<File>> {
+    /// Open a binary treebank file, checking its header
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> BinaryTreeReader<R> {
+    /// Start reading a binary treebank, checking the magic bytes and version
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => invalid_data("not a treesearch binary treebank"),
+            _ => e,
+        })?;
+        if header[..4] != MAGIC[..] {
+            return Err(invalid_data("not a treesearch binary treebank"));
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != FORMAT_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported binary treebank version {} (expected {})",
+                version, FORMAT_VERSION
+            )));
+        }
+        Ok(Self {
+            reader,
+            string_pool: BytestringPool::new(),
+            syms: Vec::new(),
+        })
+    }
+
+    fn varint(&mut self) -> io::Result<u64> {
+        read_varint(&mut self.reader)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn usize(&mut self) -> io::Result<usize> {
+        usize::try_from(self.varint()?).map_err(|_| invalid_data("number out of range"))
+    }
+
+    fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        // Don't trust the length with an allocation: a corrupt one could be huge
+        let len = self.varint()?;
+        let mut bytes = Vec::new();
+        (&mut self.reader).take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(invalid_data("string runs past the end of the file"));
+        }
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|_| invalid_data("string is not UTF-8"))
+    }
+
+    fn optional_string(&mut self) -> io::Result<Option<String>> {
+        match self.usize()? {
+            0 => Ok(None),
+            _ => self.string().map(Some),
+        }
+    }
+
+    fn sym(&mut self) -> io::Result<Sym> {
+        let id = self.usize()?;
+        self.syms
+            .get(id)
+            .copied()
+            .ok_or_else(|| invalid_data(format!("unknown string number {}", id)))
+    }
+
+    fn features(&mut self) -> io::Result<Features> {
+        (0..self.usize()?)
+            .map(|_| Ok((self.sym()?, self.sym()?)))
+            .collect()
+    }
+
+    /// Decode an optional word id, checking it against the sentence length
+    fn head(&mut self, num_words: usize) -> io::Result<Option<usize>> {
+        match self.usize()? {
+            0 => Ok(None),
+            head if head <= num_words => Ok(Some(head - 1)),
+            head => Err(invalid_data(format!(
+                "head {} is past the end of a {}-word sentence",
+                head, num_words
+            ))),
+        }
+    }
+
+    /// Read one tree record, or `None` at the end of the file
+    fn read_tree(&mut self) -> io::Result<Option<Tree>> {
+        let Some(num_new) = read_varint(&mut self.reader)? else {
+            return Ok(None);
+        };
+        for _ in 0..num_new {
+            let bytes = self.bytes()?;
+            let sym = self.string_pool.get_or_intern(&bytes);
+            self.syms.push(sym);
+        }
+
+        let mut tree = Tree::with_metadata(&self.string_pool, None, HashMap::new());
+        let num_words = self.usize()?;
+        for word_id in 0..num_words {
+            let token_id = self.usize()?;
+            let [form, lemma, upos, xpos, deprel] = [
+                self.sym()?,
+                self.sym()?,
+                self.sym()?,
+                self.sym()?,
+                self.sym()?,
+            ];
+            let head = self.head(num_words)?;
+            let feats = self.features()?;
+            let misc = self.features()?;
+            let mut word = Word::new(
+                word_id, token_id, form, lemma, upos, xpos, feats, head, deprel, misc,
+            );
+            for _ in 0..self.usize()? {
+                word.enhanced_deps.push(Dep {
+                    head: self.head(num_words)?,
+                    deprel: self.sym()?,
+                });
+            }
+            tree.words.push(word);
+        }
+
+        for _ in 0..self.usize()? {
+            tree.multiword_tokens.push(MultiwordToken {
+                start: self.usize()?,
+                end: self.usize()?,
+                form: self.sym()?,
+                misc: self.features()?,
+            });
+        }
+
+        tree.sentence_text = self.optional_string()?;
+        for _ in 0..self.usize()? {
+            let key = self.string()?;
+            let value = self.string()?;
+            tree.metadata.insert(key, value);
+        }
+        tree.comment_order = (0..self.usize()?)
+            .map(|_| self.string())
+            .collect::<io::Result<_>>()?;
+        tree.raw_comments = (0..self.usize()?)
+            .map(|_| self.string())
+            .collect::<io::Result<_>>()?;
+        let mut flags = [0u8];
+        self.reader.read_exact(&mut flags)?;
+        tree.is_new_doc = flags[0] & 1 != 0;
+        tree.is_new_par = flags[0] & 2 != 0;
+        tree.doc_id = self.optional_string()?;
+
+        tree.compile_tree();
+        Ok(Some(tree))
+    }
+}
+
+impl<R: Read> Iterator for BinaryTreeReader<R> {
+    type Item = Result<Tree, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_tree().map_err(ParseError::IoError).transpose()
+    }
+}
+
+/// Trees from one file of a treebank, in either CoNLL-U or the binary format
+pub(crate) enum FileTrees {
+    Conllu(TreeIterator<BufReader<Box<dyn Read + Send>>>),
+    Binary(BinaryTreeReader<BufReader<File>>),
+}
+
+impl Iterator for FileTrees {
+    type Item = Result<Tree, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            FileTrees::Conllu(trees) => trees.next(),
+            FileTrees::Binary(trees) => trees.next(),
+        }
+    }
+}
+
+/// Check whether a file starts with the binary treebank magic bytes
+pub fn is_binary_treebank(path: &Path) -> io::Result<bool> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(reader.fill_buf()?.starts_with(MAGIC))
+}
+
+/// Open a treebank file, choosing the reader by its first bytes
+pub(crate) fn open_trees(path: &Path) -> io::Result<FileTrees> {
+    if is_binary_treebank(path)? {
+        BinaryTreeReader::from_file(path).map(FileTrees::Binary)
+    } else {
+        Ok(FileTrees::Conllu(TreeIterator::from_reader(open_conllu(
+            path,
+        )?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONLLU: &str = "# newdoc id = d1\n\
+                          # sent_id = s1\n\
+                          # text = Del perro.\n\
+                          1-2\tDel\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                          1\tDe\tde\tADP\t_\t_\t3\tcase\t_\t_\n\
+                          2\tel\tel\tDET\t_\tDefinite=Def|Gender=Masc\t3\tdet\t_\t_\n\
+                          3\tperro\tperro\tNOUN\t_\tGender=Masc\t0\troot\t0:root\tSpaceAfter=No\n\
+                          4\t.\t.\tPUNCT\t_\t_\t3\tpunct\t3:punct\t_\n\n\
+                          # sent_id = s2\n\
+                          1\tEl\tel\tDET\t_\tDefinite=Def|Gender=Masc\t2\tdet\t_\t_\n\
+                          2\tperro\tperro\tNOUN\t_\tGender=Masc\t0\troot\t_\t_\n\n";
+
+    fn encode(trees: &[Tree]) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(Vec::new()).unwrap();
+        for tree in trees {
+            writer.write_tree(tree).unwrap();
+        }
+        writer.writer
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let trees: Vec<Tree> = TreeIterator::from_string(CONLLU)
+            .map(Result::unwrap)
+            .collect();
+        let bytes = encode(&trees);
+        assert!(bytes.starts_with(b"TSBT\x01\x00\x00\x00"));
+
+        let decoded: Vec<Tree> = BinaryTreeReader::new(&bytes[..])
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(decoded.len(), 2);
+        for (tree, original) in decoded.iter().zip(&trees) {
+            assert_eq!(tree.to_conllu(), original.to_conllu());
+            assert_eq!(tree.is_new_doc, original.is_new_doc);
+            assert_eq!(tree.doc_id, original.doc_id);
+            assert_eq!(tree.root_id, original.root_id);
+        }
+        assert_eq!(decoded[1].lemma_index.len(), 2);
+    }
+
+    #[test]
+    fn test_binary_reader_errors() {
+        let err = BinaryTreeReader::new(&b"1\tThe\tthe"[..]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = BinaryTreeReader::new(&b"TSBT\x02\x00\x00\x00"[..])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("version 2"));
+
+        // A record cut short is an error, not the end of the file
+        let trees: Vec<Tree> = TreeIterator::from_string(CONLLU)
+            .map(Result::unwrap)
+            .collect();
+        let bytes = encode(&trees[..1]);
+        let mut reader = BinaryTreeReader::new(&bytes[..bytes.len() - 3]).unwrap();
+        assert!(matches!(reader.next(), Some(Err(ParseError::IoError(_)))));
+
+        // A huge string length is rejected without allocating it
+        let mut corrupt = b"TSBT\x01\x00\x00\x00\x01".to_vec();
+        corrupt.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        corrupt.extend_from_slice(b"dog");
+        let mut reader = BinaryTreeReader::new(&corrupt[..]).unwrap();
+        match reader.next() {
+            Some(Err(ParseError::IoError(err))) => {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData)
+            }
+            other => panic!(
+                "expected an InvalidData error, got {:?}",
+                other.map(|r| r.is_ok())
+            ),
+        }
+    }
+}
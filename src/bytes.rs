@@ -28,6 +28,12 @@ impl BytestringPool {
         self.0.lock().unwrap().get_or_intern(bytes)
     }
 
+    /// Look up a bytestring without interning it
+    #[inline]
+    pub fn get(&self, bytes: &[u8]) -> Option<Sym> {
+        self.0.lock().unwrap().get(bytes)
+    }
+
     #[inline]
     pub fn resolve(&self, sym: Sym) -> Arc<[u8]> {
         self.0.lock().unwrap().resolve(sym)
@@ -38,6 +44,11 @@ impl BytestringPool {
         self.0.lock().unwrap().compare_bytes(sym, bytes)
     }
 
+    #[inline(always)]
+    pub fn compare_bytes_ci(&self, sym: Sym, bytes: &[u8]) -> bool {
+        self.0.lock().unwrap().compare_bytes_ci(sym, bytes)
+    }
+
     #[inline(always)]
     pub fn compare_kv(
         &self,
@@ -105,6 +116,11 @@ impl ByteInterner {
         }
     }
 
+    #[inline]
+    pub fn get(&self, bytes: &[u8]) -> Option<Sym> {
+        self.map.get(bytes).copied()
+    }
+
     #[inline]
     pub fn resolve(&self, sym: Sym) -> Arc<[u8]> {
         self.slab[(sym.0.get() - 1) as usize].clone()
@@ -115,6 +131,11 @@ impl ByteInterner {
         &*self.slab[(sym.0.get() - 1) as usize] == bytes
     }
 
+    #[inline(always)]
+    pub fn compare_bytes_ci(&self, sym: Sym, bytes: &[u8]) -> bool {
+        compare_bytes_ci(&self.slab[(sym.0.get() - 1) as usize], bytes)
+    }
+
     #[inline(always)]
     pub fn compare_kv(
         &self,
@@ -134,6 +155,30 @@ pub fn bs_split_once(bytes: &[u8], delim: u8) -> Option<(&[u8], &[u8])> {
     Some((pair.next()?, pair.next()?))
 }
 
+// Case-insensitive equality: ASCII folding, or Unicode lowercasing if either side
+// is non-ASCII (invalid UTF-8 only matches byte-for-byte)
+pub fn compare_bytes_ci(a: &[u8], b: &[u8]) -> bool {
+    if a.is_ascii() && b.is_ascii() {
+        return a.eq_ignore_ascii_case(b);
+    }
+    match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+        (Ok(a), Ok(b)) => a
+            .chars()
+            .flat_map(char::to_lowercase)
+            .eq(b.chars().flat_map(char::to_lowercase)),
+        _ => a == b,
+    }
+}
+
+// Substring search (the empty needle is always found)
+#[inline]
+pub fn bs_contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
 #[inline]
 pub fn bs_atoi(bytes: &[u8]) -> Option<usize> {
     let mut n: usize = 0;
@@ -167,6 +212,31 @@ mod tests {
 
     // ===== BytestringPool / ByteInterner Tests =====
 
+    #[test]
+    fn test_bs_contains() {
+        assert!(bs_contains(b"book", b"oo"));
+        assert!(bs_contains(b"book", b"book"));
+        assert!(bs_contains(b"book", b""));
+        assert!(!bs_contains(b"book", b"books"));
+        assert!(!bs_contains(b"", b"a"));
+    }
+
+    #[test]
+    fn test_compare_bytes_ci() {
+        assert!(compare_bytes_ci(b"Running", b"running"));
+        assert!(compare_bytes_ci(b"VERB", b"verb"));
+        assert!(!compare_bytes_ci(b"run", b"running"));
+        assert!(compare_bytes_ci("Straße".as_bytes(), "STRAßE".as_bytes()));
+        assert!(compare_bytes_ci("ÉTÉ".as_bytes(), "été".as_bytes()));
+        assert!(!compare_bytes_ci("été".as_bytes(), b"ete"));
+        assert!(!compare_bytes_ci(b"\xff", b"\xfe"));
+
+        let mut pool = BytestringPool::new();
+        let sym = pool.get_or_intern(b"Dog");
+        assert!(pool.compare_bytes_ci(sym, b"dOG"));
+        assert!(!pool.compare_bytes(sym, b"dOG"));
+    }
+
     #[test]
     fn test_interner_basic() {
         let mut pool = BytestringPool::new();
@@ -218,7 +288,7 @@ mod tests {
 
         // Intern all strings
         for s in &strings {
-            syms.push(pool.get_or_intern(*s));
+            syms.push(pool.get_or_intern(s));
         }
 
         // Verify all are different
@@ -0,0 +1,254 @@
+//! Static cost estimates for patterns
+//!
+//! Before running a query on a large treebank it helps to know whether it is
+//! likely to be slow. [`Pattern::estimated_complexity`] multiplies the expected
+//! number of candidate words for each variable in a sentence, which bounds the
+//! number of partial matches the solver may examine before edge constraints
+//! prune them. Without statistics the per-variable domains come from fixed
+//! heuristics; with [`TreebankStats`] the tag and relation frequencies observed
+//! in the corpus are used instead.
+
+use crate::pattern::{BasePattern, Constraint, ConstraintValue, Pattern};
+use crate::stats::TreebankStats;
+use std::collections::HashMap;
+
+/// Sentence length assumed when there are no corpus statistics
+const DEFAULT_SENTENCE_LENGTH: f64 = 25.0;
+
+/// Share of words assumed to pass a tag, relation or other field constraint
+const FIELD_SELECTIVITY: f64 = 0.1;
+
+/// Share of words assumed to pass a regex or substring test on a lemma or form
+const PARTIAL_WORD_SELECTIVITY: f64 = 0.05;
+
+/// Share of words assumed to pass a structural test (children, position, leaves)
+const STRUCTURAL_SELECTIVITY: f64 = 0.5;
+
+/// Candidate combinations per sentence above which [`ComplexityBound::to_warning`] warns
+pub const COMPLEXITY_WARNING_THRESHOLD: f64 = 1e6;
+
+/// Worst-case size of a pattern search, per sentence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexityBound {
+    /// Distinct variables in all blocks (MATCH, EXCEPT and OPTIONAL); MATCH
+    /// variables mentioned again in another block count once
+    pub n_vars: usize,
+    /// Product of the expected candidate counts of all variables
+    pub domain_size_product: f64,
+    /// Edge constraints in all blocks
+    pub n_edge_checks: usize,
+}
+
+impl ComplexityBound {
+    /// A human-readable warning if the search may be expensive, otherwise `None`
+    pub fn to_warning(&self) -> Option<String> {
+        (self.domain_size_product > COMPLEXITY_WARNING_THRESHOLD).then(|| {
+            format!(
+                "Query may be slow: up to {:.1e} candidate combinations per sentence for {} variables; \
+                 constrain the broadest variables (e.g. with upos or lemma) to narrow the search",
+                self.domain_size_product, self.n_vars
+            )
+        })
+    }
+}
+
+/// Frequencies used to estimate how many words pass a constraint
+struct DomainModel<'a> {
+    stats: Option<&'a TreebankStats>,
+    sentence_length: f64,
+}
+
+impl DomainModel<'_> {
+    /// Expected share of words (0 to 1) that satisfy the constraint
+    fn selectivity(&self, constraint: &Constraint) -> f64 {
+        let one_word = 1.0 / self.sentence_length;
+        let unique = |count: Option<usize>| match count {
+            Some(count) if count > 0 => 1.0 / count as f64,
+            _ => one_word,
+        };
+        match constraint {
+            Constraint::Any => 1.0,
+            Constraint::Lemma(value) if is_exact(value) => {
+                unique(self.stats.map(|stats| stats.n_unique_lemmas))
+            }
+            Constraint::Form(value) if is_exact(value) => {
+                unique(self.stats.map(|stats| stats.vocabulary_size))
+            }
+            Constraint::Lemma(_) | Constraint::Form(_) => PARTIAL_WORD_SELECTIVITY,
+            Constraint::UPOS(value) => self.observed_share(value, |stats| &stats.upos_frequencies),
+            Constraint::DepRel(value) => {
+                self.observed_share(value, |stats| &stats.deprel_frequencies)
+            }
+            Constraint::XPOS(_)
+            | Constraint::Feature(..)
+            | Constraint::Misc(..)
+            | Constraint::HasFeature(_)
+            | Constraint::HasMisc(_) => FIELD_SELECTIVITY,
+            Constraint::IsRoot => one_word,
+            Constraint::TokenId(..)
+            | Constraint::IsChild(_)
+            | Constraint::HasChild(_)
+            | Constraint::ChildCount { .. }
+            | Constraint::IsLeaf
+            | Constraint::SubtreeContains(_) => STRUCTURAL_SELECTIVITY,
+            // Treat the parts as independent
+            Constraint::And(parts) => parts.iter().map(|part| self.selectivity(part)).product(),
+            Constraint::Or(parts) => parts
+                .iter()
+                .map(|part| self.selectivity(part))
+                .sum::<f64>()
+                .min(1.0),
+            Constraint::Not(inner) => (1.0 - self.selectivity(inner)).max(one_word),
+        }
+    }
+
+    /// Share of corpus words whose tag or relation matches the value, or the default guess
+    fn observed_share(
+        &self,
+        value: &ConstraintValue,
+        frequencies: impl Fn(&TreebankStats) -> &HashMap<Vec<u8>, usize>,
+    ) -> f64 {
+        match self.stats {
+            Some(stats) if stats.n_words > 0 => {
+                let matching: usize = frequencies(stats)
+                    .iter()
+                    .filter(|(tag, _)| value_matches(value, tag))
+                    .map(|(_, &count)| count)
+                    .sum();
+                matching as f64 / stats.n_words as f64
+            }
+            _ => FIELD_SELECTIVITY,
+        }
+    }
+
+    /// Expected candidates per sentence for a variable, at least one
+    fn domain_size(&self, constraint: &Constraint) -> f64 {
+        (self.selectivity(constraint) * self.sentence_length).max(1.0)
+    }
+}
+
+/// Whether the value names a single string (so it picks out about one word type)
+fn is_exact(value: &ConstraintValue) -> bool {
+    matches!(
+        value,
+        ConstraintValue::Literal(_) | ConstraintValue::CaseInsensitive(_)
+    )
+}
+
+/// Check a corpus value against a constraint value, as the solver would
+fn value_matches(value: &ConstraintValue, bytes: &[u8]) -> bool {
+    match value {
+        ConstraintValue::Literal(literal) => bytes == literal.as_bytes(),
+        ConstraintValue::CaseInsensitive(literal) => bytes.eq_ignore_ascii_case(literal.as_bytes()),
+        ConstraintValue::Prefix(prefix) => bytes.starts_with(prefix.as_bytes()),
+        ConstraintValue::Suffix(suffix) => bytes.ends_with(suffix.as_bytes()),
+        ConstraintValue::Contains(needle) => bytes
+            .windows(needle.len().max(1))
+            .any(|window| window == needle.as_bytes()),
+        ConstraintValue::Regex(_, regex) => {
+            std::str::from_utf8(bytes).is_ok_and(|s| regex.is_match(s))
+        }
+    }
+}
+
+impl Pattern {
+    /// Estimate the worst-case number of candidate combinations examined per sentence
+    ///
+    /// Without statistics, sentences are taken to have 25 words, exact lemmas and
+    /// forms to match about one word, tags, relations and features about 10% of
+    /// words, and unconstrained variables every word. With statistics (from
+    /// [`TreebankStats::collect`]), the corpus' mean sentence length and observed
+    /// tag, relation and vocabulary counts are used.
+    pub fn estimated_complexity(&self, corpus_stats: Option<&TreebankStats>) -> ComplexityBound {
+        let sentence_length = corpus_stats
+            .map(|stats| stats.mean_sentence_length)
+            .filter(|&length| length >= 1.0)
+            .unwrap_or(DEFAULT_SENTENCE_LENGTH);
+        let model = DomainModel {
+            stats: corpus_stats,
+            sentence_length,
+        };
+        let blocks: Vec<&BasePattern> = std::iter::once(&self.match_pattern)
+            .chain(&self.except_patterns)
+            .chain(&self.optional_patterns)
+            .collect();
+        // MATCH variables reused in an extension block are already bound there
+        let new_vars: Vec<&Constraint> = self
+            .match_pattern
+            .var_constraints
+            .iter()
+            .chain(
+                blocks[1..]
+                    .iter()
+                    .flat_map(|block| block.var_names.iter().zip(&block.var_constraints))
+                    .filter(|(name, _)| !self.match_pattern.var_ids.contains_key(*name))
+                    .map(|(_, constraint)| constraint),
+            )
+            .collect();
+        ComplexityBound {
+            n_vars: new_vars.len(),
+            domain_size_product: new_vars
+                .iter()
+                .map(|constraint| model.domain_size(constraint))
+                .product(),
+            n_edge_checks: blocks
+                .iter()
+                .map(|block| block.edge_constraints.len())
+                .sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iterators::Treebank;
+    use crate::query::compile_query;
+
+    #[test]
+    fn test_estimated_complexity_heuristics() {
+        let pattern =
+            compile_query(r#"MATCH { V [lemma="help"]; N [upos="NOUN"]; V -> N; }"#).unwrap();
+        let bound = pattern.estimated_complexity(None);
+        assert_eq!(bound.n_vars, 2);
+        assert_eq!(bound.n_edge_checks, 1);
+        // One candidate for the lemma, 10% of 25 words for the tag
+        assert!((bound.domain_size_product - 2.5).abs() < 1e-9);
+        assert_eq!(bound.to_warning(), None);
+
+        let pattern = compile_query("MATCH { A []; B []; C []; D []; E []; }").unwrap();
+        let bound = pattern.estimated_complexity(None);
+        assert!((bound.domain_size_product - 25f64.powi(5)).abs() < 1e-3);
+        assert!(bound.to_warning().unwrap().contains("5 variables"));
+    }
+
+    #[test]
+    fn test_estimated_complexity_with_stats() {
+        let conllu = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                      2\tdog\tdog\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                      3\tsaw\tsee\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      4\tcats\tcat\tNOUN\tNNS\t_\t3\tobj\t_\t_\n\n";
+        let stats = TreebankStats::collect(Treebank::from_string(conllu)).unwrap();
+        let pattern = compile_query(r#"MATCH { N [upos=/NOUN|PROPN/]; X []; N -> X; }"#).unwrap();
+        let bound = pattern.estimated_complexity(Some(&stats));
+        // Two nouns and four candidates for X in a four-word sentence
+        assert!((bound.domain_size_product - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_estimated_complexity_reused_match_vars() {
+        let base = compile_query(r#"MATCH { V [lemma="help"]; N [upos="NOUN"]; }"#).unwrap();
+        let extended = compile_query(
+            r#"MATCH { V [lemma="help"]; N [upos="NOUN"]; }
+               OPTIONAL { V -[obj]-> O; }
+               EXCEPT { N -> D; D [upos="DET"]; }"#,
+        )
+        .unwrap();
+        let base = base.estimated_complexity(None);
+        let bound = extended.estimated_complexity(None);
+        // V and N count once; only O (an obj dependent) and D (a DET) are new, 10% of words each
+        assert_eq!(bound.n_vars, 4);
+        assert!((bound.domain_size_product - base.domain_size_product * 2.5 * 2.5).abs() < 1e-9);
+        assert_eq!(bound.n_edge_checks, 2);
+    }
+}
@@ -0,0 +1,223 @@
+//! Concordance (KWIC) lines for matches
+//!
+//! A KWIC ("keyword in context") line centres one matched word, the anchor, with
+//! a few words of context on each side. Other matched words are shown in
+//! brackets wherever they fall in the context.
+
+use crate::iterators::{Treebank, TreebankError};
+use crate::pattern::Pattern;
+use crate::searcher::Match;
+use std::fmt;
+
+/// One concordance line: the anchor word and the words around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KwicLine {
+    /// Words before the keyword, in sentence order
+    pub left: Vec<String>,
+    /// Form of the anchor word
+    pub keyword: String,
+    /// Words after the keyword, in sentence order
+    pub right: Vec<String>,
+    /// Number of context words requested
+    pub context: usize,
+}
+
+impl KwicLine {
+    /// Build the line for a match, or `None` if `anchor_var` is not bound
+    pub fn new(m: &Match, anchor_var: &str, context: usize) -> Option<Self> {
+        let anchor = *m.bindings.get(anchor_var)?;
+        let tree = &m.tree;
+        let word_text = |word_id: usize| {
+            let form = String::from_utf8_lossy(&tree.string_pool.resolve(tree.words[word_id].form))
+                .into_owned();
+            if m.bindings.values().any(|&bound| bound == word_id) {
+                format!("[{}]", form)
+            } else {
+                form
+            }
+        };
+        let end = (anchor + 1 + context).min(tree.words.len());
+        Some(Self {
+            left: (anchor.saturating_sub(context)..anchor)
+                .map(word_text)
+                .collect(),
+            keyword: word_text(anchor),
+            right: (anchor + 1..end).map(word_text).collect(),
+            context,
+        })
+    }
+
+    /// Width of the left context as printed, in characters
+    pub fn left_width(&self) -> usize {
+        self.left.join(" ").chars().count()
+    }
+
+    /// The line with its left context right-aligned in a column `left_width`
+    /// characters wide, so that lines formatted with the same width have their
+    /// keywords lined up (see [`format_concordance`])
+    pub fn aligned(&self, left_width: usize) -> String {
+        let left = self.left.join(" ");
+        let mut out = if left.is_empty() && left_width == 0 {
+            self.keyword.clone()
+        } else {
+            format!("{:>left_width$} {}", left, self.keyword)
+        };
+        if !self.right.is_empty() {
+            out.push(' ');
+            out.push_str(&self.right.join(" "));
+        }
+        out
+    }
+}
+
+/// The words separated by spaces, without padding
+impl fmt::Display for KwicLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.aligned(0))
+    }
+}
+
+/// Format concordance lines with their keywords lined up, padding each left
+/// context to the widest one among `lines`
+pub fn format_concordance(lines: &[KwicLine]) -> Vec<String> {
+    let width = lines.iter().map(KwicLine::left_width).max().unwrap_or(0);
+    lines.iter().map(|line| line.aligned(width)).collect()
+}
+
+/// Format a match as a KWIC line (see [`KwicLine`]), or `None` if the anchor is unbound
+pub fn format_kwic(m: &Match, anchor_var: &str, context: usize) -> Option<String> {
+    KwicLine::new(m, anchor_var, context).map(|line| line.to_string())
+}
+
+/// Order for reading a concordance
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConcordanceSortKey {
+    /// By the `n`th word left of the keyword (1 = the word right before it)
+    LeftContext(usize),
+    /// By the `n`th word right of the keyword (1 = the word right after it)
+    RightContext(usize),
+    /// By the keyword itself
+    Keyword,
+}
+
+/// Sort concordance lines, ignoring case and match brackets; ties keep their order
+///
+/// Lines without a word at the requested position sort first.
+pub fn sort_concordance(lines: &mut [KwicLine], key: ConcordanceSortKey) {
+    let sort_word = |line: &KwicLine| -> Option<String> {
+        let word = match key {
+            ConcordanceSortKey::Keyword => Some(&line.keyword),
+            ConcordanceSortKey::LeftContext(n) => line
+                .left
+                .len()
+                .checked_sub(n)
+                .and_then(|i| line.left.get(i)),
+            ConcordanceSortKey::RightContext(n) => n.checked_sub(1).and_then(|i| line.right.get(i)),
+        };
+        word.map(|word| {
+            let word = word
+                .strip_prefix('[')
+                .and_then(|word| word.strip_suffix(']'))
+                .unwrap_or(word);
+            word.to_lowercase()
+        })
+    };
+    lines.sort_by_cached_key(sort_word);
+}
+
+impl Treebank {
+    /// Stream a KWIC line for each match of `pattern`, centred on `anchor_var`
+    ///
+    /// Matches that leave the anchor unbound (an OPTIONAL variable) are skipped.
+    pub fn kwic(
+        self,
+        pattern: Pattern,
+        anchor_var: &str,
+        context: usize,
+        ordered: bool,
+    ) -> impl Iterator<Item = Result<KwicLine, TreebankError>> {
+        let anchor_var = anchor_var.to_string();
+        self.match_iter(pattern, ordered)
+            .filter_map(move |m| match m {
+                Ok(m) => KwicLine::new(&m, &anchor_var, context).map(Ok),
+                Err(e) => Some(Err(e)),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_query;
+    use std::collections::HashSet;
+
+    const CONLLU: &str = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                          2\tdog\tdog\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                          3\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n\
+                          4\tvery\tvery\tADV\tRB\t_\t5\tadvmod\t_\t_\n\
+                          5\tfast\tfast\tADV\tRB\t_\t3\tadvmod\t_\t_\n\n\
+                          1\tBirds\tbird\tNOUN\tNNS\t_\t2\tnsubj\t_\t_\n\
+                          2\tsing\tsing\tVERB\tVBP\t_\t0\troot\t_\t_\n\n";
+
+    fn lines(query: &str, context: usize) -> Vec<KwicLine> {
+        Treebank::from_string(CONLLU)
+            .kwic(compile_query(query).unwrap(), "V", context, true)
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_kwic_lines() {
+        let found = lines("MATCH { V [upos=\"VERB\"]; S []; V -[nsubj]-> S; }", 2);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].left, vec!["The", "[dog]"]);
+        assert_eq!(found[0].keyword, "[runs]");
+        assert_eq!(found[0].right, vec!["very", "fast"]);
+        assert_eq!(found[0].to_string(), "The [dog] [runs] very fast");
+        assert_eq!(found[1].to_string(), "[Birds] [sing]");
+        assert_eq!(
+            format_concordance(&found),
+            vec!["The [dog] [runs] very fast", "  [Birds] [sing]"]
+        );
+
+        // Keywords line up whatever the length of the words before them, and
+        // lines without left context are padded to the others
+        let found = lines("MATCH { V []; }", 2);
+        let formatted = format_concordance(&found);
+        assert_eq!(formatted[0], "          [The] dog runs");
+        assert_eq!(formatted[4], "runs very [fast]");
+        let columns: HashSet<usize> = formatted
+            .iter()
+            .map(|line| line.find('[').unwrap())
+            .collect();
+        assert_eq!(columns.len(), 1);
+
+        // The anchor has to be bound
+        let pattern = compile_query("MATCH { S [upos=\"NOUN\"]; }").unwrap();
+        let m = Treebank::from_string(CONLLU)
+            .match_iter(pattern, true)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(format_kwic(&m, "V", 1), None);
+        assert_eq!(format_kwic(&m, "S", 1).unwrap(), "The [dog] runs");
+    }
+
+    #[test]
+    fn test_sort_concordance() {
+        let mut found = lines("MATCH { V [upos=\"VERB\"]; }", 1);
+        let keywords = |lines: &[KwicLine]| -> Vec<String> {
+            lines.iter().map(|line| line.keyword.clone()).collect()
+        };
+
+        sort_concordance(&mut found, ConcordanceSortKey::Keyword);
+        assert_eq!(keywords(&found), vec!["[runs]", "[sing]"]);
+        sort_concordance(&mut found, ConcordanceSortKey::LeftContext(1));
+        assert_eq!(keywords(&found), vec!["[sing]", "[runs]"]); // "Birds" < "dog"
+        // "sing" has no right context, so it sorts first
+        sort_concordance(&mut found, ConcordanceSortKey::RightContext(1));
+        assert_eq!(keywords(&found), vec!["[sing]", "[runs]"]);
+        sort_concordance(&mut found, ConcordanceSortKey::LeftContext(0));
+        assert_eq!(keywords(&found), vec!["[sing]", "[runs]"]);
+    }
+}
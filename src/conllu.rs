@@ -5,13 +5,18 @@
 //! enhanced dependencies, and sentence metadata.
 //!
 //! CoNLL-U format: https://universaldependencies.org/format.html
+//!
+//! The older 8- or 10-column CoNLL-X format (CoNLL-2006/2007 shared tasks) can
+//! be read too; see [`InputFormat`].
 
 use crate::bytes::{BytestringPool, bs_atoi, bs_split_once};
-use crate::tree::{Dep, Features, Misc, TokenId, Tree, WordId};
+use crate::tree::{Dep, Features, Misc, MultiwordToken, TokenId, Tree, WordId};
+use flate2::Compression;
 use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::Path;
 use thiserror::Error;
 
@@ -40,9 +45,6 @@ pub enum ParseError {
     #[error("Missing field {field_num}")]
     MissingField { field_num: usize },
 
-    #[error("Extended deprels not yet supported")]
-    UnsupportedExtendedDeprels,
-
     #[error("Expected 10 fields, found more than 10")]
     TooManyFields,
 
@@ -63,6 +65,49 @@ pub enum ParseError {
 
     #[error("Invalid MISC pair (missing '='): {pair}")]
     InvalidMiscPair { pair: String },
+
+    #[error("Expected 8 or 10 CoNLL-X fields, found {found}")]
+    WrongFieldCount { found: usize },
+}
+
+/// Column layout of the input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// CoNLL-U: ID FORM LEMMA UPOS XPOS FEATS HEAD DEPREL DEPS MISC
+    #[default]
+    Conllu,
+    /// CoNLL-X: ID FORM LEMMA CPOSTAG POSTAG FEATS HEAD DEPREL, optionally followed
+    /// by PHEAD and PDEPREL (ignored). CPOSTAG is read as UPOS and POSTAG as XPOS.
+    ConllX,
+}
+
+/// How strictly to treat unsupported CoNLL-U constructs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Fail the sentence on anything unsupported (default)
+    #[default]
+    Strict,
+    /// Skip lines that fail to parse (including empty nodes) instead of failing the
+    /// sentence; each skipped line is reported to the error callback, or to stderr
+    Lenient,
+}
+
+/// Callback for lines skipped in lenient mode
+pub type ErrorCallback = Box<dyn Fn(ParseError) + Send>;
+
+/// A tree parsed in lenient mode, with the lines that were skipped
+#[derive(Debug)]
+pub struct LenientTree {
+    pub tree: Tree,
+    /// Line numbers (1-based) of the skipped lines
+    pub skipped_lines: Vec<usize>,
+}
+
+impl LenientTree {
+    /// Whether every line of the sentence was parsed
+    pub fn is_complete(&self) -> bool {
+        self.skipped_lines.is_empty()
+    }
 }
 
 /// CoNLL-U reader that iterates over sentences
@@ -70,17 +115,73 @@ pub struct TreeIterator<R: BufRead> {
     reader: R,
     line_num: usize,
     string_pool: BytestringPool,
+    parse_mode: ParseMode,
+    on_error: Option<ErrorCallback>,
+    format: InputFormat,
 }
 
 impl<R: BufRead> TreeIterator<R> {
+    /// Create a reader with the default settings over any buffered input
+    pub(crate) fn from_reader(reader: R) -> Self {
+        Self {
+            reader,
+            line_num: 0,
+            string_pool: BytestringPool::new(),
+            parse_mode: ParseMode::default(),
+            on_error: None,
+            format: InputFormat::default(),
+        }
+    }
+
+    /// Set the parse mode (see [`ParseMode`])
+    pub fn with_parse_mode(mut self, mode: ParseMode) -> Self {
+        self.parse_mode = mode;
+        self
+    }
+
+    /// Set the column layout of the input (see [`InputFormat`])
+    pub fn with_format(mut self, format: InputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Parse leniently, passing the error for each skipped line to `callback`
+    pub fn with_lenient(mut self, callback: impl Fn(ParseError) + Send + 'static) -> Self {
+        self.parse_mode = ParseMode::Lenient;
+        self.on_error = Some(Box::new(callback));
+        self
+    }
+
+    /// Parse leniently, printing a warning on stderr for each skipped line
+    pub fn with_lenient_to_stderr(self) -> Self {
+        self.with_lenient(|err| eprintln!("warning: {}", err))
+    }
+
+    /// Iterate over trees along with the lines skipped in each (see [`LenientTree`])
+    pub fn lenient_trees(mut self) -> impl Iterator<Item = Result<LenientTree, ParseError>> {
+        std::iter::from_fn(move || self.next_tree())
+    }
+
+    /// Report a skipped line in lenient mode
+    fn report(&self, err: ParseError) {
+        match &self.on_error {
+            Some(callback) => callback(err),
+            None => eprintln!("warning: {}", err),
+        }
+    }
+
     /// Parse a single CoNLL-U line into a Word
-    /// Skips multiword tokens (not yet supported), errors on empty nodes
+    /// Records multiword tokens separately, errors on empty nodes.
+    /// Returns whether a word was added to the tree.
     fn parse_line(
         &mut self,
         tree: &mut Tree,
         line: &[u8],
         word_id: WordId,
-    ) -> Result<(), ParseError> {
+    ) -> Result<bool, ParseError> {
+        if self.format == InputFormat::ConllX {
+            return self.parse_conllx_line(tree, line, word_id);
+        }
         let mut fields = line.split(|b| *b == b'\t');
         let mut field_num = 0;
 
@@ -100,9 +201,25 @@ impl<R: BufRead> TreeIterator<R> {
 
         let token_id_field = next_field!();
 
-        // Skip multiword tokens (e.g., "1-2")
+        // Multiword tokens (e.g., "1-2") aren't words: keep only their form and misc
         if token_id_field.contains(&b'-') {
-            return Ok(());
+            let (start, end) = parse_range(token_id_field)?;
+            let form = next_field!();
+            for _ in 0..7 {
+                next_field!(); // lemma..deps are always "_"
+            }
+            let misc = self.parse_features(next_field!())?;
+            if fields.next().is_some() {
+                return Err(ParseError::TooManyFields);
+            }
+            let form = tree.string_pool.get_or_intern(form);
+            tree.multiword_tokens.push(MultiwordToken {
+                start,
+                end,
+                form,
+                misc,
+            });
+            return Ok(false);
         }
 
         let token_id = parse_id(token_id_field)?;
@@ -113,9 +230,7 @@ impl<R: BufRead> TreeIterator<R> {
         let feats = self.parse_features(next_field!())?;
         let head = parse_head(next_field!())?;
         let deprel = next_field!();
-        if next_field!() != b"_" {
-            return Err(ParseError::UnsupportedExtendedDeprels);
-        }
+        let enhanced_deps = self.parse_deps(next_field!())?;
         let misc = self.parse_features(next_field!())?;
 
         if fields.next().is_some() {
@@ -125,7 +240,41 @@ impl<R: BufRead> TreeIterator<R> {
         tree.add_word(
             word_id, token_id, form, lemma, upos, xpos, feats, head, deprel, misc,
         );
-        Ok(())
+        if let Some(word) = tree.words.last_mut() {
+            word.enhanced_deps = enhanced_deps;
+        }
+        Ok(true)
+    }
+
+    /// Parse a CoNLL-X line into a Word, with no MISC or enhanced dependencies
+    fn parse_conllx_line(
+        &mut self,
+        tree: &mut Tree,
+        line: &[u8],
+        word_id: WordId,
+    ) -> Result<bool, ParseError> {
+        let fields: Vec<&[u8]> = line.split(|b| *b == b'\t').collect();
+        if fields.len() != 8 && fields.len() != 10 {
+            return Err(ParseError::WrongFieldCount {
+                found: fields.len(),
+            });
+        }
+        let token_id = parse_id(fields[0])?;
+        let feats = self.parse_features(fields[5])?;
+        let head = parse_head(fields[6])?;
+        tree.add_word(
+            word_id,
+            token_id,
+            fields[1],
+            fields[2],
+            fields[3],
+            fields[4],
+            feats,
+            head,
+            fields[7],
+            Features::new(),
+        );
+        Ok(true)
     }
 
     /// Parse FEATS field (key=value|key=value)
@@ -152,7 +301,7 @@ impl<R: BufRead> TreeIterator<R> {
     }
 
     /// Parse DEPS field (head:deprel|head:deprel)
-    fn _parse_deps(&mut self, s: &[u8]) -> Result<Vec<Dep>, ParseError> {
+    fn parse_deps(&mut self, s: &[u8]) -> Result<Vec<Dep>, ParseError> {
         let mut deps = Vec::new();
 
         if s == b"_" {
@@ -184,25 +333,137 @@ impl<R: BufRead> TreeIterator<R> {
     }
 }
 
+/// Write trees as CoNLL-U, one sentence after another (see `Tree::to_conllu`)
+pub fn write_conllu<'a>(
+    trees: impl Iterator<Item = &'a Tree>,
+    writer: &mut dyn Write,
+) -> std::io::Result<()> {
+    for tree in trees {
+        writer.write_all(tree.to_conllu().as_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Streaming CoNLL-U writer
+///
+/// ```no_run
+/// # use treesearch::conllu::TreeWriter;
+/// # use std::path::Path;
+/// let mut writer = TreeWriter::to_gz_file(Path::new("out.conllu.gz"))?;
+/// # let trees: Vec<treesearch::Tree> = vec![];
+/// for tree in &trees {
+///     writer.write_tree(tree)?;
+/// }
+/// writer.finish()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct TreeWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TreeWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Write one tree (see `Tree::to_conllu`)
+    pub fn write_tree(&mut self, tree: &Tree) -> std::io::Result<()> {
+        self.writer.write_all(tree.to_conllu().as_bytes())
+    }
+
+    /// Flush buffered output; the writer stays usable
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// File opened by `TreeWriter::to_file` or `TreeWriter::to_gz_file`
+pub enum OutputFile {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(writer) => writer.write(buf),
+            Self::Gzip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(writer) => writer.flush(),
+        }
+    }
+}
+
+impl OutputFile {
+    /// Flush the file, first writing the gzip trailer for compressed files
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush(),
+            Self::Gzip(writer) => {
+                writer.try_finish()?;
+                writer.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl TreeWriter<OutputFile> {
+    /// Create (or truncate) a plain CoNLL-U file
+    pub fn to_file(path: &Path) -> std::io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::new(OutputFile::Plain(file)))
+    }
+
+    /// Create (or truncate) a gzip-compressed CoNLL-U file
+    pub fn to_gz_file(path: &Path) -> std::io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self::new(OutputFile::Gzip(GzEncoder::new(
+            file,
+            Compression::default(),
+        ))))
+    }
+
+    /// Flush and close the file, ending the gzip stream
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+/// Open a CoNLL-U file for reading (transparently handles gzip compression)
+pub fn open_conllu(path: &Path) -> std::io::Result<BufReader<Box<dyn Read + Send>>> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+
+    // Peek at the magic bytes to detect gzip
+    let buf = reader.fill_buf()?;
+    let reader: Box<dyn Read + Send> = if buf.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(BufReader::new(reader))
+}
+
 impl TreeIterator<BufReader<Box<dyn Read + Send>>> {
     /// Create a reader from a file path (transparently handles gzip compression)
     pub fn from_file(path: &Path) -> std::io::Result<Self> {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
-
-        // Peek at the magic bytes to detect gzip
-        let buf = reader.fill_buf()?;
-        let reader: Box<dyn Read + Send> = if buf.starts_with(&[0x1f, 0x8b]) {
-            Box::new(GzDecoder::new(reader))
-        } else {
-            Box::new(reader)
-        };
+        Ok(Self::from_reader(open_conllu(path)?))
+    }
 
-        Ok(Self {
-            reader: BufReader::new(reader),
-            line_num: 0,
-            string_pool: BytestringPool::new(),
-        })
+    /// Create a CoNLL-X reader from a file path (see [`InputFormat::ConllX`])
+    pub fn from_conllx_file(path: &Path) -> std::io::Result<Self> {
+        Ok(Self::from_file(path)?.with_format(InputFormat::ConllX))
     }
 }
 
@@ -210,20 +471,28 @@ impl TreeIterator<BufReader<std::io::Cursor<String>>> {
     /// Create a reader from a string
     pub fn from_string(text: &str) -> Self {
         let cursor = std::io::Cursor::new(text.to_string());
-        let reader = BufReader::new(cursor);
-        Self {
-            reader,
-            line_num: 0,
-            string_pool: BytestringPool::new(),
-        }
+        Self::from_reader(BufReader::new(cursor))
+    }
+
+    /// Create a CoNLL-X reader from a string (see [`InputFormat::ConllX`])
+    pub fn from_conllx_string(text: &str) -> Self {
+        Self::from_string(text).with_format(InputFormat::ConllX)
     }
 }
 
-impl<R: BufRead> Iterator for TreeIterator<R> {
-    type Item = Result<Tree, ParseError>;
+impl TreeIterator<BufReader<std::io::Cursor<Vec<u8>>>> {
+    /// Create a reader from raw bytes, which need not be valid UTF-8
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let cursor = std::io::Cursor::new(bytes.to_vec());
+        Self::from_reader(BufReader::new(cursor))
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
+impl<R: BufRead> TreeIterator<R> {
+    /// Read the next sentence
+    fn next_tree(&mut self) -> Option<Result<LenientTree, ParseError>> {
         let mut tree = Tree::with_metadata(&self.string_pool, None, HashMap::new());
+        let mut skipped_lines = Vec::new();
         let mut word_id: WordId = 0;
         let mut buffer: Vec<u8> = Vec::with_capacity(100);
         let mut has_content = false;
@@ -258,16 +527,24 @@ impl<R: BufRead> Iterator for TreeIterator<R> {
                     } else {
                         // Regular token line - parse immediately
                         has_content = true;
-                        if let Err(e) = self.parse_line(&mut tree, line, word_id) {
-                            // Wrap error with line context
-                            let enriched_error = ParseError::LineError {
-                                line_num: self.line_num,
-                                line_content: String::from_utf8_lossy(line).to_string(),
-                                message: e.to_string(),
-                            };
-                            return Some(Err(enriched_error));
+                        match self.parse_line(&mut tree, line, word_id) {
+                            Ok(true) => word_id += 1,
+                            Ok(false) => {}
+                            Err(e) => {
+                                // Wrap error with line context
+                                let enriched_error = ParseError::LineError {
+                                    line_num: self.line_num,
+                                    line_content: String::from_utf8_lossy(line).to_string(),
+                                    message: e.to_string(),
+                                };
+                                if self.parse_mode == ParseMode::Lenient {
+                                    self.report(enriched_error);
+                                    skipped_lines.push(self.line_num);
+                                    continue;
+                                }
+                                return Some(Err(enriched_error));
+                            }
                         }
-                        word_id += 1;
                     }
                 }
             }
@@ -278,21 +555,103 @@ impl<R: BufRead> Iterator for TreeIterator<R> {
             return None;
         }
 
+        if self.parse_mode == ParseMode::Lenient {
+            self.remap_heads(&mut tree);
+        } else {
+            // A head past the last word would leave a dangling reference
+            let num_words = tree.words.len();
+            if let Some(head) = tree
+                .words
+                .iter()
+                .filter_map(|w| w.head)
+                .find(|&h| h >= num_words)
+            {
+                return Some(Err(ParseError::InvalidHead {
+                    head: format!("{} (sentence has {} words)", head + 1, num_words),
+                }));
+            }
+        }
+
         // Compile tree
         tree.compile_tree();
-        Some(Ok(tree))
+        Some(Ok(LenientTree {
+            tree,
+            skipped_lines,
+        }))
+    }
+
+    /// Point heads at the words with the right token ids after lines were skipped
+    ///
+    /// Heads are parsed as token id - 1, which is only the word id if no word line
+    /// was dropped. Heads naming a missing token (skipped or out of range) become
+    /// roots and are reported.
+    fn remap_heads(&self, tree: &mut Tree) {
+        let num_words = tree.words.len();
+        // Without skipped lines the word ids are the heads as parsed
+        let word_ids: Option<HashMap<TokenId, WordId>> = tree
+            .words
+            .iter()
+            .any(|w| w.token_id != w.id + 1)
+            .then(|| tree.words.iter().map(|w| (w.token_id, w.id)).collect());
+        let lookup = |head: WordId| match &word_ids {
+            Some(word_ids) => word_ids.get(&(head + 1)).copied(),
+            None => (head < num_words).then_some(head),
+        };
+        for word in &mut tree.words {
+            if let Some(head) = word.head {
+                word.head = lookup(head);
+                if word.head.is_none() {
+                    self.report(ParseError::InvalidHead {
+                        head: format!("{} of token {} (no such token)", head + 1, word.token_id),
+                    });
+                }
+            }
+            word.enhanced_deps.retain_mut(|dep| match dep.head {
+                Some(head) => {
+                    dep.head = lookup(head);
+                    dep.head.is_some()
+                }
+                None => true,
+            });
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for TreeIterator<R> {
+    type Item = Result<Tree, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_tree()
+            .map(|result| result.map(|lenient| lenient.tree))
     }
 }
 
 /// Parse a comment line (starts with #)
-fn parse_comment(line: &[u8], tree: &mut Tree) {
+pub(crate) fn parse_comment(line: &[u8], tree: &mut Tree) {
     // TODO: deal with bytestring stuff here
 
-    // Check for key = value format
     let line = str::from_utf8(line).unwrap().to_string();
-    if let Some((key, value)) = line[1..].split_once("=") {
-        let key = key.trim();
-        let value = value.trim();
+    // Document and paragraph boundaries, with or without an id
+    let (key, value) = match split_comment(&line) {
+        Some((key, value)) => (key, Some(value)),
+        None => (line[1..].trim(), None),
+    };
+    match key {
+        "newdoc" | "newdoc id" => {
+            tree.is_new_doc = true;
+            if let Some(doc_id) = value.filter(|_| key == "newdoc id") {
+                tree.doc_id = Some(doc_id.to_string());
+            }
+        }
+        "newpar" | "newpar id" => tree.is_new_par = true,
+        "doc_id" => tree.doc_id = value.map(str::to_string),
+        _ => {}
+    }
+
+    if let Some((key, value)) = split_comment(&line) {
+        if !tree.comment_order.iter().any(|k| k == key) {
+            tree.comment_order.push(key.to_string());
+        }
 
         if key == "text" {
             tree.sentence_text = Some(value.to_string());
@@ -300,6 +659,13 @@ fn parse_comment(line: &[u8], tree: &mut Tree) {
             tree.metadata.insert(key.to_string(), value.to_string());
         }
     }
+    tree.raw_comments.push(line);
+}
+
+/// Split a `# key = value` comment line into its trimmed key and value
+pub(crate) fn split_comment(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.strip_prefix('#')?.split_once('=')?;
+    Some((key.trim(), value.trim()))
 }
 
 /// Parse ID field (single integer only)
@@ -319,6 +685,21 @@ fn parse_id(s: &[u8]) -> Result<TokenId, ParseError> {
     Ok(id)
 }
 
+/// Parse a multiword token range ID field ("1-2")
+fn parse_range(s: &[u8]) -> Result<(TokenId, TokenId), ParseError> {
+    if let Some((start, end)) = bs_split_once(s, b'-')
+        && !start.is_empty()
+        && !end.is_empty()
+        && let (Some(start), Some(end)) = (bs_atoi(start), bs_atoi(end))
+        && start <= end
+    {
+        return Ok((start, end));
+    }
+    Err(ParseError::InvalidTokenId {
+        token_id: str::from_utf8(s)?.to_string(),
+    })
+}
+
 /// Parse HEAD field (0 or integer)
 fn parse_head(s: &[u8]) -> Result<Option<WordId>, ParseError> {
     if s == b"0" || s == b"_" {
@@ -502,6 +883,326 @@ mod tests {
         assert!(err.to_string().contains("2.1"));
     }
 
+    const EMPTY_NODE_CONLLU: &str = "1\tSue\tSue\tPROPN\t_\t_\t2\tnsubj\t_\t_\n\
+                                     2\tlikes\tlike\tVERB\t_\t_\t0\troot\t_\t_\n\
+                                     2.1\tlikes\tlike\tVERB\t_\t_\t_\t_\t_\t_\n\
+                                     3\ttea\ttea\tNOUN\t_\t_\t2\tobj\t_\t_\n\n";
+
+    #[test]
+    fn test_empty_node_strict() {
+        let mut reader = TreeIterator::from_string(EMPTY_NODE_CONLLU);
+        let err = reader.next().unwrap().unwrap_err();
+        assert!(err.to_string().contains("2.1"));
+    }
+
+    #[test]
+    fn test_empty_node_lenient() {
+        let mut reader =
+            TreeIterator::from_string(EMPTY_NODE_CONLLU).with_parse_mode(ParseMode::Lenient);
+        let tree = reader.next().unwrap().unwrap();
+        assert_eq!(tree.words.len(), 3);
+        // Word ids stay contiguous after the skipped line
+        assert_eq!(tree.words[2].id, 2);
+        assert_eq!(tree.words[2].head, Some(1));
+        assert_eq!(tree.words[1].children, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_lenient_skips_malformed_lines() {
+        use std::sync::{Arc, Mutex};
+
+        let conllu = "# text = Sue likes green tea\n\
+                      1\tSue\tSue\tPROPN\t_\t_\t2\tnsubj\t_\t_\n\
+                      2\tlikes\tlike\tVERB\t_\t_\t0\troot\t_\t_\n\
+                      3\tgreen\tgreen\tADJ\t_\tDegree\t4\tamod\t_\t_\n\
+                      4\ttea\ttea\tNOUN\t_\t_\t2\tobj\t_\t_\n\
+                      5\t.\t.\tPUNCT\t_\t_\t3\tpunct\t_\t_\n\n\
+                      1\tOK\tok\tINTJ\t_\t_\t0\troot\t_\t_\n\n";
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&errors);
+        let mut trees = TreeIterator::from_string(conllu)
+            .with_lenient(move |err| sink.lock().unwrap().push(err.to_string()))
+            .lenient_trees();
+
+        let first = trees.next().unwrap().unwrap();
+        assert_eq!(first.skipped_lines, vec![4]);
+        assert!(!first.is_complete());
+        let tree = &first.tree;
+        assert_eq!(tree.words.len(), 4);
+        // "tea" (token 4) is now word 2, and heads follow it
+        assert_eq!(tree.words[2].token_id, 4);
+        assert_eq!(tree.words[2].head, Some(1));
+        assert_eq!(tree.words[1].children, vec![0, 2]);
+        // "." was attached to the skipped word, so it becomes a root
+        assert_eq!(tree.words[3].head, None);
+
+        let second = trees.next().unwrap().unwrap();
+        assert!(second.is_complete());
+        assert!(trees.next().is_none());
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains("line 4") && errors[0].contains("Degree"));
+        assert!(errors[1].contains("Invalid HEAD: 3"));
+    }
+
+    #[test]
+    fn test_lenient_out_of_range_head_without_skipped_lines() {
+        use std::sync::{Arc, Mutex};
+
+        let conllu = "1\tSue\tSue\tPROPN\t_\t_\t2\tnsubj\t_\t_\n\
+                      2\tsleeps\tsleep\tVERB\t_\t_\t0\troot\t_\t_\n\
+                      3\t.\t.\tPUNCT\t_\t_\t9\tpunct\t_\t_\n\n";
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&errors);
+        let first = TreeIterator::from_string(conllu)
+            .with_lenient(move |err| sink.lock().unwrap().push(err.to_string()))
+            .lenient_trees()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert!(first.skipped_lines.is_empty());
+        // The bad head becomes a root, as it would after a skipped line
+        assert_eq!(first.tree.words[2].head, None);
+        assert_eq!(first.tree.words[0].head, Some(1));
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Invalid HEAD: 9"));
+
+        // Strict mode still rejects the sentence
+        let err = TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, ParseError::InvalidHead { .. }));
+    }
+
+    #[test]
+    fn test_multiword_token_keeps_ids_contiguous() {
+        let conllu = "1-2\tdel\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                      1\tde\tde\tADP\t_\t_\t3\tcase\t_\t_\n\
+                      2\tel\tel\tDET\t_\t_\t3\tdet\t_\t_\n\
+                      3\tmar\tmar\tNOUN\t_\t_\t0\troot\t_\t_\n\n";
+        let tree = TreeIterator::from_string(conllu).next().unwrap().unwrap();
+        let ids: Vec<_> = tree.words.iter().map(|w| w.id).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(tree.root_id, Some(2));
+    }
+
+    #[test]
+    fn test_trees_share_string_pool() {
+        let conllu = "1\tdog\tdog\tNOUN\t_\t_\t0\troot\t_\t_\n\n\
+                      1\tcat\tcat\tNOUN\t_\t_\t0\troot\t_\t_\n\n";
+        let trees: Vec<Tree> = TreeIterator::from_string(conllu)
+            .map(Result::unwrap)
+            .collect();
+        // The same string gets the same symbol in every tree from a reader
+        assert_eq!(trees[0].words[0].upos, trees[1].words[0].upos);
+        assert_eq!(trees[0].words[0].deprel, trees[1].words[0].deprel);
+        assert_ne!(trees[0].words[0].form, trees[1].words[0].form);
+        let cat = trees[1].words[0].form;
+        assert_eq!(&*trees[0].string_pool.resolve(cat), b"cat");
+    }
+
+    #[test]
+    fn test_multiword_tokens() {
+        let conllu = "1-2\tdel\t_\t_\t_\t_\t_\t_\t_\tSpaceAfter=No\n\
+                      1\tde\tde\tADP\t_\t_\t3\tcase\t_\t_\n\
+                      2\tel\tel\tDET\t_\t_\t3\tdet\t_\t_\n\
+                      3\tmar\tmar\tNOUN\t_\t_\t0\troot\t_\t_\n\n";
+        let tree = TreeIterator::from_string(conllu).next().unwrap().unwrap();
+        assert_eq!(tree.multiword_tokens.len(), 1);
+        let mwt = &tree.multiword_tokens[0];
+        assert_eq!((mwt.start, mwt.end), (1, 2));
+        assert_eq!(&*tree.string_pool.resolve(mwt.form), b"del");
+        assert_eq!(mwt.misc.len(), 1);
+        assert_eq!(&*tree.string_pool.resolve(mwt.misc[0].1), b"No");
+
+        assert_eq!(parse_range(b"3-4").unwrap(), (3, 4));
+        for bad in [&b"-2"[..], b"2-", b"4-3", b"a-b"] {
+            assert!(matches!(
+                parse_range(bad),
+                Err(ParseError::InvalidTokenId { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_to_conllu_round_trip() {
+        let conllu = "# sent_id = es-1\n\
+                      # text = Vamos al mar.\n\
+                      # genre = news\n\
+                      1\tVamos\tir\tVERB\t_\tPerson=1|Number=Plur\t0\troot\t_\t_\n\
+                      2-3\tal\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                      2\ta\ta\tADP\t_\t_\t4\tcase\t_\t_\n\
+                      3\tel\tel\tDET\t_\t_\t4\tdet\t_\t_\n\
+                      4\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No\n\
+                      5\t.\t.\tPUNCT\t_\t_\t1\tpunct\t_\t_\n\n";
+        let tree = TreeIterator::from_string(conllu).next().unwrap().unwrap();
+        assert_eq!(tree.to_conllu(), conllu);
+
+        let reparsed = TreeIterator::from_string(&tree.to_conllu())
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(reparsed.sentence_text, tree.sentence_text);
+        assert_eq!(reparsed.metadata, tree.metadata);
+        assert_eq!(reparsed.words.len(), tree.words.len());
+        for (a, b) in reparsed.words.iter().zip(&tree.words) {
+            assert_eq!((a.token_id, a.head, a.depth), (b.token_id, b.head, b.depth));
+            assert_eq!(
+                reparsed.string_pool.resolve(a.form),
+                tree.string_pool.resolve(b.form)
+            );
+        }
+
+        let mut out = Vec::new();
+        write_conllu([&tree, &reparsed].into_iter(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), conllu.repeat(2));
+    }
+
+    #[test]
+    fn test_comment_order_preserved() {
+        let conllu = "# newpar id = p1\n\
+                      # text = Hi\n\
+                      # sent_id = s1\n\
+                      1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n\n";
+        let mut tree = TreeIterator::from_string(conllu).next().unwrap().unwrap();
+        assert_eq!(tree.comment_order, vec!["newpar id", "text", "sent_id"]);
+        assert_eq!(tree.to_conllu(), conllu);
+
+        // Metadata added later goes after the recorded comments
+        tree.metadata
+            .insert("genre".to_string(), "chat".to_string());
+        assert!(
+            tree.to_conllu()
+                .contains("# sent_id = s1\n# genre = chat\n1\t")
+        );
+    }
+
+    #[test]
+    fn test_raw_comments_round_trip() {
+        let conllu = "# newdoc id = d1\n\
+                      # newpar\n\
+                      #sent_id=s1\n\
+                      # free-form note, no key\n\
+                      # text = Hi\n\
+                      1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n\n";
+        let mut tree = TreeIterator::from_string(conllu).next().unwrap().unwrap();
+        assert_eq!(tree.raw_comments.len(), 5);
+        assert_eq!(tree.raw_comments[1], "# newpar");
+        assert_eq!(tree.metadata["sent_id"], "s1");
+        assert_eq!(tree.to_conllu(), conllu);
+
+        // Edited values are rewritten in place, removed keys dropped
+        tree.metadata
+            .insert("sent_id".to_string(), "s2".to_string());
+        tree.metadata.remove("newdoc id");
+        assert_eq!(
+            tree.to_conllu(),
+            "# newpar\n# sent_id = s2\n# free-form note, no key\n# text = Hi\n\
+             1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_conllx() {
+        // 10 columns (PHEAD/PDEPREL ignored) and the 8-column variant
+        let conllx = "1\tThe\tthe\tDT\tDT\t_\t2\tNMOD\t_\t_\n\
+                      2\tdog\tdog\tNN\tNN\tnum=sg\t3\tSBJ\t_\t_\n\
+                      3\tbarks\tbark\tVB\tVBZ\t_\t0\tROOT\t_\t_\n\n\
+                      1\tHi\thi\tUH\tUH\t_\t0\tROOT\n\n";
+        let trees: Vec<Tree> = TreeIterator::from_conllx_string(conllx)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(trees.len(), 2);
+        let tree = &trees[0];
+        let dog = &tree.words[1];
+        assert_eq!(*tree.string_pool.resolve(dog.upos), *b"NN");
+        assert_eq!(*tree.string_pool.resolve(dog.deprel), *b"SBJ");
+        assert_eq!(dog.head, Some(2));
+        assert_eq!(tree.feature_value(1, "num").as_deref(), Some(&b"sg"[..]));
+        assert!(dog.misc.is_empty() && dog.enhanced_deps.is_empty());
+        assert_eq!(trees[1].words.len(), 1);
+
+        // CoNLL-U lines have the wrong number of columns for CoNLL-X
+        let result = TreeIterator::from_conllx_string("1\tHi\thi\tUH\tUH\t_\t0\tROOT\t_\n\n")
+            .next()
+            .unwrap();
+        assert!(matches!(
+            result,
+            Err(ParseError::LineError { ref message, .. }) if message.contains("found 9")
+        ));
+    }
+
+    #[test]
+    fn test_document_boundaries() {
+        let conllu = "# newdoc id = d1\n\
+                      # newpar\n\
+                      1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n\n\
+                      # newpar id = p2\n\
+                      1\tYo\tyo\tINTJ\t_\t_\t0\troot\t_\t_\n\n\
+                      # newdoc\n\
+                      # doc_id = d2\n\
+                      1\tOk\tok\tINTJ\t_\t_\t0\troot\t_\t_\n\n\
+                      1\tNo\tno\tINTJ\t_\t_\t0\troot\t_\t_\n\n";
+        let trees: Vec<Tree> = TreeIterator::from_string(conllu)
+            .map(Result::unwrap)
+            .collect();
+        let flags: Vec<_> = trees
+            .iter()
+            .map(|tree| (tree.is_new_doc, tree.is_new_par, tree.doc_id.as_deref()))
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                (true, true, Some("d1")),
+                (false, true, None),
+                (true, false, Some("d2")),
+                (false, false, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tree_writer() {
+        let conllu = "# sent_id = a\n1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n\n\
+                      # sent_id = b\n1\tYo\tyo\tINTJ\t_\t_\t0\troot\t_\t_\n\n";
+        let dir = tempfile::tempdir().unwrap();
+        let plain = dir.path().join("writer.conllu");
+        let gz = dir.path().join("writer.conllu.gz");
+
+        for path in [&plain, &gz] {
+            let mut writer = if path == &gz {
+                TreeWriter::to_gz_file(path).unwrap()
+            } else {
+                TreeWriter::to_file(path).unwrap()
+            };
+            // Flushing between trees must not end the gzip stream
+            for tree in TreeIterator::from_string(conllu) {
+                writer.write_tree(&tree.unwrap()).unwrap();
+                writer.flush().unwrap();
+            }
+            writer.finish().unwrap();
+
+            let mut text = String::new();
+            open_conllu(path)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text, conllu);
+        }
+
+        let mut out = TreeWriter::new(Vec::new());
+        out.write_tree(&TreeIterator::from_string(conllu).next().unwrap().unwrap())
+            .unwrap();
+        assert_eq!(
+            out.writer,
+            conllu.split_inclusive("\n\n").next().unwrap().as_bytes()
+        );
+    }
+
     #[test]
     fn test_error_invalid_token_id() {
         let err = parse_id(b"abc").unwrap_err();
@@ -543,6 +1244,9 @@ mod tests {
             reader: BufReader::new(std::io::Cursor::new("")),
             line_num: 0,
             string_pool: pool,
+            parse_mode: ParseMode::default(),
+            on_error: None,
+            format: InputFormat::default(),
         };
         let err = reader.parse_features(b"InvalidPair").unwrap_err();
         assert!(matches!(err, ParseError::InvalidFeatsPair { .. }));
@@ -550,14 +1254,21 @@ mod tests {
     }
 
     #[test]
-    fn test_error_unsupported_enhanced_deprels() {
-        let conllu = "1\tword\tlemma\tNOUN\tNN\t_\t2\tnsubj\t2:dep\t_\n\n"; // DEPS field not "_"
+    fn test_error_head_out_of_range() {
+        let conllu = "1\tword\tlemma\tNOUN\tNN\t_\t2\tnsubj\t_\t_\n\n";
+        let err = TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(err, ParseError::InvalidHead { .. }));
+    }
+
+    #[test]
+    fn test_error_invalid_enhanced_deps() {
+        let conllu = "1\tword\tlemma\tNOUN\tNN\t_\t0\troot\troot\t_\n\n"; // missing head
         let mut reader = TreeIterator::from_string(conllu);
         let err = reader.next().unwrap().unwrap_err();
-        assert!(
-            err.to_string()
-                .contains("Extended deprels not yet supported")
-        );
+        assert!(err.to_string().contains("Invalid DEPS pair: root"));
     }
 
     #[test]
@@ -583,29 +1294,42 @@ abc	invalid	lemma	NOUN	NN	_	0	root	_	_
         assert!(err_str.contains("abc")); // Line content in error
     }
 
-    /*
-        #[test]
-        fn test_parse_deps() {
-            let deps = parse_deps("2:nsubj|3:obj").unwrap();
-            assert_eq!(deps.len(), 2);
-            assert_eq!(deps[0].head, Some(1)); // 2 -> 1 (0-indexed)
-            assert_eq!(deps[0].deprel, "nsubj");
-            assert_eq!(deps[1].head, Some(2)); // 3 -> 2 (0-indexed)
-            assert_eq!(deps[1].deprel, "obj");
-
-            // Test root attachment
-            let deps = parse_deps("0:root").unwrap();
-            assert_eq!(deps.len(), 1);
-            assert_eq!(deps[0].head, None); // 0 -> None
-            assert_eq!(deps[0].deprel, "root");
-
-            let empty = parse_deps("_").unwrap();
-            assert!(empty.is_empty());
-
-            // Test error cases
-            assert!(parse_deps("InvalidPair").is_err()); // Missing ':'
-            assert!(parse_deps("foo:bar").is_err()); // Non-numeric head
-            assert!(parse_deps("1:nsubj|invalid").is_err()); // One valid, one invalid
-        }
-    */
+    #[test]
+    fn test_parse_deps() {
+        let mut reader = TreeIterator::from_string("");
+        let deps = reader.parse_deps(b"2:nsubj|3:nsubj:pass").unwrap();
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[0].head, Some(1)); // 2 -> 1 (0-indexed)
+        assert_eq!(&*reader.string_pool.resolve(deps[0].deprel), b"nsubj");
+        assert_eq!(deps[1].head, Some(2)); // 3 -> 2 (0-indexed)
+        assert_eq!(&*reader.string_pool.resolve(deps[1].deprel), b"nsubj:pass");
+
+        // Test root attachment
+        let deps = reader.parse_deps(b"0:root").unwrap();
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].head, None); // 0 -> None
+        assert_eq!(&*reader.string_pool.resolve(deps[0].deprel), b"root");
+
+        let empty = reader.parse_deps(b"_").unwrap();
+        assert!(empty.is_empty());
+
+        // Test error cases
+        assert!(reader.parse_deps(b"InvalidPair").is_err()); // Missing ':'
+        assert!(reader.parse_deps(b"foo:bar").is_err()); // Non-numeric head
+        assert!(reader.parse_deps(b"1:nsubj|invalid").is_err()); // One valid, one invalid
+    }
+
+    #[test]
+    fn test_enhanced_deps_in_tree() {
+        let conllu = "1\tit\tit\tPRON\t_\t_\t2\tnsubj\t2:nsubj|3:nsubj:xsubj\t_\n\
+                      2\tstarts\tstart\tVERB\t_\t_\t0\troot\t0:root\t_\n\
+                      3\training\train\tVERB\t_\t_\t2\txcomp\t2:xcomp\t_\n\n";
+        let tree = TreeIterator::from_string(conllu).next().unwrap().unwrap();
+        let deps = &tree.words[0].enhanced_deps;
+        assert_eq!(deps.len(), 2);
+        assert_eq!(deps[1].head, Some(2));
+        assert_eq!(&*tree.string_pool.resolve(deps[1].deprel), b"nsubj:xsubj");
+        assert_eq!(tree.words[1].enhanced_deps[0].head, None);
+        assert_eq!(tree.to_conllu(), conllu);
+    }
 }
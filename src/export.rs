@@ -0,0 +1,244 @@
+//! Export of match results for other tools
+//!
+//! `write_matches_jsonl` writes one JSON object per match, ready for
+//! `pandas.read_json(path, lines=True)` or `jsonlite::stream_in`;
+//! `write_matches_csv` writes a table with one row per match.
+
+use crate::pattern::FieldSelector;
+use crate::searcher::Match;
+use crate::tree::{Tree, Word, push_json_string};
+use std::borrow::Borrow;
+use std::io::{self, Write};
+
+/// Fields written for each bound word when none are given
+pub const DEFAULT_MATCH_FIELDS: [FieldSelector; 3] = [
+    FieldSelector::Form,
+    FieldSelector::Lemma,
+    FieldSelector::UPOS,
+];
+
+/// Raw bytes of a word field (token ids as decimal digits)
+pub(crate) fn field_bytes(tree: &Tree, word: &Word, field: FieldSelector) -> Vec<u8> {
+    let sym = match field {
+        FieldSelector::Lemma => word.lemma,
+        FieldSelector::UPOS => word.upos,
+        FieldSelector::XPOS => word.xpos,
+        FieldSelector::Form => word.form,
+        FieldSelector::DepRel => word.deprel,
+        FieldSelector::TokenId => return word.token_id.to_string().into_bytes(),
+    };
+    tree.string_pool.resolve(sym).to_vec()
+}
+
+/// Text of a word field
+pub(crate) fn field_text(tree: &Tree, word: &Word, field: FieldSelector) -> String {
+    String::from_utf8_lossy(&field_bytes(tree, word, field)).into_owned()
+}
+
+/// Append a word field as a JSON value (token ids are numbers, the rest strings)
+fn push_field_json(out: &mut String, tree: &Tree, word: &Word, field: FieldSelector) {
+    let text = field_text(tree, word, field);
+    match field {
+        FieldSelector::TokenId => out.push_str(&text),
+        _ => push_json_string(out, &text),
+    }
+}
+
+/// Serialize a match as a single-line JSON object
+///
+/// The object has the tree's `sent_id` and `sentence_text` (`null` if missing),
+/// then one object per variable, in name order, with the requested fields of the
+/// bound word: `{"sent_id": "s1", "sentence_text": "...", "V": {"form": "ran", ...}}`.
+/// An empty `fields` means `DEFAULT_MATCH_FIELDS`.
+pub fn match_to_json(m: &Match, fields: &[FieldSelector]) -> String {
+    let fields = if fields.is_empty() {
+        &DEFAULT_MATCH_FIELDS[..]
+    } else {
+        fields
+    };
+    let tree = &m.tree;
+    let mut out = String::from("{\"sent_id\": ");
+    match tree.metadata.get("sent_id") {
+        Some(sent_id) => push_json_string(&mut out, sent_id),
+        None => out.push_str("null"),
+    }
+    out.push_str(", \"sentence_text\": ");
+    match &tree.sentence_text {
+        Some(text) => push_json_string(&mut out, text),
+        None => out.push_str("null"),
+    }
+
+    let mut bindings: Vec<_> = m.bindings.iter().collect();
+    bindings.sort();
+    for (var_name, &word_id) in bindings {
+        out.push_str(", ");
+        push_json_string(&mut out, var_name);
+        out.push_str(": {");
+        for (i, &field) in fields.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            push_json_string(&mut out, field.name());
+            out.push_str(": ");
+            push_field_json(&mut out, tree, &tree.words[word_id], field);
+        }
+        out.push('}');
+    }
+    out.push('}');
+    out
+}
+
+/// Write matches (owned or borrowed) as JSON Lines, one `match_to_json` object per line
+///
+/// Returns the number of matches written.
+pub fn write_matches_jsonl(
+    matches: impl IntoIterator<Item = impl Borrow<Match>>,
+    fields: &[FieldSelector],
+    writer: &mut dyn Write,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for m in matches {
+        writeln!(writer, "{}", match_to_json(m.borrow(), fields))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Write one CSV cell, quoting it if it holds the delimiter, a quote or a line break
+fn write_csv_cell(writer: &mut dyn Write, cell: &str, delimiter: u8) -> io::Result<()> {
+    let needs_quotes = cell
+        .bytes()
+        .any(|b| b == delimiter || matches!(b, b'"' | b'\n' | b'\r'));
+    if needs_quotes {
+        write!(writer, "\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        writer.write_all(cell.as_bytes())
+    }
+}
+
+/// Write one CSV row, ending with a newline
+fn write_csv_row<'a>(
+    writer: &mut dyn Write,
+    cells: impl IntoIterator<Item = &'a str>,
+    delimiter: u8,
+) -> io::Result<()> {
+    for (i, cell) in cells.into_iter().enumerate() {
+        if i > 0 {
+            writer.write_all(&[delimiter])?;
+        }
+        write_csv_cell(writer, cell, delimiter)?;
+    }
+    writer.write_all(b"\n")
+}
+
+/// Write matches (owned or borrowed) as a CSV table: a header row, then one row per match
+///
+/// Each column is `(column_name, variable, field)`, e.g. `("verb_lemma", "V",
+/// FieldSelector::Lemma)`. Cells for unbound variables (from OPTIONAL blocks) are
+/// left empty. Use `b','` as the delimiter for CSV or `b'\t'` for TSV.
+///
+/// Returns the number of matches written.
+pub fn write_matches_csv(
+    matches: impl IntoIterator<Item = impl Borrow<Match>>,
+    columns: &[(&str, &str, FieldSelector)],
+    writer: &mut dyn Write,
+    delimiter: u8,
+) -> io::Result<usize> {
+    write_csv_row(writer, columns.iter().map(|&(name, _, _)| name), delimiter)?;
+    let mut count = 0;
+    for m in matches {
+        let m = m.borrow();
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|&(_, var_name, field)| match m.bindings.get(var_name) {
+                Some(&word_id) => field_text(&m.tree, &m.tree.words[word_id], field),
+                None => String::new(),
+            })
+            .collect();
+        write_csv_row(writer, cells.iter().map(String::as_str), delimiter)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Treebank;
+    use crate::compile_query;
+
+    const CONLLU: &str = "# sent_id = s1\n\
+                          # text = The dog runs.\n\
+                          1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                          2\tdog\tdog\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                          3\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n\
+                          1\t\"Go\"\tgo\tVERB\tVB\t_\t0\troot\t_\t_\n\n";
+
+    fn matches(query: &str) -> Vec<Match> {
+        Treebank::from_string(CONLLU)
+            .match_iter(compile_query(query).unwrap(), true)
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_match_to_json() {
+        let found = matches("MATCH { V [upos=\"VERB\"]; N []; V -[nsubj]-> N; }");
+        assert_eq!(
+            match_to_json(&found[0], &[]),
+            concat!(
+                r#"{"sent_id": "s1", "sentence_text": "The dog runs.", "#,
+                r#""N": {"form": "dog", "lemma": "dog", "upos": "NOUN"}, "#,
+                r#""V": {"form": "runs", "lemma": "run", "upos": "VERB"}}"#
+            )
+        );
+        assert_eq!(
+            match_to_json(&found[0], &[FieldSelector::DepRel, FieldSelector::TokenId]),
+            concat!(
+                r#"{"sent_id": "s1", "sentence_text": "The dog runs.", "#,
+                r#""N": {"deprel": "nsubj", "token_id": 2}, "#,
+                r#""V": {"deprel": "root", "token_id": 3}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_matches_csv() {
+        let query = "MATCH { V [upos=\"VERB\"]; } OPTIONAL { N []; V -[nsubj]-> N; }";
+        let found = matches(query);
+        let columns = [
+            ("verb", "V", FieldSelector::Form),
+            ("verb_id", "V", FieldSelector::TokenId),
+            ("subj, lemma", "N", FieldSelector::Lemma),
+        ];
+        let mut out = Vec::new();
+        let count = write_matches_csv(&found, &columns, &mut out, b',').unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "verb,verb_id,\"subj, lemma\"\nruns,3,dog\n\"\"\"Go\"\"\",1,\n"
+        );
+
+        let mut out = Vec::new();
+        write_matches_csv(&found, &columns[..2], &mut out, b'\t').unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "verb\tverb_id\nruns\t3\n\"\"\"Go\"\"\"\t1\n"
+        );
+    }
+
+    #[test]
+    fn test_write_matches_jsonl() {
+        let found = matches("MATCH { V [upos=\"VERB\"]; }");
+        let mut out = Vec::new();
+        let count = write_matches_jsonl(&found, &[FieldSelector::Form], &mut out).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "{\"sent_id\": \"s1\", \"sentence_text\": \"The dog runs.\", \"V\": {\"form\": \"runs\"}}\n",
+                "{\"sent_id\": null, \"sentence_text\": null, \"V\": {\"form\": \"\\\"Go\\\"\"}}\n"
+            )
+        );
+    }
+}
@@ -5,12 +5,19 @@
 //! - Searching patterns across trees from a string, file, or glob pattern
 //! - Sequential and parallel iteration via standard traits
 
-use crate::conllu::{ParseError, TreeIterator};
+use crate::binary::{BinaryTreeReader, BinaryWriter, FileTrees, open_trees};
+use crate::conllu::{ParseError, TreeIterator, TreeWriter, open_conllu};
 use crate::pattern::Pattern;
-use crate::searcher::{Match, search_tree, tree_matches};
+use crate::searcher::{Match, MatchOrder, search_shared_tree, search_tree, tree_matches};
 use crate::tree::Tree;
+use rayon::iter::Either;
 use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::sync_channel;
 use std::thread;
 use thiserror::Error;
@@ -32,14 +39,36 @@ pub enum TreebankError {
         path: PathBuf,
         source: std::io::Error,
     },
+
+    /// A `map_trees` transform left a tree with bad word ids or heads
+    #[error("Invalid tree after map_trees: {0}")]
+    InvalidTree(String),
 }
 
-/// Batch size for sending matches through channels
+/// Default batch size for sending matches through channels
 const MATCH_BATCH_SIZE: usize = 500;
 
-/// Channel buffer size (in batches)
+/// Default channel buffer size (in batches)
 const CHANNEL_BUFFER_SIZE: usize = 100;
 
+/// Batch and channel sizes used by the batched parallel iterators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BufferSizes {
+    /// Number of results sent through the channel at a time
+    match_batch: usize,
+    /// Number of batches the channel holds before producers block
+    channel_batches: usize,
+}
+
+impl Default for BufferSizes {
+    fn default() -> Self {
+        Self {
+            match_batch: MATCH_BATCH_SIZE,
+            channel_batches: CHANNEL_BUFFER_SIZE,
+        }
+    }
+}
+
 /// Helper for accumulating items into batches
 struct BatchAccumulator<T> {
     batch: Vec<T>,
@@ -80,24 +109,25 @@ impl<T> BatchAccumulator<T> {
 
 /// Process trees from a string source with batching (for match_iter and filter)
 fn process_string_source_batched<T, F>(
-    text: &str,
+    text: &[u8],
     tx: &crossbeam_channel::Sender<Vec<Result<T, TreebankError>>>,
     process_tree: F,
+    batch_size: usize,
 ) where
     T: Send,
     F: Fn(Tree) -> Vec<Result<T, TreebankError>>,
 {
-    let mut batch = BatchAccumulator::new(MATCH_BATCH_SIZE);
-    for result in TreeIterator::from_string(text) {
+    let mut batch = BatchAccumulator::new(batch_size);
+    for result in TreeIterator::from_bytes(text) {
         let items = match result {
             Ok(tree) => process_tree(tree),
             Err(e) => vec![Err(TreebankError::from(e))],
         };
         for item in items {
-            if let Some(full_batch) = batch.push(item) {
-                if tx.send(full_batch).is_err() {
-                    return;
-                }
+            if let Some(full_batch) = batch.push(item)
+                && tx.send(full_batch).is_err()
+            {
+                return;
             }
         }
     }
@@ -112,6 +142,7 @@ fn process_files_ordered_batched<T, F>(
     tx: &crossbeam_channel::Sender<Vec<Result<T, TreebankError>>>,
     process_tree: F,
     chunk_size: usize,
+    batch_size: usize,
 ) where
     T: Send,
     F: Fn(Tree) -> Vec<Result<T, TreebankError>> + Send + Sync,
@@ -120,7 +151,7 @@ fn process_files_ordered_batched<T, F>(
         // Compute per-path results in parallel, keeping them grouped by path
         let per_path: Vec<Vec<Result<T, TreebankError>>> = chunk
             .par_iter()
-            .map(|path| match TreeIterator::from_file(path) {
+            .map(|path| match open_trees(path) {
                 Ok(it) => it
                     .flat_map(|result| match result {
                         Ok(tree) => process_tree(tree),
@@ -135,11 +166,19 @@ fn process_files_ordered_batched<T, F>(
             .collect();
 
         // Send batches in deterministic order: path order, then result order within each path
-        for batch in per_path {
-            if !batch.is_empty() && tx.send(batch).is_err() {
+        let mut batch = BatchAccumulator::new(batch_size);
+        for item in per_path.into_iter().flatten() {
+            if let Some(full_batch) = batch.push(item)
+                && tx.send(full_batch).is_err()
+            {
                 return;
             }
         }
+        if let Some(final_batch) = batch.flush()
+            && tx.send(final_batch).is_err()
+        {
+            return;
+        }
     }
 }
 
@@ -148,25 +187,26 @@ fn process_files_unordered_batched<T, F>(
     paths: Vec<PathBuf>,
     tx: crossbeam_channel::Sender<Vec<Result<T, TreebankError>>>,
     process_tree: F,
+    batch_size: usize,
 ) where
     T: Send,
     F: Fn(Tree) -> Vec<Result<T, TreebankError>> + Send + Sync,
 {
     paths.par_iter().for_each(|path| {
         let tx = tx.clone();
-        match TreeIterator::from_file(path) {
+        match open_trees(path) {
             Ok(reader) => {
-                let mut batch = BatchAccumulator::new(MATCH_BATCH_SIZE);
+                let mut batch = BatchAccumulator::new(batch_size);
                 for result in reader {
                     let items = match result {
                         Ok(tree) => process_tree(tree),
                         Err(e) => vec![Err(TreebankError::from(e))],
                     };
                     for item in items {
-                        if let Some(full_batch) = batch.push(item) {
-                            if tx.send(full_batch).is_err() {
-                                return;
-                            }
+                        if let Some(full_batch) = batch.push(item)
+                            && tx.send(full_batch).is_err()
+                        {
+                            return;
                         }
                     }
                 }
@@ -184,40 +224,165 @@ fn process_files_unordered_batched<T, F>(
     });
 }
 
+/// Batches of results from the parallel iterators
+type BatchReceiver<T> = crossbeam_channel::Receiver<Vec<Result<T, TreebankError>>>;
+
 /// Build a parallel iterator with batching (for match_iter and filter)
 fn build_parallel_iter_batched<T, F>(
     source: TreeSource,
     ordered: bool,
     chunk_size: usize,
+    buffers: BufferSizes,
     process_tree: F,
-) -> impl Iterator<Item = Result<T, TreebankError>>
+) -> BatchReceiver<T>
 where
     T: Send + 'static,
     F: Fn(Tree) -> Vec<Result<T, TreebankError>> + Send + Sync + Clone + 'static,
 {
-    let (tx, rx) = crossbeam_channel::bounded(CHANNEL_BUFFER_SIZE);
+    let (tx, rx) = crossbeam_channel::bounded(buffers.channel_batches);
 
     thread::spawn(move || match source {
         TreeSource::String(text) => {
-            process_string_source_batched(&text, &tx, process_tree);
+            process_string_source_batched(&text, &tx, process_tree, buffers.match_batch);
         }
         TreeSource::Files(paths) => {
             if ordered {
-                process_files_ordered_batched(paths, &tx, process_tree, chunk_size);
+                process_files_ordered_batched(
+                    paths,
+                    &tx,
+                    process_tree,
+                    chunk_size,
+                    buffers.match_batch,
+                );
             } else {
-                process_files_unordered_batched(paths, tx, process_tree);
+                process_files_unordered_batched(paths, tx, process_tree, buffers.match_batch);
             }
         }
     });
 
-    rx.into_iter().flatten()
+    rx
+}
+
+/// Number of trees searched in parallel at a time when a treebank is sliced
+const SLICE_CHUNK_SIZE: usize = 64;
+
+/// Process an already ordered stream of trees in parallel chunks, keeping the order
+fn process_tree_stream_batched<T, F>(
+    trees: impl Iterator<Item = Result<Tree, TreebankError>> + Send + 'static,
+    channel_batches: usize,
+    process_tree: F,
+) -> BatchReceiver<T>
+where
+    T: Send + 'static,
+    F: Fn(Tree) -> Vec<Result<T, TreebankError>> + Send + Sync + 'static,
+{
+    let (tx, rx) = crossbeam_channel::bounded(channel_batches);
+
+    thread::spawn(move || {
+        let mut trees = trees.peekable();
+        while trees.peek().is_some() {
+            let chunk: Vec<_> = trees.by_ref().take(SLICE_CHUNK_SIZE).collect();
+            let batches: Vec<_> = chunk
+                .into_par_iter()
+                .map(|result| match result {
+                    Ok(tree) => process_tree(tree),
+                    Err(e) => vec![Err(e)],
+                })
+                .collect();
+            for batch in batches {
+                if tx.send(batch).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    rx
+}
+
+/// Processing step applied to each tree after it is parsed
+#[derive(Clone)]
+enum TreeStage {
+    /// Drop trees for which the predicate is false
+    Filter(Arc<dyn Fn(&Tree) -> bool + Send + Sync>),
+    /// Replace each tree with the result of the transform
+    Map(Arc<dyn Fn(Tree) -> Tree + Send + Sync>),
+    /// Count the trees passing through and report every `every` trees
+    Progress {
+        every: usize,
+        count: Arc<AtomicUsize>,
+        callback: Arc<dyn Fn(usize) + Send + Sync>,
+    },
+}
+
+/// Give each progress stage a new counter, so every iteration counts from zero
+fn restart_progress(stages: Vec<TreeStage>) -> Vec<TreeStage> {
+    stages
+        .into_iter()
+        .map(|stage| match stage {
+            TreeStage::Progress {
+                every, callback, ..
+            } => TreeStage::Progress {
+                every,
+                count: Arc::new(AtomicUsize::new(0)),
+                callback,
+            },
+            stage => stage,
+        })
+        .collect()
+}
+
+/// Run a tree through the stages, returning `None` if a filter drops it
+///
+/// A transform that leaves a head pointing outside the tree gives an error
+/// rather than a panic when the tree is recompiled.
+fn apply_stages(stages: &[TreeStage], tree: Tree) -> Option<Result<Tree, TreebankError>> {
+    let mut tree = tree;
+    for stage in stages {
+        match stage {
+            TreeStage::Filter(predicate) => {
+                if !predicate(&tree) {
+                    return None;
+                }
+            }
+            TreeStage::Map(transform) => {
+                tree = transform(tree);
+                if let Err(message) = tree.check_heads() {
+                    return Some(Err(TreebankError::InvalidTree(message)));
+                }
+                tree.recompile();
+            }
+            TreeStage::Progress {
+                every,
+                count,
+                callback,
+            } => {
+                let seen = count.fetch_add(1, Ordering::Relaxed) + 1;
+                if seen % every == 0 {
+                    callback(seen);
+                }
+            }
+        }
+    }
+    Some(Ok(tree))
+}
+
+/// Apply the stages to a parse result, passing errors through
+fn staged_result(
+    stages: &[TreeStage],
+    result: Result<Tree, ParseError>,
+) -> Option<Result<Tree, TreebankError>> {
+    match result {
+        Ok(tree) => apply_stages(stages, tree),
+        Err(e) => Some(Err(e.into())),
+    }
 }
 
 /// Source of trees for a collection
 #[derive(Debug, Clone)]
 enum TreeSource {
-    /// In-memory CoNLL-U text
-    String(String),
+    /// In-memory CoNLL-U text, kept as bytes since files need not be UTF-8
+    String(Vec<u8>),
     /// Multiple file paths (from glob or explicit path(s))
     Files(Vec<PathBuf>),
 }
@@ -250,13 +415,63 @@ enum TreeSource {
 #[derive(Clone)]
 pub struct Treebank {
     source: TreeSource,
+    buffers: BufferSizes,
+    pub(crate) max_matches: Option<usize>,
+    /// Order of the matches within each tree (set by `with_match_order`)
+    match_order: MatchOrder,
+    /// Filters and transforms applied to every tree before it is yielded or searched
+    stages: Vec<TreeStage>,
+    /// Number of trees to drop from the start (set by `skip`)
+    skip: usize,
+    /// Maximum number of trees to yield after skipping (set by `take`)
+    take: Option<usize>,
+}
+
+impl fmt::Debug for Treebank {
+    /// Summarize the source rather than dumping the whole text or path list
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            TreeSource::String(text) => {
+                let text = String::from_utf8_lossy(text);
+                let preview: String = text.chars().take(50).collect();
+                let ellipsis = if text.chars().nth(50).is_some() {
+                    "..."
+                } else {
+                    ""
+                };
+                f.debug_struct("Treebank")
+                    .field("source", &"String")
+                    .field("text", &format_args!("{:?}{}", preview, ellipsis))
+                    .finish()
+            }
+            TreeSource::Files(paths) => {
+                let shown = &paths[..paths.len().min(3)];
+                let more = if paths.len() > shown.len() {
+                    ", ..."
+                } else {
+                    ""
+                };
+                f.debug_struct("Treebank")
+                    .field("source", &"Files")
+                    .field("count", &paths.len())
+                    .field("paths", &format_args!("{:?}{}", shown, more))
+                    .finish()
+            }
+        }
+    }
 }
 
 impl Treebank {
     /// Create from an in-memory CoNLL-U string
     pub fn from_string(text: &str) -> Self {
         Self {
-            source: TreeSource::String(text.to_string()),
+            source: TreeSource::String(text.as_bytes().to_vec()),
+            buffers: BufferSizes::default(),
+            max_matches: None,
+            match_order: MatchOrder::Unsorted,
+            stages: Vec::new(),
+            skip: 0,
+            take: None,
         }
     }
 
@@ -270,6 +485,12 @@ impl Treebank {
     pub fn from_paths(file_paths: Vec<PathBuf>) -> Self {
         Self {
             source: TreeSource::Files(file_paths),
+            buffers: BufferSizes::default(),
+            max_matches: None,
+            match_order: MatchOrder::Unsorted,
+            stages: Vec::new(),
+            skip: 0,
+            take: None,
         }
     }
 
@@ -282,6 +503,236 @@ impl Treebank {
         Ok(Self::from_paths(file_paths))
     }
 
+    /// Create from the files in a directory whose names match `pattern` (e.g. `*.conllu`)
+    ///
+    /// With `recursive`, subdirectories are searched too.
+    pub fn from_directory(
+        dir: impl AsRef<Path>,
+        recursive: bool,
+        pattern: &str,
+    ) -> Result<Self, glob::PatternError> {
+        let dir = glob::Pattern::escape(&dir.as_ref().to_string_lossy());
+        let subdirs = if recursive { "**/" } else { "" };
+        Self::from_glob(&format!("{}/{}{}", dir, subdirs, pattern))
+    }
+
+    /// Paths of the files in the treebank (`None` for a string treebank)
+    pub fn file_paths(&self) -> Option<&[PathBuf]> {
+        match &self.source {
+            TreeSource::String(_) => None,
+            TreeSource::Files(paths) => Some(paths),
+        }
+    }
+
+    /// Override the batch and channel sizes used by `match_iter`, `match_stream` and `filter`.
+    ///
+    /// `match_batch` is the number of results sent to the consumer at a time (default 500);
+    /// `channel_batches` is how many batches may be queued before workers block (default 100).
+    /// Larger values reduce contention on big corpora; smaller ones use less memory.
+    /// Both apply to in-memory and file sources, ordered or not. Zero values are
+    /// clamped to 1.
+    pub fn with_buffer_size(mut self, match_batch: usize, channel_batches: usize) -> Self {
+        self.buffers = BufferSizes {
+            match_batch: match_batch.max(1),
+            channel_batches: channel_batches.max(1),
+        };
+        self
+    }
+
+    /// Limit `match_iter` and `match_stream` to the first `max_matches` matches of each
+    /// tree (like TGrep2's `-l`). The search in a tree stops once the limit is reached.
+    pub fn with_max_matches(mut self, max_matches: usize) -> Self {
+        self.max_matches = Some(max_matches);
+        self
+    }
+
+    /// Sort the matches within each tree for `match_iter` and `match_stream` (see
+    /// [`MatchOrder`]). Trees are still produced in treebank order.
+    pub fn with_match_order(mut self, order: MatchOrder) -> Self {
+        self.match_order = order;
+        self
+    }
+
+    /// Keep only the trees for which `predicate` returns true.
+    ///
+    /// The predicate runs on each tree right after it is parsed, in the worker
+    /// threads, so trees it rejects are never searched. It applies to every way
+    /// of iterating over the treebank, and several filters can be chained.
+    pub fn filter_trees<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Tree) -> bool + Send + Sync + 'static,
+    {
+        self.stages.push(TreeStage::Filter(Arc::new(predicate)));
+        self
+    }
+
+    /// Transform every tree with `transform` as it is read.
+    ///
+    /// Like `filter_trees`, the transform runs in the worker threads before any
+    /// search, and stages run in the order they were added, so
+    /// `treebank.filter_trees(f).map_trees(g)` only transforms the trees `f` keeps.
+    /// The tree's children, depths and indexes are rebuilt afterwards, so the
+    /// transform may change heads, UPOS tags and lemmas freely. Every word id must
+    /// still match its position and every head refer to a word in the tree; a tree
+    /// that breaks this comes back as [`TreebankError::InvalidTree`].
+    pub fn map_trees<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(Tree) -> Tree + Send + Sync + 'static,
+    {
+        self.stages.push(TreeStage::Map(Arc::new(transform)));
+        self
+    }
+
+    /// Call `callback` with the number of trees read so far every `report_every` trees.
+    ///
+    /// The callback runs in the background threads that parse the trees, so it
+    /// should return quickly. Like the other stages it sees the trees left by any
+    /// earlier filters, so call this first to count every tree in the source.
+    /// Each iteration over the treebank counts from zero.
+    pub fn with_progress<F>(mut self, report_every: usize, callback: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.stages.push(TreeStage::Progress {
+            every: report_every.max(1),
+            count: Arc::new(AtomicUsize::new(0)),
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    /// Keep only trees with at least `min` and (if given) at most `max` words.
+    pub fn filter_by_length(self, min: usize, max: Option<usize>) -> Self {
+        self.filter_trees(move |tree| {
+            let len = tree.words.len();
+            len >= min && max.is_none_or(|max| len <= max)
+        })
+    }
+
+    /// Keep only trees whose `sent_id` starts with `prefix`.
+    ///
+    /// Trees without a `sent_id` are dropped.
+    pub fn filter_by_sent_id_prefix(self, prefix: &str) -> Self {
+        let prefix = prefix.to_string();
+        self.filter_trees(move |tree| {
+            tree.metadata
+                .get("sent_id")
+                .is_some_and(|id| id.starts_with(&prefix))
+        })
+    }
+
+    /// Keep only trees whose metadata has `key` set to exactly `value`.
+    pub fn filter_by_metadata(self, key: &str, value: &str) -> Self {
+        let (key, value) = (key.to_string(), value.to_string());
+        self.filter_trees(move |tree| tree.metadata.get(&key) == Some(&value))
+    }
+
+    /// Only yield the first `n` trees (after any `skip`).
+    ///
+    /// Like the other slicing methods, this counts trees in treebank order after
+    /// the filters have run, and errors count as trees. Iteration stops reading
+    /// the source once `n` trees have been yielded.
+    pub fn take(mut self, n: usize) -> Self {
+        self.take = Some(self.take.map_or(n, |take| take.min(n)));
+        self
+    }
+
+    /// Drop the first `n` trees, so `treebank.skip(500).take(100)` yields trees 500-599.
+    ///
+    /// Skipped trees are still read and parsed.
+    pub fn skip(mut self, n: usize) -> Self {
+        self.skip += n;
+        self.take = self.take.map(|take| take.saturating_sub(n));
+        self
+    }
+
+    /// Whether `skip` or `take` has been used
+    fn is_sliced(&self) -> bool {
+        self.skip > 0 || self.take.is_some()
+    }
+
+    /// Build a batched parallel iterator that runs the stages before `process_tree`
+    ///
+    /// A sliced treebank has to count trees in order, so its trees are read in
+    /// order and only the processing is done in parallel.
+    pub(crate) fn parallel_iter<T, F>(
+        self,
+        ordered: bool,
+        process_tree: F,
+    ) -> impl Iterator<Item = Result<T, TreebankError>>
+    where
+        T: Send + 'static,
+        F: Fn(Tree) -> Vec<Result<T, TreebankError>> + Send + Sync + Clone + 'static,
+    {
+        let rx = if self.is_sliced() {
+            let channel_batches = self.buffers.channel_batches;
+            process_tree_stream_batched(self.tree_iter(true), channel_batches, process_tree)
+        } else {
+            let stages = restart_progress(self.stages);
+            build_parallel_iter_batched(
+                self.source,
+                ordered,
+                4, // chunk_size for ordered mode
+                self.buffers,
+                move |tree| match apply_stages(&stages, tree) {
+                    Some(Ok(tree)) => process_tree(tree),
+                    Some(Err(e)) => vec![Err(e)],
+                    None => vec![],
+                },
+            )
+        };
+        rx.into_iter().flatten()
+    }
+
+    /// Load every file into memory, returning an in-memory treebank.
+    ///
+    /// Pays the IO (and decompression) cost once, so later iterations over the
+    /// result don't touch the disk. In-memory treebanks are returned unchanged.
+    /// Files are kept as raw bytes, so ones that are not valid UTF-8 load too.
+    pub fn to_memory(&self) -> Result<Treebank, TreebankError> {
+        let paths = match &self.source {
+            TreeSource::String(_) => return Ok(self.clone()),
+            TreeSource::Files(paths) => paths,
+        };
+
+        let mut text: Vec<u8> = Vec::new();
+        for path in paths {
+            let open_error = |e| TreebankError::FileOpen {
+                path: path.clone(),
+                source: e,
+            };
+            match open_trees(path).map_err(open_error)? {
+                FileTrees::Binary(trees) => {
+                    for tree in trees {
+                        text.extend_from_slice(tree?.to_conllu().as_bytes());
+                    }
+                }
+                FileTrees::Conllu(_) => {
+                    open_conllu(path)
+                        .map_err(open_error)?
+                        .read_to_end(&mut text)?;
+                }
+            }
+            // Keep the last sentence of one file from running into the next
+            if !text.is_empty() && !text.ends_with(b"\n\n") {
+                text.extend_from_slice(if text.ends_with(b"\n") {
+                    b"\n"
+                } else {
+                    b"\n\n"
+                });
+            }
+        }
+        Ok(Self {
+            source: TreeSource::String(text),
+            buffers: self.buffers,
+            max_matches: self.max_matches,
+            match_order: self.match_order.clone(),
+            stages: self.stages.clone(),
+            skip: self.skip,
+            take: self.take,
+        })
+    }
+
     /// Iterate over trees with optional ordering.
     ///
     /// Returns an iterator over `Result<Tree, TreebankError>`. Errors from file I/O
@@ -310,16 +761,31 @@ impl Treebank {
     ///     println!("Tree: {}", tree.words.len());
     /// }
     /// ```
-    pub fn tree_iter(self, ordered: bool) -> impl Iterator<Item = Result<Tree, TreebankError>> {
+    ///
+    /// A treebank sliced with `skip` or `take` is always iterated in order.
+    pub fn tree_iter(
+        self,
+        ordered: bool,
+    ) -> impl Iterator<Item = Result<Tree, TreebankError>> + Send {
+        let ordered = ordered || self.is_sliced();
+        let (skip, take) = (self.skip, self.take.unwrap_or(usize::MAX));
+        self.source_trees(ordered).skip(skip).take(take)
+    }
+
+    /// Parse the trees from the source and run them through the stages
+    fn source_trees(self, ordered: bool) -> std::sync::mpsc::IntoIter<Result<Tree, TreebankError>> {
         if ordered {
             // Ordered mode: maintain deterministic ordering via chunking
             // Smaller chunks (2 files) improve load balancing for heterogeneous file sizes
             let (tx, rx) = sync_channel(64); // larger buffer for better pipelining
+            let stages = restart_progress(self.stages);
 
             thread::spawn(move || match self.source {
                 TreeSource::String(text) => {
-                    for result in TreeIterator::from_string(&text) {
-                        let result = result.map_err(TreebankError::from);
+                    for result in TreeIterator::from_bytes(&text) {
+                        let Some(result) = staged_result(&stages, result) else {
+                            continue;
+                        };
                         if tx.send(result).is_err() {
                             return;
                         }
@@ -331,9 +797,9 @@ impl Treebank {
                             .par_iter()
                             .flat_map_iter(|path| {
                                 let file_results: Vec<Result<Tree, TreebankError>> =
-                                    match TreeIterator::from_file(path) {
+                                    match open_trees(path) {
                                         Ok(iter) => {
-                                            iter.map(|r| r.map_err(TreebankError::from)).collect()
+                                            iter.filter_map(|r| staged_result(&stages, r)).collect()
                                         }
                                         Err(e) => vec![Err(TreebankError::FileOpen {
                                             path: path.clone(),
@@ -355,11 +821,14 @@ impl Treebank {
         } else {
             // Unordered mode: maximum concurrency by removing synchronization barriers
             let (tx, rx) = sync_channel(5000); // larger buffer for higher throughput
+            let stages = restart_progress(self.stages);
 
             thread::spawn(move || match self.source {
                 TreeSource::String(text) => {
-                    for result in TreeIterator::from_string(&text) {
-                        let result = result.map_err(TreebankError::from);
+                    for result in TreeIterator::from_bytes(&text) {
+                        let Some(result) = staged_result(&stages, result) else {
+                            continue;
+                        };
                         if tx.send(result).is_err() {
                             return;
                         }
@@ -368,10 +837,12 @@ impl Treebank {
                 TreeSource::Files(paths) => {
                     paths.par_iter().for_each(|path| {
                         let tx = tx.clone(); // Clone sender for each parallel thread
-                        match TreeIterator::from_file(path) {
+                        match open_trees(path) {
                             Ok(reader) => {
                                 for result in reader {
-                                    let result = result.map_err(TreebankError::from);
+                                    let Some(result) = staged_result(&stages, result) else {
+                                        continue;
+                                    };
                                     if tx.send(result).is_err() {
                                         return;
                                     }
@@ -391,6 +862,39 @@ impl Treebank {
         }
     }
 
+    /// Iterate over trees along with their 0-based sentence index.
+    ///
+    /// The index counts items in the order they are produced, so it is deterministic
+    /// only when `ordered` is true. Errors take up an index like any other item.
+    pub fn iter_with_index(
+        self,
+        ordered: bool,
+    ) -> impl Iterator<Item = (usize, Result<Tree, TreebankError>)> {
+        self.tree_iter(ordered).enumerate()
+    }
+
+    /// Iterate over documents: runs of consecutive trees, each starting at a tree with
+    /// `is_new_doc` set (or at the first tree).
+    ///
+    /// Trees are read in order. Files are not document boundaries by themselves, but
+    /// UD files start with `# newdoc`. An error is yielded on its own and the next
+    /// document starts after it.
+    pub fn doc_iter(self) -> impl Iterator<Item = Result<Vec<Tree>, TreebankError>> {
+        let mut trees = self.tree_iter(true).peekable();
+        std::iter::from_fn(move || {
+            let mut doc = match trees.next()? {
+                Ok(tree) => vec![tree],
+                Err(e) => return Some(Err(e)),
+            };
+            while let Some(Ok(tree)) =
+                trees.next_if(|next| matches!(next, Ok(tree) if !tree.is_new_doc))
+            {
+                doc.push(tree);
+            }
+            Some(Ok(doc))
+        })
+    }
+
     /// Search for pattern matches with optional ordering.
     ///
     /// Returns an iterator over `Result<Match, TreebankError>`. Errors from file I/O
@@ -426,12 +930,241 @@ impl Treebank {
         pattern: Pattern,
         ordered: bool,
     ) -> impl Iterator<Item = Result<Match, TreebankError>> {
-        build_parallel_iter_batched(
-            self.source,
-            ordered,
-            4, // chunk_size for ordered mode
-            move |tree| search_tree(tree, &pattern).into_iter().map(Ok).collect(),
-        )
+        let max_matches = self.max_matches;
+        let order = self.match_order.clone();
+        self.parallel_iter(ordered, move |tree| {
+            search_tree(tree, &pattern, max_matches, &order)
+                .into_iter()
+                .map(Ok)
+                .collect()
+        })
+    }
+
+    /// Iterate over trees as a Rayon parallel iterator, in no particular order.
+    ///
+    /// The trees can then be processed with any Rayon adaptor (`map`, `fold`,
+    /// `reduce`, ...) on the global thread pool. Files are parsed in parallel and
+    /// streamed tree by tree; in-memory treebanks are parsed on a background
+    /// thread. A sliced treebank (`skip`/`take`) is read in order into memory first,
+    /// since the slice depends on the order.
+    pub fn par_tree_iter(self) -> impl ParallelIterator<Item = Result<Tree, TreebankError>> {
+        match self.source {
+            // The String reader thread does not use the Rayon pool, so bridging
+            // its channel cannot starve it of threads
+            TreeSource::String(_) => Either::Left(self.tree_iter(true).par_bridge()),
+            TreeSource::Files(_) if self.is_sliced() => Either::Right(Either::Left(
+                self.tree_iter(true).collect::<Vec<_>>().into_par_iter(),
+            )),
+            TreeSource::Files(paths) => {
+                let stages = Arc::new(restart_progress(self.stages));
+                Either::Right(Either::Right(paths.into_par_iter().flat_map_iter(
+                    move |path| match open_trees(&path) {
+                        Ok(trees) => {
+                            let stages = Arc::clone(&stages);
+                            Either::Left(trees.filter_map(move |r| staged_result(&stages, r)))
+                        }
+                        Err(e) => Either::Right(std::iter::once(Err(TreebankError::FileOpen {
+                            path,
+                            source: e,
+                        }))),
+                    },
+                )))
+            }
+        }
+    }
+
+    /// Search for pattern matches as a Rayon parallel iterator (see
+    /// [`par_tree_iter`](Self::par_tree_iter)), in no particular order.
+    pub fn par_match_iter(
+        self,
+        pattern: Pattern,
+    ) -> impl ParallelIterator<Item = Result<Match, TreebankError>> {
+        let max_matches = self.max_matches;
+        let order = self.match_order.clone();
+        self.par_tree_iter().flat_map_iter(move |tree| match tree {
+            Ok(tree) => Either::Left(
+                search_tree(tree, &pattern, max_matches, &order)
+                    .into_iter()
+                    .map(Ok),
+            ),
+            Err(e) => Either::Right(std::iter::once(Err(e))),
+        })
+    }
+
+    /// Search for several patterns in a single pass over the treebank.
+    ///
+    /// Yields `(index, match)` pairs, where `index` is the position of the matching
+    /// pattern in `patterns`. Each tree is read and parsed once and then searched
+    /// with every pattern in turn, so the I/O cost does not grow with the number of
+    /// patterns. The match limit and match order apply to each pattern separately.
+    pub fn multi_match_iter(
+        self,
+        patterns: Vec<Pattern>,
+        ordered: bool,
+    ) -> impl Iterator<Item = Result<(usize, Match), TreebankError>> {
+        let max_matches = self.max_matches;
+        let order = self.match_order.clone();
+        let patterns = Arc::new(patterns);
+        self.parallel_iter(ordered, move |tree| {
+            let tree = Arc::new(tree);
+            patterns
+                .iter()
+                .enumerate()
+                .flat_map(|(index, pattern)| {
+                    search_shared_tree(Arc::clone(&tree), pattern, max_matches, &order)
+                        .into_iter()
+                        .map(move |m| Ok((index, m)))
+                })
+                .collect()
+        })
+    }
+
+    /// Collect the matches of several named patterns in a single pass (see
+    /// [`multi_match_iter`](Self::multi_match_iter)).
+    ///
+    /// Every name gets an entry, empty if its pattern never matched; matches are in
+    /// treebank order. Patterns sharing a name have their matches combined.
+    pub fn multi_search(
+        self,
+        patterns: Vec<(String, Pattern)>,
+    ) -> Result<HashMap<String, Vec<Match>>, TreebankError> {
+        let (names, patterns): (Vec<String>, Vec<Pattern>) = patterns.into_iter().unzip();
+        let mut results: HashMap<String, Vec<Match>> = names
+            .iter()
+            .map(|name| (name.clone(), Vec::new()))
+            .collect();
+        for result in self.multi_match_iter(patterns, true) {
+            let (index, m) = result?;
+            results
+                .get_mut(&names[index])
+                .expect("every name has an entry")
+                .push(m);
+        }
+        Ok(results)
+    }
+
+    /// Search for pattern matches, grouped by sentence.
+    ///
+    /// Like [`match_iter`](Self::match_iter), but yields one `(tree, matches)` pair per
+    /// tree instead of flattening the matches. Trees with no matches are included with
+    /// an empty vector, so the stream covers every sentence in the treebank.
+    ///
+    /// # Arguments
+    /// * `pattern` - The pattern to search for
+    /// * `ordered` - If true, maintains file and tree order. If false, may be faster.
+    pub fn match_stream(
+        self,
+        pattern: Pattern,
+        ordered: bool,
+    ) -> impl Iterator<Item = Result<(Tree, Vec<Match>), TreebankError>> {
+        let max_matches = self.max_matches;
+        let order = self.match_order.clone();
+        self.parallel_iter(ordered, move |tree| {
+            let matches = search_tree(tree.clone(), &pattern, max_matches, &order);
+            vec![Ok((tree, matches))]
+        })
+    }
+
+    /// Check whether any tree in the treebank matches a pattern.
+    ///
+    /// Stops at the first matching tree. An error (e.g. an unreadable file) is returned
+    /// if it is reached before any match.
+    pub fn any_match(self, pattern: Pattern) -> Result<bool, TreebankError> {
+        self.filter(pattern, false)
+            .next()
+            .transpose()
+            .map(|tree| tree.is_some())
+    }
+
+    /// Count the trees in the treebank, dropping each one as soon as it is parsed.
+    pub fn count_trees(self) -> Result<usize, TreebankError> {
+        self.tree_iter(false)
+            .try_fold(0, |count, tree| tree.map(|_| count + 1))
+    }
+
+    /// Count the trees with at least one match for a pattern.
+    ///
+    /// Each tree is counted once however many matches it has.
+    pub fn count_matching_trees(self, pattern: Pattern) -> Result<usize, TreebankError> {
+        self.filter(pattern, false)
+            .try_fold(0, |count, tree| tree.map(|_| count + 1))
+    }
+
+    /// Write every tree to a CoNLL-U file, gzip-compressed if the path ends in `.gz`.
+    ///
+    /// Trees are written in treebank order. Returns the number of trees written.
+    pub fn write_conllu(self, path: &Path) -> Result<usize, TreebankError> {
+        let mut writer = if path.extension().is_some_and(|ext| ext == "gz") {
+            TreeWriter::to_gz_file(path)?
+        } else {
+            TreeWriter::to_file(path)?
+        };
+        let mut count = 0;
+        for tree in self.tree_iter(true) {
+            writer.write_tree(&tree?)?;
+            count += 1;
+        }
+        writer.finish()?;
+        Ok(count)
+    }
+
+    /// Write every tree to a binary treebank file (see [`crate::binary`]), in treebank order.
+    ///
+    /// Loading the file with `from_binary` skips CoNLL-U parsing. Returns the
+    /// number of trees written.
+    pub fn save_binary(self, path: &Path) -> Result<usize, TreebankError> {
+        let mut writer = BinaryWriter::to_file(path)?;
+        let mut count = 0;
+        for tree in self.tree_iter(true) {
+            writer.write_tree(&tree?)?;
+            count += 1;
+        }
+        writer.finish()?;
+        Ok(count)
+    }
+
+    /// Open a binary treebank file written by `save_binary`
+    ///
+    /// Fails if the file is not in the binary format or has an unsupported
+    /// version. Otherwise it behaves like `from_path`, which also recognizes
+    /// binary files.
+    pub fn from_binary(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        BinaryTreeReader::from_file(path.as_ref())?;
+        Ok(Self::from_path(path))
+    }
+
+    /// Open `bin_path` if it is at least as new as `conllu_path`; otherwise parse
+    /// `conllu_path`, save it to `bin_path`, and open that
+    ///
+    /// A cache with an unsupported format version is rebuilt too. The new cache is
+    /// written to a temporary file next to `bin_path` and renamed into place, so an
+    /// interrupted build never leaves a truncated cache behind.
+    pub fn load_or_build(
+        conllu_path: impl AsRef<Path>,
+        bin_path: impl AsRef<Path>,
+    ) -> Result<Self, TreebankError> {
+        let (conllu_path, bin_path) = (conllu_path.as_ref(), bin_path.as_ref());
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|meta| meta.modified());
+        let source_modified = modified(conllu_path).map_err(|e| TreebankError::FileOpen {
+            path: conllu_path.to_path_buf(),
+            source: e,
+        })?;
+        if modified(bin_path).is_ok_and(|cache_modified| cache_modified >= source_modified)
+            && let Ok(treebank) = Self::from_binary(bin_path)
+        {
+            return Ok(treebank);
+        }
+        let mut tmp_path = bin_path.as_os_str().to_owned();
+        tmp_path.push(format!(".{}.tmp", std::process::id()));
+        let tmp_path = PathBuf::from(tmp_path);
+        let saved = Self::from_path(conllu_path)
+            .save_binary(&tmp_path)
+            .and_then(|_| Ok(std::fs::rename(&tmp_path, bin_path)?));
+        if let Err(e) = saved {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+        Ok(Self::from_binary(bin_path)?)
     }
 
     /// Filter trees that match a pattern.
@@ -448,19 +1181,70 @@ impl Treebank {
         pattern: Pattern,
         ordered: bool,
     ) -> impl Iterator<Item = Result<Tree, TreebankError>> {
-        build_parallel_iter_batched(
-            self.source,
-            ordered,
-            4, // chunk_size for ordered mode
-            move |tree| {
-                if tree_matches(&tree, &pattern) {
-                    vec![Ok(tree)]
-                } else {
-                    vec![]
-                }
-            },
-        )
+        self.parallel_iter(ordered, move |tree| {
+            if tree_matches(&tree, &pattern) {
+                vec![Ok(tree)]
+            } else {
+                vec![]
+            }
+        })
+    }
+
+    /// Draw a random sample of `n` trees (reservoir sampling, Algorithm R).
+    ///
+    /// Only the sample is kept in memory. Trees are read in order so that a seed
+    /// always gives the same sample, and the sample is returned in treebank order.
+    /// Returns every tree if there are fewer than `n`.
+    pub fn sample(self, n: usize, seed: u64) -> Result<Vec<Tree>, TreebankError> {
+        reservoir_sample(self.tree_iter(true), n, seed)
     }
+
+    /// Draw a random sample of `n` trees from those matching a pattern (see `sample`).
+    pub fn sample_matching(
+        self,
+        pattern: Pattern,
+        n: usize,
+        seed: u64,
+    ) -> Result<Vec<Tree>, TreebankError> {
+        reservoir_sample(self.filter(pattern, true), n, seed)
+    }
+}
+
+/// SplitMix64 generator; small and fast, good enough for sampling
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Keep a uniform sample of `n` items, returned in their original order
+fn reservoir_sample<T>(
+    items: impl Iterator<Item = Result<T, TreebankError>>,
+    n: usize,
+    seed: u64,
+) -> Result<Vec<T>, TreebankError> {
+    let mut rng = SplitMix64(seed);
+    // The reservoir grows as trees arrive, so a huge `n` doesn't reserve memory up front
+    let mut reservoir: Vec<(usize, T)> = Vec::with_capacity(n.min(1024));
+    for (i, item) in items.enumerate() {
+        let item = item?;
+        if reservoir.len() < n {
+            reservoir.push((i, item));
+        } else {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            if j < n {
+                reservoir[j] = (i, item);
+            }
+        }
+    }
+    reservoir.sort_by_key(|&(i, _)| i);
+    Ok(reservoir.into_iter().map(|(_, item)| item).collect())
 }
 
 #[cfg(test)]
@@ -489,6 +1273,38 @@ mod tests {
 
 "#;
 
+    #[test]
+    fn test_multi_search() {
+        let patterns = vec![
+            (
+                "verb".to_string(),
+                compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap(),
+            ),
+            (
+                "subj".to_string(),
+                compile_query("MATCH { N []; V -[nsubj]-> N; }").unwrap(),
+            ),
+            (
+                "adj".to_string(),
+                compile_query("MATCH { A [upos=\"ADJ\"]; }").unwrap(),
+            ),
+        ];
+        let indices: Vec<usize> = Treebank::from_string(TWO_TREE_CONLLU)
+            .multi_match_iter(patterns.iter().map(|(_, p)| p.clone()).collect(), true)
+            .map(|result| result.unwrap().0)
+            .collect();
+        assert_eq!(indices, vec![0, 1, 0, 1]);
+
+        let results = Treebank::from_string(TWO_TREE_CONLLU)
+            .multi_search(patterns)
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results["adj"].is_empty());
+        let verbs: Vec<usize> = results["verb"].iter().map(|m| m.bindings["V"]).collect();
+        assert_eq!(verbs, vec![2, 1]);
+        assert_eq!(results["subj"].len(), 2);
+    }
+
     #[test]
     fn test_treebank_from_string() {
         let trees: Vec<_> = Treebank::from_string(TWO_TREE_CONLLU)
@@ -501,6 +1317,36 @@ mod tests {
         assert_eq!(trees[1].words.len(), 2);
     }
 
+    #[test]
+    fn test_iter_with_index() {
+        let indexed: Vec<_> = Treebank::from_string(THREE_VERB_CONLLU)
+            .iter_with_index(true)
+            .map(|(idx, result)| (idx, result.unwrap().words.len()))
+            .collect();
+        assert_eq!(indexed, vec![(0, 2), (1, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn test_doc_iter() {
+        let conllu = "1\tA\ta\tX\t_\t_\t0\troot\t_\t_\n\n\
+                      # newdoc id = d2\n\
+                      1\tB\tb\tX\t_\t_\t0\troot\t_\t_\n\n\
+                      1\tC\tc\tX\t_\t_\t0\troot\t_\t_\n\n\
+                      # newdoc\n\
+                      1\tD\td\tX\t_\t_\t0\troot\t_\t_\n\n";
+        let sizes: Vec<_> = Treebank::from_string(conllu)
+            .doc_iter()
+            .map(|doc| {
+                let doc = doc.unwrap();
+                (doc[0].doc_id.clone(), doc.len())
+            })
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![(None, 1), (Some("d2".to_string()), 2), (None, 1)]
+        );
+    }
+
     #[test]
     fn test_match_set_from_string() {
         let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
@@ -529,6 +1375,32 @@ mod tests {
         assert_eq!(matches.len(), 2);
     }
 
+    #[test]
+    fn test_with_max_matches() {
+        let conllu = "1\tsaw\tsee\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      2\tJohn\tJohn\tPROPN\tNNP\t_\t1\tobj\t_\t_\n\
+                      3\trunning\trun\tVERB\tVBG\t_\t1\txcomp\t_\t_\n\n\
+                      1\tran\trun\tVERB\tVBD\t_\t0\troot\t_\t_\n\n";
+
+        let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+        let treebank = Treebank::from_string(conllu).with_max_matches(1);
+        let matches: Vec<_> = treebank
+            .clone()
+            .match_iter(pattern.clone(), true)
+            .filter_map(Result::ok)
+            .map(|m| m.bindings["V"])
+            .collect();
+        // One match from each tree
+        assert_eq!(matches, vec![0, 0]);
+
+        let per_tree: Vec<usize> = treebank
+            .match_stream(pattern, true)
+            .filter_map(Result::ok)
+            .map(|(_, matches)| matches.len())
+            .collect();
+        assert_eq!(per_tree, vec![1, 1]);
+    }
+
     #[test]
     fn test_match_set_no_matches() {
         let conllu = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
@@ -591,6 +1463,329 @@ mod tests {
         assert_eq!(trees.len(), 0);
     }
 
+    #[test]
+    fn test_filter_trees() {
+        let conllu = "# sent_id = train-1\n\
+                      1\thelped\thelp\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      2\tus\twe\tPRON\tPRP\t_\t1\tobj\t_\t_\n\n\
+                      # sent_id = dev-1\n\
+                      1\tran\trun\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      2\tquickly\tquickly\tADV\tRB\t_\t1\tadvmod\t_\t_\n\n\
+                      # sent_id = train-2\n\
+                      1\tsleeps\tsleep\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n";
+        let treebank = Treebank::from_string(conllu);
+        let ids = |treebank: Treebank, ordered: bool| -> Vec<String> {
+            let mut ids: Vec<String> = treebank
+                .tree_iter(ordered)
+                .map(|tree| tree.unwrap().metadata["sent_id"].clone())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let short = treebank.clone().filter_trees(|tree| tree.words.len() < 2);
+        assert_eq!(ids(short.clone(), true), vec!["train-2"]);
+        assert_eq!(ids(short, false), vec!["train-2"]);
+        assert_eq!(
+            ids(treebank.clone().filter_by_sent_id_prefix("train"), true),
+            vec!["train-1", "train-2"]
+        );
+        assert_eq!(
+            ids(treebank.clone().filter_by_length(2, Some(2)), true),
+            vec!["dev-1", "train-1"]
+        );
+        assert_eq!(
+            ids(
+                treebank.clone().filter_by_metadata("sent_id", "dev-1"),
+                true
+            ),
+            vec!["dev-1"]
+        );
+        assert!(ids(treebank.clone().filter_by_metadata("genre", "news"), true).is_empty());
+        // Filters chain, and carry over to the in-memory copy
+        let chained = treebank
+            .clone()
+            .filter_by_sent_id_prefix("train")
+            .filter_by_length(2, None);
+        assert_eq!(ids(chained.to_memory().unwrap(), true), vec!["train-1"]);
+
+        // Rejected trees are not searched
+        let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+        let train = treebank.filter_by_sent_id_prefix("train");
+        assert_eq!(train.clone().match_iter(pattern.clone(), true).count(), 2);
+        assert_eq!(
+            train.clone().match_stream(pattern.clone(), false).count(),
+            2
+        );
+        assert_eq!(train.count_matching_trees(pattern).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_map_trees() {
+        // Retag nouns as PROPN; the UPOS index has to be rebuilt for the search to see it
+        let retag = |mut tree: Tree| {
+            let (noun, propn) = (
+                tree.string_pool.get_or_intern(b"NOUN"),
+                tree.string_pool.get_or_intern(b"PROPN"),
+            );
+            for word in &mut tree.words {
+                if word.upos == noun {
+                    word.upos = propn;
+                }
+            }
+            tree
+        };
+        let pattern = compile_query("MATCH { N [upos=\"PROPN\"]; }").unwrap();
+        let treebank = Treebank::from_string(TWO_TREE_CONLLU);
+        assert_eq!(
+            treebank.clone().match_iter(pattern.clone(), true).count(),
+            0
+        );
+        let mapped = treebank.clone().map_trees(retag);
+        assert_eq!(mapped.clone().match_iter(pattern.clone(), true).count(), 2);
+        assert_eq!(mapped.match_iter(pattern.clone(), false).count(), 2);
+
+        // Stages run in order: the filter sees the original or the transformed tree
+        let has_propn = |tree: &Tree| {
+            tree.words
+                .iter()
+                .any(|word| &*tree.string_pool.resolve(word.upos) == b"PROPN")
+        };
+        let filter_first = treebank.clone().filter_trees(has_propn).map_trees(retag);
+        assert_eq!(filter_first.count_trees().unwrap(), 0);
+        let map_first = treebank.map_trees(retag).filter_trees(has_propn);
+        let trees: Vec<Tree> = map_first.tree_iter(true).map(Result::unwrap).collect();
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].upos_index.len(), 3);
+        assert_eq!(trees[0].words[2].children, vec![1]);
+    }
+
+    #[test]
+    fn test_map_trees_invalid_heads() {
+        // Dropping the first word leaves ids and heads pointing past the end
+        let drop_first = |mut tree: Tree| {
+            tree.words.remove(0);
+            tree
+        };
+        let treebank = Treebank::from_string(TWO_TREE_CONLLU).map_trees(drop_first);
+        for ordered in [true, false] {
+            let results: Vec<_> = treebank.clone().tree_iter(ordered).collect();
+            assert_eq!(results.len(), 2);
+            assert!(
+                results
+                    .iter()
+                    .all(|r| matches!(r, Err(TreebankError::InvalidTree(_))))
+            );
+        }
+        let pattern = compile_query("MATCH { W []; }").unwrap();
+        assert!(
+            treebank
+                .match_iter(pattern, true)
+                .all(|r| matches!(r, Err(TreebankError::InvalidTree(_))))
+        );
+
+        // Dropping a trailing leaf keeps the tree valid
+        let strip_punct = |mut tree: Tree| {
+            let punct = tree.string_pool.get_or_intern(b"PUNCT");
+            tree.words.retain(|word| word.upos != punct);
+            tree
+        };
+        let trees: Vec<_> = Treebank::from_string(
+            "1\tHi\thi\tINTJ\tUH\t_\t0\troot\t_\t_\n2\t!\t!\tPUNCT\t.\t_\t1\tpunct\t_\t_\n\n",
+        )
+        .map_trees(strip_punct)
+        .tree_iter(true)
+        .collect::<Result<_, _>>()
+        .unwrap();
+        assert_eq!(trees[0].words.len(), 1);
+    }
+
+    #[test]
+    fn test_skip_take() {
+        let treebank = Treebank::from_string(THREE_VERB_CONLLU);
+        let lemmas = |treebank: Treebank, ordered: bool| -> Vec<String> {
+            treebank
+                .tree_iter(ordered)
+                .map(|tree| {
+                    let tree = tree.unwrap();
+                    String::from_utf8(tree.string_pool.resolve(tree.words[0].lemma).to_vec())
+                        .unwrap()
+                })
+                .collect()
+        };
+        assert_eq!(lemmas(treebank.clone().take(2), false), vec!["help", "run"]);
+        assert_eq!(lemmas(treebank.clone().skip(1), true), vec!["run", "sleep"]);
+        assert_eq!(lemmas(treebank.clone().skip(1).take(1), true), vec!["run"]);
+        assert_eq!(lemmas(treebank.clone().take(2).skip(1), true), vec!["run"]);
+        assert_eq!(lemmas(treebank.clone().take(2).take(5), true).len(), 2);
+        assert!(lemmas(treebank.clone().skip(5), true).is_empty());
+
+        // Slices count the trees left after filtering, and apply to searches too
+        let sliced = treebank
+            .filter_trees(|tree| tree.words.len() == 2)
+            .skip(1)
+            .to_memory()
+            .unwrap();
+        assert_eq!(lemmas(sliced.clone(), true), vec!["run"]);
+        let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+        assert_eq!(sliced.clone().match_iter(pattern.clone(), false).count(), 1);
+        assert_eq!(
+            sliced.clone().match_stream(pattern.clone(), true).count(),
+            1
+        );
+        assert_eq!(sliced.count_matching_trees(pattern).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_with_progress() {
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let treebank = Treebank::from_string(THREE_VERB_CONLLU)
+            .with_progress(2, move |n| sink.lock().unwrap().push(n));
+
+        assert_eq!(treebank.clone().count_trees().unwrap(), 3);
+        assert_eq!(*reports.lock().unwrap(), vec![2]);
+
+        // A new iteration starts counting again
+        let pattern = compile_query("MATCH { V []; }").unwrap();
+        assert_eq!(treebank.match_iter(pattern, true).count(), 5);
+        assert_eq!(*reports.lock().unwrap(), vec![2, 2]);
+    }
+
+    #[test]
+    fn test_reservoir_sample() {
+        let items = |count: usize| (0..count).map(Ok::<usize, TreebankError>);
+        let sample = reservoir_sample(items(1000), 10, 7).unwrap();
+        assert_eq!(sample.len(), 10);
+        assert!(sample.windows(2).all(|w| w[0] < w[1])); // original order, no repeats
+        assert_eq!(reservoir_sample(items(1000), 10, 7).unwrap(), sample);
+        assert_ne!(reservoir_sample(items(1000), 10, 8).unwrap(), sample);
+        assert_eq!(reservoir_sample(items(3), 10, 7).unwrap(), vec![0, 1, 2]);
+
+        // Every item is about equally likely to be picked
+        let mut counts = [0usize; 20];
+        for seed in 0..2000 {
+            for i in reservoir_sample(items(20), 5, seed).unwrap() {
+                counts[i] += 1;
+            }
+        }
+        assert!(
+            counts.iter().all(|&c| (400..600).contains(&c)),
+            "{:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn test_sample_trees() {
+        let treebank = Treebank::from_string(TWO_TREE_CONLLU);
+        let sample = treebank.clone().sample(1, 3).unwrap();
+        assert_eq!(sample.len(), 1);
+        assert_eq!(treebank.clone().sample(5, 3).unwrap().len(), 2);
+        assert_eq!(treebank.clone().sample(usize::MAX, 3).unwrap().len(), 2);
+
+        let pattern = compile_query("MATCH { D [upos=\"DET\"]; }").unwrap();
+        let matching = treebank.sample_matching(pattern, 5, 3).unwrap();
+        assert_eq!(matching.len(), 1);
+        assert_eq!(matching[0].sentence_text.as_deref(), Some("The dog runs."));
+    }
+
+    #[test]
+    fn test_count_trees() {
+        let treebank = Treebank::from_string(TWO_TREE_CONLLU);
+        assert_eq!(treebank.clone().count_trees().unwrap(), 2);
+        let count = |query: &str| {
+            treebank
+                .clone()
+                .count_matching_trees(compile_query(query).unwrap())
+                .unwrap()
+        };
+        // Both trees have several words, but each is counted once
+        assert_eq!(count("MATCH { W []; }"), 2);
+        assert_eq!(count("MATCH { V [upos=\"VERB\"]; }"), 2);
+        assert_eq!(count("MATCH { N [upos=\"NONE\"]; }"), 0);
+
+        assert!(
+            Treebank::from_path("/nonexistent/file.conllu")
+                .count_trees()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_any_match() {
+        let treebank = Treebank::from_string(THREE_VERB_CONLLU);
+        let any = |query: &str| {
+            treebank
+                .clone()
+                .any_match(compile_query(query).unwrap())
+                .unwrap()
+        };
+        assert!(any("MATCH { V [lemma=\"help\"]; }"));
+        assert!(!any("MATCH { N [upos=\"NOUN\"]; }"));
+
+        let missing = Treebank::from_path("/nonexistent/file.conllu");
+        let pattern = compile_query("MATCH { V []; }").unwrap();
+        assert!(missing.any_match(pattern).is_err());
+    }
+
+    #[test]
+    fn test_match_stream_groups_by_sentence() {
+        let conllu = "1\tsaw\tsee\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      2\trunning\trun\tVERB\tVBG\t_\t1\txcomp\t_\t_\n\
+                      \n\
+                      1\tCats\tcat\tNOUN\tNNS\t_\t0\troot\t_\t_\n\
+                      \n\
+                      1\tsleeps\tsleep\tVERB\tVBZ\t_\t0\troot\t_\t_\n";
+        let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+        let results: Vec<_> = Treebank::from_string(conllu)
+            .match_stream(pattern, true)
+            .filter_map(Result::ok)
+            .collect();
+
+        // One entry per sentence, including the one without matches
+        assert_eq!(results.len(), 3);
+        let counts: Vec<usize> = results.iter().map(|(_, ms)| ms.len()).collect();
+        assert_eq!(counts, vec![2, 0, 1]);
+        assert_eq!(results[0].0.words.len(), 2);
+        assert_eq!(results[1].0.words.len(), 1);
+    }
+
+    #[test]
+    fn test_with_buffer_size() {
+        let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+        let default: Vec<_> = Treebank::from_string(THREE_VERB_CONLLU)
+            .match_iter(pattern.clone(), true)
+            .map(|m| m.unwrap().tree.words.len())
+            .collect();
+
+        for (match_batch, channel_batches) in [(1, 1), (2, 1), (1000, 200), (0, 0)] {
+            let treebank = Treebank::from_string(THREE_VERB_CONLLU)
+                .with_buffer_size(match_batch, channel_batches);
+            let matches: Vec<_> = treebank
+                .match_iter(pattern.clone(), true)
+                .map(|m| m.unwrap().tree.words.len())
+                .collect();
+            assert_eq!(matches, default);
+        }
+    }
+
+    #[test]
+    fn test_treebank_debug() {
+        let debug = format!("{:?}", Treebank::from_string(TWO_TREE_CONLLU));
+        assert!(debug.contains("String"));
+        assert!(debug.contains("The dog runs."));
+        assert!(debug.contains("..."));
+
+        let paths: Vec<PathBuf> = (0..5)
+            .map(|i| PathBuf::from(format!("f{}.conllu", i)))
+            .collect();
+        let debug = format!("{:?}", Treebank::from_paths(paths));
+        assert!(debug.contains("count: 5"));
+        assert!(debug.contains("f2.conllu"));
+        assert!(!debug.contains("f3.conllu"));
+    }
+
     #[cfg(test)]
     mod multi_file {
         use super::*;
@@ -637,6 +1832,42 @@ mod tests {
             assert_eq!(results[1].words.len(), 2);
         }
 
+        #[test]
+        fn test_treebank_from_directory() {
+            let (dir, _paths) = create_test_files(&[
+                ("a.conllu", "1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n"),
+                ("b.txt", "ignored"),
+            ]);
+            let nested = dir.path().join("sub[1]");
+            fs::create_dir(&nested).unwrap();
+            fs::write(
+                nested.join("c.conllu"),
+                "1\tYo\tyo\tINTJ\t_\t_\t0\troot\t_\t_\n",
+            )
+            .unwrap();
+
+            let count = |tb: Treebank| tb.file_paths().unwrap().len();
+            assert_eq!(
+                count(Treebank::from_directory(dir.path(), false, "*.conllu").unwrap()),
+                1
+            );
+            assert_eq!(
+                count(Treebank::from_directory(dir.path(), true, "*.conllu").unwrap()),
+                2
+            );
+            // Glob characters in the directory name are matched literally
+            assert_eq!(
+                count(Treebank::from_directory(&nested, false, "*").unwrap()),
+                1
+            );
+            assert_eq!(
+                count(Treebank::from_directory(dir.path(), false, "*.none").unwrap()),
+                0
+            );
+            assert!(Treebank::from_directory(dir.path(), false, "[").is_err());
+            assert!(Treebank::from_string("").file_paths().is_none());
+        }
+
         #[test]
         fn test_treebank_from_glob() {
             let (dir, _paths) = create_test_files(&[
@@ -828,5 +2059,191 @@ mod tests {
             // Should get all matches, order doesn't matter
             assert_eq!(results.len(), 2);
         }
+
+        #[test]
+        fn test_to_memory() {
+            let (dir, mut paths) = create_test_files(&[
+                // No trailing blank line, so files must be separated on load
+                ("a.conllu", "1\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n"),
+                ("b.conllu", TWO_TREE_CONLLU),
+            ]);
+            let gz_path = dir.path().join("c.conllu.gz");
+            let mut encoder = flate2::write::GzEncoder::new(
+                fs::File::create(&gz_path).unwrap(),
+                flate2::Compression::default(),
+            );
+            encoder.write_all(THREE_VERB_CONLLU.as_bytes()).unwrap();
+            encoder.finish().unwrap();
+            paths.push(gz_path);
+
+            let treebank = Treebank::from_paths(paths).to_memory().unwrap();
+            drop(dir); // Files are no longer needed
+
+            let lengths = |tb: Treebank| -> Vec<usize> {
+                tb.tree_iter(true).map(|t| t.unwrap().words.len()).collect()
+            };
+            let first = lengths(treebank.clone());
+            assert_eq!(first, vec![1, 3, 2, 2, 2, 1]);
+            assert_eq!(lengths(treebank), first);
+        }
+
+        #[test]
+        fn test_write_conllu() {
+            let (dir, paths) = create_test_files(&[
+                ("a.conllu", TWO_TREE_CONLLU),
+                ("b.conllu", THREE_VERB_CONLLU),
+            ]);
+            let sentences = |tb: Treebank| -> Vec<String> {
+                tb.tree_iter(true).map(|t| t.unwrap().to_conllu()).collect()
+            };
+            let treebank = Treebank::from_paths(paths);
+            let expected = sentences(treebank.clone());
+            for name in ["out.conllu", "out.conllu.gz"] {
+                let out = dir.path().join(name);
+                let count = treebank.clone().write_conllu(&out).unwrap();
+                assert_eq!(count, expected.len());
+                assert_eq!(sentences(Treebank::from_path(&out)), expected);
+            }
+        }
+
+        #[test]
+        fn test_par_tree_iter() {
+            let (_dir, paths) = create_test_files(&[
+                ("a.conllu", TWO_TREE_CONLLU),
+                ("b.conllu", THREE_VERB_CONLLU),
+                ("c.conllu", THREE_VERB_CONLLU),
+            ]);
+            let lengths = |tb: Treebank| -> Vec<usize> {
+                let mut lengths: Vec<usize> =
+                    tb.par_tree_iter().map(|t| t.unwrap().words.len()).collect();
+                lengths.sort();
+                lengths
+            };
+            let treebank = Treebank::from_paths(paths.clone());
+            assert_eq!(lengths(treebank.clone()), vec![1, 1, 2, 2, 2, 2, 2, 3]);
+            assert_eq!(
+                lengths(treebank.clone().filter_by_length(2, Some(2))),
+                vec![2, 2, 2, 2, 2]
+            );
+            assert_eq!(lengths(treebank.clone().skip(1).take(3)), vec![2, 2, 2]);
+            assert_eq!(lengths(Treebank::from_string(TWO_TREE_CONLLU)), vec![2, 3]);
+
+            let verbs = || compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+            assert_eq!(treebank.clone().par_match_iter(verbs()).count(), 8);
+            assert_eq!(
+                treebank.with_max_matches(0).par_match_iter(verbs()).count(),
+                0
+            );
+
+            let missing = Treebank::from_path("/nonexistent/file.conllu");
+            let results: Vec<_> = missing.par_tree_iter().collect();
+            assert!(matches!(results[..], [Err(TreebankError::FileOpen { .. })]));
+        }
+
+        #[test]
+        fn test_save_binary() {
+            let (dir, paths) = create_test_files(&[
+                ("a.conllu", TWO_TREE_CONLLU),
+                ("b.conllu", THREE_VERB_CONLLU),
+            ]);
+            let sentences = |tb: Treebank| -> Vec<String> {
+                tb.tree_iter(true).map(|t| t.unwrap().to_conllu()).collect()
+            };
+            let expected = sentences(Treebank::from_paths(paths.clone()));
+            let bin = dir.path().join("out.tsbt");
+            let count = Treebank::from_paths(paths.clone())
+                .save_binary(&bin)
+                .unwrap();
+            assert_eq!(count, expected.len());
+            assert_eq!(sentences(Treebank::from_binary(&bin).unwrap()), expected);
+            assert_eq!(
+                sentences(Treebank::from_path(&bin).to_memory().unwrap()),
+                expected
+            );
+            let verbs = || compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+            assert_eq!(
+                Treebank::from_path(&bin).match_iter(verbs(), false).count(),
+                Treebank::from_paths(paths.clone())
+                    .match_iter(verbs(), false)
+                    .count()
+            );
+            assert!(Treebank::from_binary(&paths[0]).is_err());
+
+            // The cache is built on first use and reused while it is fresh
+            let cache = dir.path().join("a.tsbt");
+            let treebank = Treebank::load_or_build(&paths[0], &cache).unwrap();
+            assert_eq!(treebank.file_paths(), Some(&[cache.clone()][..]));
+            assert_eq!(sentences(treebank).len(), 2);
+            fs::write(&cache, b"TSBT\x09\x00\x00\x00").unwrap();
+            let treebank = Treebank::load_or_build(&paths[0], &cache).unwrap();
+            assert_eq!(sentences(treebank).len(), 2);
+            // The cache is renamed into place, leaving no temporary file
+            assert!(fs::read_dir(dir.path()).unwrap().all(|entry| {
+                !entry
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".tmp")
+            }));
+        }
+
+        #[test]
+        fn test_with_buffer_size_files() {
+            let (_dir, paths) = create_test_files(&[
+                ("a.conllu", TWO_TREE_CONLLU),
+                ("b.conllu", THREE_VERB_CONLLU),
+            ]);
+            let pattern = compile_query("MATCH { W []; }").unwrap();
+            let words = |treebank: Treebank| -> Vec<(usize, usize)> {
+                treebank
+                    .match_iter(pattern.clone(), true)
+                    .map(|m| {
+                        let m = m.unwrap();
+                        (m.tree.words.len(), m.bindings["W"])
+                    })
+                    .collect()
+            };
+            let default = words(Treebank::from_paths(paths.clone()));
+            assert_eq!(default.len(), 10);
+            for (match_batch, channel_batches) in [(1, 1), (3, 1), (1000, 200)] {
+                let treebank = Treebank::from_paths(paths.clone())
+                    .with_buffer_size(match_batch, channel_batches);
+                assert_eq!(words(treebank), default);
+            }
+        }
+
+        #[test]
+        fn test_to_memory_missing_file() {
+            let treebank = Treebank::from_path("/nonexistent/file.conllu");
+            assert!(matches!(
+                treebank.to_memory(),
+                Err(TreebankError::FileOpen { .. })
+            ));
+        }
+
+        #[test]
+        fn test_to_memory_invalid_utf8() {
+            let dir = tempdir().unwrap();
+            let path = dir.path().join("latin1.conllu");
+            fs::write(
+                &path,
+                b"1\tcaf\xe9\tcaf\xe9\tNOUN\tNN\t_\t0\troot\t_\t_\n\n",
+            )
+            .unwrap();
+            let forms = |treebank: Treebank| -> Vec<Vec<u8>> {
+                treebank
+                    .tree_iter(true)
+                    .map(|tree| {
+                        let tree = tree.unwrap();
+                        tree.string_pool.resolve(tree.words[0].form).to_vec()
+                    })
+                    .collect()
+            };
+            let treebank = Treebank::from_path(&path);
+            // The bytes survive the copy into memory unchanged
+            let in_memory = treebank.to_memory().unwrap();
+            assert_eq!(forms(in_memory), forms(treebank));
+            assert_eq!(forms(Treebank::from_path(&path)), vec![b"caf\xe9".to_vec()]);
+        }
     }
 }
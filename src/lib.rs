@@ -6,19 +6,42 @@
 //! Core implementation in Rust with Python bindings.
 
 // Core modules
+pub mod binary; // Binary treebank cache format
 pub mod bytes;
+pub mod complexity; // Pattern cost estimates
+pub mod concordance; // KWIC concordance lines
 pub mod conllu; // CoNLL-U file parsing
+pub mod export; // Match export (JSON Lines, CSV)
 pub mod iterators; // Iterator interfaces for trees and matches
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap; // Memory-mapped input
 pub mod pattern; // Pattern AST
 pub mod python;
 pub mod query; // Query language parser
 pub mod searcher;
+pub mod stats; // Corpus summary statistics
+pub mod structure; // Tree shapes and isomorphism
 pub mod tree; // Tree data structures with full CoNLL-U support
+pub mod validate; // UD validation of parsed trees
 
 // Re-exports for convenience
-pub use conllu::TreeIterator;
+pub use complexity::ComplexityBound;
+pub use concordance::{
+    ConcordanceSortKey, KwicLine, format_concordance, format_kwic, sort_concordance,
+};
+pub use conllu::{InputFormat, LenientTree, ParseMode, TreeIterator, TreeWriter, write_conllu};
+pub use export::{match_to_json, write_matches_csv, write_matches_jsonl};
 pub use iterators::{Treebank, TreebankError};
-pub use pattern::{Constraint, EdgeConstraint, Pattern, PatternVar, RelationType, VarId};
+pub use pattern::{
+    Constraint, EdgeConstraint, FieldSelector, LabelMatcher, OrderKey, Pattern, PatternVar,
+    RelationType, SentenceConstraint, VarId,
+};
 pub use query::compile_query;
-pub use searcher::{Match, search_tree, search_tree_query, tree_matches};
-pub use tree::{Features, TokenId, Tree, Word, WordId};
+pub use searcher::{
+    BindingsDisplay, Match, MatchOrder, MatchStream, find_first_match, match_stream, order_matches,
+    search_shared_tree, search_tree, search_tree_query, tree_matches,
+};
+pub use stats::{FrequencyEntry, TreebankStats, frequency_table, group_and_count};
+pub use structure::{canonical_structure, tree_isomorphic};
+pub use tree::{DotStyle, Features, LayoutDirection, MultiwordToken, TokenId, Tree, Word, WordId};
+pub use validate::{ValidationError, ValidationErrorKind, ValidationWarning, validate_tree};
@@ -0,0 +1,168 @@
+//! Memory-mapped CoNLL-U input (`mmap` feature, Unix only)
+//!
+//! [`TreeIterator::from_mmap`] parses a file directly from its memory map: the
+//! mapped bytes serve as the reader's buffer, so lines are found in place
+//! instead of being copied through a `BufReader`. For files already in the page
+//! cache this also saves a `read` call per buffer.
+
+use crate::conllu::TreeIterator;
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+/// A read-only mapping of a whole file
+pub struct MappedFile {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// SAFETY: the mapping is private and read-only, so it can be read from any thread
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    /// Map a file into memory
+    ///
+    /// Truncating the file while it is mapped makes reads past the new end
+    /// fault, as with any memory-mapped reader.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        if len == 0 {
+            // mmap rejects empty mappings
+            return Ok(Self {
+                ptr: std::ptr::null_mut(),
+                len: 0,
+            });
+        }
+        // SAFETY: a new private read-only mapping of an open file, which stays
+        // valid after the file is closed
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    /// The mapped bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: `ptr` points to `len` mapped bytes that live as long as `self`
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: `ptr` and `len` come from a successful mmap, unmapped only here
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
+
+/// Buffered reader over a mapped file; the buffer is the unread rest of the mapping
+pub struct MmapReader {
+    map: MappedFile,
+    pos: usize,
+}
+
+impl MmapReader {
+    pub fn new(map: MappedFile) -> Self {
+        Self { map, pos: 0 }
+    }
+}
+
+impl Read for MmapReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for MmapReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.map.as_bytes()[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.map.len);
+    }
+}
+
+impl TreeIterator<MmapReader> {
+    /// Create a reader that parses a plain CoNLL-U file through a memory map
+    ///
+    /// Gzipped files cannot be mapped; use [`TreeIterator::from_file`] for them.
+    pub fn from_mmap(path: &Path) -> io::Result<Self> {
+        let map = MappedFile::open(path)?;
+        if map.as_bytes().starts_with(&[0x1f, 0x8b]) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is gzipped and cannot be memory-mapped", path.display()),
+            ));
+        }
+        Ok(Self::from_reader(MmapReader::new(map)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conllu::TreeWriter;
+    use std::io::Write;
+
+    const CONLLU: &str = "# sent_id = s1\n\
+                          1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                          2\tdog\tdog\tNOUN\tNN\t_\t0\troot\t_\t_\n\n\
+                          1\tBirds\tbird\tNOUN\tNNS\t_\t2\tnsubj\t_\t_\n\
+                          2\tsing\tsing\tVERB\tVBP\t_\t0\troot\t_\t_\n";
+
+    #[test]
+    fn test_from_mmap() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(CONLLU.as_bytes()).unwrap();
+        let mapped: Vec<_> = TreeIterator::from_mmap(file.path())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let read: Vec<_> = TreeIterator::from_string(CONLLU)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(mapped.len(), 2);
+        for (a, b) in mapped.iter().zip(&read) {
+            assert_eq!(a.to_conllu(), b.to_conllu());
+        }
+
+        let empty = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(TreeIterator::from_mmap(empty.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_from_mmap_rejects_gzip() {
+        let file = tempfile::Builder::new().suffix(".gz").tempfile().unwrap();
+        let mut writer = TreeWriter::to_gz_file(file.path()).unwrap();
+        for tree in TreeIterator::from_string(CONLLU) {
+            writer.write_tree(&tree.unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+        let err = TreeIterator::from_mmap(file.path()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
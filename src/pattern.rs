@@ -4,17 +4,30 @@
 //! in the CSP-based matching algorithm.
 
 use regex::Regex;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 
-/// Type alias for pattern variable identifiers (indices into Pattern.vars)
-pub type VarId = usize;
+/// Pattern variable identifier (index into BasePattern.var_names etc.)
+///
+/// A newtype rather than a bare `usize` so variable indices can't be confused
+/// with `WordId`s in the solver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VarId(pub usize);
 
 /// Value in a constraint: either a literal string or a regex pattern
 #[derive(Clone)]
 pub enum ConstraintValue {
     Literal(String),
+    /// Literal compared ignoring case (`="..." /i` or `~="..."`)
+    CaseInsensitive(String),
+    /// Field starts with the string (`^=`)
+    Prefix(String),
+    /// Field ends with the string (`$=`)
+    Suffix(String),
+    /// Field contains the string (`*=`)
+    Contains(String),
     Regex(String, Regex), // Pattern string + compiled regex
 }
 
@@ -23,22 +36,130 @@ impl Debug for ConstraintValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ConstraintValue::Literal(s) => f.debug_tuple("Literal").field(s).finish(),
+            ConstraintValue::CaseInsensitive(s) => {
+                f.debug_tuple("CaseInsensitive").field(s).finish()
+            }
+            ConstraintValue::Prefix(s) => f.debug_tuple("Prefix").field(s).finish(),
+            ConstraintValue::Suffix(s) => f.debug_tuple("Suffix").field(s).finish(),
+            ConstraintValue::Contains(s) => f.debug_tuple("Contains").field(s).finish(),
             ConstraintValue::Regex(pattern, _) => f.debug_tuple("Regex").field(pattern).finish(),
         }
     }
 }
 
+/// Escape the `/`s that would end a regex literal early
+fn escape_slashes(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut escaped = false;
+    for c in pattern.chars() {
+        if c == '/' && !escaped {
+            out.push('\\');
+        }
+        escaped = c == '\\' && !escaped;
+        out.push(c);
+    }
+    out
+}
+
+impl ConstraintValue {
+    /// The value as written after `=` or `!=`: a quoted string where the query
+    /// language can quote it, otherwise an equivalent regex literal
+    ///
+    /// String literals have no escapes, so strings containing `"` or `\` (and
+    /// prefix, suffix and substring tests, which have no `!=` form) become regexes.
+    fn eq_operand(&self) -> String {
+        let quotable = |s: &str| !s.contains(['"', '\\']);
+        let regex = |before: &str, s: &str, after: &str, flag: &str| {
+            format!(
+                "/{}{}{}/{}",
+                before,
+                escape_slashes(&regex::escape(s)),
+                after,
+                flag
+            )
+        };
+        match self {
+            ConstraintValue::Literal(s) if quotable(s) => format!("\"{}\"", s),
+            ConstraintValue::Literal(s) => regex("", s, "", ""),
+            ConstraintValue::CaseInsensitive(s) if quotable(s) => format!("\"{}\"/i", s),
+            ConstraintValue::CaseInsensitive(s) => regex("", s, "", "i"),
+            ConstraintValue::Prefix(s) => regex("", s, ".*", ""),
+            ConstraintValue::Suffix(s) => regex(".*", s, "", ""),
+            ConstraintValue::Contains(s) => regex(".*", s, ".*", ""),
+            ConstraintValue::Regex(pattern, compiled) => {
+                // The compiled regex also holds any anchoring and flags the operator added
+                if compiled.as_str() == format!("^(?:{})$", pattern) {
+                    format!("/{}/", pattern)
+                } else if compiled.as_str() == format!("(?i)^(?:{})$", pattern) {
+                    format!("/{}/i", pattern)
+                } else {
+                    format!("/{}/", escape_slashes(compiled.as_str()))
+                }
+            }
+        }
+    }
+}
+
+/// Operator and value as written in queries: `="run"`, `^="un"`, `=/run.*/`
+impl fmt::Display for ConstraintValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let quotable = |s: &str| !s.contains(['"', '\\']);
+        match self {
+            ConstraintValue::CaseInsensitive(s) if quotable(s) => write!(f, "~=\"{}\"", s),
+            ConstraintValue::Prefix(s) if quotable(s) => write!(f, "^=\"{}\"", s),
+            ConstraintValue::Suffix(s) if quotable(s) => write!(f, "$=\"{}\"", s),
+            ConstraintValue::Contains(s) if quotable(s) => write!(f, "*=\"{}\"", s),
+            value => write!(f, "={}", value.eq_operand()),
+        }
+    }
+}
+
 // Manual PartialEq implementation (compare pattern strings, not compiled regex)
 impl PartialEq for ConstraintValue {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (ConstraintValue::Literal(a), ConstraintValue::Literal(b)) => a == b,
+            (ConstraintValue::CaseInsensitive(a), ConstraintValue::CaseInsensitive(b)) => a == b,
+            (ConstraintValue::Prefix(a), ConstraintValue::Prefix(b)) => a == b,
+            (ConstraintValue::Suffix(a), ConstraintValue::Suffix(b)) => a == b,
+            (ConstraintValue::Contains(a), ConstraintValue::Contains(b)) => a == b,
             (ConstraintValue::Regex(a, _), ConstraintValue::Regex(b, _)) => a == b,
             _ => false,
         }
     }
 }
 
+/// Dependency label(s) accepted by an edge: `-[nsubj]->` or `-[nsubj|expl]->`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelMatcher {
+    Exact(String),
+    AnyOf(Vec<String>),
+}
+
+impl LabelMatcher {
+    /// All labels accepted by this matcher
+    pub fn labels(&self) -> &[String] {
+        match self {
+            LabelMatcher::Exact(label) => std::slice::from_ref(label),
+            LabelMatcher::AnyOf(labels) => labels,
+        }
+    }
+
+    /// Labels as written in queries: `nsubj|expl`
+    fn joined(&self) -> String {
+        self.labels().join("|")
+    }
+
+    /// The equivalent DepRel constraint on the edge's target
+    fn to_deprel_constraint(&self) -> Constraint {
+        let deprel = |label: &String| Constraint::DepRel(ConstraintValue::Literal(label.clone()));
+        match self {
+            LabelMatcher::Exact(label) => deprel(label),
+            LabelMatcher::AnyOf(labels) => Constraint::Or(labels.iter().map(deprel).collect()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Constraint {
     Any,
@@ -49,10 +170,136 @@ pub enum Constraint {
     DepRel(ConstraintValue),
     Feature(String, ConstraintValue),
     Misc(String, ConstraintValue),
+    /// Word has the feature, with any value
+    HasFeature(String),
+    /// Word has the misc annotation, with any value
+    HasMisc(String),
+    /// Compare the 1-based CoNLL-U token ID to a bound (`Less` means `token_id < bound`)
+    TokenId(Ordering, usize),
     And(Vec<Constraint>),
+    Or(Vec<Constraint>),
     Not(Box<Constraint>),
-    IsChild(Option<String>),
-    HasChild(Option<String>),
+    IsChild(Option<LabelMatcher>),
+    HasChild(Option<LabelMatcher>),
+    /// Word has between `min` and `max` (inclusive) children, optionally with a given deprel
+    ChildCount {
+        label: Option<LabelMatcher>,
+        min: usize,
+        max: Option<usize>,
+    },
+    /// Word has no head
+    IsRoot,
+    /// Word has no children
+    IsLeaf,
+    /// Some word below this one (a descendant at any depth) satisfies the constraint
+    SubtreeContains(Box<Constraint>),
+}
+
+impl Constraint {
+    /// Field name and value of a field comparison (`lemma`, `feats.Tense`, ...)
+    fn field_value(&self) -> Option<(String, &ConstraintValue)> {
+        match self {
+            Constraint::Lemma(value) => Some(("lemma".to_string(), value)),
+            Constraint::UPOS(value) => Some(("upos".to_string(), value)),
+            Constraint::XPOS(value) => Some(("xpos".to_string(), value)),
+            Constraint::Form(value) => Some(("form".to_string(), value)),
+            Constraint::DepRel(value) => Some(("deprel".to_string(), value)),
+            Constraint::Feature(key, value) => Some((format!("feats.{}", key), value)),
+            Constraint::Misc(key, value) => Some((format!("misc.{}", key), value)),
+            _ => None,
+        }
+    }
+}
+
+/// Query syntax as written inside a variable's brackets (`Any` is empty), so
+/// `X [<constraint>]` compiles back to the same constraint
+///
+/// Two kinds of constraint have no bracket syntax. Those that only an anonymous
+/// `_` edge can express print as that edge, with `*` for the constrained word
+/// (`_ -[obj]-> *`, `* -[nsubj]{2,}-> _`); negations the language has no
+/// operator for print as `!(...)`.
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = |neg: &str, label: &Option<LabelMatcher>| match label {
+            Some(label) => format!("{}-[{}]->", neg, label.joined()),
+            None => format!("{}->", neg),
+        };
+        let join = |f: &mut fmt::Formatter<'_>, parts: &[Constraint], sep: &str| {
+            for (i, part) in parts.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(sep)?;
+                }
+                write!(f, "{}", part)?;
+            }
+            Ok(())
+        };
+        if let Some((name, value)) = self.field_value() {
+            return write!(f, "{}{}", name, value);
+        }
+        match self {
+            Constraint::Any => Ok(()),
+            Constraint::Lemma(_)
+            | Constraint::UPOS(_)
+            | Constraint::XPOS(_)
+            | Constraint::Form(_)
+            | Constraint::DepRel(_)
+            | Constraint::Feature(..)
+            | Constraint::Misc(..) => unreachable!("handled by field_value"),
+            Constraint::HasFeature(key) => write!(f, "feats.{}", key),
+            Constraint::HasMisc(key) => write!(f, "misc.{}", key),
+            Constraint::TokenId(ordering, bound) => {
+                let op = match ordering {
+                    Ordering::Less => "<",
+                    Ordering::Equal => "=",
+                    Ordering::Greater => ">",
+                };
+                write!(f, "token_id{}{}", op, bound)
+            }
+            Constraint::And(parts) => join(f, parts, " & "),
+            Constraint::Or(parts) => {
+                f.write_str("(")?;
+                join(f, parts, " | ")?;
+                f.write_str(")")
+            }
+            Constraint::Not(inner) => {
+                if let Some((name, value)) = inner.field_value() {
+                    return write!(f, "{}!={}", name, value.eq_operand());
+                }
+                match inner.as_ref() {
+                    Constraint::HasFeature(key) => write!(f, "!feats.{}", key),
+                    Constraint::HasMisc(key) => write!(f, "!misc.{}", key),
+                    Constraint::TokenId(Ordering::Equal, bound) => write!(f, "token_id!={}", bound),
+                    Constraint::TokenId(Ordering::Greater, bound) => {
+                        write!(f, "token_id<={}", bound)
+                    }
+                    Constraint::TokenId(Ordering::Less, bound) => write!(f, "token_id>={}", bound),
+                    Constraint::SubtreeContains(contained) => {
+                        write!(f, "!CONTAINS ({})", contained)
+                    }
+                    Constraint::IsChild(label) => write!(f, "_ {} *", arrow("!", label)),
+                    Constraint::HasChild(label) => write!(f, "* {} _", arrow("!", label)),
+                    Constraint::Not(positive) => write!(f, "{}", positive),
+                    _ => write!(f, "!({})", inner),
+                }
+            }
+            Constraint::IsChild(label) => write!(f, "_ {} *", arrow("", label)),
+            Constraint::HasChild(label) => write!(f, "* {} _", arrow("", label)),
+            Constraint::ChildCount { label, min, max } => {
+                let bound = match max {
+                    Some(max) if max == min => format!("{{{}}}", min),
+                    Some(max) => format!("{{{},{}}}", min, max),
+                    None => format!("{{{},}}", min),
+                };
+                let label = label
+                    .as_ref()
+                    .map_or(String::new(), |label| format!("[{}]", label.joined()));
+                write!(f, "* -{}{}-> _", label, bound)
+            }
+            Constraint::IsRoot => f.write_str("IS_ROOT"),
+            Constraint::IsLeaf => f.write_str("IS_LEAF"),
+            Constraint::SubtreeContains(inner) => write!(f, "CONTAINS ({})", inner),
+        }
+    }
 }
 
 pub fn merge_constraints(a: &Constraint, b: &Constraint) -> Constraint {
@@ -94,6 +341,21 @@ pub enum RelationType {
     Child,
     Precedes,
     ImmediatelyPrecedes,
+    /// `from` precedes `to` by at most this many positions
+    PrecedesWithin(usize),
+    /// `from` and `to` are at most this many positions apart, in either order
+    WithinDistance(usize),
+    /// `to` is a descendant of `from`, between `min` and `max` (inclusive) edges below it
+    Descendant {
+        min: usize,
+        max: Option<usize>,
+    },
+    /// `from` and `to` have the same parent
+    Sibling,
+    /// `to` is a sibling of `from` and immediately follows it
+    ImmediateSibling,
+    /// `to` is the next sibling after `from`, with any words in between
+    NextSibling,
 }
 
 #[derive(Debug, Clone)]
@@ -101,21 +363,116 @@ pub struct EdgeConstraint {
     pub from: String,
     pub to: String,
     pub relation: RelationType,
-    pub label: Option<String>,
+    pub label: Option<LabelMatcher>,
     pub negated: bool,
 }
 
+/// The edge as written in queries: `V -[nsubj]-> N`, `A !<< B`, `A >>{2,4} B`
+impl fmt::Display for EdgeConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let neg = if self.negated { "!" } else { "" };
+        let op = match self.relation {
+            RelationType::Child => match &self.label {
+                Some(label) => format!("{}-[{}]->", neg, label.joined()),
+                None => format!("{}->", neg),
+            },
+            RelationType::Precedes => format!("{}<<", neg),
+            RelationType::ImmediatelyPrecedes => format!("{}<", neg),
+            RelationType::PrecedesWithin(n) => format!("{}<{}", neg, n),
+            RelationType::WithinDistance(n) => format!("{}~{}", neg, n),
+            RelationType::Descendant { min: 1, max: None } => format!("{}>>", neg),
+            RelationType::Descendant {
+                min,
+                max: Some(max),
+            } if min == max => {
+                format!("{}>>{}", neg, min)
+            }
+            RelationType::Descendant { min, max } => {
+                let max = max.map_or(String::new(), |max| max.to_string());
+                format!("{}>>{{{},{}}}", neg, min, max)
+            }
+            RelationType::Sibling => "$$".to_string(),
+            RelationType::ImmediateSibling => "$.".to_string(),
+            RelationType::NextSibling => "$+".to_string(),
+        };
+        write!(f, "{} {} {}", self.from, op, self.to)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum DirectedEdge {
     In(usize),
     Out(usize),
 }
 
+/// Word field that matches can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldSelector {
+    Lemma,
+    UPOS,
+    XPOS,
+    Form,
+    DepRel,
+    TokenId,
+}
+
+impl FieldSelector {
+    /// Field name as written in queries
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldSelector::Lemma => "lemma",
+            FieldSelector::UPOS => "upos",
+            FieldSelector::XPOS => "xpos",
+            FieldSelector::Form => "form",
+            FieldSelector::DepRel => "deprel",
+            FieldSelector::TokenId => "token_id",
+        }
+    }
+
+    /// Field for a name as written in queries (`"lemma"`, `"upos"`, ...)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lemma" => Some(FieldSelector::Lemma),
+            "upos" => Some(FieldSelector::UPOS),
+            "xpos" => Some(FieldSelector::XPOS),
+            "form" => Some(FieldSelector::Form),
+            "deprel" => Some(FieldSelector::DepRel),
+            "token_id" => Some(FieldSelector::TokenId),
+            _ => None,
+        }
+    }
+}
+
+/// One key of an ORDER BY clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderKey {
+    pub var_name: String,
+    pub field: FieldSelector,
+    pub ascending: bool,
+}
+
+/// Condition on a whole sentence, from a SENTENCE block
+#[derive(Debug, Clone)]
+pub enum SentenceConstraint {
+    /// Metadata comment `key` has exactly this value (`text` is the sentence text)
+    MetadataEquals(String, String),
+    /// The sentence text contains the string
+    TextContains(String),
+    /// The regex matches somewhere in the sentence text (unanchored)
+    TextRegex(String, Regex),
+}
+
 #[derive(Debug, Clone)]
 pub struct Pattern {
+    /// Conditions a tree has to meet before the MATCH block is searched
+    pub sentence_constraints: Vec<SentenceConstraint>,
     pub match_pattern: BasePattern,
     pub except_patterns: Vec<BasePattern>,
     pub optional_patterns: Vec<BasePattern>,
+    /// Sort keys for the matches in each tree (empty = solver order)
+    pub order_by: Vec<OrderKey>,
+    /// Query the pattern was compiled from, if any; used for serialization
+    pub source_query: Option<String>,
 }
 
 /// A complete pattern to match against dependency trees
@@ -166,11 +523,11 @@ impl BasePattern {
     pub fn add_var(&mut self, var_name: &str, constr: Constraint) {
         match self.var_ids.entry(var_name.to_owned()) {
             Entry::Occupied(e) => {
-                let id = *e.get();
+                let VarId(id) = *e.get();
                 self.var_constraints[id] = merge_constraints(&self.var_constraints[id], &constr);
             }
             Entry::Vacant(e) => {
-                let var_id = self.var_constraints.len();
+                let var_id = VarId(self.var_constraints.len());
                 e.insert(var_id);
                 self.var_names.push(var_name.to_string());
                 self.var_constraints.push(constr);
@@ -219,10 +576,7 @@ impl BasePattern {
                 self.add_var(&edge_constraint.from, Constraint::Any);
                 if let Some(label) = &edge_constraint.label {
                     if !edge_constraint.negated {
-                        self.add_var(
-                            &edge_constraint.to,
-                            Constraint::DepRel(ConstraintValue::Literal(label.clone())),
-                        );
+                        self.add_var(&edge_constraint.to, label.to_deprel_constraint());
                     } else {
                         self.add_var(&edge_constraint.to, Constraint::Any);
                     }
@@ -231,19 +585,45 @@ impl BasePattern {
                 }
 
                 let edge_id = self.edge_constraints.len();
-                let from_var_id = self.var_ids.get(&edge_constraint.from).unwrap();
-                let to_var_id = self.var_ids.get(&edge_constraint.to).unwrap();
+                let VarId(from_var_id) = self.var_ids[&edge_constraint.from];
+                let VarId(to_var_id) = self.var_ids[&edge_constraint.to];
 
-                self.out_edges[*from_var_id].push(edge_id);
-                self.in_edges[*to_var_id].push(edge_id);
-                self.incident_edges[*from_var_id].push(DirectedEdge::Out(edge_id));
-                self.incident_edges[*to_var_id].push(DirectedEdge::In(edge_id));
+                self.out_edges[from_var_id].push(edge_id);
+                self.in_edges[to_var_id].push(edge_id);
+                self.incident_edges[from_var_id].push(DirectedEdge::Out(edge_id));
+                self.incident_edges[to_var_id].push(DirectedEdge::In(edge_id));
                 self.edge_constraints.push(edge_constraint);
             }
         }
     }
 }
 
+impl BasePattern {
+    /// Restrict every other variable to the subtree dominated by `root`.
+    ///
+    /// `root` becomes a variable of the pattern (merging its constraint if it
+    /// already is one), with a `Descendant` edge to each other variable.
+    pub fn add_within(&mut self, root: PatternVar) {
+        let others: Vec<String> = self
+            .var_names
+            .iter()
+            .filter(|name| **name != root.var_name)
+            .cloned()
+            .collect();
+        self.add_var(&root.var_name, root.constraint);
+        for var_name in others {
+            self.add_edge_constraint(EdgeConstraint {
+                from: root.var_name.clone(),
+                to: var_name,
+                relation: RelationType::Descendant { min: 1, max: None },
+                label: None,
+                negated: false,
+            });
+        }
+        self.n_vars = self.var_constraints.len();
+    }
+}
+
 impl Default for BasePattern {
     fn default() -> Self {
         Self::new()
@@ -276,7 +656,7 @@ mod tests {
             from: "verb".to_string(),
             to: "noun".to_string(),
             relation: RelationType::Child,
-            label: Some("nsubj".to_string()),
+            label: Some(LabelMatcher::Exact("nsubj".to_string())),
             negated: false,
         }];
 
@@ -6,16 +6,25 @@
 //! the Python thread state (in free-threaded Python) during expensive Rust operations,
 //! allowing better parallel performance.
 
-use pyo3::exceptions::{PyIOError, PyIndexError, PyValueError};
+use pyo3::IntoPyObjectExt;
+use pyo3::exceptions::{PyIOError, PyImportError, PyIndexError, PyUserWarning, PyValueError};
 use pyo3::prelude::*;
+use pyo3::pyclass::CompareOp;
+use pyo3::types::{PyDict, PyList, PyModule};
+use std::collections::BTreeSet;
+use std::ffi::CString;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use crate::concordance::{ConcordanceSortKey, KwicLine, format_concordance, sort_concordance};
+use crate::export::{field_text, write_matches_csv, write_matches_jsonl};
 use crate::iterators::{Treebank, TreebankError};
-use crate::pattern::Pattern as RustPattern;
+use crate::pattern::{FieldSelector, Pattern as RustPattern};
 use crate::query::compile_query;
-use crate::searcher::search_tree;
-use crate::tree::{Tree as RustTree, Word as RustWord};
+use crate::searcher::{Bindings, BindingsDisplay, Match, MatchOrder, search_tree, tree_matches};
+use crate::stats::{TreebankStats, group_and_count};
+use crate::tree::{DotStyle, LayoutDirection, Tree as RustTree, Word as RustWord};
 
 /// Convert TreebankError to Python exception
 impl From<TreebankError> for PyErr {
@@ -28,6 +37,9 @@ impl From<TreebankError> for PyErr {
                 path.display(),
                 source
             )),
+            TreebankError::InvalidTree(message) => {
+                PyValueError::new_err(format!("Invalid tree after map_trees: {}", message))
+            }
         }
     }
 }
@@ -59,6 +71,33 @@ impl PyTree {
         self.inner.words.len()
     }
 
+    fn __iter__(&self) -> PyWordIterator {
+        PyWordIterator {
+            inner: Arc::clone(&self.inner),
+            pos: 0,
+        }
+    }
+
+    #[getter]
+    fn root(&self) -> Option<PyWord> {
+        self.inner.root().map(|word| PyWord {
+            inner: word.clone(),
+            tree: Arc::clone(&self.inner),
+        })
+    }
+
+    #[getter]
+    fn roots(&self) -> Vec<PyWord> {
+        self.inner
+            .roots()
+            .into_iter()
+            .map(|word| PyWord {
+                inner: word.clone(),
+                tree: Arc::clone(&self.inner),
+            })
+            .collect()
+    }
+
     #[getter]
     fn sentence_text(&self) -> Option<String> {
         self.inner.sentence_text.clone()
@@ -69,6 +108,220 @@ impl PyTree {
         self.inner.metadata.clone()
     }
 
+    /// Whether the sentence starts a document (`# newdoc`)
+    #[getter]
+    fn is_new_doc(&self) -> bool {
+        self.inner.is_new_doc
+    }
+
+    /// Whether the sentence starts a paragraph (`# newpar`)
+    #[getter]
+    fn is_new_par(&self) -> bool {
+        self.inner.is_new_par
+    }
+
+    /// Document id from `# newdoc id = ...` or `# doc_id = ...`, if any
+    #[getter]
+    fn doc_id(&self) -> Option<String> {
+        self.inner.doc_id.clone()
+    }
+
+    /// Every comment line as read, in order, including free-form ones like `# newpar`
+    #[getter]
+    fn raw_comments(&self) -> Vec<String> {
+        self.inner.raw_comments.clone()
+    }
+
+    /// Check whether word `id` has feature `key=value`
+    fn has_feature(&self, id: usize, key: &str, value: &str) -> PyResult<bool> {
+        self.word(id)?;
+        Ok(self.inner.has_feature(id, key, value))
+    }
+
+    /// Value of feature `key` on word `id`, or None if absent
+    fn feature_value(&self, id: usize, key: &str) -> PyResult<Option<String>> {
+        self.word(id)?;
+        Ok(self
+            .inner
+            .feature_value(id, key)
+            .map(|value| String::from_utf8_lossy(&value).to_string()))
+    }
+
+    /// True if no dependency arc crosses another
+    fn is_projective(&self) -> bool {
+        self.inner.is_projective()
+    }
+
+    /// Non-projective arcs as (head, dependent) pairs
+    fn non_projective_arcs(&self) -> Vec<(usize, usize)> {
+        self.inner.non_projective_arcs()
+    }
+
+    /// Greatest depth of any word
+    fn height(&self) -> usize {
+        self.inner.height()
+    }
+
+    /// Number of words in the subtree rooted at word `id`, including itself
+    fn subtree_size(&self, id: usize) -> PyResult<usize> {
+        self.word(id)?;
+        Ok(self.inner.subtree_size(id))
+    }
+
+    /// Surface text of the subtree rooted at word `id`, respecting `SpaceAfter=No`
+    fn subtree_text(&self, id: usize) -> PyResult<String> {
+        self.word(id)?;
+        Ok(self.inner.subtree_text(id))
+    }
+
+    /// Leftmost and rightmost word ids of the subtree rooted at word `id`
+    fn subtree_span(&self, id: usize) -> PyResult<(usize, usize)> {
+        self.word(id)?;
+        Ok(self.inner.subtree_span(id))
+    }
+
+    /// Mean number of children over the words that have any
+    fn mean_branching_factor(&self) -> f64 {
+        self.inner.mean_branching_factor()
+    }
+
+    /// Lowest common ancestor of two words (a word counts as its own ancestor)
+    fn lca(&self, word_a: usize, word_b: usize) -> PyResult<Option<usize>> {
+        self.word(word_a)?;
+        self.word(word_b)?;
+        Ok(self.inner.lca(word_a, word_b))
+    }
+
+    /// Copy the subtree rooted at word `word_id` into a new tree (ids renumbered from 0)
+    fn subtree(&self, word_id: usize) -> PyResult<PyTree> {
+        self.word(word_id)?;
+        Ok(PyTree {
+            inner: Arc::new(self.inner.extract_subtree(word_id)),
+        })
+    }
+
+    /// Serialize the tree as a JSON string (see `Tree::to_json`)
+    fn to_json(&self) -> String {
+        self.inner.to_json()
+    }
+
+    /// Rebuild a tree saved with Tree.to_json().
+    ///
+    /// Raises:
+    ///     ValueError: If the JSON is malformed or a head is not in the tree
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<PyTree> {
+        RustTree::from_json(json, &crate::bytes::BytestringPool::new())
+            .map(|tree| PyTree {
+                inner: Arc::new(tree),
+            })
+            .map_err(|e| PyValueError::new_err(format!("Invalid tree JSON: {}", e)))
+    }
+
+    /// Check the tree against the UD guidelines, returning one message per problem
+    fn validate(&self) -> Vec<String> {
+        crate::validate::validate_tree(&self.inner)
+            .into_iter()
+            .map(|error| error.message)
+            .collect()
+    }
+
+    /// Serialize the tree as a CoNLL-U sentence (see `Tree::to_conllu`)
+    fn to_conllu(&self) -> String {
+        self.inner.to_conllu()
+    }
+
+    /// Render the tree as a GraphViz DOT graph (see `Tree::write_dot`)
+    ///
+    /// Args:
+    ///     show_lemma: Add lemmas to the node labels
+    ///     show_xpos: Add XPOS tags to the node labels
+    ///     direction: "TB" (top to bottom) or "LR" (left to right)
+    #[pyo3(signature = (show_lemma=false, show_xpos=false, direction="TB"))]
+    fn to_dot(&self, show_lemma: bool, show_xpos: bool, direction: &str) -> PyResult<String> {
+        let style = dot_style(show_lemma, show_xpos, direction)?;
+        Ok(self.inner.to_dot_with_style(&style))
+    }
+
+    /// Write the tree as a GraphViz DOT file (same options as `to_dot`)
+    #[pyo3(signature = (path, show_lemma=false, show_xpos=false, direction="TB"))]
+    fn save_dot(
+        &self,
+        path: PathBuf,
+        show_lemma: bool,
+        show_xpos: bool,
+        direction: &str,
+    ) -> PyResult<()> {
+        let style = dot_style(show_lemma, show_xpos, direction)?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        self.inner.write_dot(&mut file, &style)?;
+        std::io::Write::flush(&mut file)?;
+        Ok(())
+    }
+
+    /// Draw the tree as text, with dependents indented under their heads (see `Tree::to_ascii`)
+    #[pyo3(signature = (show_features=false))]
+    fn to_ascii(&self, show_features: bool) -> String {
+        self.inner.to_ascii(show_features)
+    }
+
+    /// Print the tree drawn by `to_ascii`
+    #[pyo3(signature = (show_features=false))]
+    fn display(&self, py: Python, show_features: bool) -> PyResult<()> {
+        let text = self.inner.to_ascii(show_features);
+        py.import("builtins")?
+            .getattr("print")?
+            .call1((text.trim_end(),))?;
+        Ok(())
+    }
+
+    /// Convert the tree to a networkx DiGraph (requires networkx)
+    ///
+    /// Nodes are word ids with `form`, `lemma`, `upos`, `xpos`, `deprel` and
+    /// `feats` attributes; edges run from head to dependent with a `deprel`
+    /// attribute.
+    fn to_networkx<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.networkx_graph(py, "DiGraph", "to_networkx")
+    }
+
+    /// Like `to_networkx`, but returns an undirected networkx Graph
+    fn to_networkx_undirected<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        self.networkx_graph(py, "Graph", "to_networkx_undirected")
+    }
+
+    /// Multiword tokens as dicts with `start`, `end` (token ids), `form` and `misc`
+    #[getter]
+    fn multiword_tokens<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let resolve =
+            |sym| String::from_utf8_lossy(&self.inner.string_pool.resolve(sym)).to_string();
+        self.inner
+            .multiword_tokens
+            .iter()
+            .map(|mwt| {
+                let misc: std::collections::HashMap<String, String> = mwt
+                    .misc
+                    .iter()
+                    .map(|&(k, v)| (resolve(k), resolve(v)))
+                    .collect();
+                let dict = PyDict::new(py);
+                dict.set_item("start", mwt.start)?;
+                dict.set_item("end", mwt.end)?;
+                dict.set_item("form", resolve(mwt.form))?;
+                dict.set_item("misc", misc)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Format a match dict from this tree as `{V=2 (runs/VERB), ...}`
+    fn format_match(&self, bindings: Bindings) -> String {
+        BindingsDisplay {
+            bindings: &bindings,
+            tree: &self.inner,
+        }
+        .to_string()
+    }
+
     fn __repr__(&self) -> String {
         let n = self.inner.words.len();
         if n == 0 {
@@ -88,6 +341,39 @@ impl PyTree {
     }
 }
 
+impl PyTree {
+    /// Build an instance of the networkx graph class `class` for the tree
+    fn networkx_graph<'py>(
+        &self,
+        py: Python<'py>,
+        class: &str,
+        method: &str,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let networkx = import_optional(py, "networkx", method)?;
+        let graph = networkx.getattr(class)?.call0()?;
+        for word_id in 0..self.inner.words.len() {
+            let word = self.word(word_id)?;
+            let attrs = PyDict::new(py);
+            attrs.set_item("form", word.form())?;
+            attrs.set_item("lemma", word.lemma())?;
+            attrs.set_item("upos", word.upos())?;
+            attrs.set_item("xpos", word.xpos())?;
+            attrs.set_item("deprel", word.deprel())?;
+            attrs.set_item("feats", word.feats())?;
+            graph.call_method("add_node", (word_id,), Some(&attrs))?;
+        }
+        for word_id in 0..self.inner.words.len() {
+            let word = self.word(word_id)?;
+            if let Some(head) = word.head() {
+                let attrs = PyDict::new(py);
+                attrs.set_item("deprel", word.deprel())?;
+                graph.call_method("add_edge", (head, word_id), Some(&attrs))?;
+            }
+        }
+        Ok(graph)
+    }
+}
+
 #[pyclass(name = "Word")]
 pub struct PyWord {
     inner: RustWord,
@@ -106,6 +392,11 @@ impl PyWord {
         self.inner.token_id
     }
 
+    #[getter]
+    fn depth(&self) -> u16 {
+        self.inner.depth
+    }
+
     #[getter]
     fn form(&self) -> String {
         String::from_utf8_lossy(&self.tree.string_pool.resolve(self.inner.form)).to_string()
@@ -169,6 +460,21 @@ impl PyWord {
             .collect()
     }
 
+    /// Enhanced dependencies as `(head, deprel)` pairs; `head` is `None` for the root
+    #[getter]
+    fn enhanced_deps(&self) -> Vec<(Option<usize>, String)> {
+        self.inner
+            .enhanced_deps
+            .iter()
+            .map(|dep| {
+                (
+                    dep.head,
+                    String::from_utf8_lossy(&self.tree.string_pool.resolve(dep.deprel)).to_string(),
+                )
+            })
+            .collect()
+    }
+
     fn parent(&self) -> Option<PyWord> {
         self.inner.parent(&self.tree).map(|word| PyWord {
             inner: word.clone(),
@@ -181,6 +487,18 @@ impl PyWord {
         self.inner.children.clone()
     }
 
+    /// This word followed by its ancestors, ending at the root
+    fn path_to_root(&self) -> Vec<PyWord> {
+        self.tree
+            .path_to_root(self.inner.id)
+            .into_iter()
+            .map(|id| PyWord {
+                inner: self.tree.words[id].clone(),
+                tree: Arc::clone(&self.tree),
+            })
+            .collect()
+    }
+
     fn children(&self) -> Vec<PyWord> {
         self.inner
             .children(&self.tree)
@@ -203,6 +521,30 @@ impl PyWord {
             .collect()
     }
 
+    #[getter]
+    fn left_siblings(&self) -> Vec<PyWord> {
+        self.inner
+            .left_siblings(&self.tree)
+            .into_iter()
+            .map(|word| PyWord {
+                inner: word.clone(),
+                tree: Arc::clone(&self.tree),
+            })
+            .collect()
+    }
+
+    #[getter]
+    fn right_siblings(&self) -> Vec<PyWord> {
+        self.inner
+            .right_siblings(&self.tree)
+            .into_iter()
+            .map(|word| PyWord {
+                inner: word.clone(),
+                tree: Arc::clone(&self.tree),
+            })
+            .collect()
+    }
+
     // TODO: add xpos and head to these (but they're optional)
     fn __repr__(&self) -> String {
         format!(
@@ -214,6 +556,23 @@ impl PyWord {
             self.deprel()
         )
     }
+
+    /// Words are equal if they are the same position in the same tree object;
+    /// ordering compares linear position
+    fn __richcmp__(&self, other: PyRef<PyWord>, op: CompareOp) -> bool {
+        let same_tree = Arc::ptr_eq(&self.tree, &other.tree);
+        match op {
+            CompareOp::Eq => same_tree && self.inner.id == other.inner.id,
+            CompareOp::Ne => !same_tree || self.inner.id != other.inner.id,
+            _ => op.matches(self.inner.id.cmp(&other.inner.id)),
+        }
+    }
+
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (Arc::as_ptr(&self.tree) as usize, self.inner.id).hash(&mut hasher);
+        hasher.finish()
+    }
 }
 
 #[pyclass(name = "Pattern")]
@@ -224,9 +583,181 @@ pub struct PyPattern {
 
 #[pymethods]
 impl PyPattern {
+    /// ORDER BY keys as (variable, field, ascending) tuples
+    #[getter]
+    fn order_by(&self) -> Vec<(String, String, bool)> {
+        self.inner
+            .order_by
+            .iter()
+            .map(|key| {
+                (
+                    key.var_name.clone(),
+                    key.field.name().to_string(),
+                    key.ascending,
+                )
+            })
+            .collect()
+    }
+
+    /// Check whether the pattern has at least one match in a tree.
+    ///
+    /// Stops at the first match, so it's cheaper than searching when
+    /// the bindings aren't needed.
+    fn matches(&self, tree: &PyTree) -> bool {
+        tree_matches(&tree.inner, &self.inner)
+    }
+
+    /// Find the matches of the pattern in one tree.
+    ///
+    /// Example:
+    ///     >>> pattern.search(tree, order="leftmost")
+    ///     [{'V': 1, 'N': 2}, {'V': 4, 'N': 3}]
+    ///
+    /// Args:
+    ///     tree: Tree to search
+    ///     order: "unsorted" (solver order, the default), "leftmost" (by the
+    ///         leftmost bound word), "rightmost" (by the rightmost bound word,
+    ///         from the right) or a variable name (by that variable's word)
+    ///
+    /// Returns:
+    ///     List of matches, each a dict from variable name to word ID
+    ///
+    /// Raises:
+    ///     ValueError: If order is not one of the above
+    #[pyo3(signature = (tree, order="unsorted"))]
+    fn search(&self, tree: &PyTree, order: &str) -> PyResult<Vec<Bindings>> {
+        let order = match order {
+            "unsorted" => MatchOrder::Unsorted,
+            "leftmost" => MatchOrder::LeftmostFirst,
+            "rightmost" => MatchOrder::RightmostFirst,
+            var_name if self.has_var(var_name) => MatchOrder::ByVar(var_name.to_string()),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown order {:?}; expected \"unsorted\", \"leftmost\", \"rightmost\" or a variable name",
+                    order
+                )));
+            }
+        };
+        Ok(
+            search_tree((*tree.inner).clone(), &self.inner, None, &order)
+                .into_iter()
+                .map(|m| m.bindings)
+                .collect(),
+        )
+    }
+
+    /// Describe how the pattern would be searched in a tree.
+    ///
+    /// Lists each block's variables in search order with their candidate
+    /// counts, and when each edge constraint is checked. Useful for
+    /// understanding slow queries or unexpected results.
+    ///
+    /// Example:
+    ///     >>> print(compile_query('MATCH { V [lemma="help"]; N []; V -> N; }').explain(tree))
+    ///
+    /// Args:
+    ///     tree: Tree to plan the search for (domain sizes depend on it)
+    ///
+    /// Returns:
+    ///     The plan as multi-line text
+    fn explain(&self, tree: &PyTree) -> String {
+        self.inner.explain(&tree.inner)
+    }
+
+    /// Estimate how expensive the pattern is to search.
+    ///
+    /// Multiplies the expected number of candidate words per sentence for
+    /// each variable. Without a treebank the estimate uses fixed guesses
+    /// (exact lemma ≈ 1 word, upos ≈ 10% of words, no constraint = every
+    /// word); with one, its tag and relation frequencies are read first.
+    ///
+    /// Example:
+    ///     >>> print(pattern.complexity())
+    ///     {'n_vars': 2, 'domain_size_product': 2.5, 'n_edge_checks': 1, 'warning': None}
+    ///
+    /// Args:
+    ///     treebank: Treebank to take statistics from (read once in full)
+    ///
+    /// Returns:
+    ///     Dict with n_vars, domain_size_product (candidate combinations per
+    ///     sentence), n_edge_checks and warning (a message if the search
+    ///     looks expensive, otherwise None)
+    #[pyo3(signature = (treebank=None))]
+    fn complexity<'py>(
+        &self,
+        py: Python<'py>,
+        treebank: Option<&PyTreebank>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let stats = match treebank {
+            Some(treebank) => Some(py.detach(|| TreebankStats::collect(treebank.inner.clone()))?),
+            None => None,
+        };
+        let bound = self.inner.estimated_complexity(stats.as_ref());
+        let dict = PyDict::new(py);
+        dict.set_item("n_vars", bound.n_vars)?;
+        dict.set_item("domain_size_product", bound.domain_size_product)?;
+        dict.set_item("n_edge_checks", bound.n_edge_checks)?;
+        dict.set_item("warning", bound.to_warning())?;
+        Ok(dict)
+    }
+
     fn __repr__(&self) -> String {
         format!("Pattern({} vars)", self.inner.match_pattern.n_vars)
     }
+
+    /// Pickle as a call to compile_query with the original query string
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(Bound<'py, PyAny>, (String,))> {
+        let query = self
+            .inner
+            .source_query
+            .clone()
+            .ok_or_else(|| PyValueError::new_err("pattern has no source query"))?;
+        let compile = py
+            .import("treesearch.treesearch")?
+            .getattr("compile_query")?;
+        Ok((compile, (query,)))
+    }
+
+    /// Serialize the pattern as JSON, for saving or sharing.
+    ///
+    /// The JSON holds the original query string: {"query": "..."}.
+    ///
+    /// Returns:
+    ///     JSON text that Pattern.from_json() reads back
+    fn to_json(&self) -> PyResult<String> {
+        self.inner
+            .to_json()
+            .ok_or_else(|| PyValueError::new_err("pattern has no source query"))
+    }
+
+    /// Recompile a pattern saved with Pattern.to_json().
+    ///
+    /// Raises:
+    ///     ValueError: If the JSON is malformed or the query doesn't compile
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<PyPattern> {
+        RustPattern::from_json(json)
+            .map(|inner| PyPattern { inner })
+            .map_err(|e| PyValueError::new_err(format!("Query parse error: {}", e)))
+    }
+}
+
+impl PyPattern {
+    /// Names of the MATCH and OPTIONAL variables (those that can be bound), sorted
+    fn var_names(&self) -> Vec<String> {
+        let names: BTreeSet<&String> = std::iter::once(&self.inner.match_pattern)
+            .chain(&self.inner.optional_patterns)
+            .flat_map(|base| &base.var_names)
+            .collect();
+        names.into_iter().cloned().collect()
+    }
+
+    /// Whether a MATCH or OPTIONAL variable has this name (so it can be bound)
+    fn has_var(&self, var_name: &str) -> bool {
+        std::iter::once(&self.inner.match_pattern)
+            .chain(&self.inner.optional_patterns)
+            .any(|base| base.var_ids.contains_key(var_name))
+    }
 }
 
 /// Wrapper that accepts either a query string or compiled Pattern
@@ -245,6 +776,41 @@ impl QueryArg {
     }
 }
 
+/// Build a DOT style from the Python keyword arguments
+fn dot_style(show_lemma: bool, show_xpos: bool, direction: &str) -> PyResult<DotStyle> {
+    let direction = match direction {
+        "TB" => LayoutDirection::TopToBottom,
+        "LR" => LayoutDirection::LeftToRight,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "direction must be \"TB\" or \"LR\", not {:?}",
+                direction
+            )));
+        }
+    };
+    Ok(DotStyle {
+        show_lemma,
+        show_xpos,
+        direction,
+    })
+}
+
+/// Seed for sampling when the caller doesn't give one
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// Emit a UserWarning if a glob found no files
+fn warn_if_no_files(py: Python, treebank: &Treebank, pattern: &str) -> PyResult<()> {
+    if treebank.file_paths().is_some_and(|paths| paths.is_empty()) {
+        let message = CString::new(format!("no files match {:?}", pattern))?;
+        PyErr::warn(py, &py.get_type::<PyUserWarning>(), &message, 1)?;
+    }
+    Ok(())
+}
+
 /// A compiled query pattern for tree matching.
 ///
 /// Created by parse_query() and used with search functions. Patterns are
@@ -299,6 +865,47 @@ impl PyTreebank {
         }
     }
 
+    /// Open a binary treebank file written by save_binary().
+    ///
+    /// Args:
+    ///     path: Path to the binary file
+    ///
+    /// Returns:
+    ///     Treebank instance
+    ///
+    /// Raises:
+    ///     OSError: If the file cannot be read, is not a binary treebank, or
+    ///         has an unsupported format version
+    #[classmethod]
+    fn from_binary(_cls: &Bound<'_, pyo3::types::PyType>, path: PathBuf) -> PyResult<Self> {
+        Ok(PyTreebank {
+            inner: Treebank::from_binary(path)?,
+        })
+    }
+
+    /// Open a binary cache of a CoNLL-U file, rebuilding it if it is missing or stale.
+    ///
+    /// Args:
+    ///     conllu_path: CoNLL-U file (plain or gzipped)
+    ///     bin_path: Binary cache, used if it is at least as new as conllu_path
+    ///
+    /// Returns:
+    ///     Treebank instance backed by the binary cache
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read or written
+    ///     ValueError: If a tree cannot be parsed
+    #[classmethod]
+    fn load_or_build(
+        _cls: &Bound<'_, pyo3::types::PyType>,
+        py: Python,
+        conllu_path: PathBuf,
+        bin_path: PathBuf,
+    ) -> PyResult<Self> {
+        let inner = py.detach(|| Treebank::load_or_build(&conllu_path, &bin_path))?;
+        Ok(PyTreebank { inner })
+    }
+
     /// Create a Treebank from multiple file paths.
     ///
     /// Args:
@@ -331,12 +938,48 @@ impl PyTreebank {
     ///
     /// Raises:
     ///     ValueError: If glob pattern is invalid
-    // #[classmethod]
-    // fn from_glob(_cls: &Bound<'_, pyo3::types::PyType>, pattern: &str) -> PyResult<Self> {
-    //     Treebank::from_glob(pattern)
-    //         .map(|inner| PyTreebank { inner })
-    //         .map_err(|e| PyValueError::new_err(format!("Glob pattern error: {}", e)))
-    // }
+    ///
+    /// Warns:
+    ///     UserWarning: If no files match
+    #[classmethod]
+    fn from_glob(cls: &Bound<'_, pyo3::types::PyType>, pattern: &str) -> PyResult<Self> {
+        let inner = Treebank::from_glob(pattern).map_err(|e| {
+            PyValueError::new_err(format!("Invalid glob pattern {:?}: {}", pattern, e))
+        })?;
+        warn_if_no_files(cls.py(), &inner, pattern)?;
+        Ok(PyTreebank { inner })
+    }
+
+    /// Create a Treebank from the files in a directory.
+    ///
+    /// Args:
+    ///     dir: Directory to search
+    ///     recursive: Also search subdirectories (default False)
+    ///     pattern: Glob pattern for file names (default "*.conllu")
+    ///
+    /// Returns:
+    ///     Treebank instance
+    ///
+    /// Raises:
+    ///     ValueError: If the file name pattern is invalid
+    ///
+    /// Warns:
+    ///     UserWarning: If no files match
+    #[classmethod]
+    #[pyo3(signature = (dir, recursive=false, pattern="*.conllu"))]
+    fn from_directory(
+        cls: &Bound<'_, pyo3::types::PyType>,
+        dir: &str,
+        recursive: bool,
+        pattern: &str,
+    ) -> PyResult<Self> {
+        let inner = Treebank::from_directory(dir, recursive, pattern).map_err(|e| {
+            PyValueError::new_err(format!("Invalid glob pattern {:?}: {}", pattern, e))
+        })?;
+        warn_if_no_files(cls.py(), &inner, &format!("{}/{}", dir, pattern))?;
+        Ok(PyTreebank { inner })
+    }
+
     /// Iterate over all trees in the treebank.
     ///
     /// Can be called multiple times. Uses automatic parallel processing
@@ -367,18 +1010,43 @@ impl PyTreebank {
         }
     }
 
-    /// Search for pattern matches across all trees.
-    ///
-    /// Can be called multiple times. Uses automatic parallel processing
-    /// for multi-file treebanks.
+    /// Iterate over trees along with their 0-based sentence index.
     ///
     /// Args:
-    ///     pattern: Compiled pattern from compile_query() or a query string
-    ///     ordered: If True (default), matches are returned in deterministic order.
-    ///              If False, matches may arrive in any order for better performance.
+    ///     ordered: If True (default), trees are returned in deterministic order.
+    ///              If False, indices reflect the order trees happen to arrive in.
     ///
     /// Returns:
-    ///     Iterator over (tree, match) tuples
+    ///     Iterator over (index, Tree) tuples
+    ///
+    /// Example:
+    ///     >>> for idx, tree in tb.trees_with_index():
+    ///     ...     print(idx, tree.sentence_text)
+    #[pyo3(signature = (ordered=true))]
+    fn trees_with_index(&self, ordered: bool) -> PyIndexedTreeIterator {
+        PyIndexedTreeIterator {
+            inner: Box::new(
+                self.inner
+                    .clone()
+                    .iter_with_index(ordered)
+                    .map(|(idx, result)| (idx, result.map(Arc::new))),
+            ),
+        }
+    }
+
+    /// Search for pattern matches across all trees.
+    ///
+    /// Can be called multiple times. Uses automatic parallel processing
+    /// for multi-file treebanks.
+    ///
+    /// Args:
+    ///     pattern: Compiled pattern from compile_query() or a query string
+    ///     ordered: If True (default), matches are returned in deterministic order.
+    ///              If False, matches may arrive in any order for better performance.
+    ///     max_matches: If given, return at most this many matches from each tree.
+    ///
+    /// Returns:
+    ///     Iterator over (tree, match) tuples
     ///
     /// Example:
     ///     >>> tb = Treebank.from_glob("data/*.conllu")
@@ -389,16 +1057,26 @@ impl PyTreebank {
     ///     >>> # Or use query string directly:
     ///     >>> for tree, match in tb.search("MATCH { V [upos='VERB']; }"):
     ///     ...     print(match)
-    #[pyo3(signature = (pattern, ordered=true))]
-    fn search(&self, pattern: QueryArg, ordered: bool) -> PyResult<PyMatchIterator> {
+    #[pyo3(signature = (pattern, ordered=true, max_matches=None))]
+    fn search(
+        &self,
+        pattern: QueryArg,
+        ordered: bool,
+        max_matches: Option<usize>,
+    ) -> PyResult<PyMatchIterator> {
         let compiled = pattern.into_pattern()?;
+        let mut treebank = self.inner.clone();
+        if let Some(max_matches) = max_matches {
+            treebank = treebank.with_max_matches(max_matches);
+        }
+        let var_names = compiled.var_names();
         Ok(PyMatchIterator {
             inner: Box::new(
-                self.inner
-                    .clone()
+                treebank
                     .match_iter(compiled.inner, ordered)
                     .map(|result| result.map(|m| (m.tree, m.bindings))),
             ),
+            var_names,
         })
     }
 
@@ -421,25 +1099,582 @@ impl PyTreebank {
     ///     >>> pattern = compile_query("MATCH { V [upos='VERB']; }")
     ///     >>> for tree in tb.filter(pattern):
     ///     ...     print(tree.sentence_text)
+    ///
+    /// A callable instead of a pattern returns a FilteredTreebank holding the
+    /// trees for which it returns True:
+    ///     >>> long = tb.filter(lambda t: len(t) > 5)
+    ///     >>> for tree, match in long.search(pattern):
+    ///     ...     print(match)
     #[pyo3(signature = (pattern, ordered=true))]
-    fn filter(&self, pattern: QueryArg, ordered: bool) -> PyResult<PyTreeIterator> {
-        let compiled = pattern.into_pattern()?;
-        Ok(PyTreeIterator {
+    fn filter<'py>(
+        &self,
+        pattern: &Bound<'py, PyAny>,
+        ordered: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = pattern.py();
+        if pattern.is_callable() {
+            let predicate = TreeFilter::Predicate(Arc::new(pattern.clone().unbind()));
+            return PyFilteredTreebank::new(self.inner.clone(), predicate).into_bound_py_any(py);
+        }
+        let compiled = pattern.extract::<QueryArg>()?.into_pattern()?;
+        PyTreeIterator {
             inner: Box::new(
                 self.inner
                     .clone()
                     .filter(compiled.inner, ordered)
                     .map(|result| result.map(Arc::new)),
             ),
+        }
+        .into_bound_py_any(py)
+    }
+
+    /// Keep the trees with between `min` and `max` words (inclusive).
+    ///
+    /// Returns:
+    ///     FilteredTreebank
+    #[pyo3(signature = (min=0, max=None))]
+    fn filter_by_length(&self, min: usize, max: Option<usize>) -> PyFilteredTreebank {
+        PyFilteredTreebank::new(self.inner.clone(), TreeFilter::Length { min, max })
+    }
+
+    /// Keep the trees whose metadata has `key` set to `value`.
+    ///
+    /// Returns:
+    ///     FilteredTreebank
+    fn filter_by_metadata(&self, key: String, value: String) -> PyFilteredTreebank {
+        PyFilteredTreebank::new(self.inner.clone(), TreeFilter::Metadata { key, value })
+    }
+
+    /// Check whether any tree matches a pattern.
+    ///
+    /// Stops at the first matching tree.
+    ///
+    /// Args:
+    ///     pattern: Compiled pattern from compile_query() or a query string
+    ///
+    /// Returns:
+    ///     True if some tree has a match
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read before a match is found
+    fn any_match(&self, py: Python, pattern: QueryArg) -> PyResult<bool> {
+        let compiled = pattern.into_pattern()?;
+        Ok(py.detach(|| self.inner.clone().any_match(compiled.inner))?)
+    }
+
+    /// Count the trees in the treebank without keeping them.
+    ///
+    /// Returns:
+    ///     Number of trees
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    ///     ValueError: If a tree cannot be parsed
+    fn count(&self, py: Python) -> PyResult<usize> {
+        Ok(py.detach(|| self.inner.clone().count_trees())?)
+    }
+
+    /// Count the trees that match a pattern (each tree at most once).
+    ///
+    /// Args:
+    ///     pattern: Compiled Pattern or query string
+    ///
+    /// Returns:
+    ///     Number of trees with at least one match
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    ///     ValueError: If a tree cannot be parsed
+    fn count_matching_sentences(&self, py: Python, pattern: QueryArg) -> PyResult<usize> {
+        let compiled = pattern.into_pattern()?;
+        Ok(py.detach(|| self.inner.clone().count_matching_trees(compiled.inner))?)
+    }
+
+    /// Matches of a pattern per million words of the treebank.
+    ///
+    /// The standard normalized frequency for comparing corpora of different sizes.
+    ///
+    /// Args:
+    ///     pattern: Compiled Pattern or query string
+    ///
+    /// Returns:
+    ///     Number of matches per million words (0.0 for an empty treebank)
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    ///     ValueError: If a tree cannot be parsed
+    fn frequency_per_million(&self, py: Python, pattern: QueryArg) -> PyResult<f64> {
+        let compiled = pattern.into_pattern()?;
+        Ok(py.detach(|| self.inner.clone().frequency_per_million(&compiled.inner))?)
+    }
+
+    /// Search for several patterns in a single pass over the treebank.
+    ///
+    /// Each tree is read once and searched with every pattern, which is much
+    /// faster than one search() per pattern on large treebanks.
+    ///
+    /// Example:
+    ///     >>> results = tb.multi_search({"passive": passive, "ditrans": ditransitive})
+    ///     >>> len(results["passive"])
+    ///
+    /// Args:
+    ///     patterns: Dict from names to compiled Patterns or query strings
+    ///
+    /// Returns:
+    ///     Dict from each name to its (tree, match_dict) tuples, in treebank order
+    ///
+    /// Raises:
+    ///     ValueError: If a query is invalid or a tree cannot be parsed
+    ///     OSError: If a file cannot be read
+    fn multi_search(
+        &self,
+        py: Python,
+        patterns: std::collections::HashMap<String, QueryArg>,
+    ) -> PyResult<std::collections::HashMap<String, Vec<(PyTree, Bindings)>>> {
+        let compiled = patterns
+            .into_iter()
+            .map(|(name, pattern)| Ok((name, pattern.into_pattern()?.inner)))
+            .collect::<PyResult<Vec<_>>>()?;
+        let results = py.detach(|| self.inner.clone().multi_search(compiled))?;
+        Ok(results
+            .into_iter()
+            .map(|(name, matches)| {
+                let matches = matches
+                    .into_iter()
+                    .map(|m| (PyTree { inner: m.tree }, m.bindings))
+                    .collect();
+                (name, matches)
+            })
+            .collect())
+    }
+
+    /// Count matches grouped by a field of one variable's word.
+    ///
+    /// Example:
+    ///     >>> tb.count_by('MATCH { V [upos="VERB"]; S []; V -[nsubj]-> S; }', group_by="V")
+    ///     {'run': 12, 'see': 7, ...}
+    ///
+    /// Args:
+    ///     pattern: Compiled Pattern or query string
+    ///     group_by: Name of the variable to group by
+    ///     field: Word field to group on (form, lemma, upos, xpos, deprel or token_id)
+    ///
+    /// Returns:
+    ///     Dict from field value to number of matches
+    ///
+    /// Raises:
+    ///     ValueError: For an unknown variable or field, or if a tree cannot be parsed
+    ///     OSError: If a file cannot be read
+    #[pyo3(signature = (pattern, group_by, field="lemma"))]
+    fn count_by(
+        &self,
+        py: Python,
+        pattern: QueryArg,
+        group_by: &str,
+        field: &str,
+    ) -> PyResult<std::collections::HashMap<String, usize>> {
+        let compiled = pattern.into_pattern()?;
+        let field = parse_field(field)?;
+        if !compiled.has_var(group_by) {
+            return Err(PyValueError::new_err(format!(
+                "Unknown variable {:?} in pattern",
+                group_by
+            )));
+        }
+        let counts =
+            py.detach(|| group_and_count(self.inner.clone(), compiled.inner, group_by, field))?;
+        // Values that only differ in invalid UTF-8 decode to the same key, so add them up
+        let mut decoded = std::collections::HashMap::new();
+        for (value, count) in counts {
+            *decoded
+                .entry(String::from_utf8_lossy(&value).into_owned())
+                .or_default() += count;
+        }
+        Ok(decoded)
+    }
+
+    /// Concordance (KWIC) lines for the matches of a pattern.
+    ///
+    /// Each line shows the anchor word with `context` words on either side, the
+    /// left context right-aligned to the widest one so the keywords line up.
+    /// Matched words are in brackets.
+    ///
+    /// Example:
+    ///     >>> for line in tb.kwic('MATCH { V [lemma="run"]; }', anchor="V", context=3):
+    ///     ...     print(line)
+    ///
+    /// Args:
+    ///     pattern: Compiled Pattern or query string
+    ///     anchor: Variable whose word is the keyword
+    ///     context: Number of words shown on each side (default: 5)
+    ///     ordered: Keep treebank order (default True); ignored when sorting
+    ///     sort: Sort the lines by "keyword", "left"/"right" (the adjacent word) or
+    ///         "leftN"/"rightN" (the Nth word away), ignoring case
+    ///
+    /// Returns:
+    ///     List of concordance lines
+    ///
+    /// Raises:
+    ///     ValueError: For an unknown sort key, or if a tree cannot be parsed
+    ///     OSError: If a file cannot be read
+    #[pyo3(signature = (pattern, anchor, context=5, ordered=true, sort=None))]
+    fn kwic(
+        &self,
+        py: Python,
+        pattern: QueryArg,
+        anchor: &str,
+        context: usize,
+        ordered: bool,
+        sort: Option<&str>,
+    ) -> PyResult<Vec<String>> {
+        let compiled = pattern.into_pattern()?;
+        let sort_key = sort.map(parse_sort_key).transpose()?;
+        let mut lines = py.detach(|| {
+            self.inner
+                .clone()
+                .kwic(compiled.inner, anchor, context, ordered)
+                .collect::<Result<Vec<KwicLine>, _>>()
+        })?;
+        if let Some(key) = sort_key {
+            sort_concordance(&mut lines, key);
+        }
+        Ok(format_concordance(&lines))
+    }
+
+    /// Summarize the treebank in a single pass.
+    ///
+    /// Returns:
+    ///     Dict with n_sentences, n_words, mean_sentence_length, max_sentence_length,
+    ///     upos_frequencies and deprel_frequencies (dicts of counts),
+    ///     mean_dependency_distance, n_nonprojective, vocabulary_size and n_unique_lemmas
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    ///     ValueError: If a tree cannot be parsed
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = py.detach(|| TreebankStats::collect(self.inner.clone()))?;
+        let frequencies = |freqs: &std::collections::HashMap<Vec<u8>, usize>| {
+            freqs
+                .iter()
+                .map(|(value, &count)| (String::from_utf8_lossy(value).to_string(), count))
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("n_sentences", stats.n_sentences)?;
+        dict.set_item("n_words", stats.n_words)?;
+        dict.set_item("mean_sentence_length", stats.mean_sentence_length)?;
+        dict.set_item("max_sentence_length", stats.max_sentence_length)?;
+        dict.set_item("upos_frequencies", frequencies(&stats.upos_frequencies))?;
+        dict.set_item("deprel_frequencies", frequencies(&stats.deprel_frequencies))?;
+        dict.set_item("mean_dependency_distance", stats.mean_dependency_distance)?;
+        dict.set_item("n_nonprojective", stats.n_nonprojective)?;
+        dict.set_item("vocabulary_size", stats.vocabulary_size)?;
+        dict.set_item("n_unique_lemmas", stats.n_unique_lemmas)?;
+        Ok(dict)
+    }
+
+    /// Draw a random sample of trees without loading the whole treebank.
+    ///
+    /// Uses reservoir sampling; the sampled trees keep their treebank order.
+    ///
+    /// Args:
+    ///     n: Number of trees to draw (all trees if there are fewer)
+    ///     seed: Random seed for a reproducible sample (default: random)
+    ///
+    /// Returns:
+    ///     In-memory Treebank holding the sample
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    ///     ValueError: If a tree cannot be parsed
+    #[pyo3(signature = (n, seed=None))]
+    fn sample(&self, py: Python, n: usize, seed: Option<u64>) -> PyResult<Self> {
+        let seed = seed.unwrap_or_else(random_seed);
+        let trees = py.detach(|| self.inner.clone().sample(n, seed))?;
+        let text: String = trees.iter().map(RustTree::to_conllu).collect();
+        Ok(PyTreebank {
+            inner: Treebank::from_string(&text),
         })
     }
 
+    /// Draw a random sample of the trees that match a pattern.
+    ///
+    /// Args:
+    ///     pattern: Compiled Pattern or query string
+    ///     n: Number of trees to draw (all matching trees if there are fewer)
+    ///     seed: Random seed for a reproducible sample (default: random)
+    ///
+    /// Returns:
+    ///     List of trees, in treebank order
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    ///     ValueError: If a tree cannot be parsed
+    #[pyo3(signature = (pattern, n, seed=None))]
+    fn sample_matching(
+        &self,
+        py: Python,
+        pattern: QueryArg,
+        n: usize,
+        seed: Option<u64>,
+    ) -> PyResult<Vec<PyTree>> {
+        let compiled = pattern.into_pattern()?;
+        let seed = seed.unwrap_or_else(random_seed);
+        let trees = py.detach(|| self.inner.clone().sample_matching(compiled.inner, n, seed))?;
+        Ok(trees
+            .into_iter()
+            .map(|tree| PyTree {
+                inner: Arc::new(tree),
+            })
+            .collect())
+    }
+
+    /// Write all trees to a CoNLL-U file, in treebank order.
+    ///
+    /// The file is gzip-compressed if the path ends in ".gz".
+    ///
+    /// Args:
+    ///     path: Output file path (overwritten if it exists)
+    ///
+    /// Returns:
+    ///     Number of trees written
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read or written
+    ///     ValueError: If a tree cannot be parsed
+    fn write_conllu(&self, py: Python, path: &str) -> PyResult<usize> {
+        let path = PathBuf::from(path);
+        Ok(py.detach(|| self.inner.clone().write_conllu(&path))?)
+    }
+
+    /// Write all trees to a binary treebank file, in treebank order.
+    ///
+    /// Binary files load faster than CoNLL-U because they need no parsing;
+    /// open them with Treebank.from_binary().
+    ///
+    /// Args:
+    ///     path: Output file path (overwritten if it exists)
+    ///
+    /// Returns:
+    ///     Number of trees written
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read or written
+    ///     ValueError: If a tree cannot be parsed
+    fn save_binary(&self, py: Python, path: PathBuf) -> PyResult<usize> {
+        Ok(py.detach(|| self.inner.clone().save_binary(&path))?)
+    }
+
+    /// Load all files into memory and return an in-memory Treebank.
+    ///
+    /// Useful when the same treebank will be iterated many times: the IO cost
+    /// is paid once here rather than on every iteration.
+    ///
+    /// Returns:
+    ///     Treebank instance backed by memory
+    ///
+    /// Raises:
+    ///     OSError: If a file cannot be read
+    fn to_memory(&self, py: Python) -> PyResult<Self> {
+        let inner = py.detach(|| self.inner.to_memory())?;
+        Ok(PyTreebank { inner })
+    }
+
+    /// Return a copy of this treebank with different batch and channel sizes.
+    ///
+    /// Args:
+    ///     match_batch: Number of results sent from worker threads at a time (default 500)
+    ///     channel_batches: Number of batches buffered before workers block (default 100)
+    ///
+    /// Returns:
+    ///     Treebank instance with the new buffer sizes
+    #[pyo3(signature = (match_batch=500, channel_batches=100))]
+    fn with_buffer_size(&self, match_batch: usize, channel_batches: usize) -> Self {
+        PyTreebank {
+            inner: self
+                .inner
+                .clone()
+                .with_buffer_size(match_batch, channel_batches),
+        }
+    }
+
     // TODO: make this more interesting (number of files? start of string?)
     fn __repr__(&self) -> String {
         "<Treebank>".to_string()
     }
 }
 
+/// Condition applied to each tree by a `FilteredTreebank`
+#[derive(Clone)]
+enum TreeFilter {
+    /// Python callable taking a Tree and returning a bool
+    Predicate(Arc<Py<PyAny>>),
+    Length {
+        min: usize,
+        max: Option<usize>,
+    },
+    Metadata {
+        key: String,
+        value: String,
+    },
+}
+
+/// First exception raised by a Python predicate during one iteration
+type PredicateError = Arc<Mutex<Option<PyErr>>>;
+
+/// Raise the exception a predicate raised, if any
+fn check_predicate_error(error: &PredicateError) -> PyResult<()> {
+    match error.lock().unwrap().take() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// A treebank restricted to the trees that pass a set of filters.
+///
+/// Created by Treebank.filter(callable), filter_by_length() and filter_by_metadata().
+/// The filters run as each tree is read, in the background threads and before any
+/// search, so rejected trees are never searched. Python predicates hold the GIL
+/// only while they are being called.
+#[pyclass(name = "FilteredTreebank")]
+#[derive(Clone)]
+pub struct PyFilteredTreebank {
+    treebank: Treebank,
+    filters: Vec<TreeFilter>,
+}
+
+impl PyFilteredTreebank {
+    fn new(treebank: Treebank, filter: TreeFilter) -> Self {
+        PyFilteredTreebank {
+            treebank,
+            filters: vec![filter],
+        }
+    }
+
+    fn with_filter(&self, filter: TreeFilter) -> Self {
+        let mut filtered = self.clone();
+        filtered.filters.push(filter);
+        filtered
+    }
+
+    /// The treebank with the filters added as tree stages, and the slot where a
+    /// failing predicate leaves its exception (a predicate that raises rejects
+    /// its tree, and no predicate is called after that)
+    fn filtered(&self) -> (Treebank, PredicateError) {
+        let error: PredicateError = Arc::new(Mutex::new(None));
+        let mut treebank = self.treebank.clone();
+        for filter in &self.filters {
+            treebank = match filter.clone() {
+                TreeFilter::Length { min, max } => treebank.filter_by_length(min, max),
+                TreeFilter::Metadata { key, value } => treebank.filter_by_metadata(&key, &value),
+                TreeFilter::Predicate(predicate) => {
+                    let error = Arc::clone(&error);
+                    treebank.filter_trees(move |tree| {
+                        Python::attach(|py| {
+                            if error.lock().unwrap().is_some() {
+                                return false;
+                            }
+                            let tree = PyTree {
+                                inner: Arc::new(tree.clone()),
+                            };
+                            match predicate
+                                .bind(py)
+                                .call1((tree,))
+                                .and_then(|r| r.is_truthy())
+                            {
+                                Ok(accepted) => accepted,
+                                Err(err) => {
+                                    error.lock().unwrap().get_or_insert(err);
+                                    false
+                                }
+                            }
+                        })
+                    })
+                }
+            };
+        }
+        (treebank, error)
+    }
+}
+
+#[pymethods]
+impl PyFilteredTreebank {
+    /// Iterate over the trees that pass the filters.
+    ///
+    /// Args:
+    ///     ordered: If True (default), trees are returned in deterministic order.
+    ///
+    /// Returns:
+    ///     Iterator over Tree objects
+    #[pyo3(signature = (ordered=true))]
+    fn trees(&self, ordered: bool) -> PyFilteredTreeIterator {
+        let (treebank, error) = self.filtered();
+        PyFilteredTreeIterator {
+            inner: Box::new(
+                treebank
+                    .tree_iter(ordered)
+                    .map(|result| result.map(Arc::new)),
+            ),
+            error,
+        }
+    }
+
+    fn __iter__(&self) -> PyFilteredTreeIterator {
+        self.trees(true)
+    }
+
+    /// Search the trees that pass the filters.
+    ///
+    /// Args:
+    ///     pattern: Compiled pattern from compile_query() or a query string
+    ///     ordered: If True (default), matches are returned in deterministic order.
+    ///     max_matches: If given, return at most this many matches from each tree.
+    ///
+    /// Returns:
+    ///     Iterator over (tree, match) tuples
+    #[pyo3(signature = (pattern, ordered=true, max_matches=None))]
+    fn search(
+        &self,
+        pattern: QueryArg,
+        ordered: bool,
+        max_matches: Option<usize>,
+    ) -> PyResult<PyFilteredMatchIterator> {
+        let compiled = pattern.into_pattern()?;
+        let (mut treebank, error) = self.filtered();
+        if let Some(max_matches) = max_matches {
+            treebank = treebank.with_max_matches(max_matches);
+        }
+        Ok(PyFilteredMatchIterator {
+            inner: Box::new(
+                treebank
+                    .match_iter(compiled.inner, ordered)
+                    .map(|result| result.map(|m| (m.tree, m.bindings))),
+            ),
+            error,
+        })
+    }
+
+    /// Add a predicate; trees must pass it as well as the existing filters.
+    fn filter(&self, predicate: Py<PyAny>) -> Self {
+        self.with_filter(TreeFilter::Predicate(Arc::new(predicate)))
+    }
+
+    /// Add a length filter (see Treebank.filter_by_length).
+    #[pyo3(signature = (min=0, max=None))]
+    fn filter_by_length(&self, min: usize, max: Option<usize>) -> Self {
+        self.with_filter(TreeFilter::Length { min, max })
+    }
+
+    /// Add a metadata filter (see Treebank.filter_by_metadata).
+    fn filter_by_metadata(&self, key: String, value: String) -> Self {
+        self.with_filter(TreeFilter::Metadata { key, value })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("<FilteredTreebank ({} filters)>", self.filters.len())
+    }
+}
+
 /// Iterator over trees from a treebank.
 ///
 /// Note: Marked as unsendable because iterators have mutable state and shouldn't
@@ -467,6 +1702,102 @@ impl PyTreeIterator {
     }
 }
 
+/// Iterator over the trees of a FilteredTreebank.
+#[pyclass(name = "FilteredTreeIterator", unsendable)]
+struct PyFilteredTreeIterator {
+    inner: Box<dyn Iterator<Item = Result<Arc<RustTree>, TreebankError>> + Send>,
+    error: PredicateError,
+}
+
+#[pymethods]
+impl PyFilteredTreeIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<PyTree>> {
+        let result = py.detach(|| self.inner.next());
+        check_predicate_error(&self.error)?;
+        match result {
+            Some(Ok(tree)) => Ok(Some(PyTree { inner: tree })),
+            Some(Err(e)) => Err(e.into()),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Iterator over the matches in the trees of a FilteredTreebank.
+#[pyclass(name = "FilteredMatchIterator", unsendable)]
+struct PyFilteredMatchIterator {
+    inner: Box<dyn Iterator<Item = PyMatchResult> + Send>,
+    error: PredicateError,
+}
+
+#[pymethods]
+impl PyFilteredMatchIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<(PyTree, Bindings)>> {
+        let result = py.detach(|| self.inner.next());
+        check_predicate_error(&self.error)?;
+        match result {
+            Some(Ok((tree, bindings))) => Ok(Some((PyTree { inner: tree }, bindings))),
+            Some(Err(e)) => Err(e.into()),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Iterator over the words of a tree, in linear order.
+#[pyclass(name = "WordIterator")]
+struct PyWordIterator {
+    inner: Arc<RustTree>,
+    pos: usize,
+}
+
+#[pymethods]
+impl PyWordIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<PyWord> {
+        let word = self.inner.words.get(self.pos)?;
+        self.pos += 1;
+        Some(PyWord {
+            inner: word.clone(),
+            tree: Arc::clone(&self.inner),
+        })
+    }
+}
+
+/// Iterator over (index, tree) tuples from a treebank.
+#[pyclass(name = "IndexedTreeIterator", unsendable)]
+struct PyIndexedTreeIterator {
+    inner: Box<dyn Iterator<Item = PyIndexedTreeResult> + Send>,
+}
+
+/// Item type produced by the iterator behind `PyIndexedTreeIterator`
+type PyIndexedTreeResult = (usize, Result<Arc<RustTree>, TreebankError>);
+
+#[pymethods]
+impl PyIndexedTreeIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<(usize, PyTree)>> {
+        let result = py.detach(|| self.inner.next());
+        match result {
+            Some((idx, Ok(tree))) => Ok(Some((idx, PyTree { inner: tree }))),
+            Some((_, Err(e))) => Err(e.into()),
+            None => Ok(None),
+        }
+    }
+}
+
 /// Iterator over (tree, match) tuples from a pattern search.
 ///
 /// Note: Marked as unsendable because iterators have mutable state and shouldn't
@@ -474,26 +1805,21 @@ impl PyTreeIterator {
 /// other Python threads to run in parallel.
 #[pyclass(name = "MatchIterator", unsendable)]
 struct PyMatchIterator {
-    inner: Box<
-        dyn Iterator<
-                Item = Result<
-                    (Arc<RustTree>, std::collections::HashMap<String, usize>),
-                    TreebankError,
-                >,
-            > + Send,
-    >,
+    inner: Box<dyn Iterator<Item = PyMatchResult> + Send>,
+    /// MATCH and OPTIONAL variables of the pattern, sorted (default export columns)
+    var_names: Vec<String>,
 }
 
+/// Item type produced by the iterator behind `PyMatchIterator`
+type PyMatchResult = Result<(Arc<RustTree>, Bindings), TreebankError>;
+
 #[pymethods]
 impl PyMatchIterator {
     fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
         slf
     }
 
-    fn __next__(
-        &mut self,
-        py: Python,
-    ) -> PyResult<Option<(PyTree, std::collections::HashMap<String, usize>)>> {
+    fn __next__(&mut self, py: Python) -> PyResult<Option<(PyTree, Bindings)>> {
         // Release GIL during expensive pattern matching
         let result = py.detach(|| self.inner.next());
         match result {
@@ -502,6 +1828,298 @@ impl PyMatchIterator {
             None => Ok(None),
         }
     }
+
+    /// Write the remaining matches to a JSON Lines file, one object per match.
+    ///
+    /// Each line holds the tree's sent_id and sentence_text and, for every
+    /// variable, an object with the chosen fields of the bound word.
+    ///
+    /// Args:
+    ///     path: Output file
+    ///     fields: Word fields to include (default: form, lemma, upos); any of
+    ///         form, lemma, upos, xpos, deprel, token_id
+    ///
+    /// Returns:
+    ///     Number of matches written
+    ///
+    /// Raises:
+    ///     ValueError: For an unknown field, or if a tree cannot be parsed
+    ///     OSError: If a file cannot be read or written
+    #[pyo3(name = "to_jsonl", signature = (path, fields=None))]
+    fn write_jsonl(
+        &mut self,
+        py: Python,
+        path: PathBuf,
+        fields: Option<Vec<String>>,
+    ) -> PyResult<usize> {
+        let fields = parse_fields(fields.unwrap_or_default())?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let count = py.detach(|| -> Result<usize, TreebankError> {
+            let mut error = None;
+            let matches = matches_until_error(&mut self.inner, &mut error);
+            let count = write_matches_jsonl(matches, &fields, &mut file)?;
+            std::io::Write::flush(&mut file)?;
+            error.map_or(Ok(count), Err)
+        })?;
+        Ok(count)
+    }
+
+    /// Write the remaining matches to a CSV (or TSV) file, one row per match.
+    ///
+    /// Example:
+    ///     >>> matches.to_csv("out.tsv", [("V.lemma", "V", "lemma"), ("N.form", "N", "form")], sep="\t")
+    ///
+    /// Args:
+    ///     path: Output file
+    ///     columns: (column_name, variable, field) triples; the names make up the
+    ///         header row. Unbound (OPTIONAL) variables give empty cells.
+    ///     sep: Single-character delimiter (default ",")
+    ///
+    /// Returns:
+    ///     Number of matches written
+    ///
+    /// Raises:
+    ///     ValueError: For an unknown field, a bad separator, or if a tree cannot be parsed
+    ///     OSError: If a file cannot be read or written
+    #[pyo3(name = "to_csv", signature = (path, columns, sep=","))]
+    fn write_csv(
+        &mut self,
+        py: Python,
+        path: PathBuf,
+        columns: Vec<(String, String, String)>,
+        sep: &str,
+    ) -> PyResult<usize> {
+        let delimiter = match sep.as_bytes() {
+            &[byte] if byte.is_ascii() && !matches!(byte, b'"' | b'\n' | b'\r') => byte,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "sep must be a single ASCII character, not {:?}",
+                    sep
+                )));
+            }
+        };
+        let fields = columns
+            .iter()
+            .map(|(_, _, field)| parse_field(field))
+            .collect::<PyResult<Vec<_>>>()?;
+        let columns: Vec<(&str, &str, FieldSelector)> = columns
+            .iter()
+            .zip(fields)
+            .map(|((name, var_name, _), field)| (name.as_str(), var_name.as_str(), field))
+            .collect();
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let count = py.detach(|| -> Result<usize, TreebankError> {
+            let mut error = None;
+            let matches = matches_until_error(&mut self.inner, &mut error);
+            let count = write_matches_csv(matches, &columns, &mut file, delimiter)?;
+            std::io::Write::flush(&mut file)?;
+            error.map_or(Ok(count), Err)
+        })?;
+        Ok(count)
+    }
+
+    /// Collect the remaining matches into a pyarrow RecordBatch, one row per match.
+    ///
+    /// Columns are built in Rust one field at a time, without a dict per match.
+    /// Requires pyarrow.
+    ///
+    /// Example:
+    ///     >>> batch = matches.to_arrow(["V.lemma", "N.form"])
+    ///
+    /// Args:
+    ///     schema: Columns as "variable.field" names (default: lemma and upos of
+    ///         every MATCH and OPTIONAL variable). token_id columns are int64,
+    ///         the rest strings; unbound (OPTIONAL) variables give nulls.
+    ///     batch_size: Take at most this many matches, so that repeated calls
+    ///         stream the results in batches (default: all remaining matches)
+    ///
+    /// Returns:
+    ///     pyarrow.RecordBatch with the columns in schema order
+    ///
+    /// Raises:
+    ///     ImportError: If pyarrow is not installed
+    ///     ValueError: For a malformed column name, a variable not in the pattern,
+    ///         or if a tree cannot be parsed
+    ///     OSError: If a file cannot be read
+    #[pyo3(name = "to_arrow", signature = (schema=None, batch_size=None))]
+    fn arrow_batch<'py>(
+        &mut self,
+        py: Python<'py>,
+        schema: Option<Vec<String>>,
+        batch_size: Option<usize>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let pyarrow = import_optional(py, "pyarrow", "to_arrow")?;
+        let columns = schema
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| parse_column(name))
+                    .collect::<PyResult<Vec<_>>>()
+            })
+            .transpose()?;
+        // Checked before taking any matches, so a typo doesn't use up the batch
+        if let Some((var_name, _)) = columns
+            .iter()
+            .flatten()
+            .find(|(var_name, _)| self.var_names.binary_search(var_name).is_err())
+        {
+            return Err(PyValueError::new_err(format!(
+                "Schema names variable {:?}, which is not in the pattern (variables: {})",
+                var_name,
+                self.var_names.join(", ")
+            )));
+        }
+        let matches = py.detach(|| -> Result<Vec<Match>, TreebankError> {
+            let mut error = None;
+            let matches: Vec<Match> = matches_until_error(&mut self.inner, &mut error)
+                .take(batch_size.unwrap_or(usize::MAX))
+                .collect();
+            error.map_or(Ok(matches), Err)
+        })?;
+        // Columns come from the pattern, not the batch, so every batch has the same schema
+        let columns = columns.unwrap_or_else(|| {
+            self.var_names
+                .iter()
+                .flat_map(|var_name| {
+                    [FieldSelector::Lemma, FieldSelector::UPOS]
+                        .map(|field| (var_name.clone(), field))
+                })
+                .collect()
+        });
+
+        let string_type = pyarrow.getattr("string")?.call0()?;
+        let int_type = pyarrow.getattr("int64")?.call0()?;
+        let names = PyList::empty(py);
+        let arrays = PyList::empty(py);
+        for (var_name, field) in &columns {
+            let bound_words = matches.iter().map(|m| {
+                m.bindings
+                    .get(var_name)
+                    .map(|&id| (&m.tree, &m.tree.words[id]))
+            });
+            let array = match field {
+                FieldSelector::TokenId => {
+                    let values: Vec<Option<usize>> = bound_words
+                        .map(|bound| bound.map(|(_, word)| word.token_id))
+                        .collect();
+                    pyarrow.call_method1("array", (values, &int_type))?
+                }
+                _ => {
+                    let values: Vec<Option<String>> = bound_words
+                        .map(|bound| bound.map(|(tree, word)| field_text(tree, word, *field)))
+                        .collect();
+                    pyarrow.call_method1("array", (values, &string_type))?
+                }
+            };
+            names.append(format!("{}.{}", var_name, field.name()))?;
+            arrays.append(array)?;
+        }
+        pyarrow
+            .getattr("RecordBatch")?
+            .call_method1("from_arrays", (arrays, names))
+    }
+
+    /// Collect the remaining matches into a polars DataFrame, one row per match.
+    ///
+    /// Takes the same arguments as to_arrow() and converts its RecordBatch.
+    /// Requires polars and pyarrow.
+    ///
+    /// Raises:
+    ///     ImportError: If polars or pyarrow is not installed
+    #[pyo3(name = "to_polars", signature = (schema=None, batch_size=None))]
+    fn polars_frame<'py>(
+        &mut self,
+        py: Python<'py>,
+        schema: Option<Vec<String>>,
+        batch_size: Option<usize>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let polars = import_optional(py, "polars", "to_polars")?;
+        let batch = self.arrow_batch(py, schema, batch_size)?;
+        polars.call_method1("from_arrow", (batch,))
+    }
+}
+
+/// Import a package that only some methods need, pointing to it if it is missing
+fn import_optional<'py>(
+    py: Python<'py>,
+    module: &str,
+    method: &str,
+) -> PyResult<Bound<'py, PyModule>> {
+    py.import(module).map_err(|e| {
+        if e.is_instance_of::<PyImportError>(py) {
+            PyImportError::new_err(format!(
+                "{}() requires {}; install it with `pip install {}`",
+                method, module, module
+            ))
+        } else {
+            e
+        }
+    })
+}
+
+/// Parse a "variable.field" column name, as in "V.lemma"
+fn parse_column(name: &str) -> PyResult<(String, FieldSelector)> {
+    let (var_name, field) = name.split_once('.').ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Column {:?} should be \"variable.field\", e.g. \"V.lemma\"",
+            name
+        ))
+    })?;
+    Ok((var_name.to_string(), parse_field(field)?))
+}
+
+/// Stream matches from a match iterator, stopping at (and keeping) the first error
+fn matches_until_error<'a>(
+    results: &'a mut (dyn Iterator<Item = PyMatchResult> + Send),
+    error: &'a mut Option<TreebankError>,
+) -> impl Iterator<Item = Match> + 'a {
+    results.map_while(move |result| match result {
+        Ok((tree, bindings)) => Some(Match { tree, bindings }),
+        Err(e) => {
+            *error = Some(e);
+            None
+        }
+    })
+}
+
+/// Parse a concordance sort key: "keyword", "left", "right", "left2", ...
+fn parse_sort_key(key: &str) -> PyResult<ConcordanceSortKey> {
+    let distance = |digits: &str| -> Option<usize> {
+        match digits {
+            "" => Some(1),
+            _ => digits.parse().ok().filter(|&n| n > 0),
+        }
+    };
+    let parsed = if key == "keyword" {
+        Some(ConcordanceSortKey::Keyword)
+    } else if let Some(digits) = key.strip_prefix("left") {
+        distance(digits).map(ConcordanceSortKey::LeftContext)
+    } else if let Some(digits) = key.strip_prefix("right") {
+        distance(digits).map(ConcordanceSortKey::RightContext)
+    } else {
+        None
+    };
+    parsed.ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unknown sort key {:?}; expected \"keyword\", \"left\", \"right\", \"leftN\" or \"rightN\"",
+            key
+        ))
+    })
+}
+
+/// Parse a field name given from Python
+fn parse_field(name: &str) -> PyResult<FieldSelector> {
+    FieldSelector::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unknown field {:?}; expected form, lemma, upos, xpos, deprel or token_id",
+            name
+        ))
+    })
+}
+
+/// Parse field names given from Python
+fn parse_fields(names: Vec<String>) -> PyResult<Vec<FieldSelector>> {
+    names.iter().map(|name| parse_field(name)).collect()
 }
 
 /// Search a list of trees for pattern matches.
@@ -527,14 +2145,20 @@ fn py_search_trees(trees: Vec<PyTree>, pattern: QueryArg) -> PyResult<PyMatchIte
         .into_iter()
         .flat_map(|tree| {
             let tree_arc = tree.inner.clone();
-            search_tree((*tree_arc).clone(), &compiled.inner)
-                .into_iter()
-                .map(move |m| Ok((tree_arc.clone(), m.bindings)))
+            search_tree(
+                (*tree_arc).clone(),
+                &compiled.inner,
+                None,
+                &MatchOrder::Unsorted,
+            )
+            .into_iter()
+            .map(move |m| Ok((tree_arc.clone(), m.bindings)))
         })
         .collect();
 
     Ok(PyMatchIterator {
         inner: Box::new(results.into_iter()),
+        var_names: compiled.var_names(),
     })
 }
 
@@ -635,7 +2259,12 @@ fn treesearch(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyWord>()?;
     m.add_class::<PyPattern>()?;
     m.add_class::<PyTreebank>()?;
+    m.add_class::<PyFilteredTreebank>()?;
+    m.add_class::<PyFilteredTreeIterator>()?;
+    m.add_class::<PyFilteredMatchIterator>()?;
     m.add_class::<PyTreeIterator>()?;
+    m.add_class::<PyWordIterator>()?;
+    m.add_class::<PyIndexedTreeIterator>()?;
     m.add_class::<PyMatchIterator>()?;
 
     m.add_function(wrap_pyfunction!(py_compile_query, m)?)?;
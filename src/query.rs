@@ -5,12 +5,15 @@
 use pest::Parser;
 use pest::iterators::Pair;
 use pest_derive::Parser;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use thiserror::Error;
 
 use crate::pattern::{
-    BasePattern, Constraint, ConstraintValue, EdgeConstraint, Pattern, PatternVar, RelationType,
+    BasePattern, Constraint, ConstraintValue, EdgeConstraint, FieldSelector, LabelMatcher,
+    OrderKey, Pattern, PatternVar, RelationType, SentenceConstraint, merge_constraints,
 };
+use crate::tree::push_json_string;
 use regex::Regex;
 
 #[derive(Parser)]
@@ -36,42 +39,364 @@ pub enum QueryError {
 
     #[error("Query error: Invalid regex pattern '{0}': {1}")]
     InvalidRegex(String, String),
+
+    #[error("Query error: Invalid number: {0}")]
+    InvalidNumber(String),
+
+    #[error("Query error: ORDER BY refers to unknown variable '{0}'")]
+    UnknownOrderVariable(String),
+
+    #[error("Query error: Invalid depth range: {0}")]
+    InvalidDepthRange(String),
+
+    #[error("Query error: Invalid distance: {0} (must be at least 1)")]
+    InvalidDistance(String),
+
+    #[error("Query error: Invalid quantified edge: {0}")]
+    InvalidQuantifier(String),
+
+    #[error("Query error: Invalid pattern JSON: {0}")]
+    InvalidJson(String),
+
+    #[error("Query error: Undefined macro: %{0}")]
+    UndefinedMacro(String),
+
+    #[error("Query error: Macro %{0} is defined more than once")]
+    DuplicateMacro(String),
 }
 
+/// Constraint lists named with `%define`, by name
+type Macros = HashMap<String, Constraint>;
+
 pub fn compile_query(input: &str) -> Result<Pattern, QueryError> {
+    let mut sentence_constraints: Vec<SentenceConstraint> = vec![];
     let mut match_pattern: Option<BasePattern> = None;
+    let mut within: Option<PatternVar> = None;
     let mut except_patterns: Vec<BasePattern> = vec![];
     let mut optional_patterns: Vec<BasePattern> = vec![];
+    let mut order_by: Vec<OrderKey> = vec![];
+    let mut macros = Macros::new();
 
     let mut pairs = QueryParser::parse(Rule::query, input)?;
     let query_pair = pairs.next().unwrap();
 
     for item in query_pair.into_inner() {
         match item.as_rule() {
-            Rule::match_block => match_pattern = Some(compile_query_block(item)?),
-            Rule::except_block => except_patterns.push(compile_query_block(item)?),
-            Rule::optional_block => optional_patterns.push(compile_query_block(item)?),
+            Rule::macro_def => {
+                // Macros can use the ones defined before them
+                let mut inner = item.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let constraint = compile_constraint_list(inner.next().unwrap(), &macros)?;
+                if macros.insert(name.clone(), constraint).is_some() {
+                    return Err(QueryError::DuplicateMacro(name));
+                }
+            }
+            Rule::sentence_block => sentence_constraints = compile_sentence_block(item)?,
+            Rule::match_block => match_pattern = Some(compile_query_block(item, &macros)?),
+            Rule::within_block => {
+                within = Some(compile_var_decl(
+                    item.into_inner().next().unwrap(),
+                    &macros,
+                )?)
+            }
+            Rule::except_block => except_patterns.push(compile_query_block(item, &macros)?),
+            Rule::optional_block => optional_patterns.push(compile_query_block(item, &macros)?),
+            Rule::order_by_clause => order_by = compile_order_by(item),
             Rule::EOI => {}
             _ => unreachable!(),
         }
     }
 
-    if let Some(match_pattern) = match_pattern {
+    if let Some(mut match_pattern) = match_pattern {
+        if let Some(root) = within {
+            match_pattern.add_within(root);
+        }
         // Validate that new variables in extension blocks are unique
         validate_unique_extension_variables(&match_pattern, &except_patterns, &optional_patterns)?;
+        // ORDER BY can only use variables that end up in the bindings
+        for key in &order_by {
+            let known = match_pattern.var_ids.contains_key(&key.var_name)
+                || optional_patterns
+                    .iter()
+                    .any(|optional| optional.var_ids.contains_key(&key.var_name));
+            if !known {
+                return Err(QueryError::UnknownOrderVariable(key.var_name.clone()));
+            }
+        }
         Ok(Pattern {
+            sentence_constraints,
             match_pattern,
             except_patterns,
             optional_patterns,
+            order_by,
+            source_query: Some(input.to_string()),
         })
     } else {
         Err(QueryError::NoMATCH)
     }
 }
 
-pub fn compile_query_block(item: Pair<Rule>) -> Result<BasePattern, QueryError> {
+impl Pattern {
+    /// Serialize the pattern as `{"query": "..."}`
+    ///
+    /// The pattern is stored as the query it was compiled from, so changes made to
+    /// the compiled pattern afterwards are not kept. Returns `None` for patterns
+    /// that were not built by [`compile_query`].
+    pub fn to_json(&self) -> Option<String> {
+        let query = self.source_query.as_ref()?;
+        let mut out = String::from("{\"query\": ");
+        push_json_string(&mut out, query);
+        out.push('}');
+        Some(out)
+    }
+
+    /// Recompile a pattern serialized by [`Pattern::to_json`]
+    ///
+    /// Keys other than `query` are ignored, but their values must be strings.
+    pub fn from_json(json: &str) -> Result<Pattern, QueryError> {
+        let fields = parse_json_string_object(json)
+            .ok_or_else(|| QueryError::InvalidJson("expected an object of strings".to_string()))?;
+        let query = fields
+            .into_iter()
+            .find_map(|(key, value)| (key == "query").then_some(value))
+            .ok_or_else(|| QueryError::InvalidJson("missing \"query\" key".to_string()))?;
+        compile_query(&query)
+    }
+}
+
+/// Parse a JSON object whose values are all strings into its key/value pairs
+fn parse_json_string_object(json: &str) -> Option<Vec<(String, String)>> {
+    let JsonValue::Object(fields) = parse_json(json)? else {
+        return None;
+    };
+    fields
+        .into_iter()
+        .map(|(key, value)| match value {
+            JsonValue::String(value) => Some((key, value)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A parsed JSON value; numbers are limited to the non-negative integers that
+/// [`Pattern::to_json`] and [`crate::tree::Tree::to_json`] write, and objects keep
+/// their key order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Number(usize),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Value of the first `key` in an object
+    pub(crate) fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields.iter().find_map(|(k, v)| (k == key).then_some(v)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_usize(&self) -> Option<usize> {
+        match self {
+            JsonValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            JsonValue::Object(fields) => Some(fields),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a JSON document (`None` if it is malformed or uses values `JsonValue` lacks)
+pub(crate) fn parse_json(json: &str) -> Option<JsonValue> {
+    let mut chars = json.chars().peekable();
+    let value = parse_json_value(&mut chars)?;
+    chars.next().is_none().then_some(value)
+}
+
+/// Parse a JSON value and the whitespace around it
+fn parse_json_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<JsonValue> {
+    let skip_ws = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    skip_ws(chars);
+    let value = match *chars.peek()? {
+        '"' => JsonValue::String(parse_json_string(chars)?),
+        'n' => {
+            for c in "null".chars() {
+                chars.next_if_eq(&c)?;
+            }
+            JsonValue::Null
+        }
+        '0'..='9' => {
+            let mut n: usize = 0;
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                n = n
+                    .checked_mul(10)?
+                    .checked_add(digit as usize - '0' as usize)?;
+            }
+            JsonValue::Number(n)
+        }
+        '[' => {
+            chars.next();
+            let mut items = Vec::new();
+            skip_ws(chars);
+            if chars.next_if_eq(&']').is_none() {
+                loop {
+                    items.push(parse_json_value(chars)?);
+                    match chars.next()? {
+                        ',' => continue,
+                        ']' => break,
+                        _ => return None,
+                    }
+                }
+            }
+            JsonValue::Array(items)
+        }
+        '{' => {
+            chars.next();
+            let mut fields = Vec::new();
+            skip_ws(chars);
+            if chars.next_if_eq(&'}').is_none() {
+                loop {
+                    skip_ws(chars);
+                    let key = parse_json_string(chars)?;
+                    skip_ws(chars);
+                    chars.next_if_eq(&':')?;
+                    fields.push((key, parse_json_value(chars)?));
+                    match chars.next()? {
+                        ',' => continue,
+                        '}' => break,
+                        _ => return None,
+                    }
+                }
+            }
+            JsonValue::Object(fields)
+        }
+        _ => return None,
+    };
+    skip_ws(chars);
+    Some(value)
+}
+
+/// Parse a JSON string literal, including its quotes
+fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    fn hex4(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<u32> {
+        let digits: String = chars.by_ref().take(4).collect();
+        if digits.len() != 4 {
+            return None;
+        }
+        u32::from_str_radix(&digits, 16).ok()
+    }
+
+    chars.next_if_eq(&'"')?;
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                '/' => out.push('/'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'u' => {
+                    let mut code = hex4(chars)?;
+                    if (0xD800..0xDC00).contains(&code) {
+                        // High surrogate: the low half follows as another escape
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = hex4(chars)?;
+                        if !(0xDC00..0xE000).contains(&low) {
+                            return None;
+                        }
+                        code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                    }
+                    out.push(char::from_u32(code)?);
+                }
+                _ => return None,
+            },
+            c if (c as u32) < 0x20 => return None,
+            c => out.push(c),
+        }
+    }
+}
+
+fn compile_sentence_block(pair: Pair<Rule>) -> Result<Vec<SentenceConstraint>, QueryError> {
+    pair.into_inner()
+        .map(|constraint| {
+            let inner = constraint.into_inner().next().unwrap();
+            let rule = inner.as_rule();
+            let mut parts = inner.into_inner();
+            match rule {
+                Rule::metadata_equals => {
+                    let key = parts.next().unwrap().as_str().to_string();
+                    let value = parts.next().unwrap().into_inner().as_str().to_string();
+                    Ok(SentenceConstraint::MetadataEquals(key, value))
+                }
+                Rule::text_contains => {
+                    let value = parts.next().unwrap().into_inner().as_str().to_string();
+                    Ok(SentenceConstraint::TextContains(value))
+                }
+                Rule::text_regex => {
+                    let pattern = parts.next().unwrap().into_inner().as_str().to_string();
+                    match Regex::new(&pattern) {
+                        Ok(regex) => Ok(SentenceConstraint::TextRegex(pattern, regex)),
+                        Err(e) => Err(QueryError::InvalidRegex(pattern, e.to_string())),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        })
+        .collect()
+}
+
+fn compile_order_by(pair: Pair<Rule>) -> Vec<OrderKey> {
+    pair.into_inner()
+        .map(|key| {
+            let mut inner = key.into_inner();
+            let var_name = inner.next().unwrap().as_str().to_string();
+            // The grammar only accepts known field names
+            let field = FieldSelector::from_name(inner.next().unwrap().as_str()).unwrap();
+            let ascending = inner.next().is_none_or(|dir| dir.as_str() == "ASC");
+            OrderKey {
+                var_name,
+                field,
+                ascending,
+            }
+        })
+        .collect()
+}
+
+pub fn compile_query_block(item: Pair<Rule>, macros: &Macros) -> Result<BasePattern, QueryError> {
     let mut vars: HashMap<String, PatternVar> = HashMap::new();
     let mut edges: Vec<EdgeConstraint> = Vec::new();
+    let mut child_counts: Vec<(String, Constraint)> = Vec::new();
 
     for statement in item.into_inner() {
         match statement.as_rule() {
@@ -79,12 +404,15 @@ pub fn compile_query_block(item: Pair<Rule>) -> Result<BasePattern, QueryError>
                 let inner = statement.into_inner().next().unwrap();
                 match inner.as_rule() {
                     Rule::node_decl => {
-                        let var = compile_var_decl(inner)?;
+                        let var = compile_var_decl(inner, macros)?;
                         if vars.contains_key(&var.var_name) {
                             return Err(QueryError::DuplicateVariable(var.var_name));
                         };
                         vars.insert(var.var_name.to_string(), var);
                     }
+                    Rule::quantified_edge_decl => {
+                        child_counts.push(compile_quantified_edge_decl(inner)?);
+                    }
                     Rule::edge_decl => {
                         let edge_constraint = compile_edge_decl(inner)?;
                         edges.push(edge_constraint);
@@ -93,6 +421,14 @@ pub fn compile_query_block(item: Pair<Rule>) -> Result<BasePattern, QueryError>
                         let edge_constraint = compile_precedence_constraint(inner)?;
                         edges.push(edge_constraint);
                     }
+                    Rule::sibling_decl => {
+                        let edge_constraint = compile_sibling_constraint(inner)?;
+                        edges.push(edge_constraint);
+                    }
+                    Rule::dominance_decl => {
+                        let edge_constraint = compile_dominance_constraint(inner)?;
+                        edges.push(edge_constraint);
+                    }
                     _ => unreachable!(),
                 }
             }
@@ -100,6 +436,16 @@ pub fn compile_query_block(item: Pair<Rule>) -> Result<BasePattern, QueryError>
         };
     }
 
+    // Child counts are constraints on the parent, which may be declared anywhere in the block
+    for (var_name, constraint) in child_counts {
+        match vars.get_mut(&var_name) {
+            Some(var) => var.constraint = merge_constraints(&var.constraint, &constraint),
+            None => {
+                vars.insert(var_name.clone(), PatternVar::new(&var_name, constraint));
+            }
+        }
+    }
+
     Ok(BasePattern::with_constraints(vars, edges))
 }
 
@@ -124,41 +470,118 @@ fn validate_unique_extension_variables(
     Ok(())
 }
 
-fn compile_var_decl(pair: Pair<Rule>) -> Result<PatternVar, QueryError> {
+fn compile_var_decl(pair: Pair<Rule>, macros: &Macros) -> Result<PatternVar, QueryError> {
     let mut inner = pair.into_inner();
 
     let ident_pair = inner.next().unwrap();
     let var_name = ident_pair.as_str().to_string();
     let constraint_list = inner.next().unwrap();
-    let constraints = compile_constraint_list(constraint_list)?;
+    let constraints = compile_constraint_list(constraint_list, macros)?;
 
     Ok(PatternVar::new(&var_name, constraints))
 }
 
-fn compile_constraint_list(pair: Pair<Rule>) -> Result<Constraint, QueryError> {
-    let constraints: Vec<Constraint> = pair
+fn compile_constraint_list(pair: Pair<Rule>, macros: &Macros) -> Result<Constraint, QueryError> {
+    match pair.into_inner().next() {
+        Some(expr) => compile_constraint_expr(expr, macros),
+        None => Ok(Constraint::Any),
+    }
+}
+
+/// Compile `a & b | c` into Or([And([a, b]), c]), dropping single-element wrappers
+fn compile_constraint_expr(pair: Pair<Rule>, macros: &Macros) -> Result<Constraint, QueryError> {
+    let mut alternatives: Vec<Constraint> = pair
         .into_inner()
-        .map(compile_constraint)
-        .collect::<Result<Vec<_>, _>>()?;
+        .map(|and_expr| {
+            let mut constraints: Vec<Constraint> = and_expr
+                .into_inner()
+                .map(|constraint| compile_constraint(constraint, macros))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(match constraints.len() {
+                1 => constraints.pop().unwrap(),
+                _ => Constraint::And(constraints),
+            })
+        })
+        .collect::<Result<Vec<_>, QueryError>>()?;
 
-    match constraints.len() {
-        0 => Ok(Constraint::Any),
-        1 => Ok(constraints.into_iter().next().unwrap()),
-        _ => Ok(Constraint::And(constraints)),
+    match alternatives.len() {
+        1 => Ok(alternatives.pop().unwrap()),
+        _ => Ok(Constraint::Or(alternatives)),
     }
 }
 
-fn compile_constraint(pair: Pair<Rule>) -> Result<Constraint, QueryError> {
+fn compile_constraint(pair: Pair<Rule>, macros: &Macros) -> Result<Constraint, QueryError> {
     let inner = pair.into_inner().next().unwrap();
 
     match inner.as_rule() {
+        Rule::constraint_group => {
+            compile_constraint_expr(inner.into_inner().next().unwrap(), macros)
+        }
+        Rule::macro_ref => {
+            let name = inner.into_inner().next().unwrap().as_str();
+            macros
+                .get(name)
+                .cloned()
+                .ok_or_else(|| QueryError::UndefinedMacro(name.to_string()))
+        }
+        Rule::subtree_constraint => compile_subtree_constraint(inner, macros),
+        Rule::structural_constraint => match inner.as_str() {
+            "IS_ROOT" => Ok(Constraint::IsRoot),
+            "IS_LEAF" => Ok(Constraint::IsLeaf),
+            _ => unreachable!(),
+        },
         Rule::feature_constraint => compile_feature_constraint(inner, Constraint::Feature),
         Rule::misc_constraint => compile_feature_constraint(inner, Constraint::Misc),
+        Rule::existence_constraint => compile_existence_constraint(inner),
+        Rule::numeric_constraint => compile_numeric_constraint(inner),
         Rule::regular_constraint => compile_regular_constraint(inner),
         _ => unreachable!(),
     }
 }
 
+/// Compile `CONTAINS c` or `!CONTAINS c`
+fn compile_subtree_constraint(pair: Pair<Rule>, macros: &Macros) -> Result<Constraint, QueryError> {
+    let mut inner = pair.into_inner().peekable();
+    let negated = inner
+        .next_if(|part| part.as_rule() == Rule::negation)
+        .is_some();
+    let constraint =
+        Constraint::SubtreeContains(Box::new(compile_constraint(inner.next().unwrap(), macros)?));
+    if negated {
+        Ok(Constraint::Not(Box::new(constraint)))
+    } else {
+        Ok(constraint)
+    }
+}
+
+fn compile_numeric_constraint(pair: Pair<Rule>) -> Result<Constraint, QueryError> {
+    let mut inner = pair.into_inner();
+
+    let key = inner.next().unwrap().as_str();
+    let operator = inner.next().unwrap().as_str();
+    let number = inner.next().unwrap().as_str();
+    let bound: usize = number
+        .parse()
+        .map_err(|_| QueryError::InvalidNumber(number.to_string()))?;
+
+    let make_constraint = match key {
+        "token_id" => Constraint::TokenId,
+        _ => return Err(QueryError::UnknownConstraintKey(key.to_string())),
+    };
+
+    // <=, >= and != are negations of >, < and =
+    let constraint = match operator {
+        "=" => make_constraint(Ordering::Equal, bound),
+        "<" => make_constraint(Ordering::Less, bound),
+        ">" => make_constraint(Ordering::Greater, bound),
+        "!=" => Constraint::Not(Box::new(make_constraint(Ordering::Equal, bound))),
+        "<=" => Constraint::Not(Box::new(make_constraint(Ordering::Greater, bound))),
+        ">=" => Constraint::Not(Box::new(make_constraint(Ordering::Less, bound))),
+        _ => unreachable!(),
+    };
+    Ok(constraint)
+}
+
 fn compile_feature_constraint<F>(
     pair: Pair<Rule>,
     make_constraint: F,
@@ -170,7 +593,7 @@ where
     let feature_key = inner.next().unwrap().as_str().to_string();
     let operator = inner.next().unwrap().as_str();
     let value_pair = inner.next().unwrap(); // constraint_value
-    let value = parse_constraint_value(value_pair)?;
+    let value = parse_constraint_value(value_pair, operator)?;
 
     let constraint = make_constraint(feature_key, value);
 
@@ -181,16 +604,67 @@ where
     }
 }
 
-fn parse_constraint_value(pair: Pair<Rule>) -> Result<ConstraintValue, QueryError> {
-    // pair is a constraint_value, which contains either string_literal or regex_literal
-    let inner = pair.into_inner().next().unwrap();
+fn compile_existence_constraint(pair: Pair<Rule>) -> Result<Constraint, QueryError> {
+    let mut negated = false;
+    let mut field = "";
+    let mut key = String::new();
+    for part in pair.into_inner() {
+        match part.as_rule() {
+            Rule::negation => negated = true,
+            Rule::existence_field => field = part.as_str(),
+            Rule::feature_key => key = part.as_str().to_string(),
+            _ => unreachable!(),
+        }
+    }
+
+    let constraint = match field {
+        "feats" => Constraint::HasFeature(key),
+        "misc" => Constraint::HasMisc(key),
+        _ => unreachable!(),
+    };
+
+    if negated {
+        Ok(Constraint::Not(Box::new(constraint)))
+    } else {
+        Ok(constraint)
+    }
+}
+
+fn parse_constraint_value(pair: Pair<Rule>, operator: &str) -> Result<ConstraintValue, QueryError> {
+    // pair is a constraint_value, which contains either string_literal or regex_literal,
+    // possibly followed by an /i flag
+    let mut parts = pair.into_inner();
+    let inner = parts.next().unwrap();
+    let case_insensitive = operator == "~=" || parts.next().is_some();
     let rule = inner.as_rule();
     let value_str = inner.into_inner().as_str().to_string();
 
+    // Prefix/suffix/substring operators wrap the value in ".*" when it goes through a regex
+    let (before, after) = match operator {
+        "^=" => ("", ".*"),
+        "$=" => (".*", ""),
+        "*=" => (".*", ".*"),
+        _ => ("", ""),
+    };
+
     match rule {
-        Rule::string_literal => Ok(ConstraintValue::Literal(value_str)),
+        Rule::string_literal if !case_insensitive => Ok(match operator {
+            "^=" => ConstraintValue::Prefix(value_str),
+            "$=" => ConstraintValue::Suffix(value_str),
+            "*=" => ConstraintValue::Contains(value_str),
+            _ => ConstraintValue::Literal(value_str),
+        }),
+        Rule::string_literal if before.is_empty() && after.is_empty() => {
+            Ok(ConstraintValue::CaseInsensitive(value_str))
+        }
+        Rule::string_literal => {
+            let pattern = format!("(?i)^{}{}{}$", before, regex::escape(&value_str), after);
+            let regex = Regex::new(&pattern).expect("escaped literal is a valid regex");
+            Ok(ConstraintValue::Regex(value_str, regex))
+        }
         Rule::regex_literal => {
-            let anchored_pattern = format!("^{}$", value_str);
+            let flags = if case_insensitive { "(?i)" } else { "" };
+            let anchored_pattern = format!("{}^{}(?:{}){}$", flags, before, value_str, after);
             match Regex::new(&anchored_pattern) {
                 Ok(regex) => Ok(ConstraintValue::Regex(value_str, regex)),
                 Err(e) => Err(QueryError::InvalidRegex(value_str, e.to_string())),
@@ -206,7 +680,7 @@ fn compile_regular_constraint(pair: Pair<Rule>) -> Result<Constraint, QueryError
     let key = inner.next().unwrap().as_str();
     let operator = inner.next().unwrap().as_str();
     let value_pair = inner.next().unwrap(); // constraint_value
-    let value = parse_constraint_value(value_pair)?;
+    let value = parse_constraint_value(value_pair, operator)?;
 
     let constraint = match key {
         "lemma" => Constraint::Lemma(value),
@@ -238,10 +712,7 @@ fn compile_edge_decl(pair: Pair<Rule>) -> Result<EdgeConstraint, QueryError> {
     let negated = matches!(op_rule, Rule::neg_labeled_edge | Rule::neg_unlabeled_edge);
 
     let label = if matches!(op_rule, Rule::neg_labeled_edge | Rule::labeled_edge) {
-        actual_op
-            .into_inner()
-            .next()
-            .map(|p| p.as_str().to_string())
+        actual_op.into_inner().next().map(compile_edge_labels)
     } else {
         None
     };
@@ -257,16 +728,116 @@ fn compile_edge_decl(pair: Pair<Rule>) -> Result<EdgeConstraint, QueryError> {
     })
 }
 
+/// Compile `nsubj` or `nsubj|expl` into a label matcher
+fn compile_edge_labels(pair: Pair<Rule>) -> LabelMatcher {
+    let mut labels: Vec<String> = pair.into_inner().map(|p| p.as_str().to_string()).collect();
+    if labels.len() == 1 {
+        LabelMatcher::Exact(labels.pop().unwrap())
+    } else {
+        LabelMatcher::AnyOf(labels)
+    }
+}
+
+/// Compile `V -[label]{min,max}-> _` into a child-count constraint on V
+fn compile_quantified_edge_decl(pair: Pair<Rule>) -> Result<(String, Constraint), QueryError> {
+    let text = pair.as_str().to_string();
+    let mut inner = pair.into_inner();
+
+    let from = inner.next().unwrap().as_str().to_string();
+    let edge = inner.next().unwrap();
+    let to = inner.next().unwrap().as_str();
+    if to != "_" {
+        return Err(QueryError::InvalidQuantifier(format!(
+            "{} (the target must be _)",
+            text.trim_end_matches(';')
+        )));
+    }
+
+    let mut label = None;
+    let (mut min, mut max) = (0, None);
+    for part in edge.into_inner() {
+        match part.as_rule() {
+            Rule::edge_labels => label = Some(compile_edge_labels(part)),
+            Rule::count_bound => {
+                let bound = part.into_inner().next().unwrap();
+                let rule = bound.as_rule();
+                let mut numbers = bound.into_inner().map(|n| {
+                    n.as_str()
+                        .parse::<usize>()
+                        .map_err(|_| QueryError::InvalidNumber(n.as_str().to_string()))
+                });
+                min = numbers.next().unwrap()?;
+                max = match rule {
+                    Rule::count_exact => Some(min),
+                    Rule::count_range => numbers.next().transpose()?,
+                    _ => unreachable!(),
+                };
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if max.is_some_and(|max| max < min) {
+        return Err(QueryError::InvalidQuantifier(text));
+    }
+
+    Ok((from, Constraint::ChildCount { label, min, max }))
+}
+
 fn compile_precedence_constraint(pair: Pair<Rule>) -> Result<EdgeConstraint, QueryError> {
     let mut inner = pair.into_inner();
 
+    let from = inner.next().unwrap().as_str().to_string();
+    let operator = inner.next().unwrap();
+    let to = inner.next().unwrap().as_str().to_string();
+
+    let full_op = operator.as_str();
+    let mut negated = false;
+    let mut distance = None;
+    for part in operator.into_inner() {
+        match part.as_rule() {
+            Rule::neg_precedence => negated = true,
+            Rule::distance => {
+                let number = part.as_str();
+                distance = match number.parse::<usize>() {
+                    Ok(0) => return Err(QueryError::InvalidDistance(full_op.to_string())),
+                    Ok(n) => Some(n),
+                    Err(_) => return Err(QueryError::InvalidNumber(number.to_string())),
+                };
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    let op_str = full_op.trim_start_matches('!');
+    let relation = match (op_str, distance) {
+        ("<<", _) => RelationType::Precedes,
+        ("<", None) => RelationType::ImmediatelyPrecedes,
+        (_, Some(n)) if op_str.starts_with('<') => RelationType::PrecedesWithin(n),
+        (_, Some(n)) => RelationType::WithinDistance(n),
+        (_, None) => unreachable!(),
+    };
+
+    Ok(EdgeConstraint {
+        from,
+        to,
+        relation,
+        label: None,
+        negated,
+    })
+}
+
+fn compile_sibling_constraint(pair: Pair<Rule>) -> Result<EdgeConstraint, QueryError> {
+    let mut inner = pair.into_inner();
+
     let from = inner.next().unwrap().as_str().to_string();
     let operator = inner.next().unwrap().as_str();
     let to = inner.next().unwrap().as_str().to_string();
 
     let relation = match operator {
-        "<<" => RelationType::Precedes,
-        "<" => RelationType::ImmediatelyPrecedes,
+        "$$" => RelationType::Sibling,
+        "$." => RelationType::ImmediateSibling,
+        "$+" => RelationType::NextSibling,
         _ => unreachable!(),
     };
 
@@ -279,9 +850,56 @@ fn compile_precedence_constraint(pair: Pair<Rule>) -> Result<EdgeConstraint, Que
     })
 }
 
+fn compile_dominance_constraint(pair: Pair<Rule>) -> Result<EdgeConstraint, QueryError> {
+    let mut inner = pair.into_inner();
+
+    let from = inner.next().unwrap().as_str().to_string();
+    let operator = inner.next().unwrap();
+    let to = inner.next().unwrap().as_str().to_string();
+
+    let op_str = operator.as_str();
+    let mut negated = false;
+    let (mut min, mut max) = (1, None);
+    for part in operator.into_inner() {
+        match part.as_rule() {
+            Rule::neg_dominance => negated = true,
+            Rule::depth_bound => {
+                let bound = part.into_inner().next().unwrap();
+                let rule = bound.as_rule();
+                let mut numbers = bound.into_inner().map(|n| {
+                    n.as_str()
+                        .parse::<usize>()
+                        .map_err(|_| QueryError::InvalidNumber(n.as_str().to_string()))
+                });
+                min = numbers.next().unwrap()?;
+                max = match rule {
+                    Rule::depth_exact => Some(min),
+                    Rule::depth_range => numbers.next().transpose()?,
+                    _ => unreachable!(),
+                };
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Depth 0 would be the node itself, which AllDifferent rules out anyway
+    if min == 0 || max.is_some_and(|max| max < min) {
+        return Err(QueryError::InvalidDepthRange(op_str.to_string()));
+    }
+
+    Ok(EdgeConstraint {
+        from,
+        to,
+        relation: RelationType::Descendant { min, max },
+        label: None,
+        negated,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pattern::VarId;
 
     #[test]
     fn test_parse_constraints() {
@@ -289,7 +907,10 @@ mod tests {
         let pattern = compile_query(query).unwrap();
 
         assert_eq!(pattern.match_pattern.var_constraints.len(), 1);
-        assert_eq!(*pattern.match_pattern.var_ids.get("Node").unwrap(), 0);
+        assert_eq!(
+            *pattern.match_pattern.var_ids.get("Node").unwrap(),
+            VarId(0)
+        );
         assert!(matches!(
             pattern.match_pattern.var_constraints[0],
             Constraint::Any
@@ -299,7 +920,10 @@ mod tests {
         let pattern = compile_query(query).unwrap();
 
         assert_eq!(pattern.match_pattern.var_constraints.len(), 1);
-        assert_eq!(*pattern.match_pattern.var_ids.get("Verb").unwrap(), 0);
+        assert_eq!(
+            *pattern.match_pattern.var_ids.get("Verb").unwrap(),
+            VarId(0)
+        );
         assert_eq!(
             pattern.match_pattern.var_constraints[0],
             Constraint::UPOS(ConstraintValue::Literal("VERB".to_string()))
@@ -309,7 +933,10 @@ mod tests {
         let pattern = compile_query(query).unwrap();
 
         assert_eq!(pattern.match_pattern.var_constraints.len(), 1);
-        assert_eq!(*pattern.match_pattern.var_ids.get("Help").unwrap(), 0);
+        assert_eq!(
+            *pattern.match_pattern.var_ids.get("Help").unwrap(),
+            VarId(0)
+        );
         match &pattern.match_pattern.var_constraints[0] {
             Constraint::And(constraints) => {
                 assert_eq!(constraints.len(), 2);
@@ -342,7 +969,10 @@ mod tests {
         assert_eq!(edge_constraint.from, "Help");
         assert_eq!(edge_constraint.to, "To");
         assert_eq!(edge_constraint.relation, RelationType::Child);
-        assert_eq!(edge_constraint.label, Some("xcomp".to_string()));
+        assert_eq!(
+            edge_constraint.label,
+            Some(LabelMatcher::Exact("xcomp".to_string()))
+        );
     }
 
     #[test]
@@ -380,7 +1010,7 @@ mod tests {
         assert_eq!(edge_constraint.to, "To");
         assert_eq!(edge_constraint.relation, RelationType::Child);
         assert_eq!(edge_constraint.label, None);
-        assert_eq!(edge_constraint.negated, true);
+        assert!(edge_constraint.negated);
     }
 
     #[test]
@@ -398,8 +1028,58 @@ mod tests {
         assert_eq!(edge_constraint.from, "Help");
         assert_eq!(edge_constraint.to, "To");
         assert_eq!(edge_constraint.relation, RelationType::Child);
-        assert_eq!(edge_constraint.label, Some("xcomp".to_string()));
-        assert_eq!(edge_constraint.negated, true);
+        assert_eq!(
+            edge_constraint.label,
+            Some(LabelMatcher::Exact("xcomp".to_string()))
+        );
+        assert!(edge_constraint.negated);
+    }
+
+    #[test]
+    fn test_parse_multi_label_edge() {
+        let any_of = |labels: &[&str]| {
+            Some(LabelMatcher::AnyOf(
+                labels.iter().map(|l| l.to_string()).collect(),
+            ))
+        };
+
+        let pattern = compile_query("MATCH { V []; N []; V -[nsubj|expl | csubj]-> N; }").unwrap();
+        let edge_constraint = &pattern.match_pattern.edge_constraints[0];
+        assert_eq!(edge_constraint.label, any_of(&["nsubj", "expl", "csubj"]));
+        assert!(!edge_constraint.negated);
+        // The target's deprel is restricted to one of the labels
+        let deprel = |l: &str| Constraint::DepRel(ConstraintValue::Literal(l.to_string()));
+        assert_eq!(
+            pattern.match_pattern.var_constraints[pattern.match_pattern.var_ids["N"].0],
+            Constraint::Or(vec![deprel("nsubj"), deprel("expl"), deprel("csubj")])
+        );
+
+        let pattern = compile_query("MATCH { V []; N []; V !-[nsubj|obj]-> N; }").unwrap();
+        let edge_constraint = &pattern.match_pattern.edge_constraints[0];
+        assert_eq!(edge_constraint.label, any_of(&["nsubj", "obj"]));
+        assert!(edge_constraint.negated);
+        assert_eq!(
+            pattern.match_pattern.var_constraints[pattern.match_pattern.var_ids["N"].0],
+            Constraint::Any
+        );
+
+        let pattern = compile_query("MATCH { V -[obj|iobj]-> _; }").unwrap();
+        assert_eq!(
+            pattern.match_pattern.var_constraints[0],
+            Constraint::HasChild(any_of(&["obj", "iobj"]))
+        );
+
+        let pattern = compile_query("MATCH { V -[obj|iobj]{2}-> _; }").unwrap();
+        assert_eq!(
+            pattern.match_pattern.var_constraints[0],
+            Constraint::ChildCount {
+                label: any_of(&["obj", "iobj"]),
+                min: 2,
+                max: Some(2)
+            }
+        );
+
+        assert!(compile_query("MATCH { V []; N []; V -[nsubj|]-> N; }").is_err());
     }
 
     #[test]
@@ -413,7 +1093,7 @@ mod tests {
         let pattern = compile_query(query).unwrap();
 
         let edge_constraint = &pattern.match_pattern.edge_constraints[0];
-        assert_eq!(edge_constraint.negated, false);
+        assert!(!edge_constraint.negated);
     }
 
     #[test]
@@ -665,7 +1345,7 @@ MATCH {
         let pattern = compile_query(query).unwrap();
 
         assert_eq!(pattern.match_pattern.var_constraints.len(), 1);
-        assert_eq!(*pattern.match_pattern.var_ids.get("V").unwrap(), 0);
+        assert_eq!(*pattern.match_pattern.var_ids.get("V").unwrap(), VarId(0));
         match &pattern.match_pattern.var_constraints[0] {
             Constraint::Feature(key, ConstraintValue::Literal(value)) => {
                 assert_eq!(key, "Tense");
@@ -777,7 +1457,7 @@ MATCH {
 
         assert_eq!(pattern.match_pattern.var_constraints.len(), 1);
         assert_eq!(pattern.match_pattern.edge_constraints.len(), 0); // Anonymous edges don't create edge constraints
-        assert_eq!(*pattern.match_pattern.var_ids.get("X").unwrap(), 0);
+        assert_eq!(*pattern.match_pattern.var_ids.get("X").unwrap(), VarId(0));
 
         match &pattern.match_pattern.var_constraints[0] {
             Constraint::And(constraints) => {
@@ -788,7 +1468,7 @@ MATCH {
                     )))
                 );
                 assert!(constraints.iter().any(|c| matches!(
-                    c, Constraint::IsChild(Some(label)) if label == "obj"
+                    c, Constraint::IsChild(Some(LabelMatcher::Exact(label))) if label == "obj"
                 )));
             }
             _ => panic!("Expected And constraint"),
@@ -816,7 +1496,7 @@ MATCH {
                     )))
                 );
                 assert!(constraints.iter().any(|c| matches!(
-                    c, Constraint::HasChild(Some(label)) if label == "nsubj"
+                    c, Constraint::HasChild(Some(LabelMatcher::Exact(label))) if label == "nsubj"
                 )));
             }
             _ => panic!("Expected And constraint"),
@@ -900,12 +1580,12 @@ MATCH {
         assert_eq!(pattern.match_pattern.var_constraints.len(), 2);
         assert_eq!(pattern.match_pattern.edge_constraints.len(), 1); // Only X -> Y creates edge constraint
 
-        let x_constraints = &pattern.match_pattern.var_constraints
-            [*pattern.match_pattern.var_ids.get("X").unwrap()];
+        let x_constraints =
+            &pattern.match_pattern.var_constraints[pattern.match_pattern.var_ids["X"].0];
         match x_constraints {
             Constraint::And(constraints) => {
                 assert!(constraints.iter().any(|c| matches!(
-                    c, Constraint::IsChild(Some(label)) if label == "obj"
+                    c, Constraint::IsChild(Some(LabelMatcher::Exact(label))) if label == "obj"
                 )));
             }
             _ => panic!("Expected And constraint for X"),
@@ -1077,6 +1757,637 @@ MATCH {
         assert!(matches!(result, Err(QueryError::InvalidRegex(_, _))));
     }
 
+    #[test]
+    fn test_parse_token_id_constraint() {
+        let pattern = compile_query("MATCH { W [token_id=1]; }").unwrap();
+        assert_eq!(
+            pattern.match_pattern.var_constraints[0],
+            Constraint::TokenId(Ordering::Equal, 1)
+        );
+
+        let pattern = compile_query(r#"MATCH { W [upos="NOUN" & token_id<=3]; }"#).unwrap();
+        match &pattern.match_pattern.var_constraints[0] {
+            Constraint::And(constraints) => assert_eq!(
+                constraints[1],
+                Constraint::Not(Box::new(Constraint::TokenId(Ordering::Greater, 3)))
+            ),
+            _ => panic!("Expected And constraint"),
+        }
+
+        // Bounds must be unquoted integers
+        assert!(compile_query(r#"MATCH { W [token_id="1"]; }"#).is_err());
+        assert!(matches!(
+            compile_query("MATCH { W [token_id=99999999999999999999999]; }"),
+            Err(QueryError::InvalidNumber(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_case_insensitive() {
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.var_constraints[0].clone()
+        };
+        let ci_form = Constraint::Form(ConstraintValue::CaseInsensitive("Running".to_string()));
+
+        assert_eq!(constraint(r#"MATCH { W [form="Running" /i]; }"#), ci_form);
+        assert_eq!(constraint(r#"MATCH { W [form="Running"/i]; }"#), ci_form);
+        assert_eq!(constraint(r#"MATCH { W [form~="Running"]; }"#), ci_form);
+        assert_eq!(
+            constraint(r#"MATCH { W [form!="Running" /i]; }"#),
+            Constraint::Not(Box::new(ci_form))
+        );
+        assert_eq!(
+            constraint(r#"MATCH { W [feats.Case~="nom"]; }"#),
+            Constraint::Feature(
+                "Case".to_string(),
+                ConstraintValue::CaseInsensitive("nom".to_string())
+            )
+        );
+        // Default stays case-sensitive
+        assert_eq!(
+            constraint(r#"MATCH { W [form="Running"]; }"#),
+            Constraint::Form(ConstraintValue::Literal("Running".to_string()))
+        );
+
+        match constraint("MATCH { W [lemma=/run.*/i]; }") {
+            Constraint::Lemma(ConstraintValue::Regex(pattern, regex)) => {
+                assert_eq!(pattern, "run.*");
+                assert!(regex.is_match("RUNNING"));
+            }
+            other => panic!("expected regex lemma constraint, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_substring_operators() {
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.var_constraints[0].clone()
+        };
+
+        assert_eq!(
+            constraint(r#"MATCH { W [form^="un"]; }"#),
+            Constraint::Form(ConstraintValue::Prefix("un".to_string()))
+        );
+        assert_eq!(
+            constraint(r#"MATCH { W [form$="ing"]; }"#),
+            Constraint::Form(ConstraintValue::Suffix("ing".to_string()))
+        );
+        assert_eq!(
+            constraint(r#"MATCH { W [misc.Gloss*="oo"]; }"#),
+            Constraint::Misc(
+                "Gloss".to_string(),
+                ConstraintValue::Contains("oo".to_string())
+            )
+        );
+
+        // With /i the value is escaped into a case-insensitive regex
+        match constraint(r#"MATCH { W [form$="(ing)" /i]; }"#) {
+            Constraint::Form(ConstraintValue::Regex(_, regex)) => {
+                assert!(regex.is_match("RUN(ING)"));
+                assert!(!regex.is_match("RUNNING"));
+            }
+            other => panic!("expected regex form constraint, got {:?}", other),
+        }
+
+        // Regex values are matched as a prefix, suffix or substring
+        match constraint("MATCH { W [lemma*=/a|b/]; }") {
+            Constraint::Lemma(ConstraintValue::Regex(_, regex)) => {
+                assert!(regex.is_match("xbx"));
+                assert!(!regex.is_match("xyz"));
+            }
+            other => panic!("expected regex lemma constraint, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_or_constraint() {
+        let upos = |value: &str| Constraint::UPOS(ConstraintValue::Literal(value.to_string()));
+        let lemma = |value: &str| Constraint::Lemma(ConstraintValue::Literal(value.to_string()));
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.var_constraints[0].clone()
+        };
+
+        assert_eq!(
+            constraint(r#"MATCH { N [upos="NOUN" | upos="PROPN"]; }"#),
+            Constraint::Or(vec![upos("NOUN"), upos("PROPN")])
+        );
+        // & binds tighter than |
+        assert_eq!(
+            constraint(r#"MATCH { N [upos="NOUN" & lemma="dog" | upos="PROPN"]; }"#),
+            Constraint::Or(vec![
+                Constraint::And(vec![upos("NOUN"), lemma("dog")]),
+                upos("PROPN"),
+            ])
+        );
+        // Parentheses group an OR inside an AND
+        assert_eq!(
+            constraint(r#"MATCH { N [(upos="NOUN" | upos="PROPN") & lemma="dog"]; }"#),
+            Constraint::And(vec![
+                Constraint::Or(vec![upos("NOUN"), upos("PROPN")]),
+                lemma("dog"),
+            ])
+        );
+        assert_eq!(
+            constraint(r#"MATCH { N [upos="NOUN" | upos!="VERB"]; }"#),
+            Constraint::Or(vec![upos("NOUN"), Constraint::Not(Box::new(upos("VERB"))),])
+        );
+
+        assert!(compile_query(r#"MATCH { N [upos="NOUN" |]; }"#).is_err());
+        assert!(compile_query(r#"MATCH { N [()]; }"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_dominance() {
+        let relation = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            let edge = &pattern.match_pattern.edge_constraints[0];
+            assert_eq!((edge.from.as_str(), edge.to.as_str()), ("V", "N"));
+            (edge.relation, edge.negated)
+        };
+        let descendant = |min, max| RelationType::Descendant { min, max };
+
+        assert_eq!(relation("MATCH { V >> N; }"), (descendant(1, None), false));
+        assert_eq!(
+            relation("MATCH { V >>2 N; }"),
+            (descendant(2, Some(2)), false)
+        );
+        assert_eq!(
+            relation("MATCH { V >>{2,4} N; }"),
+            (descendant(2, Some(4)), false)
+        );
+        assert_eq!(
+            relation("MATCH { V >>{2,} N; }"),
+            (descendant(2, None), false)
+        );
+        assert_eq!(relation("MATCH { V !>> N; }"), (descendant(1, None), true));
+
+        for query in [
+            "MATCH { V >>0 N; }",
+            "MATCH { V >>{0,2} N; }",
+            "MATCH { V >>{3,2} N; }",
+        ] {
+            let result = compile_query(query);
+            assert!(
+                matches!(result, Err(QueryError::InvalidDepthRange(_))),
+                "{query}"
+            );
+        }
+        assert!(compile_query("MATCH { V >>{,2} N; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_structural_keywords() {
+        let pattern =
+            compile_query(r#"MATCH { R [IS_ROOT]; L [IS_LEAF & upos="NOUN"]; }"#).unwrap();
+        let base = &pattern.match_pattern;
+        assert_eq!(
+            base.var_constraints[base.var_ids["R"].0],
+            Constraint::IsRoot
+        );
+        assert_eq!(
+            base.var_constraints[base.var_ids["L"].0],
+            Constraint::And(vec![
+                Constraint::IsLeaf,
+                Constraint::UPOS(ConstraintValue::Literal("NOUN".to_string())),
+            ])
+        );
+        assert!(compile_query("MATCH { R [is_root]; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_macros() {
+        let constraints = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            let base = pattern.match_pattern;
+            base.var_names
+                .iter()
+                .map(|name| {
+                    (
+                        name.clone(),
+                        base.var_constraints[base.var_ids[name].0].clone(),
+                    )
+                })
+                .collect::<HashMap<_, _>>()
+        };
+
+        assert_eq!(
+            constraints(r#"%define A [upos="VERB"]; MATCH { X [%A]; }"#),
+            constraints(r#"MATCH { X [upos="VERB"]; }"#)
+        );
+        // A macro expands as a group and can use earlier macros
+        assert_eq!(
+            constraints(
+                r#"%define N [upos="NOUN" | upos="PROPN"]
+                   %define SUBJ [%N & deprel="nsubj"];
+                   MATCH { V []; S [%SUBJ & lemma="dog"]; V -> S; }"#
+            ),
+            constraints(
+                r#"MATCH { V []; S [((upos="NOUN" | upos="PROPN") & deprel="nsubj") & lemma="dog"]; V -> S; }"#
+            )
+        );
+
+        let result = compile_query("MATCH { X [%A]; }");
+        assert!(matches!(result, Err(QueryError::UndefinedMacro(name)) if name == "A"));
+        let result = compile_query(r#"%define A [upos="VERB"]; %define A []; MATCH { X [%A]; }"#);
+        assert!(matches!(result, Err(QueryError::DuplicateMacro(name)) if name == "A"));
+        // Definitions must come before the query blocks
+        assert!(compile_query(r#"MATCH { X [%A]; } %define A [upos="VERB"];"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_subtree_contains() {
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.var_constraints[0].clone()
+        };
+        let noun = Constraint::UPOS(ConstraintValue::Literal("NOUN".to_string()));
+        let det = Constraint::UPOS(ConstraintValue::Literal("DET".to_string()));
+
+        assert_eq!(
+            constraint(r#"MATCH { V [CONTAINS upos="NOUN"]; }"#),
+            Constraint::SubtreeContains(Box::new(noun.clone()))
+        );
+        // CONTAINS applies to one constraint; group several with parentheses
+        assert_eq!(
+            constraint(r#"MATCH { V [CONTAINS upos="NOUN" & upos="DET"]; }"#),
+            Constraint::And(vec![
+                Constraint::SubtreeContains(Box::new(noun.clone())),
+                det.clone()
+            ])
+        );
+        assert_eq!(
+            constraint(r#"MATCH { V [CONTAINS (upos="NOUN" | upos="DET")]; }"#),
+            Constraint::SubtreeContains(Box::new(Constraint::Or(vec![noun, det.clone()])))
+        );
+        assert_eq!(
+            constraint(r#"MATCH { V [!CONTAINS upos="DET"]; }"#),
+            Constraint::Not(Box::new(Constraint::SubtreeContains(Box::new(det))))
+        );
+        assert!(compile_query("MATCH { V [CONTAINS]; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_feature_existence() {
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.var_constraints[0].clone()
+        };
+
+        assert_eq!(
+            constraint("MATCH { W [feats.Tense]; }"),
+            Constraint::HasFeature("Tense".to_string())
+        );
+        assert_eq!(
+            constraint("MATCH { W [!feats.Tense]; }"),
+            Constraint::Not(Box::new(Constraint::HasFeature("Tense".to_string())))
+        );
+        assert_eq!(
+            constraint("MATCH { W [misc.SpaceAfter]; }"),
+            Constraint::HasMisc("SpaceAfter".to_string())
+        );
+        assert_eq!(
+            constraint(r#"MATCH { W [feats.Tense & upos="VERB"]; }"#),
+            Constraint::And(vec![
+                Constraint::HasFeature("Tense".to_string()),
+                Constraint::UPOS(ConstraintValue::Literal("VERB".to_string())),
+            ])
+        );
+        assert!(compile_query("MATCH { W [!upos]; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_quantified_edge() {
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            let base = &pattern.match_pattern;
+            assert!(base.edge_constraints.is_empty());
+            base.var_constraints[base.var_ids["V"].0].clone()
+        };
+        let count = |label: Option<&str>, min, max| Constraint::ChildCount {
+            label: label.map(|l: &str| LabelMatcher::Exact(l.to_string())),
+            min,
+            max,
+        };
+
+        assert_eq!(
+            constraint("MATCH { V -[nsubj]{2,}-> _; }"),
+            count(Some("nsubj"), 2, None)
+        );
+        assert_eq!(constraint("MATCH { V -{3}-> _; }"), count(None, 3, Some(3)));
+        // Declared before or after the node, the constraints are merged
+        let expected = Constraint::And(vec![
+            Constraint::UPOS(ConstraintValue::Literal("VERB".to_string())),
+            count(Some("obj"), 0, Some(1)),
+        ]);
+        assert_eq!(
+            constraint(r#"MATCH { V [upos="VERB"]; V -[obj]{0,1}-> _; }"#),
+            expected
+        );
+        assert_eq!(
+            constraint(r#"MATCH { V -[obj]{0,1}-> _; V [upos="VERB"]; }"#),
+            expected
+        );
+
+        assert!(matches!(
+            compile_query("MATCH { V []; N []; V -[nsubj]{2,}-> N; }"),
+            Err(QueryError::InvalidQuantifier(_))
+        ));
+        assert!(matches!(
+            compile_query("MATCH { V -[nsubj]{3,2}-> _; }"),
+            Err(QueryError::InvalidQuantifier(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_sentence_block() {
+        let pattern = compile_query(
+            "SENTENCE { metadata.sent_id = \"s-1\"; text CONTAINS \"dog\"; text = /^The/ }
+             MATCH { V []; }",
+        )
+        .unwrap();
+        let constraints = &pattern.sentence_constraints;
+        assert_eq!(constraints.len(), 3);
+        assert!(
+            matches!(&constraints[0], SentenceConstraint::MetadataEquals(k, v) if k == "sent_id" && v == "s-1")
+        );
+        assert!(matches!(&constraints[1], SentenceConstraint::TextContains(s) if s == "dog"));
+        assert!(matches!(&constraints[2], SentenceConstraint::TextRegex(s, _) if s == "^The"));
+
+        assert!(
+            compile_query("MATCH { V []; }")
+                .unwrap()
+                .sentence_constraints
+                .is_empty()
+        );
+        assert!(matches!(
+            compile_query("SENTENCE { text = /(/ } MATCH { V []; }"),
+            Err(QueryError::InvalidRegex(_, _))
+        ));
+        // The block has to come first
+        assert!(compile_query("MATCH { V []; } SENTENCE { text CONTAINS \"a\" }").is_err());
+    }
+
+    #[test]
+    fn test_parse_negated_precedence() {
+        let edge = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            let edge = &pattern.match_pattern.edge_constraints[0];
+            (edge.relation, edge.negated)
+        };
+
+        assert_eq!(edge("MATCH { V !<< N; }"), (RelationType::Precedes, true));
+        assert_eq!(
+            edge("MATCH { V !< N; }"),
+            (RelationType::ImmediatelyPrecedes, true)
+        );
+        assert_eq!(
+            edge("MATCH { V !~3 N; }"),
+            (RelationType::WithinDistance(3), true)
+        );
+        assert_eq!(edge("MATCH { V << N; }"), (RelationType::Precedes, false));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        // Edges and constraints print back in query syntax
+        for edge in [
+            "V -[nsubj|expl]-> N",
+            "V !-> N",
+            "V !<< N",
+            "V <3 N",
+            "V ~2 N",
+            "V >> N",
+            "V >>2 N",
+            "V >>{2,} N",
+            "V $. N",
+        ] {
+            let pattern = compile_query(&format!("MATCH {{ {}; }}", edge)).unwrap();
+            assert_eq!(pattern.match_pattern.edge_constraints[0].to_string(), edge);
+        }
+        let pattern =
+            compile_query("MATCH { V [lemma^=\"un\" & (upos=\"VERB\" | feats.Tense)]; }").unwrap();
+        assert_eq!(
+            pattern.match_pattern.var_constraints[0].to_string(),
+            "lemma^=\"un\" & (upos=\"VERB\" | feats.Tense)"
+        );
+    }
+
+    #[test]
+    fn test_constraint_display_compiles() {
+        let constraint = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.var_constraints[0].clone()
+        };
+        let bracketed = |c: &str| constraint(&format!("MATCH {{ X [{}]; }}", c));
+
+        // Printing, compiling and printing again gives the same text
+        for c in [
+            "lemma=\"run\"",
+            "form~=\"Run\"",
+            "form=\"Run\"/i",
+            "upos!=\"VERB\"",
+            "lemma^=\"un\"",
+            "lemma!=/un.*/",
+            "lemma$=\"ing\"/i",
+            "xpos*=\"N\"",
+            "form=/a\\/b/i",
+            "deprel=/nsubj|obj/",
+            "feats.Tense=\"Past\" | !misc.SpaceAfter",
+            "misc.Gloss!=\"x\" & token_id<=3 & token_id!=1",
+            "!CONTAINS (upos=\"NOUN\" & feats.Number)",
+            "IS_ROOT | IS_LEAF",
+        ] {
+            let shown = bracketed(c).to_string();
+            assert_eq!(bracketed(&shown).to_string(), shown, "{}", c);
+        }
+
+        // Values the string syntax cannot quote print as equivalent regexes
+        for value in ["say \"hi\"", "C:\\dir/a.b", "\\u{301}"] {
+            let literal = |value: ConstraintValue| match bracketed(&format!("lemma{}", value)) {
+                Constraint::Lemma(ConstraintValue::Regex(_, regex)) => regex,
+                other => panic!("{} did not compile to a regex", other),
+            };
+            let exact = literal(ConstraintValue::Literal(value.to_string()));
+            assert!(exact.is_match(value) && !exact.is_match(&format!("x{}", value)));
+            let folded = literal(ConstraintValue::CaseInsensitive(value.to_string()));
+            assert!(folded.is_match(&value.to_uppercase()));
+            let suffix = literal(ConstraintValue::Suffix(value.to_string()));
+            assert!(suffix.is_match(&format!("x{}", value)) && !suffix.is_match("x"));
+
+            let negated = Constraint::Not(Box::new(Constraint::Feature(
+                "Gloss".to_string(),
+                ConstraintValue::Contains(value.to_string()),
+            )));
+            let shown = negated.to_string();
+            assert_eq!(bracketed(&shown).to_string(), shown);
+        }
+
+        // Constraints from anonymous edges print as those edges, with * for the word
+        for edge in [
+            "_ -[obj]-> *",
+            "_ !-> *",
+            "* -[nsubj|expl]-> _",
+            "* !-[obj]-> _",
+            "* -[nsubj]{2,}-> _",
+            "* -{1,3}-> _",
+            "* -[obj]{2}-> _",
+        ] {
+            let shown = constraint(&format!("MATCH {{ {}; }}", edge.replace('*', "X"))).to_string();
+            assert_eq!(shown, edge);
+        }
+    }
+
+    #[test]
+    fn test_parse_distance() {
+        let relation = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            pattern.match_pattern.edge_constraints[0].relation
+        };
+
+        assert_eq!(
+            relation("MATCH { V <5 N; }"),
+            RelationType::PrecedesWithin(5)
+        );
+        assert_eq!(
+            relation("MATCH { V ~5 N; }"),
+            RelationType::WithinDistance(5)
+        );
+        assert_eq!(
+            relation("MATCH { V < N; }"),
+            RelationType::ImmediatelyPrecedes
+        );
+        assert_eq!(relation("MATCH { V << N; }"), RelationType::Precedes);
+
+        assert!(matches!(
+            compile_query("MATCH { V ~0 N; }"),
+            Err(QueryError::InvalidDistance(_))
+        ));
+        assert!(compile_query("MATCH { V ~ N; }").is_err());
+        assert!(compile_query("MATCH { V <<5 N; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_sibling() {
+        let pattern = compile_query("MATCH { A $$ B; B $. C; C $+ D; }").unwrap();
+        let relations: Vec<_> = pattern
+            .match_pattern
+            .edge_constraints
+            .iter()
+            .map(|e| (e.from.as_str(), e.to.as_str(), e.relation))
+            .collect();
+        assert_eq!(
+            relations,
+            vec![
+                ("A", "B", RelationType::Sibling),
+                ("B", "C", RelationType::ImmediateSibling),
+                ("C", "D", RelationType::NextSibling),
+            ]
+        );
+        assert_eq!(pattern.match_pattern.n_vars, 4);
+    }
+
+    #[test]
+    fn test_parse_within() {
+        let query =
+            r#"MATCH { V [upos="VERB"]; S []; V -[nsubj]-> S; } WITHIN { Root [upos="NOUN"]; }"#;
+        let pattern = compile_query(query).unwrap();
+        let match_pattern = &pattern.match_pattern;
+
+        assert_eq!(match_pattern.n_vars, 3);
+        let VarId(root_id) = match_pattern.var_ids["Root"];
+        assert_eq!(
+            match_pattern.var_constraints[root_id],
+            Constraint::UPOS(ConstraintValue::Literal("NOUN".to_string()))
+        );
+        let mut dominated: Vec<_> = match_pattern
+            .edge_constraints
+            .iter()
+            .filter(|e| e.relation == RelationType::Descendant { min: 1, max: None })
+            .map(|e| {
+                assert_eq!(e.from, "Root");
+                e.to.as_str()
+            })
+            .collect();
+        dominated.sort();
+        assert_eq!(dominated, vec!["S", "V"]);
+
+        assert!(compile_query("MATCH { V []; } WITHIN { }").is_err());
+        assert!(compile_query("MATCH { V []; } WITHIN { A []; B []; }").is_err());
+    }
+
+    #[test]
+    fn test_parse_order_by() {
+        let query =
+            r#"MATCH { V [upos="VERB"]; S []; V -[nsubj]-> S; } ORDER BY V.lemma, S.form DESC"#;
+        let pattern = compile_query(query).unwrap();
+        assert_eq!(
+            pattern.order_by,
+            vec![
+                OrderKey {
+                    var_name: "V".to_string(),
+                    field: FieldSelector::Lemma,
+                    ascending: true,
+                },
+                OrderKey {
+                    var_name: "S".to_string(),
+                    field: FieldSelector::Form,
+                    ascending: false,
+                },
+            ]
+        );
+
+        // No ORDER BY clause
+        let pattern = compile_query("MATCH { V []; }").unwrap();
+        assert!(pattern.order_by.is_empty());
+
+        // OPTIONAL variables may be used
+        let query = "MATCH { V []; } OPTIONAL { O []; V -[obj]-> O; } ORDER BY O.token_id ASC";
+        assert!(compile_query(query).is_ok());
+
+        let result = compile_query("MATCH { V []; } ORDER BY X.lemma");
+        assert!(matches!(result, Err(QueryError::UnknownOrderVariable(name)) if name == "X"));
+
+        assert!(compile_query("MATCH { V []; } ORDER BY V.feats").is_err());
+    }
+
+    #[test]
+    fn test_pattern_json() {
+        let query = "MATCH { V [upos=\"VERB\" & form=/caf\u{e9}\\w*/]; N []; V -[obj]-> N; }";
+        let pattern = compile_query(query).unwrap();
+        let json = pattern.to_json().unwrap();
+        let restored = Pattern::from_json(&json).unwrap();
+        assert_eq!(restored.source_query.as_deref(), Some(query));
+        assert_eq!(restored.match_pattern.n_vars, 2);
+
+        // Escapes, surrogate pairs and extra keys
+        let json = r#" { "version": "1", "query": "MATCH { V [lemma=\"\u00e9t\u00e9\ud83d\ude00\"]; }" } "#;
+        let pattern = Pattern::from_json(json).unwrap();
+        assert_eq!(
+            pattern.source_query.as_deref(),
+            Some("MATCH { V [lemma=\"été😀\"]; }")
+        );
+
+        assert!(matches!(
+            Pattern::from_json(r#"{"name": "x"}"#),
+            Err(QueryError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Pattern::from_json(r#"{"query": 3}"#),
+            Err(QueryError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Pattern::from_json(r#"{"query": "MATCH { V []; }"} extra"#),
+            Err(QueryError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Pattern::from_json(r#"{"query": "MATCH { V [; }"}"#),
+            Err(QueryError::ParseError(_))
+        ));
+    }
+
     #[test]
     fn test_regex_anchor_behavior() {
         // Test to understand anchor behavior
@@ -2,18 +2,23 @@
 //!
 //! The search pipeline:
 //! 1. Parse query string into Pattern
-//! 2. Solve CSP to find ALL matches (exhaustive search)
+//! 2. Solve CSP to find ALL matches (exhaustive, incremental DFS)
 //! 3. Yield matches
 //!
 
 use crate::RelationType;
-use crate::bytes::Sym;
-use crate::pattern::{BasePattern, Constraint, ConstraintValue, EdgeConstraint, Pattern};
+use crate::bytes::{Sym, bs_contains};
+use crate::pattern::{
+    BasePattern, Constraint, ConstraintValue, DirectedEdge, EdgeConstraint, FieldSelector,
+    LabelMatcher, OrderKey, Pattern, SentenceConstraint, VarId,
+};
 use crate::query::{QueryError, compile_query};
 use crate::tree::Word;
-use crate::tree::{Tree, WordId};
+use crate::tree::{FieldIndex, Tree, WordId};
 use fastbit::{BitFixed, BitRead, BitWrite};
-use std::collections::HashMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Write};
 use std::sync::Arc;
 
 pub type Bindings = HashMap<String, WordId>;
@@ -23,12 +28,110 @@ pub struct Match {
     pub bindings: Bindings,
 }
 
+impl Match {
+    /// Human-readable view of the bindings (see [`BindingsDisplay`])
+    pub fn display(&self) -> BindingsDisplay<'_> {
+        BindingsDisplay {
+            bindings: &self.bindings,
+            tree: &self.tree,
+        }
+    }
+
+    /// Smallest word id among the bindings (`None` if nothing is bound)
+    pub fn leftmost_word_id(&self) -> Option<WordId> {
+        self.bindings.values().copied().min()
+    }
+
+    /// Largest word id among the bindings (`None` if nothing is bound)
+    pub fn rightmost_word_id(&self) -> Option<WordId> {
+        self.bindings.values().copied().max()
+    }
+
+    /// Smallest window `(leftmost, rightmost)` holding every bound word, e.g. to
+    /// pull out the text of a whole construction
+    pub fn span(&self) -> Option<(WordId, WordId)> {
+        Some((self.leftmost_word_id()?, self.rightmost_word_id()?))
+    }
+}
+
+/// Order of the matches that [`search_tree`] returns from a tree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MatchOrder {
+    /// As the solver finds them (or as ORDER BY sorts them)
+    #[default]
+    Unsorted,
+    /// By `leftmost_word_id`, smallest first
+    LeftmostFirst,
+    /// By `rightmost_word_id`, largest first
+    RightmostFirst,
+    /// By the position of the word bound to this variable; matches that leave it
+    /// unbound come last
+    ByVar(String),
+}
+
+/// Sort matches by a `MatchOrder` (stable, so ties keep their current order)
+pub fn order_matches(matches: &mut [Match], order: &MatchOrder) {
+    match order {
+        MatchOrder::Unsorted => {}
+        MatchOrder::LeftmostFirst => matches.sort_by_key(Match::leftmost_word_id),
+        MatchOrder::RightmostFirst => matches.sort_by_key(|m| Reverse(m.rightmost_word_id())),
+        MatchOrder::ByVar(var_name) => {
+            matches.sort_by_key(|m| m.bindings.get(var_name).map_or((1, 0), |&id| (0, id)))
+        }
+    }
+}
+
+/// Formats bindings with the form and UPOS of each bound word,
+/// e.g. `{S=1 (dog/NOUN), V=2 (runs/VERB)}`. Variables are sorted by name.
+pub struct BindingsDisplay<'a> {
+    pub bindings: &'a Bindings,
+    pub tree: &'a Tree,
+}
+
+impl fmt::Display for BindingsDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<_> = self.bindings.iter().collect();
+        entries.sort();
+        write!(f, "{{")?;
+        for (i, (var_name, &word_id)) in entries.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", var_name, word_id)?;
+            if let Some(word) = self.tree.words.get(word_id) {
+                let pool = &self.tree.string_pool;
+                write!(
+                    f,
+                    " ({}/{})",
+                    String::from_utf8_lossy(&pool.resolve(word.form)),
+                    String::from_utf8_lossy(&pool.resolve(word.upos))
+                )?;
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
 /// Check if a string (from string pool) matches a constraint value (literal or regex)
 fn matches_constraint_value(tree: &Tree, str_id: Sym, value: &ConstraintValue) -> bool {
     match value {
         ConstraintValue::Literal(literal) => {
             tree.string_pool.compare_bytes(str_id, literal.as_bytes())
         }
+        ConstraintValue::CaseInsensitive(literal) => tree
+            .string_pool
+            .compare_bytes_ci(str_id, literal.as_bytes()),
+        ConstraintValue::Prefix(prefix) => tree
+            .string_pool
+            .resolve(str_id)
+            .starts_with(prefix.as_bytes()),
+        ConstraintValue::Suffix(suffix) => tree
+            .string_pool
+            .resolve(str_id)
+            .ends_with(suffix.as_bytes()),
+        ConstraintValue::Contains(needle) => {
+            bs_contains(&tree.string_pool.resolve(str_id), needle.as_bytes())
+        }
         ConstraintValue::Regex(_pattern, regex) => {
             let bytes = tree.string_pool.resolve(str_id);
             if let Ok(s) = std::str::from_utf8(&bytes) {
@@ -40,9 +143,43 @@ fn matches_constraint_value(tree: &Tree, str_id: Sym, value: &ConstraintValue) -
     }
 }
 
+/// Check if a deprel (from string pool) is one of the labels accepted by an edge
+fn matches_label(tree: &Tree, deprel: Sym, label: &LabelMatcher) -> bool {
+    label
+        .labels()
+        .iter()
+        .any(|expected| tree.string_pool.compare_bytes(deprel, expected.as_bytes()))
+}
+
+/// Check if some descendant of a word satisfies a constraint (depth-first, stopping at the first)
+fn subtree_contains(tree: &Tree, word: &Word, constraint: &Constraint) -> bool {
+    // The visited flags guard against head cycles (and keep the word itself out)
+    let mut visited = vec![false; tree.words.len()];
+    visited[word.id] = true;
+    let mut stack = vec![word.id];
+    while let Some(word_id) = stack.pop() {
+        for &child in &tree.words[word_id].children {
+            if !visited[child] {
+                if satisfies_var_constraint(tree, &tree.words[child], constraint) {
+                    return true;
+                }
+                visited[child] = true;
+                stack.push(child);
+            }
+        }
+    }
+    false
+}
+
 /// Check if a tree word satisfies a pattern variable's constraint
 fn satisfies_var_constraint(tree: &Tree, word: &Word, constraint: &Constraint) -> bool {
     match constraint {
+        Constraint::ChildCount { label, min, max } => {
+            satisfies_quantified_edge(tree, word, label.as_ref(), *min, *max)
+        }
+        Constraint::IsRoot => word.head.is_none(),
+        Constraint::IsLeaf => word.children.is_empty(),
+        Constraint::SubtreeContains(inner) => subtree_contains(tree, word, inner),
         Constraint::Lemma(value) => matches_constraint_value(tree, word.lemma, value),
         Constraint::UPOS(value) => matches_constraint_value(tree, word.upos, value),
         Constraint::XPOS(value) => matches_constraint_value(tree, word.xpos, value),
@@ -62,29 +199,37 @@ fn satisfies_var_constraint(tree: &Tree, word: &Word, constraint: &Constraint) -
                     && matches_constraint_value(tree, *v, value)
             })
         }
+        Constraint::HasFeature(key) => word
+            .feats
+            .iter()
+            .any(|(k, _)| tree.string_pool.compare_bytes(*k, key.as_bytes())),
+        Constraint::HasMisc(key) => word
+            .misc
+            .iter()
+            .any(|(k, _)| tree.string_pool.compare_bytes(*k, key.as_bytes())),
+        Constraint::TokenId(ordering, bound) => word.token_id.cmp(bound) == *ordering,
         Constraint::And(constraints) => constraints
             .iter()
             .all(|constraint| satisfies_var_constraint(tree, word, constraint)),
-        //        Constraint::Or(constraints) => constraints
-        //            .iter()
-        //            .any(|constraint| satisfies_var_constraint(tree, word, constraint)),
+        Constraint::Or(constraints) => constraints
+            .iter()
+            .any(|constraint| satisfies_var_constraint(tree, word, constraint)),
         Constraint::Not(inner_constraint) => {
             !satisfies_var_constraint(tree, word, inner_constraint)
         }
         Constraint::Any => true, // No filtering
         Constraint::IsChild(label) => {
             if let Some(required_label) = label {
-                word.head.is_some()
-                    && tree
-                        .string_pool
-                        .compare_bytes(word.deprel, required_label.as_bytes())
+                word.head.is_some() && matches_label(tree, word.deprel, required_label)
             } else {
                 word.head.is_some()
             }
         }
         Constraint::HasChild(label) => {
             if let Some(required_label) = label {
-                !word.children_by_deprel(tree, required_label).is_empty()
+                word.children
+                    .iter()
+                    .any(|&child| matches_label(tree, tree.words[child].deprel, required_label))
             } else {
                 !word.children.is_empty()
             }
@@ -92,6 +237,25 @@ fn satisfies_var_constraint(tree: &Tree, word: &Word, constraint: &Constraint) -
     }
 }
 
+/// Check that the number of children (with deprel `label`, if given) is within [min, max]
+fn satisfies_quantified_edge(
+    tree: &Tree,
+    word: &Word,
+    label: Option<&LabelMatcher>,
+    min: usize,
+    max: Option<usize>,
+) -> bool {
+    let count = match label {
+        Some(label) => word
+            .children
+            .iter()
+            .filter(|&&child| matches_label(tree, tree.words[child].deprel, label))
+            .count(),
+        None => word.children.len(),
+    };
+    count >= min && max.is_none_or(|max| count <= max)
+}
+
 fn satisfies_arc_constraint(
     tree: &Tree,
     from_word_id: WordId,
@@ -106,12 +270,23 @@ fn satisfies_arc_constraint(
                     .as_ref()
                     .is_none_or(|expected_deprel| {
                         let actual_deprel = tree.word(to_word_id).unwrap().deprel;
-                        tree.string_pool
-                            .compare_bytes(actual_deprel, expected_deprel.as_bytes())
+                        matches_label(tree, actual_deprel, expected_deprel)
                     })
         }
         RelationType::Precedes => from_word_id < to_word_id,
         RelationType::ImmediatelyPrecedes => to_word_id == from_word_id + 1,
+        RelationType::PrecedesWithin(n) => {
+            from_word_id < to_word_id && to_word_id - from_word_id <= n
+        }
+        RelationType::WithinDistance(n) => from_word_id.abs_diff(to_word_id) <= n,
+        RelationType::Sibling => tree.are_siblings(from_word_id, to_word_id),
+        RelationType::ImmediateSibling => {
+            to_word_id == from_word_id + 1 && tree.are_siblings(from_word_id, to_word_id)
+        }
+        RelationType::NextSibling => tree.next_sibling(from_word_id) == Some(to_word_id),
+        RelationType::Descendant { min, max } => tree
+            .ancestor_distance(from_word_id, to_word_id)
+            .is_some_and(|distance| distance >= min && max.is_none_or(|max| distance <= max)),
     };
 
     if edge_constraint.negated {
@@ -125,46 +300,343 @@ fn has_any_match(tree: &Tree, pattern: &BasePattern, initial_bindings: &Bindings
     !solve_with_bindings(tree, pattern, initial_bindings, true).is_empty()
 }
 
-/// Process OPTIONAL blocks: extend base bindings with cross-product of all extensions.
-/// Each OPTIONAL is evaluated independently against base_bindings.
-/// Returns all combinations of optional extensions (or just base if none match).
-fn process_optionals(
-    tree: &Tree,
+/// The OPTIONAL extensions of one base match, as a cross-product produced one
+/// combination at a time.
+///
+/// Each OPTIONAL is solved independently against the base bindings; blocks without
+/// a match leave the bindings unchanged. Combinations come in nested-loop order,
+/// with the last block varying fastest. A block's solutions are only searched for
+/// when a combination first needs them, so the first match costs one solution per
+/// block and stopping after a few matches doesn't pay for the whole product.
+struct OptionalProduct<'p> {
     base_bindings: Bindings,
-    optional_patterns: &[BasePattern],
-) -> Vec<Bindings> {
-    let extension_sets: Vec<Vec<Bindings>> = optional_patterns
-        .iter()
-        .map(|optional| solve_with_bindings(tree, optional, &base_bindings, false))
-        .collect();
+    /// Blocks still in the product (those without a match are dropped on the first call)
+    blocks: Vec<OptionalSolutions<'p>>,
+    /// Solution chosen from each block for the last combination
+    indices: Vec<usize>,
+    started: bool,
+    done: bool,
+}
 
-    let mut results = vec![base_bindings];
+/// Solutions of one OPTIONAL block, found as the product asks for them
+struct OptionalSolutions<'p> {
+    /// `None` once the block has no more solutions
+    solver: Option<Solver<'p>>,
+    found: Vec<Bindings>,
+}
+
+impl OptionalSolutions<'_> {
+    /// Whether the block has a solution `index`, searching for it if needed
+    fn has(&mut self, tree: &Tree, index: usize) -> bool {
+        while self.found.len() <= index {
+            match self
+                .solver
+                .as_mut()
+                .and_then(|solver| solver.next_solution(tree))
+            {
+                Some(solution) => self.found.push(solution),
+                None => {
+                    self.solver = None;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
 
-    // Compute cross-product of all extensions
+impl<'p> OptionalProduct<'p> {
+    fn new(tree: &Tree, base_bindings: Bindings, optional_patterns: &'p [BasePattern]) -> Self {
+        let blocks = optional_patterns
+            .iter()
+            .map(|optional| OptionalSolutions {
+                solver: Some(Solver::new(tree, optional, &base_bindings)),
+                found: Vec::new(),
+            })
+            .collect();
+        OptionalProduct {
+            base_bindings,
+            blocks,
+            indices: Vec::new(),
+            started: false,
+            done: false,
+        }
+    }
 
-    for extensions in extension_sets {
-        if extensions.is_empty() {
-            // No match for this OPTIONAL - keep results unchanged
-            continue;
+    /// Next combination, or `None` once the product is exhausted
+    fn next(&mut self, tree: &Tree) -> Option<Bindings> {
+        if self.done {
+            return None;
         }
-        // Replace each current result with extended versions
-        let mut new_results = Vec::new();
-        for result in &results {
-            for ext in &extensions {
-                let mut combined = result.clone();
-                // Merge in the new bindings from this OPTIONAL
-                for (k, v) in ext {
-                    if !combined.contains_key(k) {
-                        combined.insert(k.clone(), *v);
+        if !self.started {
+            self.started = true;
+            self.blocks.retain_mut(|block| block.has(tree, 0));
+            self.indices = vec![0; self.blocks.len()];
+        } else if !self.advance(tree) {
+            self.done = true;
+            return None;
+        }
+        let mut combined = self.base_bindings.clone();
+        for (block, &index) in self.blocks.iter().zip(&self.indices) {
+            // Merge in the new bindings from this OPTIONAL
+            for (k, v) in &block.found[index] {
+                combined.entry(k.clone()).or_insert(*v);
+            }
+        }
+        Some(combined)
+    }
+
+    /// Step the indices like an odometer; wrapping around the first block ends the product
+    fn advance(&mut self, tree: &Tree) -> bool {
+        for (index, block) in self.indices.iter_mut().zip(&mut self.blocks).rev() {
+            if block.has(tree, *index + 1) {
+                *index += 1;
+                return true;
+            }
+            *index = 0;
+        }
+        false
+    }
+}
+
+/// Look up the words whose field has a literal value in one of the tree's indices
+fn index_lookup(tree: &Tree, index: &FieldIndex, literal: &str) -> BitFixed<u64> {
+    let mut words = BitFixed::new(tree.words.len());
+    if let Some(word_ids) = tree
+        .string_pool
+        .get(literal.as_bytes())
+        .and_then(|sym| index.get(&sym))
+    {
+        for &word_id in word_ids {
+            words.set(word_id);
+        }
+    }
+    words
+}
+
+/// Candidate words for a constraint from the tree's field indices.
+///
+/// Returns `None` if no part of the constraint is indexed. Otherwise returns a superset
+/// of the satisfying words, plus a flag that is true when the set is exact (every part
+/// of the constraint was answered by an index), so no further checking is needed.
+fn indexed_candidates(tree: &Tree, constraint: &Constraint) -> Option<(BitFixed<u64>, bool)> {
+    match constraint {
+        Constraint::UPOS(ConstraintValue::Literal(value)) if !tree.upos_index.is_empty() => {
+            Some((index_lookup(tree, &tree.upos_index, value), true))
+        }
+        Constraint::Lemma(ConstraintValue::Literal(value)) if !tree.lemma_index.is_empty() => {
+            Some((index_lookup(tree, &tree.lemma_index, value), true))
+        }
+        Constraint::Form(ConstraintValue::Literal(value)) if !tree.form_index.is_empty() => {
+            Some((index_lookup(tree, &tree.form_index, value), true))
+        }
+        Constraint::DepRel(ConstraintValue::Literal(value)) if !tree.deprel_index.is_empty() => {
+            Some((index_lookup(tree, &tree.deprel_index, value), true))
+        }
+        Constraint::And(constraints) => {
+            let mut exact = true;
+            let mut result: Option<BitFixed<u64>> = None;
+            for constraint in constraints {
+                match indexed_candidates(tree, constraint) {
+                    Some((candidates, sub_exact)) => {
+                        exact &= sub_exact;
+                        result = Some(match result {
+                            Some(acc) => acc & &candidates,
+                            None => candidates,
+                        });
+                    }
+                    None => exact = false,
+                }
+            }
+            result.map(|candidates| (candidates, exact))
+        }
+        // Usually a single word, so it narrows any conjunction it appears in
+        Constraint::IsRoot => {
+            let mut roots = BitFixed::new(tree.words.len());
+            for root in tree.roots() {
+                roots.set(root.id);
+            }
+            Some((roots, true))
+        }
+        // A union bounds the domain only if every branch is indexed
+        Constraint::Or(constraints) => {
+            let mut exact = true;
+            let mut result = BitFixed::new(tree.words.len());
+            for constraint in constraints {
+                let (candidates, sub_exact) = indexed_candidates(tree, constraint)?;
+                exact &= sub_exact;
+                result |= &candidates;
+            }
+            Some((result, exact))
+        }
+        _ => None,
+    }
+}
+
+/// A partial assignment waiting on the DFS work stack, with the domains of its
+/// unassigned variables as narrowed by forward checking
+struct PartialAssignment {
+    assign: Vec<Option<WordId>>,
+    assigned_words: BitFixed<u64>,
+    domains: Vec<BitFixed<u64>>,
+}
+
+/// Incremental DFS over the assignments of one base pattern.
+///
+/// Instead of recursing, the search keeps its frontier in a work stack, so each
+/// call to `next_solution` does only as much work as it takes to reach the next
+/// complete assignment. Solutions come out in the same order as a recursive DFS.
+struct Solver<'p> {
+    pattern: &'p BasePattern,
+    stack: VecDeque<PartialAssignment>,
+}
+
+impl<'p> Solver<'p> {
+    /// Set up a search with pre-bound variables from initial_bindings
+    fn new(tree: &Tree, pattern: &'p BasePattern, initial_bindings: &Bindings) -> Self {
+        let mut solver = Solver {
+            pattern,
+            stack: VecDeque::new(),
+        };
+
+        let num_words = tree.words.len();
+        let mut assign: Vec<Option<WordId>> = vec![None; pattern.n_vars];
+        let mut assigned_words: BitFixed<u64> = BitFixed::new(num_words);
+
+        // Pre-assign from initial_bindings and validate constraints on pre-bound variables
+        for (var_name, &word_id) in initial_bindings {
+            if let Some(&VarId(var_id)) = pattern.var_ids.get(var_name) {
+                // Check that pre-bound variable satisfies its constraints in this pattern
+                let word = &tree.words[word_id];
+                let constr = &pattern.var_constraints[var_id];
+                if !satisfies_var_constraint(tree, word, constr) {
+                    return solver; // Pre-bound variable fails constraint, no solutions possible
+                }
+                assign[var_id] = Some(word_id);
+                assigned_words.set(word_id);
+            }
+        }
+
+        // Initialize domains (node consistency)
+        let mut domains: Vec<BitFixed<u64>> = vec![BitFixed::new(num_words); pattern.n_vars];
+        for (var_id, constr) in pattern.var_constraints.iter().enumerate() {
+            if let Some(word_id) = assign[var_id] {
+                domains[var_id].set(word_id); // Already validated above
+                continue;
+            }
+            domains[var_id] = node_domain(tree, constr, &assigned_words);
+            if domains[var_id].count_ones() == 0 {
+                return solver; // no solution possible
+            }
+        }
+
+        // Arc consistency: drop words with no partner in a neighbouring domain
+        if !enforce_arc_consistency(tree, pattern, &mut domains) {
+            return solver;
+        }
+
+        solver.stack.push_back(PartialAssignment {
+            assign,
+            assigned_words,
+            domains,
+        });
+        solver
+    }
+
+    /// Run the DFS until the next complete assignment, or None when exhausted
+    fn next_solution(&mut self, tree: &Tree) -> Option<Bindings> {
+        let pattern = self.pattern;
+        while let Some(partial) = self.stack.pop_back() {
+            // No more variables to assign
+            if partial.assign.iter().all(|word_id| word_id.is_some()) {
+                let mut solution = Bindings::new();
+                for (var_id, word_id) in partial.assign.iter().copied().flatten().enumerate() {
+                    solution.insert(pattern.var_names[var_id].clone(), word_id);
+                }
+                return Some(solution);
+            }
+
+            let next_var = select_next_var(pattern, &partial.assign, &partial.domains);
+
+            // Try each candidate word for this variable (iterate over set bits in the domain
+            // bitset). Children are pushed in reverse so the smallest word id is popped first.
+            let candidates: Vec<WordId> = partial.domains[next_var.0].iter().collect();
+            'candidates: for word_id in candidates.into_iter().rev() {
+                // AllDifferent: Check if word_id is already assigned to another variable using bitset (O(1))
+                if partial.assigned_words.test(word_id) {
+                    continue;
+                }
+
+                // Early prune: Check arc consistency with already-assigned neighbors
+                if !check_arc_consistency(tree, pattern, &partial.assign, next_var, word_id) {
+                    continue;
+                }
+
+                // Assign var <- word_id and update bitset
+                let mut assign = partial.assign.clone();
+                assign[next_var.0] = Some(word_id);
+                let mut assigned_words = partial.assigned_words.clone();
+                assigned_words.set(word_id);
+
+                // Forward-check: Propagate along edge constraints touching next_var
+                let mut domains = partial.domains.clone();
+                if !forward_check(tree, pattern, next_var, word_id, &assign, &mut domains) {
+                    continue;
+                }
+
+                // AllDifferent: word_id is no longer available to unassigned variables
+                for (var_id, domain) in domains.iter_mut().enumerate() {
+                    if assign[var_id].is_none() {
+                        domain.reset(word_id);
+                        if domain.count_ones() == 0 {
+                            continue 'candidates; // dead end, no solutions below this branch
+                        }
                     }
                 }
-                new_results.push(combined);
+
+                self.stack.push_back(PartialAssignment {
+                    assign,
+                    assigned_words,
+                    domains,
+                });
             }
         }
-        results = new_results;
+        None
     }
+}
 
-    results
+/// Words that satisfy a variable's own constraint (node consistency), leaving out
+/// the words in `assigned_words`
+fn node_domain(tree: &Tree, constr: &Constraint, assigned_words: &BitFixed<u64>) -> BitFixed<u64> {
+    let mut domain = BitFixed::new(tree.words.len());
+    match indexed_candidates(tree, constr) {
+        // Fully indexed: the candidate set is the domain
+        Some((candidates, true)) => {
+            for word_id in candidates.iter() {
+                if !assigned_words.test(word_id) {
+                    domain.set(word_id);
+                }
+            }
+        }
+        // Partially indexed: only check the candidates
+        Some((candidates, false)) => {
+            for word_id in candidates.iter() {
+                if !assigned_words.test(word_id)
+                    && satisfies_var_constraint(tree, &tree.words[word_id], constr)
+                {
+                    domain.set(word_id);
+                }
+            }
+        }
+        None => {
+            for (word_id, word) in tree.words.iter().enumerate() {
+                if !assigned_words.test(word_id) && satisfies_var_constraint(tree, word, constr) {
+                    domain.set(word_id);
+                }
+            }
+        }
+    }
+    domain
 }
 
 /// Search with pre-bound variables from initial_bindings.
@@ -175,197 +647,305 @@ fn solve_with_bindings(
     initial_bindings: &Bindings,
     first_only: bool,
 ) -> Vec<Bindings> {
-    let num_words = tree.words.len();
-    let mut assign: Vec<Option<WordId>> = vec![None; pattern.n_vars];
-    let mut assigned_words: BitFixed<u64> = BitFixed::new(num_words);
-
-    // Pre-assign from initial_bindings and validate constraints on pre-bound variables
-    for (var_name, &word_id) in initial_bindings {
-        if let Some(&var_id) = pattern.var_ids.get(var_name) {
-            // Check that pre-bound variable satisfies its constraints in this pattern
-            let word = &tree.words[word_id];
-            let constr = &pattern.var_constraints[var_id];
-            if !satisfies_var_constraint(tree, word, constr) {
-                return Vec::new(); // Pre-bound variable fails constraint, no solutions possible
+    let mut solver = Solver::new(tree, pattern, initial_bindings);
+    let solutions = std::iter::from_fn(|| solver.next_solution(tree));
+    if first_only {
+        solutions.take(1).collect()
+    } else {
+        solutions.collect()
+    }
+}
+
+/// Lazily yields the matches of a pattern in one tree (see [`match_stream`])
+pub struct MatchStream<'p> {
+    tree: Arc<Tree>,
+    pattern: &'p Pattern,
+    solver: Solver<'p>,
+    /// Extensions of the current base match not yet returned
+    optionals: Option<OptionalProduct<'p>>,
+    /// Matches already found and sorted, for ORDER BY
+    pending: VecDeque<Match>,
+}
+
+impl<'p> MatchStream<'p> {
+    fn new(tree: Arc<Tree>, pattern: &'p Pattern) -> Self {
+        let solver = if satisfies_sentence_constraints(&tree, &pattern.sentence_constraints) {
+            Solver::new(&tree, &pattern.match_pattern, &Bindings::new())
+        } else {
+            Solver {
+                pattern: &pattern.match_pattern,
+                stack: VecDeque::new(),
             }
-            assign[var_id] = Some(word_id);
-            assigned_words.set(word_id);
+        };
+        MatchStream {
+            tree,
+            pattern,
+            solver,
+            optionals: None,
+            pending: VecDeque::new(),
         }
     }
 
-    // Initialize domains (node consistency)
-    let mut domains: Vec<BitFixed<u64>> = vec![BitFixed::new(num_words); pattern.n_vars];
-    for (var_id, constr) in pattern.var_constraints.iter().enumerate() {
-        if assign[var_id].is_some() {
-            continue; // Already validated above
-        }
-        for (word_id, word) in tree.words.iter().enumerate() {
-            if !assigned_words.test(word_id) && satisfies_var_constraint(tree, word, constr) {
-                domains[var_id].set(word_id);
+    /// Next MATCH solution that no EXCEPT block rejects
+    fn next_base_match(&mut self) -> Option<Bindings> {
+        while let Some(base_bindings) = self.solver.next_solution(&self.tree) {
+            if !is_rejected(&self.tree, self.pattern, &base_bindings) {
+                return Some(base_bindings);
             }
         }
-        if domains[var_id].count_ones() == 0 {
-            return Vec::new(); // no solution possible
+        None
+    }
+
+    /// Next match in solver order, expanding OPTIONAL blocks as needed
+    fn next_unsorted(&mut self) -> Option<Match> {
+        loop {
+            let tree = &self.tree;
+            if let Some(bindings) = self
+                .optionals
+                .as_mut()
+                .and_then(|product| product.next(tree))
+            {
+                return Some(Match {
+                    tree: Arc::clone(&self.tree),
+                    bindings,
+                });
+            }
+            let base_bindings = self.next_base_match()?;
+            self.optionals = Some(OptionalProduct::new(
+                &self.tree,
+                base_bindings,
+                &self.pattern.optional_patterns,
+            ));
         }
     }
+}
 
-    dfs(
-        tree,
-        pattern,
-        &assign,
-        &domains,
-        &assigned_words,
-        first_only,
-    )
+impl Iterator for MatchStream<'_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        self.pending.pop_front().or_else(|| self.next_unsorted())
+    }
 }
 
-pub fn find_all_matches(tree: Tree, pattern: &Pattern) -> Vec<Match> {
-    find_matches_impl(tree, pattern, false)
+/// Check the SENTENCE block; a tree without a sentence text fails text constraints
+fn satisfies_sentence_constraints(tree: &Tree, constraints: &[SentenceConstraint]) -> bool {
+    constraints.iter().all(|constraint| match constraint {
+        SentenceConstraint::MetadataEquals(key, value) => {
+            let actual = match key.as_str() {
+                "text" => tree.sentence_text.as_ref(),
+                _ => tree.metadata.get(key),
+            };
+            actual == Some(value)
+        }
+        SentenceConstraint::TextContains(needle) => tree
+            .sentence_text
+            .as_ref()
+            .is_some_and(|text| text.contains(needle.as_str())),
+        SentenceConstraint::TextRegex(_, regex) => tree
+            .sentence_text
+            .as_ref()
+            .is_some_and(|text| regex.is_match(text)),
+    })
 }
 
-/// Check if a tree has at least one match
-pub fn tree_matches(tree: &Tree, pattern: &Pattern) -> bool {
-    !find_matches_impl(tree.clone(), pattern, true).is_empty()
+/// Check whether any EXCEPT block matches given the base bindings
+fn is_rejected(tree: &Tree, pattern: &Pattern, base_bindings: &Bindings) -> bool {
+    pattern
+        .except_patterns
+        .iter()
+        .any(|except| has_any_match(tree, except, base_bindings))
 }
 
-fn find_matches_impl(tree: Tree, pattern: &Pattern, first_only: bool) -> Vec<Match> {
-    let tree = Arc::new(tree);
-    let empty_bindings = Bindings::new();
-    let base_matches =
-        solve_with_bindings(&tree, &pattern.match_pattern, &empty_bindings, first_only);
+/// Lazily find the matches of a pattern in a tree.
+///
+/// The solver only advances as far as needed to produce the next match, so taking a
+/// few matches from a tree with very many doesn't pay for the rest. With ORDER BY,
+/// all matches have to be found (and sorted) before the first one is returned.
+pub fn match_stream(tree: Arc<Tree>, pattern: &Pattern) -> MatchStream<'_> {
+    let mut stream = MatchStream::new(tree, pattern);
+    if !pattern.order_by.is_empty() {
+        let mut all: Vec<Match> = std::iter::from_fn(|| stream.next_unsorted()).collect();
+        sort_matches(&stream.tree, &mut all, &pattern.order_by);
+        stream.pending = all.into();
+    }
+    stream
+}
 
-    let mut results = Vec::new();
-    for base_bindings in base_matches {
-        let rejected = pattern
-            .except_patterns
-            .iter()
-            .any(|except| has_any_match(&tree, except, &base_bindings));
+/// Find the matches of a pattern in a tree, stopping after `max_matches` if given
+pub fn find_all_matches(tree: Tree, pattern: &Pattern, max_matches: Option<usize>) -> Vec<Match> {
+    match_stream(Arc::new(tree), pattern)
+        .take(max_matches.unwrap_or(usize::MAX))
+        .collect()
+}
 
-        if rejected {
-            continue;
-        }
+/// First match of a pattern in a tree, without searching for the rest
+/// (unless ORDER BY needs all of them to find the first)
+pub fn find_first_match(tree: Tree, pattern: &Pattern) -> Option<Match> {
+    match_stream(Arc::new(tree), pattern).next()
+}
 
-        if first_only {
-            // Skip optionals for existence check - just return first valid match
-            results.push(Match {
-                tree: Arc::clone(&tree),
-                bindings: base_bindings,
-            });
-            return results;
+/// Check if a tree has at least one match
+pub fn tree_matches(tree: &Tree, pattern: &Pattern) -> bool {
+    if !satisfies_sentence_constraints(tree, &pattern.sentence_constraints) {
+        return false;
+    }
+    // Skip optionals and ordering for existence check - any valid base match will do
+    let mut solver = Solver::new(tree, &pattern.match_pattern, &Bindings::new());
+    while let Some(base_bindings) = solver.next_solution(tree) {
+        if !is_rejected(tree, pattern, &base_bindings) {
+            return true;
         }
+    }
+    false
+}
 
-        let extended_solutions =
-            process_optionals(&tree, base_bindings, &pattern.optional_patterns);
+/// Sort matches by an ORDER BY spec (stable, so ties keep solver order).
+/// Unbound OPTIONAL variables sort last in either direction.
+fn sort_matches(tree: &Tree, matches: &mut [Match], order_by: &[OrderKey]) {
+    let compare_words = |a: WordId, b: WordId, field: FieldSelector| {
+        let (a, b) = (&tree.words[a], &tree.words[b]);
+        let sym_pair = match field {
+            FieldSelector::Lemma => (a.lemma, b.lemma),
+            FieldSelector::UPOS => (a.upos, b.upos),
+            FieldSelector::XPOS => (a.xpos, b.xpos),
+            FieldSelector::Form => (a.form, b.form),
+            FieldSelector::DepRel => (a.deprel, b.deprel),
+            FieldSelector::TokenId => return a.token_id.cmp(&b.token_id),
+        };
+        tree.string_pool
+            .resolve(sym_pair.0)
+            .cmp(&tree.string_pool.resolve(sym_pair.1))
+    };
 
-        for bindings in extended_solutions {
-            results.push(Match {
-                tree: Arc::clone(&tree),
-                bindings,
-            });
+    matches.sort_by(|x, y| {
+        for key in order_by {
+            let ordering = match (x.bindings.get(&key.var_name), y.bindings.get(&key.var_name)) {
+                (Some(&a), Some(&b)) => {
+                    let ordering = compare_words(a, b, key.field);
+                    if key.ascending {
+                        ordering
+                    } else {
+                        ordering.reverse()
+                    }
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
         }
-    }
-
-    results
+        Ordering::Equal
+    });
 }
 
-fn dfs(
-    tree: &Tree,
+/// Select an unassigned variable with Minimum Remaining Values (MRV).
+///
+/// Ties are broken by degree: a variable with more incident edges constrains more of
+/// its neighbours once assigned, so it prunes in both directions (e.g., the middle
+/// node of `A -> B -> C`).
+fn select_next_var(
     pattern: &BasePattern,
     assign: &[Option<WordId>],
     domains: &[BitFixed<u64>],
-    assigned_words: &BitFixed<u64>,
-    first_only: bool,
-) -> Vec<Bindings> {
-    // No more variables to assign
-    if assign.iter().all(|word_id| word_id.is_some()) {
-        let mut solution = Bindings::new();
-        for (var_id, word_id) in assign.iter().copied().flatten().enumerate() {
-            solution.insert(pattern.var_names[var_id].clone(), word_id);
-        }
-        return vec![solution];
-    }
-
-    // Select an unassigned variable with Minimum Remaining Values (MRV)
-    let next_var = (0..pattern.n_vars)
-        .filter(|&var_id| assign[var_id].is_none())
-        .min_by_key(|&var_id| domains[var_id].count_ones())
-        .unwrap();
+) -> VarId {
+    (0..pattern.n_vars)
+        .map(VarId)
+        .filter(|&VarId(var_id)| assign[var_id].is_none())
+        .min_by_key(|&VarId(var_id)| {
+            (
+                domains[var_id].count_ones(),
+                Reverse(pattern.incident_edges[var_id].len()),
+            )
+        })
+        .unwrap()
+}
 
-    let mut solutions: Vec<Bindings> = Vec::new();
+/// AC-3: for each edge, remove from either end's domain every word that has no partner
+/// in the other end's domain, repeating until nothing changes. Returns false if a domain
+/// becomes empty (no solutions).
+fn enforce_arc_consistency(
+    tree: &Tree,
+    pattern: &BasePattern,
+    domains: &mut [BitFixed<u64>],
+) -> bool {
+    // (edge, true) revises the domain of the edge's source, (edge, false) its target
+    let mut queue: VecDeque<(usize, bool)> = VecDeque::new();
+    let mut queued = vec![[true; 2]; pattern.edge_constraints.len()];
+    for edge_id in 0..pattern.edge_constraints.len() {
+        queue.push_back((edge_id, true));
+        queue.push_back((edge_id, false));
+    }
 
-    // Try each candidate word for this variable (iterate over set bits in the domain bitset)
-    for word_id in domains[next_var].iter() {
-        // AllDifferent: Check if word_id is already assigned to another variable using bitset (O(1))
-        if assigned_words.test(word_id) {
-            continue;
+    while let Some((edge_id, revise_source)) = queue.pop_front() {
+        queued[edge_id][revise_source as usize] = false;
+        let edge_constraint = &pattern.edge_constraints[edge_id];
+        let VarId(from_var_id) = pattern.var_ids[&edge_constraint.from];
+        let VarId(to_var_id) = pattern.var_ids[&edge_constraint.to];
+        let (var_id, other_var_id) = if revise_source {
+            (from_var_id, to_var_id)
+        } else {
+            (to_var_id, from_var_id)
+        };
+
+        let mut changed = false;
+        for w in domains[var_id].iter().collect::<Vec<_>>() {
+            // A self-edge (`X !-> X`) relates each word to itself, not to another word
+            let supported = if var_id == other_var_id {
+                satisfies_arc_constraint(tree, w, w, edge_constraint)
+            } else {
+                domains[other_var_id].iter().any(|v| {
+                    v != w
+                        && if revise_source {
+                            satisfies_arc_constraint(tree, w, v, edge_constraint)
+                        } else {
+                            satisfies_arc_constraint(tree, v, w, edge_constraint)
+                        }
+                })
+            };
+            if !supported {
+                domains[var_id].reset(w);
+                changed = true;
+            }
         }
-
-        // Early prune: Check arc consistency with already-assigned neighbors
-        if !check_arc_consistency(tree, pattern, assign, next_var, word_id) {
+        if !changed {
             continue;
         }
+        if domains[var_id].count_ones() == 0 {
+            return false;
+        }
 
-        let mut new_assign = assign.to_vec();
-        //let mut new_domains = domains.to_vec();
-        let new_domains = domains;
-
-        // Assign var <- word_id and update bitset
-        new_assign[next_var] = Some(word_id);
-        let mut new_assigned_words = assigned_words.clone();
-        new_assigned_words.set(word_id);
-
-        // AllDifferent: Remove word_id from all other unassigned variable domains
-        // for domain in &mut new_domains {
-        //     domain.set(word_id, false);
-        // }
-        // if !(0..pattern.n_vars)
-        //     .all(|var_id| new_assign[var_id].is_some() || new_domains[var_id].count_ones(..) > 0)
-        // {
-        //     continue;
-        // }
-
-        // Forward-check: Propagate along edge constraints touching next_var
-        // if !forward_check(
-        //     tree,
-        //     pattern,
-        //     next_var,
-        //     word_id,
-        //     &mut new_assign,
-        //     &mut new_domains,
-        // ) {
-        //     continue;
-        // }
-
-        // Recurse - go on to next variable
-        solutions.extend(dfs(
-            tree,
-            pattern,
-            &new_assign,
-            new_domains,
-            &new_assigned_words,
-            first_only,
-        ));
-
-        if first_only && !solutions.is_empty() {
-            return solutions;
+        // Neighbours of var_id may have lost their support
+        for directed_edge in &pattern.incident_edges[var_id] {
+            let arc = match *directed_edge {
+                DirectedEdge::Out(other_edge_id) => (other_edge_id, false),
+                DirectedEdge::In(other_edge_id) => (other_edge_id, true),
+            };
+            if !queued[arc.0][arc.1 as usize] {
+                queued[arc.0][arc.1 as usize] = true;
+                queue.push_back(arc);
+            }
         }
     }
-    solutions
+    true
 }
 
-#[allow(dead_code)]
+/// Remove the words that can no longer satisfy an edge to `next_var = word_id` from
+/// the domains of its unassigned neighbours. Returns false if a domain becomes empty.
 fn forward_check(
     tree: &Tree,
     pattern: &BasePattern,
-    next_var: usize,
+    next_var: VarId,
     word_id: WordId,
-    new_assign: &mut [Option<WordId>],
+    new_assign: &[Option<WordId>],
     new_domains: &mut [BitFixed<u64>],
 ) -> bool {
     // Propagate along edge constraints incident to next_var
-    for &edge_idx in &pattern.out_edges[next_var] {
+    for &edge_idx in &pattern.out_edges[next_var.0] {
         let edge_constraint = &pattern.edge_constraints[edge_idx];
-        let target_var_id = pattern.var_ids[&edge_constraint.to];
+        let VarId(target_var_id) = pattern.var_ids[&edge_constraint.to];
         if new_assign[target_var_id].is_some() {
             continue;
         }
@@ -380,9 +960,9 @@ fn forward_check(
         }
     }
 
-    for &edge_idx in &pattern.in_edges[next_var] {
+    for &edge_idx in &pattern.in_edges[next_var.0] {
         let edge_constraint = &pattern.edge_constraints[edge_idx];
-        let source_var_id = pattern.var_ids[&edge_constraint.from];
+        let VarId(source_var_id) = pattern.var_ids[&edge_constraint.from];
         if new_assign[source_var_id].is_some() {
             continue;
         }
@@ -402,22 +982,22 @@ fn check_arc_consistency(
     tree: &Tree,
     pattern: &BasePattern,
     assign: &[Option<WordId>],
-    next_var: usize,
+    next_var: VarId,
     word_id: WordId,
 ) -> bool {
     // Check arc consistency with already-assigned neighbors (early prune)
-    for &edge_id in &pattern.out_edges[next_var] {
+    for &edge_id in &pattern.out_edges[next_var.0] {
         let edge_constraint = &pattern.edge_constraints[edge_id];
-        let target_var_id = pattern.var_ids[&edge_constraint.to];
+        let VarId(target_var_id) = pattern.var_ids[&edge_constraint.to];
         if assign[target_var_id].is_some_and(|target_word_id| {
             !satisfies_arc_constraint(tree, word_id, target_word_id, edge_constraint)
         }) {
             return false;
         }
     }
-    for &edge_id in &pattern.in_edges[next_var] {
+    for &edge_id in &pattern.in_edges[next_var.0] {
         let edge_constraint = &pattern.edge_constraints[edge_id];
-        let source_var_id = pattern.var_ids[&edge_constraint.from];
+        let VarId(source_var_id) = pattern.var_ids[&edge_constraint.from];
         if assign[source_var_id].is_some_and(|source_word_id| {
             !satisfies_arc_constraint(tree, source_word_id, word_id, edge_constraint)
         }) {
@@ -427,15 +1007,175 @@ fn check_arc_consistency(
     true
 }
 
-/// Search a tree with a pre-compiled pattern
-pub fn search_tree(tree: Tree, pattern: &Pattern) -> Vec<Match> {
-    find_all_matches(tree, pattern)
+impl Pattern {
+    /// Describe how the solver would search `tree` for this pattern.
+    ///
+    /// For each block, lists the variables in the order MRV picks them at the start
+    /// of the search, with their candidate counts before and after arc consistency,
+    /// and the step at which each edge constraint is checked. The plan depends on the
+    /// tree: domain sizes, and so the order, change from tree to tree, and forward
+    /// checking can change the order further down the search.
+    pub fn explain(&self, tree: &Tree) -> String {
+        let mut out = String::new();
+        if !self.sentence_constraints.is_empty() {
+            if satisfies_sentence_constraints(tree, &self.sentence_constraints) {
+                out.push_str("SENTENCE: satisfied\n");
+            } else {
+                out.push_str("SENTENCE: not satisfied, so the tree is not searched\n");
+                return out;
+            }
+        }
+        explain_block(&mut out, "MATCH", tree, &self.match_pattern, &[]);
+        let match_vars = &self.match_pattern.var_names;
+        for (i, block) in self.optional_patterns.iter().enumerate() {
+            let title = format!("OPTIONAL {}", i + 1);
+            explain_block(&mut out, &title, tree, block, match_vars);
+        }
+        for (i, block) in self.except_patterns.iter().enumerate() {
+            let title = format!("EXCEPT {}", i + 1);
+            explain_block(&mut out, &title, tree, block, match_vars);
+        }
+        if !self.order_by.is_empty() {
+            out.push_str(
+                "ORDER BY: all matches are found and sorted before the first is returned\n",
+            );
+        }
+        out
+    }
+}
+
+/// Append the plan for one block; variables in `bound_vars` are bound by MATCH
+/// before the block is searched
+fn explain_block(
+    out: &mut String,
+    title: &str,
+    tree: &Tree,
+    pattern: &BasePattern,
+    bound_vars: &[String],
+) {
+    let count = |n: usize, noun: &str| match n {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", n, noun),
+    };
+    writeln!(
+        out,
+        "{}: {}, {}",
+        title,
+        count(pattern.n_vars, "variable"),
+        count(pattern.edge_constraints.len(), "edge constraint")
+    )
+    .unwrap();
+
+    let no_words = BitFixed::new(tree.words.len());
+    let node_domains: Vec<BitFixed<u64>> = pattern
+        .var_constraints
+        .iter()
+        .map(|constr| node_domain(tree, constr, &no_words))
+        .collect();
+    let mut domains = node_domains.clone();
+    let consistent = node_domains.iter().all(|domain| domain.count_ones() > 0)
+        && enforce_arc_consistency(tree, pattern, &mut domains);
+
+    // Bound variables come first, then MRV picks from the initial domains
+    let mut assign: Vec<Option<WordId>> = vec![None; pattern.n_vars];
+    let mut order = Vec::new();
+    for (var_id, var_name) in pattern.var_names.iter().enumerate() {
+        if bound_vars.contains(var_name) {
+            assign[var_id] = Some(0);
+            order.push(var_id);
+        }
+    }
+    while order.len() < pattern.n_vars {
+        let VarId(var_id) = select_next_var(pattern, &assign, &domains);
+        assign[var_id] = Some(0);
+        order.push(var_id);
+    }
+
+    let mut step = vec![0; pattern.n_vars];
+    for (i, &var_id) in order.iter().enumerate() {
+        step[var_id] = i + 1;
+        let var_name = &pattern.var_names[var_id];
+        if bound_vars.contains(var_name) {
+            writeln!(out, "  {}. {}: bound by MATCH", i + 1, var_name).unwrap();
+            continue;
+        }
+        write!(
+            out,
+            "  {}. {} [{}]: {}",
+            i + 1,
+            var_name,
+            pattern.var_constraints[var_id],
+            count(node_domains[var_id].count_ones(), "candidate")
+        )
+        .unwrap();
+        if consistent {
+            write!(
+                out,
+                ", {} after arc consistency",
+                domains[var_id].count_ones()
+            )
+            .unwrap();
+        }
+        out.push('\n');
+    }
+    for edge in &pattern.edge_constraints {
+        let later = [&edge.from, &edge.to]
+            .into_iter()
+            .max_by_key(|var_name| step[pattern.var_ids[*var_name].0])
+            .unwrap();
+        let at = step[pattern.var_ids[later].0];
+        writeln!(
+            out,
+            "  Edge {}: checked when {} is assigned (step {})",
+            edge, later, at
+        )
+        .unwrap();
+    }
+    if !consistent {
+        out.push_str("  A domain is empty: no matches in this tree\n");
+    }
+}
+
+/// Search a tree with a pre-compiled pattern.
+///
+/// `max_matches` caps the number of matches returned from this tree; the search
+/// stops as soon as the cap is reached (with ORDER BY, the first ones in order are kept).
+/// Any `order` other than `Unsorted` needs all the matches of the tree: they are
+/// sorted (after ORDER BY, which then only breaks ties) before the cap is applied.
+pub fn search_tree(
+    tree: Tree,
+    pattern: &Pattern,
+    max_matches: Option<usize>,
+    order: &MatchOrder,
+) -> Vec<Match> {
+    search_shared_tree(Arc::new(tree), pattern, max_matches, order)
+}
+
+/// [`search_tree`] for a tree that is also searched with other patterns
+pub fn search_shared_tree(
+    tree: Arc<Tree>,
+    pattern: &Pattern,
+    max_matches: Option<usize>,
+    order: &MatchOrder,
+) -> Vec<Match> {
+    let stream = match_stream(tree, pattern);
+    if *order == MatchOrder::Unsorted {
+        return stream.take(max_matches.unwrap_or(usize::MAX)).collect();
+    }
+    let mut matches: Vec<Match> = stream.collect();
+    order_matches(&mut matches, order);
+    matches.truncate(max_matches.unwrap_or(usize::MAX));
+    matches
 }
 
-/// Search a tree with a query string
-pub fn search_tree_query(tree: Tree, query: &str) -> Result<Vec<Match>, QueryError> {
+/// Search a tree with a query string (see [`search_tree`])
+pub fn search_tree_query(
+    tree: Tree,
+    query: &str,
+    max_matches: Option<usize>,
+) -> Result<Vec<Match>, QueryError> {
     let pattern = compile_query(query)?;
-    Ok(find_all_matches(tree, &pattern))
+    Ok(find_all_matches(tree, &pattern, max_matches))
 }
 
 #[cfg(test)]
@@ -450,6 +1190,28 @@ mod tests {
         }};
     }
 
+    /// Sorted ids bound to `var` by every match of the query
+    fn match_ids(tree: &Tree, query: &str, var: &str) -> Vec<WordId> {
+        let mut ids: Vec<_> = search_tree_query(tree.clone(), query, None)
+            .unwrap()
+            .iter()
+            .map(|m| m.bindings[var])
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    /// Sorted pairs of ids bound to two variables by every match of the query
+    fn match_pairs(tree: &Tree, query: &str, [x, y]: [&str; 2]) -> Vec<(WordId, WordId)> {
+        let mut pairs: Vec<_> = search_tree_query(tree.clone(), query, None)
+            .unwrap()
+            .iter()
+            .map(|m| (m.bindings[x], m.bindings[y]))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
     fn build_test_tree() -> Tree {
         let mut tree = Tree::default();
         tree.add_minimal_word(0, b"helped", b"help", b"VERB", b"_", None, b"root");
@@ -486,35 +1248,176 @@ mod tests {
     }
 
     #[test]
-    fn test_search_single_var_constraints() {
+    fn test_explain() {
         let tree = build_test_tree();
+        let pattern = compile_query(
+            "MATCH { V [lemma=\"help\"]; X []; V -> X; } OPTIONAL { X -[mark]-> M; }",
+        )
+        .unwrap();
+        assert_eq!(
+            pattern.explain(&tree),
+            "MATCH: 2 variables, 1 edge constraint\n\
+             \x20 1. V [lemma=\"help\"]: 1 candidate, 1 after arc consistency\n\
+             \x20 2. X []: 4 candidates, 2 after arc consistency\n\
+             \x20 Edge V -> X: checked when X is assigned (step 2)\n\
+             OPTIONAL 1: 2 variables, 1 edge constraint\n\
+             \x20 1. X: bound by MATCH\n\
+             \x20 2. M [deprel=\"mark\"]: 1 candidate, 1 after arc consistency\n\
+             \x20 Edge X -[mark]-> M: checked when M is assigned (step 2)\n"
+        );
 
-        // Test lemma constraint - should find one match
-        let matches: Vec<_> =
-            search_tree_query(tree.clone(), "MATCH { V [lemma=\"help\"]; }").unwrap();
-        assert_eq!(matches.len(), 1);
-        assert_eq!(matches[0].bindings, hashmap! { "V" => 0 });
-
-        // Test upos constraint - should match both verbs
-        let matches: Vec<_> =
-            search_tree_query(tree.clone(), "MATCH { V [upos=\"VERB\"]; }").unwrap();
+        let pattern = compile_query("MATCH { N [upos=\"NOUN\" | lemma=/wi.*/]; }").unwrap();
+        let plan = pattern.explain(&tree);
+        assert!(plan.contains("N [(upos=\"NOUN\" | lemma=/"), "{}", plan);
+        let pattern = compile_query("MATCH { N [upos=\"NOUN\"]; }").unwrap();
+        assert!(
+            pattern
+                .explain(&tree)
+                .ends_with("no matches in this tree\n")
+        );
+    }
+
+    #[test]
+    fn test_match_order() {
+        let tree = build_test_tree();
+        let pattern = compile_query("MATCH { H []; D []; H -> D; }").unwrap();
+        let search = |order: MatchOrder, max_matches| {
+            search_tree(tree.clone(), &pattern, max_matches, &order)
+                .iter()
+                .map(|m| (m.bindings["H"], m.bindings["D"]))
+                .collect::<Vec<_>>()
+        };
+
+        // helped -> us, helped -> win, win -> to
+        let mut unsorted = search(MatchOrder::Unsorted, None);
+        unsorted.sort();
+        assert_eq!(unsorted, vec![(0, 1), (0, 3), (3, 2)]);
+        assert_eq!(search(MatchOrder::LeftmostFirst, None)[2], (3, 2));
+        assert_eq!(search(MatchOrder::RightmostFirst, Some(1)), vec![(0, 3)]);
+        assert_eq!(
+            search(MatchOrder::ByVar("D".to_string()), None),
+            vec![(0, 1), (3, 2), (0, 3)]
+        );
+
+        let m = &search_tree(tree.clone(), &pattern, Some(1), &MatchOrder::LeftmostFirst)[0];
+        assert_eq!(
+            (m.leftmost_word_id(), m.rightmost_word_id()),
+            (Some(0), Some(1))
+        );
+        assert_eq!(m.span(), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_sentence_constraints() {
+        let mut tree = build_test_tree();
+        tree.sentence_text = Some("helped us to win".to_string());
+        tree.metadata
+            .insert("genre".to_string(), "news".to_string());
+        let count = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            assert_eq!(
+                tree_matches(&tree, &pattern),
+                !search_tree(tree.clone(), &pattern, None, &MatchOrder::Unsorted).is_empty()
+            );
+            search_tree(tree.clone(), &pattern, None, &MatchOrder::Unsorted).len()
+        };
+
+        let verbs = "MATCH { V [upos=\"VERB\"]; }";
+        assert_eq!(count(verbs), 2);
+        assert_eq!(
+            count(&format!(
+                "SENTENCE {{ metadata.genre = \"news\"; }} {}",
+                verbs
+            )),
+            2
+        );
+        assert_eq!(
+            count(&format!(
+                "SENTENCE {{ metadata.genre = \"blog\"; }} {}",
+                verbs
+            )),
+            0
+        );
+        assert_eq!(
+            count(&format!(
+                "SENTENCE {{ metadata.source = \"x\"; }} {}",
+                verbs
+            )),
+            0
+        );
+        assert_eq!(
+            count(&format!("SENTENCE {{ text CONTAINS \"us to\" }} {}", verbs)),
+            2
+        );
+        assert_eq!(count(&format!("SENTENCE {{ text = /win$/ }} {}", verbs)), 2);
+        // All constraints have to hold
+        assert_eq!(
+            count(&format!(
+                "SENTENCE {{ text CONTAINS \"us\"; text = /^win/; }} {}",
+                verbs
+            )),
+            0
+        );
+
+        tree.sentence_text = None;
+        let pattern = compile_query(&format!("SENTENCE {{ text CONTAINS \"us\" }} {}", verbs));
+        assert!(!tree_matches(&tree, &pattern.unwrap()));
+    }
+
+    #[test]
+    fn test_search_single_var_constraints() {
+        let tree = build_test_tree();
+
+        // Test lemma constraint - should find one match
+        let matches: Vec<_> =
+            search_tree_query(tree.clone(), "MATCH { V [lemma=\"help\"]; }", None).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].bindings, hashmap! { "V" => 0 });
+
+        // Test upos constraint - should match both verbs
+        let matches: Vec<_> =
+            search_tree_query(tree.clone(), "MATCH { V [upos=\"VERB\"]; }", None).unwrap();
         assert_eq!(matches.len(), 2);
         assert_eq!(matches[0].bindings, hashmap! { "V" => 0 });
         assert_eq!(matches[1].bindings, hashmap! { "V" => 3 });
 
         // Test form constraint
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), "MATCH { W [form=\"to\"]; }").unwrap();
+            search_tree_query(tree.clone(), "MATCH { W [form=\"to\"]; }", None).unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "W" => 2 });
 
         // Test deprel constraint
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), "MATCH { X [deprel=\"xcomp\"]; }").unwrap();
+            search_tree_query(tree.clone(), "MATCH { X [deprel=\"xcomp\"]; }", None).unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "X" => 3});
     }
 
+    #[test]
+    fn test_var_with_parent_and_child_edges() {
+        // saw(0) -> John(1, nsubj), running(2, xcomp); running -> quickly(3, advmod)
+        let tree = build_multi_verb_tree();
+
+        // X has an incoming edge and an outgoing edge; V has two outgoing edges
+        let matches = search_tree_query(
+            tree.clone(),
+            "MATCH { V []; S []; X []; M []; V -[nsubj]-> S; V -> X; X -> M; }",
+            None,
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            matches[0].bindings,
+            hashmap! { "V" => 0, "S" => 1, "X" => 2, "M" => 3 }
+        );
+
+        // Two parents for the same variable can never match, but must not fail
+        let matches =
+            search_tree_query(tree, "MATCH { P []; Q []; C []; P -> C; Q -> C; }", None).unwrap();
+        assert!(matches.is_empty());
+    }
+
     #[test]
     fn test_search_tree_query_multiple_children() {
         let tree = build_coord_tree();
@@ -522,6 +1425,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             "MATCH { C [upos=\"CCONJ\"]; N1 []; N2 []; C -[conj]-> N1; C -[conj]-> N2; }",
+            None,
         )
         .unwrap();
         // Should find both permutations: (and, cats, dogs) and (and, dogs, cats)
@@ -557,7 +1461,7 @@ mod tests {
         // Find chain: helped -> win -> to (tests forward-checking efficiency)
         let matches: Vec<_> = search_tree_query(
             tree,
-            "MATCH { V1 [lemma=\"help\"]; V2 [lemma=\"win\"]; T [lemma=\"to\"]; V1 -> V2; V2 -> T; }",
+            "MATCH { V1 [lemma=\"help\"]; V2 [lemma=\"win\"]; T [lemma=\"to\"]; V1 -> V2; V2 -> T; }", None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -573,20 +1477,21 @@ mod tests {
 
         // No matches - word doesn't exist
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), "MATCH { N [upos=\"NOUN\"]; }").unwrap();
+            search_tree_query(tree.clone(), "MATCH { N [upos=\"NOUN\"]; }", None).unwrap();
         assert_eq!(matches.len(), 0);
 
         // Multiple constraints (AND)
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             "MATCH { V [lemma=\"help\" & upos=\"VERB\"]; }",
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "V" => 0 });
 
         // Unconstrained variable - matches all words
-        let matches: Vec<_> = search_tree_query(tree.clone(), "MATCH { X []; }").unwrap();
+        let matches: Vec<_> = search_tree_query(tree.clone(), "MATCH { X []; }", None).unwrap();
         assert_eq!(matches.len(), 4);
     }
 
@@ -594,7 +1499,8 @@ mod tests {
     fn test_search_tree_query_exhaustive_matching() {
         let tree = build_coord_tree();
         // Find all nouns (exhaustive search should find both)
-        let matches: Vec<_> = search_tree_query(tree, "MATCH { N [upos=\"NOUN\"]; }").unwrap();
+        let matches: Vec<_> =
+            search_tree_query(tree, "MATCH { N [upos=\"NOUN\"]; }", None).unwrap();
         // Should find both "cats" and "dogs"
         assert_eq!(matches.len(), 2);
         assert!(
@@ -620,10 +1526,11 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             "MATCH { V1 [upos=\"VERB\"]; S []; V2 [upos=\"VERB\"]; V1 -[nsubj]-> S; V1 -> V2; }",
+            None,
         )
         .unwrap();
         // Should match saw -> John + saw -> running
-        assert!(matches.len() >= 1);
+        assert!(!matches.is_empty());
         assert!(
             matches
                 .iter()
@@ -637,7 +1544,7 @@ mod tests {
     fn test_search_empty_pattern() {
         let tree = build_test_tree();
         // Empty pattern has no variables, so returns one empty match
-        let matches: Vec<_> = search_tree_query(tree, "MATCH { }").unwrap();
+        let matches: Vec<_> = search_tree_query(tree, "MATCH { }", None).unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! {});
     }
@@ -651,6 +1558,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             "MATCH { V1 [lemma=\"help\"]; V2 [lemma=\"win\"]; V1 << V2; }",
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -660,6 +1568,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             "MATCH { V1 [lemma=\"win\"]; V2 [lemma=\"help\"]; V1 << V2; }",
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 0);
@@ -668,6 +1577,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             "MATCH { T [lemma=\"to\"]; V [lemma=\"win\"]; T < V; }",
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -677,6 +1587,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             "MATCH { V1 [lemma=\"help\"]; V2 [lemma=\"win\"]; V1 < V2; }",
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 0);
@@ -693,6 +1604,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             "MATCH { V1 [lemma=\"help\"]; V2 [lemma=\"win\"]; V1 -[xcomp]-> V2; V1 << V2; }",
+            None,
         )
         .unwrap();
 
@@ -709,8 +1621,12 @@ mod tests {
         let tree = build_test_tree();
 
         // Without precedence, dependency edge matches
-        let matches_no_precedence: Vec<_> =
-            search_tree_query(tree.clone(), "MATCH { V1 []; V2 []; V1 -[xcomp]-> V2; }").unwrap();
+        let matches_no_precedence: Vec<_> = search_tree_query(
+            tree.clone(),
+            "MATCH { V1 []; V2 []; V1 -[xcomp]-> V2; }",
+            None,
+        )
+        .unwrap();
         assert_eq!(matches_no_precedence.len(), 1);
 
         // But if we add a false precedence constraint (win << helped),
@@ -718,6 +1634,7 @@ mod tests {
         let matches_with_false_precedence: Vec<_> = search_tree_query(
             tree.clone(),
             "MATCH { V1 []; V2 []; V1 -[xcomp]-> V2; V2 << V1; }",
+            None,
         )
         .unwrap();
 
@@ -738,6 +1655,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             "MATCH { C [lemma=\"and\"]; N [lemma=\"cat\"]; C << N; }",
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -754,6 +1672,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             "MATCH { A [lemma=\"help\"]; B [lemma=\"we\"]; C [lemma=\"to\"]; A << B; B << C; }",
+            None,
         )
         .unwrap();
 
@@ -770,34 +1689,35 @@ mod tests {
         let mut tree = Tree::default();
 
         // Word 0: "was" - lemma=be, Tense=Past, Number=Sing
-        let mut feats_was = Features::new();
-        feats_was.push((
-            tree.string_pool.get_or_intern(b"Tense"),
-            tree.string_pool.get_or_intern(b"Past"),
-        ));
-        feats_was.push((
-            tree.string_pool.get_or_intern(b"Number"),
-            tree.string_pool.get_or_intern(b"Sing"),
-        ));
-        let mut misc_was = Features::new();
-        misc_was.push((
+        let feats_was: Features = vec![
+            (
+                tree.string_pool.get_or_intern(b"Tense"),
+                tree.string_pool.get_or_intern(b"Past"),
+            ),
+            (
+                tree.string_pool.get_or_intern(b"Number"),
+                tree.string_pool.get_or_intern(b"Sing"),
+            ),
+        ];
+        let misc_was: Features = vec![(
             tree.string_pool.get_or_intern(b"SpaceAfter"),
             tree.string_pool.get_or_intern(b"No"),
-        ));
+        )];
         tree.add_word(
             0, 1, b"was", b"be", b"VERB", b"_", feats_was, None, b"root", misc_was,
         );
 
         // Word 1: "running" - Tense=Pres, VerbForm=Part
-        let mut feats_run = Features::new();
-        feats_run.push((
-            tree.string_pool.get_or_intern(b"Tense"),
-            tree.string_pool.get_or_intern(b"Pres"),
-        ));
-        feats_run.push((
-            tree.string_pool.get_or_intern(b"VerbForm"),
-            tree.string_pool.get_or_intern(b"Part"),
-        ));
+        let feats_run: Features = vec![
+            (
+                tree.string_pool.get_or_intern(b"Tense"),
+                tree.string_pool.get_or_intern(b"Pres"),
+            ),
+            (
+                tree.string_pool.get_or_intern(b"VerbForm"),
+                tree.string_pool.get_or_intern(b"Part"),
+            ),
+        ];
         tree.add_word(
             1,
             2,
@@ -835,7 +1755,7 @@ mod tests {
 
         // Single feature constraint
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="Past"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="Past"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "V" => 0 }); // "was"
 
@@ -843,6 +1763,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             r#"MATCH { V [feats.Tense="Past" & feats.Number="Sing"]; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -852,6 +1773,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             r#"MATCH { V [lemma="be" & feats.Tense="Past"]; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -859,13 +1781,14 @@ mod tests {
 
         // Non-existent feature value
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="Fut"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="Fut"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 0); // No future tense verbs
 
         // Word with no features
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             r#"MATCH { P [upos="PUNCT" & feats.Tense="Past"]; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 0); // PUNCT has no Tense feature
@@ -877,13 +1800,18 @@ mod tests {
 
         // Single misc constraint
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { V [misc.SpaceAfter="No"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { V [misc.SpaceAfter="No"]; }"#, None)
+                .unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "V" => 0 }); // "was"
 
         // Non-existent misc value
-        let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { V [misc.SpaceAfter="Yes"]; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(
+            tree.clone(),
+            r#"MATCH { V [misc.SpaceAfter="Yes"]; }"#,
+            None,
+        )
+        .unwrap();
         assert_eq!(matches.len(), 0);
     }
 
@@ -893,17 +1821,17 @@ mod tests {
 
         // Correct case
         let matches =
-            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="Past"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="Past"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 1);
 
         // Wrong key case
         let matches =
-            search_tree_query(tree.clone(), r#"MATCH { V [feats.tense="Past"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { V [feats.tense="Past"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 0);
 
         // Wrong value case
         let matches =
-            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="past"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { V [feats.Tense="past"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 0);
     }
 
@@ -913,7 +1841,8 @@ mod tests {
         let tree = build_test_tree();
 
         // Find all words that are NOT VERBs
-        let matches: Vec<_> = search_tree_query(tree, r#"MATCH { W [upos!="VERB"]; }"#).unwrap();
+        let matches: Vec<_> =
+            search_tree_query(tree, r#"MATCH { W [upos!="VERB"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 2); // us (PRON), to (PART)
         assert!(
             matches
@@ -936,8 +1865,12 @@ mod tests {
         let tree = build_feature_tree();
 
         // Find all verbs that are NOT past tense
-        let matches: Vec<_> =
-            search_tree_query(tree, r#"MATCH { V [upos="VERB" & feats.Tense!="Past"]; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(
+            tree,
+            r#"MATCH { V [upos="VERB" & feats.Tense!="Past"]; }"#,
+            None,
+        )
+        .unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "V" => 1 }); // "running" has Tense=Pres
     }
@@ -952,12 +1885,30 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             r#"MATCH { V [upos="VERB"]; T [lemma="to"]; V !-> T; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "V" => 0, "T" => 2 }); // helped !-> to
     }
 
+    #[test]
+    fn test_multi_label_edge() {
+        // Tree: "helped" (0) -> "us" (1, obj), "win" (3, xcomp); "win" -> "to" (2, mark)
+        let tree = build_test_tree();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(ids("MATCH { V []; W []; V -[obj|xcomp]-> W; }"), vec![1, 3]);
+        assert_eq!(ids("MATCH { V []; W []; V -[mark|nsubj]-> W; }"), vec![2]);
+        assert_eq!(
+            ids("MATCH { V [lemma=\"help\"]; W []; V !-[obj|xcomp]-> W; }"),
+            vec![2]
+        );
+        assert_eq!(ids("MATCH { W -[nsubj|mark]-> _; }"), vec![3]);
+        assert_eq!(ids("MATCH { _ -[obj|mark]-> W; }"), vec![1, 2]);
+        assert_eq!(ids("MATCH { W -[obj|xcomp]{2}-> _; }"), vec![0]);
+    }
+
     #[test]
     fn test_negative_labeled_edge() {
         // Tree: "helped" (0) -> "us" (1, obj), "win" (3, xcomp)
@@ -966,8 +1917,12 @@ mod tests {
         // Find verb V and word W where V does NOT have obj edge to W
         // "helped" has obj to "us" (1), so pairs with W=1 should be excluded
         // Also, AllDifferent constraint means V != W
-        let matches: Vec<_> =
-            search_tree_query(tree, r#"MATCH { V [lemma="help"]; W []; V !-[obj]-> W; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(
+            tree,
+            r#"MATCH { V [lemma="help"]; W []; V !-[obj]-> W; }"#,
+            None,
+        )
+        .unwrap();
 
         // Should match V=0 with W=2, W=3 (not W=1 which is obj, not W=0 due to AllDifferent)
         assert_eq!(matches.len(), 2);
@@ -1004,6 +1959,7 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree,
             r#"MATCH { V []; Y []; W []; V -[xcomp]-> Y; V !-[obj]-> W; }"#,
+            None,
         )
         .unwrap();
 
@@ -1022,7 +1978,7 @@ mod tests {
         let tree = build_test_tree();
 
         // Find words that do NOT have any incoming edges (i.e., root words)
-        let matches: Vec<_> = search_tree_query(tree, r#"MATCH { W []; _ !-> W; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(tree, r#"MATCH { W []; _ !-> W; }"#, None).unwrap();
 
         // Only word 0 (helped) has no incoming edge (it's the root)
         assert_eq!(matches.len(), 1);
@@ -1035,7 +1991,8 @@ mod tests {
         let tree = build_test_tree();
 
         // Find words that are NOT anyone's obj (i.e., deprel != "obj")
-        let matches: Vec<_> = search_tree_query(tree, r#"MATCH { W []; _ !-[obj]-> W; }"#).unwrap();
+        let matches: Vec<_> =
+            search_tree_query(tree, r#"MATCH { W []; _ !-[obj]-> W; }"#, None).unwrap();
 
         // Words 0 (root), 2 (mark), 3 (xcomp) are not obj of anyone
         assert_eq!(matches.len(), 3);
@@ -1078,7 +2035,7 @@ mod tests {
         let pattern = compile_query(r#"MATCH { V []; W []; V !-[obj]-> W; }"#).unwrap();
 
         // Check that W does not have a DepRel constraint
-        let w_id = *pattern.match_pattern.var_ids.get("W").unwrap();
+        let VarId(w_id) = pattern.match_pattern.var_ids["W"];
         match &pattern.match_pattern.var_constraints[w_id] {
             Constraint::Any => { /* Expected - no constraint */ }
             Constraint::And(constraints) => {
@@ -1104,6 +2061,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [upos="VERB"]; }
                EXCEPT { M [upos="ADV"]; V -[advmod]-> M; }"#,
+            None,
         )
         .unwrap();
         // Should find word 0 ("saw") but not word 2 ("running" with advmod)
@@ -1116,6 +2074,7 @@ mod tests {
             r#"MATCH { V [upos="VERB"]; }
                EXCEPT { M [upos="ADV"]; V -[advmod]-> M; }
                EXCEPT { C [upos="VERB"]; V -[xcomp]-> C; }"#,
+            None,
         )
         .unwrap();
         // Both verbs rejected: saw has xcomp, running has advmod
@@ -1126,6 +2085,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [upos="VERB"]; S [upos="PROPN"]; V -[nsubj]-> S; }
                EXCEPT { C [upos="VERB"]; V -[xcomp]-> C; }"#,
+            None,
         )
         .unwrap();
         // saw-John pair rejected because saw has xcomp
@@ -1142,6 +2102,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [lemma="see"]; }
                OPTIONAL { S [upos="PROPN"]; V -[nsubj]-> S; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -1152,6 +2113,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [lemma="run"]; }
                OPTIONAL { S [upos="PROPN"]; V -[nsubj]-> S; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1);
@@ -1164,6 +2126,7 @@ mod tests {
             r#"MATCH { V [lemma="see"]; }
                OPTIONAL { S [upos="PROPN"]; V -[nsubj]-> S; }
                OPTIONAL { C [upos="VERB"]; V -[xcomp]-> C; }"#,
+            None,
         )
         .unwrap();
         // Both OPTIONAL blocks match, so we get the cross-product (1 result with both)
@@ -1184,6 +2147,7 @@ mod tests {
             r#"MATCH { V [upos="VERB"]; }
                EXCEPT { M [upos="ADV"]; V -[advmod]-> M; }
                OPTIONAL { S [upos="PROPN"]; V -[nsubj]-> S; }"#,
+            None,
         )
         .unwrap();
         // Should find only word 0 ("saw"), with subject
@@ -1200,6 +2164,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [upos="VERB"]; }
                EXCEPT { N [upos="NOUN"]; V -[obj]-> N; }"#,
+            None,
         )
         .unwrap();
         // Both verbs should be found (no NOUNs in tree)
@@ -1217,6 +2182,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [upos="VERB"]; }
                EXCEPT { V [upos="VERB"]; }"#,
+            None,
         )
         .unwrap();
         // All verbs should be rejected
@@ -1238,6 +2204,7 @@ mod tests {
                    V -[xcomp]-> C;
                    C -[advmod]-> M;
                }"#,
+            None,
         )
         .unwrap();
         // Only "running" should be found (not "saw", which has xcomp to running which has advmod)
@@ -1261,6 +2228,7 @@ mod tests {
             tree,
             r#"MATCH { V [lemma="help"]; }
                OPTIONAL { P [upos="PRON"]; V -> P; }"#,
+            None,
         )
         .unwrap();
 
@@ -1296,11 +2264,17 @@ mod tests {
             r#"MATCH { V [lemma="help"]; }
                OPTIONAL { P [upos="PRON"]; V -> P; }
                OPTIONAL { A [upos="ADV"]; V -> A; }"#,
+            None,
         )
         .unwrap();
 
-        // Cross-product: 2 PRONs × 2 ADVs = 4 results
+        // Cross-product: 2 PRONs × 2 ADVs = 4 results, the last block varying fastest
         assert_eq!(matches.len(), 4);
+        let pairs: Vec<_> = matches
+            .iter()
+            .map(|m| (m.bindings["P"], m.bindings["A"]))
+            .collect();
+        assert_eq!(pairs, vec![(1, 3), (1, 4), (2, 3), (2, 4)]);
 
         // Verify all combinations exist
         assert!(
@@ -1325,6 +2299,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_optional_product_solves_blocks_lazily() {
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"helped", b"help", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(1, b"He", b"he", b"PRON", b"_", Some(0), b"nsubj");
+        tree.add_minimal_word(2, b"us", b"we", b"PRON", b"_", Some(0), b"obj");
+        tree.add_minimal_word(3, b"quickly", b"quickly", b"ADV", b"_", Some(0), b"advmod");
+        tree.add_minimal_word(4, b"very", b"very", b"ADV", b"_", Some(0), b"advmod");
+        tree.compile_tree();
+        let pattern = compile_query(
+            r#"MATCH { V [lemma="help"]; }
+               OPTIONAL { P [upos="PRON"]; V -> P; }
+               OPTIONAL { N [upos="NOUN"]; V -> N; }
+               OPTIONAL { A [upos="ADV"]; V -> A; }"#,
+        )
+        .unwrap();
+
+        let mut product =
+            OptionalProduct::new(&tree, hashmap! { "V" => 0 }, &pattern.optional_patterns);
+        let found = |product: &OptionalProduct| -> Vec<usize> {
+            product
+                .blocks
+                .iter()
+                .map(|block| block.found.len())
+                .collect()
+        };
+        assert!(found(&product).iter().all(|&n| n == 0));
+
+        // The first match needs one solution per block; the NOUN block has none
+        assert_eq!(
+            product.next(&tree),
+            Some(hashmap! { "V" => 0, "P" => 1, "A" => 3 })
+        );
+        assert_eq!(found(&product), vec![1, 1]);
+        assert_eq!(product.next(&tree).unwrap()["A"], 4);
+        assert_eq!(found(&product), vec![1, 2]);
+
+        let rest: Vec<_> = std::iter::from_fn(|| product.next(&tree))
+            .map(|m| (m["P"], m["A"]))
+            .collect();
+        assert_eq!(rest, vec![(2, 3), (2, 4)]);
+        assert_eq!(product.next(&tree), None);
+    }
+
     #[test]
     fn test_optional_one_matches_one_doesnt() {
         // Test where one OPTIONAL matches and another doesn't
@@ -1335,6 +2353,7 @@ mod tests {
             r#"MATCH { V [lemma="see"]; }
                OPTIONAL { S [upos="PROPN"]; V -[nsubj]-> S; }
                OPTIONAL { N [upos="NOUN"]; V -[obj]-> N; }"#,
+            None,
         )
         .unwrap();
 
@@ -1365,6 +2384,7 @@ mod tests {
                EXCEPT { A [upos="ADV"]; V -[advmod]-> A; }
                OPTIONAL { S [upos="PRON"]; V -[nsubj]-> S; }
                OPTIONAL { O [upos="PRON"]; V -[obj]-> O; }"#,
+            None,
         )
         .unwrap();
 
@@ -1390,6 +2410,7 @@ mod tests {
             tree.clone(),
             r#"MATCH { V [upos="VERB"]; }
                EXCEPT { V -[mark]-> _; }"#,
+            None,
         )
         .unwrap();
 
@@ -1413,7 +2434,7 @@ mod tests {
         // Multiple matches - tree_matches returns true
         let tree = build_coord_tree();
         let pattern = compile_query("MATCH { N [upos=\"NOUN\"]; }").unwrap();
-        assert_eq!(find_all_matches(tree.clone(), &pattern).len(), 2);
+        assert_eq!(find_all_matches(tree.clone(), &pattern, None).len(), 2);
         assert!(tree_matches(&tree, &pattern));
 
         // No matches - tree_matches returns false
@@ -1429,6 +2450,25 @@ mod tests {
         assert!(tree_matches(&tree, &pattern));
     }
 
+    #[test]
+    fn test_find_first_match() {
+        let tree = build_coord_tree();
+        let pattern = compile_query("MATCH { N [upos=\"NOUN\"]; }").unwrap();
+        let first = find_first_match(tree.clone(), &pattern).unwrap();
+        assert_eq!(
+            first.bindings,
+            find_all_matches(tree.clone(), &pattern, None)[0].bindings
+        );
+
+        // ORDER BY decides which match comes first
+        let pattern = compile_query("MATCH { N [upos=\"NOUN\"]; } ORDER BY N.form DESC").unwrap();
+        let first = find_first_match(tree.clone(), &pattern).unwrap();
+        assert_eq!(first.bindings, hashmap! { "N" => 2 });
+
+        let pattern = compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap();
+        assert!(find_first_match(tree, &pattern).is_none());
+    }
+
     /// Helper to build a tree with xpos values
     fn build_xpos_tree() -> Tree {
         let mut tree = Tree::default();
@@ -1445,24 +2485,32 @@ mod tests {
 
         // Single xpos constraint
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [xpos="DT"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [xpos="DT"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "W" => 0 }); // "The"
 
         // xpos combined with upos
-        let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [upos="VERB" & xpos="VBZ"]; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(
+            tree.clone(),
+            r#"MATCH { W [upos="VERB" & xpos="VBZ"]; }"#,
+            None,
+        )
+        .unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "W" => 2 }); // "runs"
 
         // xpos with no match
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [xpos="VBD"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [xpos="VBD"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 0);
 
         // Negative xpos constraint
-        let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [upos="NOUN" & xpos!="NNS"]; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(
+            tree.clone(),
+            r#"MATCH { W [upos="NOUN" & xpos!="NNS"]; }"#,
+            None,
+        )
+        .unwrap();
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0].bindings, hashmap! { "W" => 1 }); // "dog" has xpos=NN
     }
@@ -1475,17 +2523,17 @@ mod tests {
 
         // Should find the single word
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [upos="NOUN"]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [upos="NOUN"]; }"#, None).unwrap();
         assert_eq!(matches.len(), 1);
 
         // Edge constraint should find nothing (no children)
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { P []; C []; P -> C; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { P []; C []; P -> C; }"#, None).unwrap();
         assert_eq!(matches.len(), 0);
 
         // Precedence on single word - nothing precedes itself
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { A []; B []; A << B; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { A []; B []; A << B; }"#, None).unwrap();
         assert_eq!(matches.len(), 0); // AllDifferent means A != B, so no pairs
     }
 
@@ -1496,7 +2544,7 @@ mod tests {
 
         // Basic regex - match lemmas starting with "w" (anchors added automatically)
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [lemma=/w.*/]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [lemma=/w.*/]; }"#, None).unwrap();
         assert_eq!(matches.len(), 2); // "we" and "win"
         let word_ids: Vec<_> = matches.iter().map(|m| m.bindings["W"]).collect();
         assert!(word_ids.contains(&1)); // "us" (lemma: we)
@@ -1504,7 +2552,7 @@ mod tests {
 
         // Regex with alternation - match exactly VERB or PRON
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [upos=/VERB|PRON/]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [upos=/VERB|PRON/]; }"#, None).unwrap();
         assert_eq!(matches.len(), 3); // "helped", "us", "win"
         let word_ids: Vec<_> = matches.iter().map(|m| m.bindings["W"]).collect();
         assert!(word_ids.contains(&0)); // helped (VERB)
@@ -1513,21 +2561,25 @@ mod tests {
 
         // Regex with .* - match words containing "el"
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [form=/.*el.*/]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [form=/.*el.*/]; }"#, None).unwrap();
         assert_eq!(matches.len(), 1); // "helped"
         assert_eq!(matches[0].bindings["W"], 0);
 
         // Negated regex - match lemmas NOT starting with "w"
         let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { W [lemma!=/w.*/]; }"#).unwrap();
+            search_tree_query(tree.clone(), r#"MATCH { W [lemma!=/w.*/]; }"#, None).unwrap();
         assert_eq!(matches.len(), 2); // "help" and "to"
         let word_ids: Vec<_> = matches.iter().map(|m| m.bindings["W"]).collect();
         assert!(word_ids.contains(&0)); // helped (lemma: help)
         assert!(word_ids.contains(&2)); // to
 
         // Mixed literal and regex
-        let matches: Vec<_> =
-            search_tree_query(tree.clone(), r#"MATCH { V [upos="VERB" & lemma=/h.*/]; }"#).unwrap();
+        let matches: Vec<_> = search_tree_query(
+            tree.clone(),
+            r#"MATCH { V [upos="VERB" & lemma=/h.*/]; }"#,
+            None,
+        )
+        .unwrap();
         assert_eq!(matches.len(), 1); // "helped"
         assert_eq!(matches[0].bindings["V"], 0);
 
@@ -1536,10 +2588,765 @@ mod tests {
         let matches: Vec<_> = search_tree_query(
             tree.clone(),
             r#"MATCH { V []; O [lemma=/w.*/]; V -[obj]-> O; }"#,
+            None,
         )
         .unwrap();
         assert_eq!(matches.len(), 1); // helped -> us (lemma: we)
         assert_eq!(matches[0].bindings["V"], 0);
         assert_eq!(matches[0].bindings["O"], 1);
     }
+
+    #[test]
+    fn test_regex_all_fields() {
+        let conllu = "1\tDogs\tdog\tNOUN\tNNS\tNumber=Plur\t2\tnsubj\t_\t_\n\
+                      2\tare\tbe\tAUX\tVBP\tMood=Ind|Tense=Pres\t3\taux\t_\t_\n\
+                      3\trunning\trun\tVERB\tVBG\tVerbForm=Part\t0\troot\t_\t_\n\
+                      4\thome\thome\tADV\tRB\t_\t3\tadvmod\t_\tSpaceAfter=No\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        // Regexes must match the whole field
+        assert_eq!(ids("MATCH { W [form=/.*ing$/]; }"), vec![2]);
+        assert_eq!(ids("MATCH { W [form=/run/]; }"), Vec::<WordId>::new());
+        assert_eq!(ids("MATCH { W [lemma=/^(walk|run|jog)/]; }"), vec![2]);
+        assert_eq!(ids("MATCH { W [xpos=/VB.*/]; }"), vec![1, 2]);
+        assert_eq!(ids("MATCH { W [deprel=/^(nsubj|aux)$/]; }"), vec![0, 1]);
+        assert_eq!(ids("MATCH { W [feats.Tense=/Pres|Past/]; }"), vec![1]);
+        assert_eq!(ids("MATCH { W [misc.SpaceAfter=/N.*/]; }"), vec![3]);
+    }
+
+    #[test]
+    fn test_case_insensitive_constraints() {
+        // helped(0), us(1), to(2), win(3)
+        let tree = build_test_tree();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(ids(r#"MATCH { W [form="HELPED"]; }"#), Vec::<WordId>::new());
+        assert_eq!(ids(r#"MATCH { W [form="HELPED" /i]; }"#), vec![0]);
+        assert_eq!(ids(r#"MATCH { W [upos~="verb"]; }"#), vec![0, 3]);
+        assert_eq!(ids(r#"MATCH { W [upos!="verb" /i]; }"#), vec![1, 2]);
+        assert_eq!(ids("MATCH { W [form=/W.*/i]; }"), vec![3]);
+    }
+
+    #[test]
+    fn test_substring_constraints() {
+        // helped(0), us(1), to(2), win(3)
+        let tree = build_test_tree();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(ids(r#"MATCH { W [form^="hel"]; }"#), vec![0]);
+        assert_eq!(ids(r#"MATCH { W [form$="s"]; }"#), vec![1]);
+        assert_eq!(ids(r#"MATCH { W [form*="i"]; }"#), vec![3]);
+        assert_eq!(ids(r#"MATCH { W [form*="lpe"]; }"#), vec![0]);
+        assert_eq!(ids(r#"MATCH { W [form^="HEL" /i]; }"#), vec![0]);
+        assert_eq!(ids(r#"MATCH { W [form^=""]; }"#), vec![0, 1, 2, 3]);
+    }
+
+    /// Number of solutions of a base pattern, found by trying every assignment
+    fn brute_force_count(tree: &Tree, pattern: &BasePattern) -> usize {
+        fn extend(tree: &Tree, pattern: &BasePattern, assign: &mut Vec<WordId>) -> usize {
+            if assign.len() == pattern.n_vars {
+                let word_of = |name: &str| assign[pattern.var_ids[name].0];
+                let edges_ok = pattern.edge_constraints.iter().all(|edge| {
+                    satisfies_arc_constraint(tree, word_of(&edge.from), word_of(&edge.to), edge)
+                });
+                return edges_ok as usize;
+            }
+            let mut count = 0;
+            for word_id in 0..tree.words.len() {
+                let constraint = &pattern.var_constraints[assign.len()];
+                if assign.contains(&word_id)
+                    || !satisfies_var_constraint(tree, &tree.words[word_id], constraint)
+                {
+                    continue;
+                }
+                assign.push(word_id);
+                count += extend(tree, pattern, assign);
+                assign.pop();
+            }
+            count
+        }
+        extend(tree, pattern, &mut Vec::new())
+    }
+
+    #[test]
+    fn test_forward_checking_preserves_matches() {
+        let conllu = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                      2\tcat\tcat\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                      3\tgave\tgive\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      4\tthe\tthe\tDET\tDT\t_\t5\tdet\t_\t_\n\
+                      5\tdog\tdog\tNOUN\tNN\t_\t3\tiobj\t_\t_\n\
+                      6\ta\ta\tDET\tDT\t_\t7\tdet\t_\t_\n\
+                      7\tbone\tbone\tNOUN\tNN\t_\t3\tobj\t_\t_\n\
+                      8\tfrom\tfrom\tADP\tIN\t_\t10\tcase\t_\t_\n\
+                      9\tthe\tthe\tDET\tDT\t_\t10\tdet\t_\t_\n\
+                      10\tyard\tyard\tNOUN\tNN\t_\t7\tnmod\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+
+        for query in [
+            "MATCH { V []; O []; N []; V -[obj]-> O; O -[nmod]-> N; }",
+            "MATCH { V []; S []; I []; O []; V -[nsubj]-> S; V -[iobj]-> I; V -[obj]-> O; }",
+            "MATCH { A []; B []; C []; A >> B; B -> C; A << C; }",
+            r#"MATCH { N [upos="NOUN"]; D [upos="DET"]; M []; N -> D; N !-> M; D < M; }"#,
+            "MATCH { A []; B []; C []; A -> B; A -> C; B $$ C; B <3 C; }",
+            "MATCH { A []; B []; C []; D []; A ~2 B; B ~2 C; C ~2 D; }",
+        ] {
+            let pattern = compile_query(query).unwrap();
+            let expected = brute_force_count(&tree, &pattern.match_pattern);
+            assert_eq!(
+                find_all_matches(tree.clone(), &pattern, None).len(),
+                expected,
+                "{}",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn test_arc_consistency_narrows_initial_domains() {
+        // helped(0) -> us(1, obj), win(3, xcomp); win -> to(2, mark)
+        let tree = build_test_tree();
+        let initial_domains = |query: &str| {
+            let pattern = compile_query(query).unwrap();
+            let base = pattern.match_pattern;
+            let solver = Solver::new(&tree, &base, &Bindings::new());
+            solver.stack.front().map(|partial| {
+                let mut domains: Vec<(String, Vec<WordId>)> = base
+                    .var_names
+                    .iter()
+                    .zip(&partial.domains)
+                    .map(|(name, domain)| (name.clone(), domain.iter().collect()))
+                    .collect();
+                domains.sort();
+                domains
+            })
+        };
+
+        assert_eq!(
+            initial_domains("MATCH { V []; O []; V -[obj]-> O; }"),
+            Some(vec![("O".to_string(), vec![1]), ("V".to_string(), vec![0])])
+        );
+        // Propagates along a chain: only win has a mark child, and only helped heads win
+        assert_eq!(
+            initial_domains("MATCH { A []; B []; C []; A -> B; B -[mark]-> C; }"),
+            Some(vec![
+                ("A".to_string(), vec![0]),
+                ("B".to_string(), vec![3]),
+                ("C".to_string(), vec![2])
+            ])
+        );
+        // No word heads both an obj and a mark: no search at all
+        assert_eq!(
+            initial_domains("MATCH { V []; O []; M []; V -[obj]-> O; V -[mark]-> M; }"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_arc_consistency_self_edge() {
+        // A self-edge relates each word to itself; no word is its own child
+        let tree = build_test_tree();
+        let ids = |query: &str| match_ids(&tree, query, "X");
+        assert_eq!(ids(r#"MATCH { X [upos="VERB"]; X !-> X; }"#), vec![0, 3]);
+        assert_eq!(
+            ids(r#"MATCH { X [upos="VERB"]; X -> X; }"#),
+            Vec::<WordId>::new()
+        );
+    }
+
+    #[test]
+    fn test_max_matches() {
+        // helped(0), us(1), to(2), win(3)
+        let tree = build_test_tree();
+        let ids = |query: &str, max_matches| {
+            search_tree_query(tree.clone(), query, max_matches)
+                .unwrap()
+                .iter()
+                .map(|m| m.bindings["W"])
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("MATCH { W []; }", None).len(), 4);
+        assert_eq!(ids("MATCH { W []; }", Some(2)).len(), 2);
+        assert_eq!(ids("MATCH { W []; }", Some(10)).len(), 4);
+        assert!(ids("MATCH { W []; }", Some(0)).is_empty());
+        // With ORDER BY, the first matches in order are kept
+        assert_eq!(
+            ids("MATCH { W []; } ORDER BY W.form DESC", Some(2)),
+            vec![3, 1]
+        );
+    }
+
+    #[test]
+    fn test_select_next_var_prefers_connected_vars() {
+        // With equal domain sizes, the middle of a chain has the most incident edges
+        let pattern = compile_query("MATCH { A []; B []; C []; A -> B; B -> C; }").unwrap();
+        let base = &pattern.match_pattern;
+        let assign = vec![None; base.n_vars];
+        let mut domain = BitFixed::new(4);
+        domain.set(0);
+        domain.set(1);
+        let domains = vec![domain; base.n_vars];
+        assert_eq!(select_next_var(base, &assign, &domains), base.var_ids["B"]);
+
+        // Domain size still takes precedence over degree
+        let mut small = BitFixed::new(4);
+        small.set(0);
+        let mut domains = domains;
+        domains[base.var_ids["C"].0] = small;
+        assert_eq!(select_next_var(base, &assign, &domains), base.var_ids["C"]);
+    }
+
+    #[test]
+    fn test_indexed_candidates() {
+        let tree = build_test_tree();
+        let upos = Constraint::UPOS(ConstraintValue::Literal("VERB".to_string()));
+        let lemma = Constraint::Lemma(ConstraintValue::Literal("help".to_string()));
+        let xpos = Constraint::XPOS(ConstraintValue::Literal("_".to_string()));
+
+        let (words, exact) = indexed_candidates(&tree, &upos).unwrap();
+        assert!(exact);
+        assert_eq!(words.iter().collect::<Vec<_>>(), vec![0, 3]);
+
+        // Intersection of two indexed fields is exact
+        let both = Constraint::And(vec![upos.clone(), lemma]);
+        let (words, exact) = indexed_candidates(&tree, &both).unwrap();
+        assert!(exact);
+        assert_eq!(words.iter().collect::<Vec<_>>(), vec![0]);
+
+        // An unindexed conjunct leaves a superset to be checked
+        let mixed = Constraint::And(vec![upos, xpos.clone()]);
+        let (words, exact) = indexed_candidates(&tree, &mixed).unwrap();
+        assert!(!exact);
+        assert_eq!(words.iter().collect::<Vec<_>>(), vec![0, 3]);
+
+        assert!(indexed_candidates(&tree, &xpos).is_none());
+
+        // Values missing from the pool give an empty candidate set
+        let missing = Constraint::Lemma(ConstraintValue::Literal("absent".to_string()));
+        let (words, _) = indexed_candidates(&tree, &missing).unwrap();
+        assert_eq!(words.count_ones(), 0);
+    }
+
+    #[test]
+    fn test_indexed_candidates_or() {
+        let tree = build_test_tree();
+        let upos = |value: &str| Constraint::UPOS(ConstraintValue::Literal(value.to_string()));
+        let xpos = Constraint::XPOS(ConstraintValue::Literal("TO".to_string()));
+
+        // Union of indexed branches
+        let either = Constraint::Or(vec![upos("PRON"), upos("PART")]);
+        let (words, exact) = indexed_candidates(&tree, &either).unwrap();
+        assert!(exact);
+        assert_eq!(words.iter().collect::<Vec<_>>(), vec![1, 2]);
+
+        // Any unindexed branch means the domain can't be bounded
+        assert!(indexed_candidates(&tree, &Constraint::Or(vec![upos("PRON"), xpos])).is_none());
+
+        // Empty OR has no candidates
+        let (words, exact) = indexed_candidates(&tree, &Constraint::Or(vec![])).unwrap();
+        assert!(exact);
+        assert_eq!(words.count_ones(), 0);
+    }
+
+    #[test]
+    fn test_or_constraints() {
+        // helped(0, VERB), us(1, PRON), to(2, PART), win(3, VERB)
+        let tree = build_test_tree();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(
+            ids(r#"MATCH { W [upos="PRON" | upos="PART"]; }"#),
+            vec![1, 2]
+        );
+        assert_eq!(ids(r#"MATCH { W [upos="PRON" | form="to"]; }"#), vec![1, 2]);
+        // OR with negation
+        assert_eq!(
+            ids(r#"MATCH { W [upos="PRON" | upos!="VERB"]; }"#),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids(r#"MATCH { W [lemma="win" | upos!="VERB"]; }"#),
+            vec![1, 2, 3]
+        );
+        // OR nested inside AND
+        assert_eq!(
+            ids(r#"MATCH { W [(upos="VERB" | upos="PRON") & lemma!="help"]; }"#),
+            vec![1, 3]
+        );
+        // Without parentheses, & binds tighter
+        assert_eq!(
+            ids(r#"MATCH { W [upos="VERB" | upos="PRON" & lemma!="we"]; }"#),
+            vec![0, 3]
+        );
+
+        let word = &tree.words[0];
+        assert!(!satisfies_var_constraint(
+            &tree,
+            word,
+            &Constraint::Or(vec![])
+        ));
+    }
+
+    #[test]
+    fn test_indexed_search_matches_unindexed() {
+        let tree = build_test_tree();
+        let mut unindexed = tree.clone();
+        unindexed.upos_index.clear();
+        unindexed.lemma_index.clear();
+        unindexed.form_index.clear();
+        unindexed.deprel_index.clear();
+
+        for query in [
+            r#"MATCH { V [upos="VERB" & lemma="help"]; O [upos="PRON"]; V -[obj]-> O; }"#,
+            r#"MATCH { V [form="helped"]; O [deprel="obj"]; V -> O; }"#,
+        ] {
+            let indexed_matches = search_tree_query(tree.clone(), query, None).unwrap();
+            let unindexed_matches = search_tree_query(unindexed.clone(), query, None).unwrap();
+            assert_eq!(indexed_matches.len(), 1);
+            assert_eq!(indexed_matches[0].bindings, unindexed_matches[0].bindings);
+        }
+
+        let (candidates, exact) = indexed_candidates(
+            &tree,
+            &Constraint::DepRel(ConstraintValue::Literal("obj".to_string())),
+        )
+        .unwrap();
+        assert!(exact);
+        assert_eq!(candidates.iter().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_token_id_constraint() {
+        let conllu = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                      2\tdog\tdog\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                      3\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(ids("MATCH { W [token_id=1]; }"), vec![0]);
+        assert_eq!(ids("MATCH { W [token_id<=2]; }"), vec![0, 1]);
+        assert_eq!(ids("MATCH { W [token_id<2]; }"), vec![0]);
+        assert_eq!(ids("MATCH { W [token_id>=2]; }"), vec![1, 2]);
+        assert_eq!(ids("MATCH { W [token_id>2]; }"), vec![2]);
+        assert_eq!(ids("MATCH { W [token_id!=2]; }"), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_match_stream_is_lazy() {
+        let mut tree = Tree::default();
+        for i in 0..30 {
+            tree.add_minimal_word(i, b"cat", b"cat", b"NOUN", b"_", None, b"root");
+        }
+        tree.compile_tree();
+        let tree = Arc::new(tree);
+        let pattern =
+            compile_query(r#"MATCH { A [upos="NOUN"]; B [upos="NOUN"]; C [upos="NOUN"]; }"#)
+                .unwrap();
+
+        let mut stream = match_stream(Arc::clone(&tree), &pattern);
+        // Only the first branch of the search has been expanded
+        assert!(stream.solver.stack.len() < 100);
+        let first: Vec<_> = stream.by_ref().take(3).map(|m| m.bindings).collect();
+        assert_eq!(first.len(), 3);
+        assert!(stream.solver.stack.len() < 100);
+        assert_eq!(stream.count(), 30 * 29 * 28 - 3);
+    }
+
+    #[test]
+    fn test_match_stream_blocks() {
+        // helped(0) -> us(1, obj), win(3, xcomp); win -> to(2, mark)
+        let tree = build_test_tree();
+        let streamed = |query: &str| -> Vec<Vec<(String, WordId)>> {
+            let pattern = compile_query(query).unwrap();
+            match_stream(Arc::new(tree.clone()), &pattern)
+                .map(|m| {
+                    let mut bindings: Vec<_> = m.bindings.into_iter().collect();
+                    bindings.sort();
+                    bindings
+                })
+                .collect()
+        };
+        let sorted = |mut matches: Vec<Vec<(String, WordId)>>| {
+            matches.sort();
+            matches
+        };
+        let bindings = |pairs: &[(&str, WordId)]| -> Vec<(String, WordId)> {
+            pairs
+                .iter()
+                .map(|&(name, id)| (name.to_string(), id))
+                .collect()
+        };
+
+        assert_eq!(
+            sorted(streamed("MATCH { X []; Y []; X -> Y; }")),
+            vec![
+                bindings(&[("X", 0), ("Y", 1)]),
+                bindings(&[("X", 0), ("Y", 3)]),
+                bindings(&[("X", 3), ("Y", 2)]),
+            ]
+        );
+        assert_eq!(
+            sorted(streamed(
+                "MATCH { V [upos=\"VERB\"]; } OPTIONAL { O []; V -> O; }"
+            )),
+            vec![
+                bindings(&[("O", 1), ("V", 0)]),
+                bindings(&[("O", 2), ("V", 3)]),
+                bindings(&[("O", 3), ("V", 0)]),
+            ]
+        );
+        assert_eq!(
+            sorted(streamed("MATCH { V []; } EXCEPT { V -[obj]-> _; }")),
+            vec![
+                bindings(&[("V", 1)]),
+                bindings(&[("V", 2)]),
+                bindings(&[("V", 3)]),
+            ]
+        );
+        // ORDER BY fixes the order: win, we, to, help
+        assert_eq!(
+            streamed("MATCH { W []; } ORDER BY W.lemma DESC"),
+            vec![
+                bindings(&[("W", 3)]),
+                bindings(&[("W", 1)]),
+                bindings(&[("W", 2)]),
+                bindings(&[("W", 0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_descendant_relation() {
+        // helped(0) -> us(1), helped(0) -> win(3) -> to(2)
+        let tree = build_test_tree();
+        let pairs = |query: &str| match_pairs(&tree, query, ["X", "Y"]);
+
+        assert_eq!(
+            pairs("MATCH { X >> Y; }"),
+            vec![(0, 1), (0, 2), (0, 3), (3, 2)]
+        );
+        assert_eq!(pairs("MATCH { X >>2 Y; }"), vec![(0, 2)]);
+        assert_eq!(pairs("MATCH { X >>{1,1} Y; }"), pairs("MATCH { X -> Y; }"));
+        assert_eq!(pairs("MATCH { X >>{2,} Y; }"), vec![(0, 2)]);
+        // Negated: Y is not below X (and X != Y)
+        assert_eq!(
+            pairs(r#"MATCH { X [upos="VERB"]; X !>> Y; }"#),
+            vec![(3, 0), (3, 1)]
+        );
+
+        // A word does not dominate itself
+        assert_eq!(tree.ancestor_distance(0, 0), None);
+        assert_eq!(tree.ancestor_distance(0, 2), Some(2));
+        assert_eq!(tree.ancestor_distance(2, 0), None);
+    }
+
+    #[test]
+    fn test_deep_descendant() {
+        // Chain of 12 words, each headed by the previous one: w0 -> w1 -> ... -> w11
+        let mut tree = Tree::default();
+        for i in 0..12 {
+            let form = format!("w{}", i);
+            let head = if i == 0 { None } else { Some(i - 1) };
+            tree.add_minimal_word(i, form.as_bytes(), b"_", b"NOUN", b"_", head, b"nmod");
+        }
+        tree.compile_tree();
+        let query = |q: &str| search_tree_query(tree.clone(), q, None).unwrap();
+
+        // Dominance has no depth limit unless one is given
+        let matches = query(r#"MATCH { X [form="w0"]; Y [form="w11"]; X >> Y; }"#);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(
+            query(r#"MATCH { X [form="w0"]; Y [form="w8"]; X >>8 Y; }"#).len(),
+            1
+        );
+        assert!(query(r#"MATCH { X [form="w0"]; Y [form="w11"]; X >>{1,7} Y; }"#).is_empty());
+    }
+
+    #[test]
+    fn test_feature_existence() {
+        let conllu = "1\tDogs\tdog\tNOUN\tNNS\tNumber=Plur\t2\tnsubj\t_\t_\n\
+                      2\tran\trun\tVERB\tVBD\tTense=Past\t0\troot\t_\tSpaceAfter=No\n\
+                      3\t.\t.\tPUNCT\t.\t_\t2\tpunct\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(ids("MATCH { W [feats.Tense]; }"), vec![1]);
+        assert_eq!(ids("MATCH { W [!feats.Tense]; }"), vec![0, 2]);
+        assert_eq!(ids("MATCH { W [feats.Mood]; }"), Vec::<WordId>::new());
+        assert_eq!(ids("MATCH { W [misc.SpaceAfter]; }"), vec![1]);
+        assert_eq!(ids("MATCH { W [feats.Number | feats.Tense]; }"), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_quantified_edges() {
+        // and(0) -> cats(1), dogs(2), birds(3) as conj, too(4) as advmod
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"and", b"and", b"CCONJ", b"_", None, b"root");
+        tree.add_minimal_word(1, b"cats", b"cat", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(2, b"dogs", b"dog", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(3, b"birds", b"bird", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(4, b"too", b"too", b"ADV", b"_", Some(0), b"advmod");
+        tree.compile_tree();
+        let count = |query: &str| search_tree_query(tree.clone(), query, None).unwrap().len();
+
+        assert_eq!(count("MATCH { V -[conj]{2,}-> _; }"), 1);
+        assert_eq!(count("MATCH { V -[conj]{3}-> _; }"), 1);
+        assert_eq!(count("MATCH { V -[conj]{4,}-> _; }"), 0);
+        assert_eq!(count("MATCH { V -[conj]{0,2}-> _; }"), 4); // the leaves have none
+        assert_eq!(count("MATCH { V -{4}-> _; }"), 1);
+        assert_eq!(count("MATCH { V -[conj]{2,}-> _; V -[advmod]-> A; }"), 1);
+    }
+
+    #[test]
+    fn test_negated_precedence() {
+        // helped(0) us(1) to(2) win(3)
+        let tree = build_test_tree();
+        let pairs = |query: &str| match_pairs(&tree, query, ["X", "Y"]);
+
+        // Not preceding means following, since X and Y are distinct
+        let follows = pairs(r#"MATCH { X [upos="VERB"]; Y []; X !<< Y; }"#);
+        assert_eq!(follows, vec![(3, 0), (3, 1), (3, 2)]);
+        assert!(follows.iter().all(|&(x, y)| x > y));
+
+        assert_eq!(
+            pairs(r#"MATCH { X [lemma="help"]; Y []; X !< Y; }"#),
+            vec![(0, 2), (0, 3)]
+        );
+    }
+
+    #[test]
+    fn test_distance_relations() {
+        // helped(0) us(1) to(2) win(3)
+        let tree = build_test_tree();
+        let pairs = |query: &str| match_pairs(&tree, query, ["X", "Y"]);
+
+        assert_eq!(
+            pairs("MATCH { X [upos=\"VERB\"]; X <2 Y; }"),
+            vec![(0, 1), (0, 2)]
+        );
+        assert_eq!(pairs("MATCH { X <1 Y; }"), pairs("MATCH { X < Y; }"));
+        assert_eq!(
+            pairs("MATCH { X [lemma=\"to\"]; X ~1 Y; }"),
+            vec![(2, 1), (2, 3)]
+        );
+        assert_eq!(
+            pairs("MATCH { X [lemma=\"help\"]; Y [upos=\"VERB\"]; X ~2 Y; }"),
+            Vec::<(WordId, WordId)>::new()
+        );
+    }
+
+    #[test]
+    fn test_is_root_is_leaf() {
+        // helped(0) -> us(1), helped(0) -> win(3) -> to(2)
+        let tree = build_test_tree();
+        let ids = |query: &str| match_ids(&tree, query, "W");
+
+        assert_eq!(ids("MATCH { W [IS_ROOT]; }"), vec![0]);
+        assert_eq!(ids("MATCH { W [IS_LEAF]; }"), vec![1, 2]);
+        assert_eq!(
+            ids(r#"MATCH { W [upos="VERB" & IS_LEAF]; }"#),
+            Vec::<WordId>::new()
+        );
+        assert_eq!(ids("MATCH { W [IS_ROOT | IS_LEAF]; }"), vec![0, 1, 2]);
+
+        let (roots, exact) = indexed_candidates(&tree, &Constraint::IsRoot).unwrap();
+        assert!(exact);
+        assert_eq!(roots.iter().collect::<Vec<_>>(), vec![0]);
+    }
+
+    #[test]
+    fn test_sibling_relations() {
+        // cats(0) and(1) dogs(2) birds(3); cats is the root, with dogs and birds as
+        // conjuncts and "and" attached to dogs
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"cats", b"cat", b"NOUN", b"_", None, b"root");
+        tree.add_minimal_word(1, b"and", b"and", b"CCONJ", b"_", Some(2), b"cc");
+        tree.add_minimal_word(2, b"dogs", b"dog", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(3, b"birds", b"bird", b"NOUN", b"_", Some(0), b"conj");
+        tree.compile_tree();
+        let pairs = |query: &str| match_pairs(&tree, query, ["X", "Y"]);
+
+        assert_eq!(pairs("MATCH { X $$ Y; }"), vec![(2, 3), (3, 2)]);
+        assert_eq!(pairs("MATCH { X $. Y; }"), vec![(2, 3)]);
+        assert_eq!(pairs("MATCH { X $+ Y; }"), vec![(2, 3)]);
+        // Adjacent words with different parents are not siblings
+        assert!(!tree.are_siblings(1, 2));
+        // The root has no siblings
+        assert!(!tree.are_siblings(0, 0));
+    }
+
+    #[test]
+    fn test_next_sibling() {
+        // ate(0) cake(1) with(2) forks(3); cake and forks depend on ate, and "with"
+        // on forks, so it comes between the two siblings
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"ate", b"eat", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(1, b"cake", b"cake", b"NOUN", b"_", Some(0), b"obj");
+        tree.add_minimal_word(2, b"with", b"with", b"ADP", b"_", Some(3), b"case");
+        tree.add_minimal_word(3, b"forks", b"fork", b"NOUN", b"_", Some(0), b"obl");
+        tree.compile_tree();
+        let pairs = |query: &str| {
+            search_tree_query(tree.clone(), query, None)
+                .unwrap()
+                .iter()
+                .map(|m| (m.bindings["X"], m.bindings["Y"]))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(pairs("MATCH { X $+ Y; }"), vec![(1, 3)]);
+        assert_eq!(pairs("MATCH { X $. Y; }"), vec![]);
+        assert_eq!(tree.next_sibling(3), None);
+        assert_eq!(tree.next_sibling(0), None);
+    }
+
+    #[test]
+    fn test_subtree_contains() {
+        // cats(0) and(1) dogs(2) birds(3), as in test_sibling_relations
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"cats", b"cat", b"NOUN", b"_", None, b"root");
+        tree.add_minimal_word(1, b"and", b"and", b"CCONJ", b"_", Some(2), b"cc");
+        tree.add_minimal_word(2, b"dogs", b"dog", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(3, b"birds", b"bird", b"NOUN", b"_", Some(0), b"conj");
+        tree.compile_tree();
+        let words = |query: &str| match_ids(&tree, query, "X");
+
+        // "and" is below dogs, and two levels below cats
+        assert_eq!(words(r#"MATCH { X [CONTAINS upos="CCONJ"]; }"#), vec![0, 2]);
+        // The word itself doesn't count
+        assert_eq!(
+            words(r#"MATCH { X [CONTAINS lemma="cat"]; }"#),
+            Vec::<WordId>::new()
+        );
+        assert_eq!(
+            words(r#"MATCH { X [!CONTAINS upos="NOUN"]; }"#),
+            vec![1, 2, 3]
+        );
+
+        // A head cycle (each word is the other's head) must not loop forever
+        let mut cyclic = Tree::default();
+        cyclic.add_minimal_word(0, b"big", b"big", b"ADJ", b"_", Some(1), b"amod");
+        cyclic.add_minimal_word(1, b"dogs", b"dog", b"NOUN", b"_", Some(0), b"nmod");
+        cyclic.compile_tree();
+        let matches = search_tree_query(
+            cyclic.clone(),
+            r#"MATCH { X [CONTAINS upos="ADJ"]; }"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].bindings["X"], 1);
+        assert!(
+            search_tree_query(cyclic, r#"MATCH { X [CONTAINS upos="VERB"]; }"#, None)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_within() {
+        // The big dog of my neighbor barked angry
+        let conllu = "1\tThe\tthe\tDET\tDT\t_\t3\tdet\t_\t_\n\
+                      2\tbig\tbig\tADJ\tJJ\t_\t3\tamod\t_\t_\n\
+                      3\tdog\tdog\tNOUN\tNN\t_\t7\tnsubj\t_\t_\n\
+                      4\tof\tof\tADP\tIN\t_\t6\tcase\t_\t_\n\
+                      5\tmy\tmy\tPRON\tPRP$\t_\t6\tnmod:poss\t_\t_\n\
+                      6\tneighbor\tneighbor\tNOUN\tNN\t_\t3\tnmod\t_\t_\n\
+                      7\tbarked\tbark\tVERB\tVBD\t_\t0\troot\t_\t_\n\
+                      8\tangry\tangry\tADJ\tJJ\t_\t7\txcomp\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let pairs = |query: &str| match_pairs(&tree, query, ["NP", "X"]);
+
+        // "angry" is outside every noun phrase
+        assert_eq!(
+            pairs(r#"MATCH { X [upos="ADJ"]; } WITHIN { NP [upos="NOUN"]; }"#),
+            vec![(2, 1)]
+        );
+        // "my" is inside both "neighbor" and the NP it modifies
+        assert_eq!(
+            pairs(r#"MATCH { X [upos="PRON"]; } WITHIN { NP [upos="NOUN"]; }"#),
+            vec![(2, 4), (5, 4)]
+        );
+        // The root may also appear in MATCH edges
+        assert_eq!(
+            pairs(r#"MATCH { X []; NP -[case]-> X; } WITHIN { NP [upos="NOUN"]; }"#),
+            vec![(5, 3)]
+        );
+    }
+
+    #[test]
+    fn test_order_by() {
+        // helped(0), us(1), to(2), win(3)
+        let tree = build_test_tree();
+        let lemmas = |query: &str| -> Vec<WordId> {
+            search_tree_query(tree.clone(), query, None)
+                .unwrap()
+                .iter()
+                .map(|m| m.bindings["W"])
+                .collect()
+        };
+
+        // help < to < we < win
+        assert_eq!(
+            lemmas("MATCH { W []; } ORDER BY W.lemma ASC"),
+            vec![0, 2, 1, 3]
+        );
+        assert_eq!(
+            lemmas("MATCH { W []; } ORDER BY W.lemma DESC"),
+            vec![3, 1, 2, 0]
+        );
+        // Secondary key breaks ties: VERB twice, then by form descending
+        assert_eq!(
+            lemmas("MATCH { W []; } ORDER BY W.upos, W.form DESC"),
+            vec![2, 1, 3, 0]
+        );
+    }
+
+    #[test]
+    fn test_order_by_unbound_optional_sorts_last() {
+        let tree = build_test_tree();
+        let query =
+            "MATCH { V [upos=\"VERB\"]; } OPTIONAL { O []; V -[obj]-> O; } ORDER BY O.form DESC";
+        let matches = search_tree_query(tree, query, None).unwrap();
+        let verbs: Vec<_> = matches.iter().map(|m| m.bindings["V"]).collect();
+        assert_eq!(verbs, vec![0, 3]); // "helped" has an object, "win" does not
+    }
+
+    #[test]
+    fn test_bindings_display() {
+        let tree = build_test_tree();
+        let matches = search_tree_query(
+            tree,
+            "MATCH { V [lemma=\"help\"]; O []; V -[obj]-> O; }",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            matches[0].display().to_string(),
+            "{O=1 (us/PRON), V=0 (helped/VERB)}"
+        );
+
+        let empty = Bindings::new();
+        let tree = build_test_tree();
+        let display = BindingsDisplay {
+            bindings: &empty,
+            tree: &tree,
+        };
+        assert_eq!(display.to_string(), "{}");
+    }
 }
@@ -0,0 +1,369 @@
+//! Corpus-wide summary statistics
+//!
+//! `TreebankStats::collect` reads a treebank once and tallies sentence lengths,
+//! tag and relation frequencies, dependency distances and vocabulary size.
+//! `group_and_count` tallies the matches of a pattern by a field of one variable,
+//! and `frequency_table` gives normalized (per million words) pattern frequencies.
+
+use crate::export::field_bytes;
+use crate::iterators::{Treebank, TreebankError};
+use crate::pattern::{FieldSelector, Pattern};
+use crate::searcher::match_stream;
+use crate::tree::Tree;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Summary of a whole treebank
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TreebankStats {
+    pub n_sentences: usize,
+    pub n_words: usize,
+    pub mean_sentence_length: f64,
+    pub max_sentence_length: usize,
+    /// UPOS tag -> number of words
+    pub upos_frequencies: HashMap<Vec<u8>, usize>,
+    /// Dependency relation -> number of words
+    pub deprel_frequencies: HashMap<Vec<u8>, usize>,
+    /// Mean linear distance between a word and its head (roots excluded)
+    pub mean_dependency_distance: f64,
+    /// Number of sentences with at least one non-projective arc
+    pub n_nonprojective: usize,
+    /// Number of distinct word forms
+    pub vocabulary_size: usize,
+    pub n_unique_lemmas: usize,
+}
+
+/// Running totals while the trees are read
+#[derive(Default)]
+struct StatsAccumulator {
+    stats: TreebankStats,
+    n_arcs: usize,
+    total_distance: usize,
+    forms: HashSet<Vec<u8>>,
+    lemmas: HashSet<Vec<u8>>,
+}
+
+impl StatsAccumulator {
+    fn add_tree(&mut self, tree: &Tree) {
+        let stats = &mut self.stats;
+        stats.n_sentences += 1;
+        stats.n_words += tree.words.len();
+        stats.max_sentence_length = stats.max_sentence_length.max(tree.words.len());
+        if !tree.is_projective() {
+            stats.n_nonprojective += 1;
+        }
+
+        for word in &tree.words {
+            let resolve = |sym| tree.string_pool.resolve(sym).to_vec();
+            *stats
+                .upos_frequencies
+                .entry(resolve(word.upos))
+                .or_default() += 1;
+            *stats
+                .deprel_frequencies
+                .entry(resolve(word.deprel))
+                .or_default() += 1;
+            self.forms.insert(resolve(word.form));
+            self.lemmas.insert(resolve(word.lemma));
+            if let Some(head) = word.head {
+                self.n_arcs += 1;
+                self.total_distance += head.abs_diff(word.id);
+            }
+        }
+    }
+
+    fn finish(self) -> TreebankStats {
+        let mean = |total: usize, count: usize| {
+            if count == 0 {
+                0.0
+            } else {
+                total as f64 / count as f64
+            }
+        };
+        TreebankStats {
+            mean_sentence_length: mean(self.stats.n_words, self.stats.n_sentences),
+            mean_dependency_distance: mean(self.total_distance, self.n_arcs),
+            vocabulary_size: self.forms.len(),
+            n_unique_lemmas: self.lemmas.len(),
+            ..self.stats
+        }
+    }
+}
+
+impl TreebankStats {
+    /// Read every tree in the treebank once and summarize it
+    pub fn collect(treebank: Treebank) -> Result<Self, TreebankError> {
+        let mut acc = StatsAccumulator::default();
+        for tree in treebank.tree_iter(false) {
+            acc.add_tree(&tree?);
+        }
+        Ok(acc.finish())
+    }
+
+    /// Format the statistics as a plain-text table, with tags and relations by frequency
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let rows = [
+            ("Sentences", self.n_sentences.to_string()),
+            ("Words", self.n_words.to_string()),
+            (
+                "Mean sentence length",
+                format!("{:.2}", self.mean_sentence_length),
+            ),
+            ("Max sentence length", self.max_sentence_length.to_string()),
+            (
+                "Mean dependency distance",
+                format!("{:.2}", self.mean_dependency_distance),
+            ),
+            ("Non-projective sentences", self.n_nonprojective.to_string()),
+            ("Vocabulary size", self.vocabulary_size.to_string()),
+            ("Unique lemmas", self.n_unique_lemmas.to_string()),
+        ];
+        for (label, value) in rows {
+            writeln!(out, "{:<26}{:>12}", label, value).unwrap();
+        }
+        for (title, freqs) in [
+            ("UPOS", &self.upos_frequencies),
+            ("DEPREL", &self.deprel_frequencies),
+        ] {
+            writeln!(out, "\n{:<26}{:>12}", title, "Count").unwrap();
+            for (value, count) in sorted_by_count(freqs) {
+                writeln!(out, "{:<26}{:>12}", String::from_utf8_lossy(value), count).unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Count the matches of a pattern, grouped by a field of the word bound to `var_name`
+///
+/// For example, grouping a verb-subject pattern by `("V", FieldSelector::Lemma)`
+/// counts how often each verb lemma occurs with a subject. Every match counts, so
+/// a tree can contribute several times. Matches that leave `var_name` unbound
+/// (an OPTIONAL variable) are not counted.
+pub fn group_and_count(
+    treebank: Treebank,
+    pattern: Pattern,
+    var_name: &str,
+    field: FieldSelector,
+) -> Result<HashMap<Vec<u8>, usize>, TreebankError> {
+    let mut counts = HashMap::new();
+    for m in treebank.match_iter(pattern, false) {
+        let m = m?;
+        if let Some(&word_id) = m.bindings.get(var_name) {
+            let value = field_bytes(&m.tree, &m.tree.words[word_id], field);
+            *counts.entry(value).or_default() += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// Frequency of one pattern in a treebank
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyEntry {
+    pub name: String,
+    pub n_matches: usize,
+    /// Number of sentences with at least one match
+    pub n_sentences: usize,
+    /// Matches per million words of the treebank
+    pub per_million: f64,
+}
+
+/// Count several patterns in a single pass over the treebank
+///
+/// Each tree is read once and searched with every pattern. Entries are returned in
+/// the order of `patterns`; the per-million rate is relative to all words read,
+/// as in `TreebankStats::n_words`.
+pub fn frequency_table(
+    treebank: Treebank,
+    patterns: &[(&str, Pattern)],
+) -> Result<Vec<FrequencyEntry>, TreebankError> {
+    let searched: Arc<Vec<Pattern>> = Arc::new(
+        patterns
+            .iter()
+            .map(|(_, pattern)| pattern.clone())
+            .collect(),
+    );
+    let max_matches = treebank.max_matches.unwrap_or(usize::MAX);
+    let per_tree = treebank.parallel_iter(false, move |tree| {
+        let n_words = tree.words.len();
+        let tree = Arc::new(tree);
+        let counts: Vec<usize> = searched
+            .iter()
+            .map(|pattern| {
+                match_stream(tree.clone(), pattern)
+                    .take(max_matches)
+                    .count()
+            })
+            .collect();
+        vec![Ok((n_words, counts))]
+    });
+
+    let mut n_words = 0;
+    let mut totals = vec![(0, 0); patterns.len()];
+    for result in per_tree {
+        let (tree_words, counts) = result?;
+        n_words += tree_words;
+        for ((n_matches, n_sentences), count) in totals.iter_mut().zip(counts) {
+            *n_matches += count;
+            *n_sentences += usize::from(count > 0);
+        }
+    }
+    Ok(patterns
+        .iter()
+        .zip(totals)
+        .map(|(&(name, _), (n_matches, n_sentences))| FrequencyEntry {
+            name: name.to_string(),
+            n_matches,
+            n_sentences,
+            per_million: per_million(n_matches, n_words),
+        })
+        .collect())
+}
+
+impl Treebank {
+    /// Matches of a pattern per million words (see [`frequency_table`])
+    pub fn frequency_per_million(self, pattern: &Pattern) -> Result<f64, TreebankError> {
+        let table = frequency_table(self, &[("", pattern.clone())])?;
+        Ok(table[0].per_million)
+    }
+}
+
+/// Normalize a count by corpus size; an empty corpus has rate 0
+fn per_million(count: usize, n_words: usize) -> f64 {
+    if n_words == 0 {
+        0.0
+    } else {
+        count as f64 / n_words as f64 * 1_000_000.0
+    }
+}
+
+/// Frequency table entries, most frequent first (ties by value)
+fn sorted_by_count(freqs: &HashMap<Vec<u8>, usize>) -> Vec<(&Vec<u8>, usize)> {
+    let mut entries: Vec<_> = freqs.iter().map(|(value, &count)| (value, count)).collect();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONLLU: &str = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                          2\tdog\tdog\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                          3\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n\
+                          1\tDogs\tdog\tNOUN\tNNS\t_\t2\tnsubj\t_\t_\n\
+                          2\trun\trun\tVERB\tVBP\t_\t0\troot\t_\t_\n\n";
+
+    #[test]
+    fn test_collect_stats() {
+        let stats = TreebankStats::collect(Treebank::from_string(CONLLU)).unwrap();
+        assert_eq!(stats.n_sentences, 2);
+        assert_eq!(stats.n_words, 5);
+        assert_eq!(stats.mean_sentence_length, 2.5);
+        assert_eq!(stats.max_sentence_length, 3);
+        assert_eq!(stats.upos_frequencies[&b"NOUN".to_vec()], 2);
+        assert_eq!(stats.deprel_frequencies[&b"root".to_vec()], 2);
+        assert_eq!(stats.mean_dependency_distance, 1.0);
+        assert_eq!(stats.n_nonprojective, 0);
+        assert_eq!(stats.vocabulary_size, 5);
+        assert_eq!(stats.n_unique_lemmas, 3);
+
+        let report = stats.report();
+        assert!(report.starts_with("Sentences"));
+        assert!(report.contains(&format!("{:<26}{:>12}\n", "Mean sentence length", "2.50")));
+        // Most frequent first, ties in alphabetical order
+        let upos = report.split("UPOS").nth(1).unwrap();
+        assert!(upos.find("NOUN").unwrap() < upos.find("VERB").unwrap());
+        assert!(upos.find("VERB").unwrap() < upos.find("DET").unwrap());
+    }
+
+    #[test]
+    fn test_group_and_count() {
+        let treebank = Treebank::from_string(CONLLU);
+        let count = |query: &str, var_name: &str, field: FieldSelector| {
+            let pattern = crate::compile_query(query).unwrap();
+            group_and_count(treebank.clone(), pattern, var_name, field).unwrap()
+        };
+
+        let subjects = count(
+            "MATCH { V [upos=\"VERB\"]; S []; V -[nsubj]-> S; }",
+            "V",
+            FieldSelector::Lemma,
+        );
+        assert_eq!(subjects, HashMap::from([(b"run".to_vec(), 2)]));
+
+        let by_upos = count("MATCH { W []; }", "W", FieldSelector::UPOS);
+        assert_eq!(by_upos.values().sum::<usize>(), 5);
+        assert_eq!(by_upos[&b"NOUN".to_vec()], 2);
+
+        // Unbound OPTIONAL variables are skipped
+        let objects = count(
+            "MATCH { V [upos=\"VERB\"]; } OPTIONAL { D []; V -[det]-> D; }",
+            "D",
+            FieldSelector::Form,
+        );
+        assert!(objects.is_empty());
+
+        // Values that are not valid UTF-8 are grouped by their bytes
+        let latin1 = b"1\tcaf\xe9\tcaf\xe9\tNOUN\t_\t_\t0\troot\t_\t_\n\n\
+                       1\tcaf\xe8\tcaf\xe8\tNOUN\t_\t_\t0\troot\t_\t_\n\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.conllu");
+        std::fs::write(&path, latin1).unwrap();
+        let pattern = crate::compile_query("MATCH { W []; }").unwrap();
+        let lemmas = group_and_count(
+            Treebank::from_path(&path),
+            pattern,
+            "W",
+            FieldSelector::Lemma,
+        )
+        .unwrap();
+        assert_eq!(
+            lemmas,
+            HashMap::from([(b"caf\xe9".to_vec(), 1), (b"caf\xe8".to_vec(), 1)])
+        );
+    }
+
+    #[test]
+    fn test_frequency_table() {
+        let patterns = [
+            (
+                "verbs",
+                crate::compile_query("MATCH { V [upos=\"VERB\"]; }").unwrap(),
+            ),
+            ("words", crate::compile_query("MATCH { W []; }").unwrap()),
+            (
+                "dets",
+                crate::compile_query("MATCH { D [upos=\"DET\"]; }").unwrap(),
+            ),
+        ];
+        let table = frequency_table(Treebank::from_string(CONLLU), &patterns).unwrap();
+        let summary: Vec<_> = table
+            .iter()
+            .map(|e| (e.name.as_str(), e.n_matches, e.n_sentences))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("verbs", 2, 2), ("words", 5, 2), ("dets", 1, 1)]
+        );
+        assert_eq!(table[0].per_million, 400_000.0);
+        assert_eq!(table[1].per_million, 1_000_000.0);
+
+        // Filters change the denominator as well as the counts
+        let long = Treebank::from_string(CONLLU).filter_by_length(3, None);
+        assert_eq!(
+            long.frequency_per_million(&patterns[2].1).unwrap(),
+            1_000_000.0 / 3.0
+        );
+        let empty = Treebank::from_string("");
+        assert_eq!(empty.frequency_per_million(&patterns[0].1).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_empty_treebank() {
+        let stats = TreebankStats::collect(Treebank::from_string("")).unwrap();
+        assert_eq!(stats, TreebankStats::default());
+    }
+}
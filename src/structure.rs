@@ -0,0 +1,150 @@
+//! Tree shapes, ignoring words and labels
+//!
+//! Two trees have the same structure when their dependency trees are isomorphic
+//! as unlabeled, unordered rooted trees: forms, lemmas, tags, relation labels and
+//! word order are all ignored. The canonical form is the AHU encoding, where each
+//! word is written as `(` followed by the sorted encodings of its children and
+//! `)`, so isomorphic trees get identical byte strings.
+
+use crate::iterators::{Treebank, TreebankError};
+use crate::tree::{Tree, WordId};
+use rustc_hash::{FxHashMap, FxHasher};
+use std::hash::Hasher;
+
+/// Canonical encoding of the subtree rooted at `word_id`
+fn encode_subtree(tree: &Tree, word_id: WordId) -> Vec<u8> {
+    let mut children: Vec<Vec<u8>> = tree.words[word_id]
+        .children
+        .iter()
+        .map(|&child| encode_subtree(tree, child))
+        .collect();
+    children.sort_unstable();
+    let mut out = Vec::with_capacity(2 + children.iter().map(Vec::len).sum::<usize>());
+    out.push(b'(');
+    children
+        .iter()
+        .for_each(|child| out.extend_from_slice(child));
+    out.push(b')');
+    out
+}
+
+/// Canonical form of a tree's structure: equal exactly when the structures are isomorphic
+///
+/// Trees with several roots (malformed ones) encode each root's subtree and
+/// concatenate them in sorted order; the empty tree encodes as an empty string.
+pub fn canonical_structure(tree: &Tree) -> Vec<u8> {
+    let mut roots: Vec<Vec<u8>> = tree
+        .roots()
+        .iter()
+        .map(|root| encode_subtree(tree, root.id))
+        .collect();
+    roots.sort_unstable();
+    roots.concat()
+}
+
+/// Check whether two trees have the same structure (see [`canonical_structure`])
+pub fn tree_isomorphic(a: &Tree, b: &Tree) -> bool {
+    a.words.len() == b.words.len() && canonical_structure(a) == canonical_structure(b)
+}
+
+impl Tree {
+    /// Hash of the tree's structure, for bucketing trees by shape
+    ///
+    /// Isomorphic trees always get the same fingerprint. Different structures can
+    /// collide, so confirm with [`tree_isomorphic`] when it matters.
+    pub fn structure_fingerprint(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        hasher.write(&canonical_structure(self));
+        hasher.finish()
+    }
+}
+
+impl Treebank {
+    /// Group the trees of the treebank by structure
+    ///
+    /// Every tree is in exactly one group; groups are in the order their first
+    /// tree appears, and trees keep their treebank order within a group.
+    pub fn find_isomorphic_groups(self) -> Result<Vec<Vec<Tree>>, TreebankError> {
+        let mut group_index: FxHashMap<Vec<u8>, usize> = FxHashMap::default();
+        let mut groups: Vec<Vec<Tree>> = Vec::new();
+        for tree in self.tree_iter(true) {
+            let tree = tree?;
+            let next_index = groups.len();
+            let index = *group_index
+                .entry(canonical_structure(&tree))
+                .or_insert(next_index);
+            if index == next_index {
+                groups.push(Vec::new());
+            }
+            groups[index].push(tree);
+        }
+        Ok(groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "The dog runs" and "The cat sits" are chains; the two four-word trees have a
+    // root with three dependents, in different orders and with different labels
+    const CONLLU: &str = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                          2\tdog\tdog\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                          3\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n\
+                          1\tBirds\tbird\tNOUN\tNNS\t_\t2\tnsubj\t_\t_\n\
+                          2\tsing\tsing\tVERB\tVBP\t_\t0\troot\t_\t_\n\
+                          3\tloudly\tloudly\tADV\tRB\t_\t2\tadvmod\t_\t_\n\n\
+                          1\tBirds\tbird\tNOUN\tNNS\t_\t2\tnsubj\t_\t_\n\
+                          2\tsing\tsing\tVERB\tVBP\t_\t0\troot\t_\t_\n\
+                          3\tloudly\tloudly\tADV\tRB\t_\t2\tadvmod\t_\t_\n\
+                          4\tnow\tnow\tADV\tRB\t_\t2\tadvmod\t_\t_\n\n\
+                          1\tTrees\ttree\tNOUN\tNNS\t_\t3\tnsubj\t_\t_\n\
+                          2\treally\treally\tADV\tRB\t_\t3\tadvmod\t_\t_\n\
+                          3\tgrow\tgrow\tVERB\tVBP\t_\t0\troot\t_\t_\n\
+                          4\ttall\ttall\tADJ\tJJ\t_\t3\txcomp\t_\t_\n\n\
+                          1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                          2\tcat\tcat\tNOUN\tNN\t_\t3\tnsubj\t_\t_\n\
+                          3\tsits\tsit\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n";
+
+    fn trees() -> Vec<Tree> {
+        Treebank::from_string(CONLLU)
+            .tree_iter(true)
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn test_canonical_structure() {
+        let trees = trees();
+        assert_eq!(canonical_structure(&trees[0]), b"((()))");
+        assert_eq!(canonical_structure(&trees[1]), b"(()())");
+        assert_eq!(canonical_structure(&trees[2]), b"(()()())");
+        assert_eq!(canonical_structure(&Tree::default()), b"");
+
+        assert!(tree_isomorphic(&trees[0], &trees[4]));
+        assert!(tree_isomorphic(&trees[2], &trees[3]));
+        assert!(!tree_isomorphic(&trees[0], &trees[1]));
+        assert_eq!(
+            trees[0].structure_fingerprint(),
+            trees[4].structure_fingerprint()
+        );
+        assert_ne!(
+            trees[0].structure_fingerprint(),
+            trees[1].structure_fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_find_isomorphic_groups() {
+        let groups = Treebank::from_string(CONLLU)
+            .find_isomorphic_groups()
+            .unwrap();
+        let sizes: Vec<Vec<usize>> = groups
+            .iter()
+            .map(|group| group.iter().map(|tree| tree.words.len()).collect())
+            .collect();
+        assert_eq!(sizes, vec![vec![3, 3], vec![3], vec![4, 4]]);
+        assert_eq!(groups[0][1].subtree_text(2), "The cat sits");
+        assert_eq!(groups[2][1].subtree_text(2), "Trees really grow tall");
+    }
+}
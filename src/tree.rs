@@ -1,7 +1,10 @@
 //! Tree data structures for dependency parsing
 
 use crate::bytes::{BytestringPool, Sym};
-use std::collections::HashMap;
+use crate::conllu::{parse_comment, split_comment};
+use crate::query::{JsonValue, parse_json};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 
 /// Word index in tree (0-based)
 pub type WordId = usize;
@@ -19,6 +22,18 @@ pub struct Dep {
     pub deprel: Sym,
 }
 
+/// Multiword token (a `1-2` range line in CoNLL-U), e.g. "del" for "de el"
+///
+/// Covers the words with token ids `start..=end`; the words themselves are parsed
+/// from the lines that follow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiwordToken {
+    pub start: TokenId,
+    pub end: TokenId,
+    pub form: Sym,
+    pub misc: Features,
+}
+
 /// Miscellaneous annotations (MISC field)
 pub type Misc = HashMap<String, String>;
 
@@ -35,7 +50,12 @@ pub struct Word {
     pub head: Option<WordId>,
     pub deprel: Sym,
     pub misc: Features,
+    /// Enhanced dependencies from the DEPS column (empty when it is `_`)
+    pub enhanced_deps: Vec<Dep>,
     pub children: Vec<WordId>,
+    /// Distance from the root; `u16::MAX` until `Tree::compile_tree` runs
+    /// (or if the word is unreachable from a root)
+    pub depth: u16,
 }
 
 impl Word {
@@ -59,7 +79,9 @@ impl Word {
             head,
             deprel,
             misc: Features::new(),
+            enhanced_deps: Vec::new(),
             children: Vec::new(),
+            depth: u16::MAX,
         }
     }
 
@@ -87,7 +109,9 @@ impl Word {
             head,
             deprel,
             misc,
+            enhanced_deps: Vec::new(),
             children: Vec::new(),
+            depth: u16::MAX,
         }
     }
 
@@ -107,8 +131,41 @@ impl Word {
     pub fn children<'a>(&self, tree: &'a Tree) -> Vec<&'a Word> {
         self.children.iter().map(|&id| &tree.words[id]).collect()
     }
+
+    /// Other children of this word's parent, in word order (empty for the root)
+    pub fn siblings<'a>(&self, tree: &'a Tree) -> Vec<&'a Word> {
+        let Some(parent) = self.parent(tree) else {
+            return Vec::new();
+        };
+        parent
+            .children(tree)
+            .into_iter()
+            .filter(|sibling| sibling.id != self.id)
+            .collect()
+    }
+
+    /// Siblings that precede this word
+    pub fn left_siblings<'a>(&self, tree: &'a Tree) -> Vec<&'a Word> {
+        self.siblings(tree)
+            .into_iter()
+            .filter(|sibling| sibling.token_id < self.token_id)
+            .collect()
+    }
+
+    /// Siblings that follow this word
+    pub fn right_siblings<'a>(&self, tree: &'a Tree) -> Vec<&'a Word> {
+        self.siblings(tree)
+            .into_iter()
+            .filter(|sibling| sibling.token_id > self.token_id)
+            .collect()
+    }
 }
 
+/// Word IDs having a given field value, in word order
+///
+/// Stored as posting lists rather than bitsets since trees must be `Send`.
+pub type FieldIndex = HashMap<Sym, Vec<WordId>>;
+
 /// A dependency tree (sentence)
 #[derive(Debug, Clone)]
 pub struct Tree {
@@ -116,7 +173,31 @@ pub struct Tree {
     pub root_id: Option<WordId>,
     pub sentence_text: Option<String>,
     pub metadata: HashMap<String, String>,
+    /// Comment keys (including `text`) in the order they were read, so that
+    /// `to_conllu` can write them back in the same order
+    pub comment_order: Vec<String>,
+    /// Every comment line as read, `#` included (`# newdoc`, `# text = ...`, ...),
+    /// so that free-form comments survive a round trip
+    pub raw_comments: Vec<String>,
+    /// The sentence starts a document (`# newdoc` or `# newdoc id = ...`)
+    pub is_new_doc: bool,
+    /// The sentence starts a paragraph (`# newpar` or `# newpar id = ...`)
+    pub is_new_par: bool,
+    /// Document id, from `# newdoc id = ...` or `# doc_id = ...`
+    pub doc_id: Option<String>,
+    /// Multiword tokens, in the order they appear
+    pub multiword_tokens: Vec<MultiwordToken>,
+    /// Interner for the word fields; a handle to the pool of the `TreeIterator`
+    /// that parsed the tree, so every tree from one reader shares its strings
     pub string_pool: BytestringPool,
+    /// UPOS value -> words (built by `compile_tree`)
+    pub upos_index: FieldIndex,
+    /// Lemma value -> words (built by `compile_tree`)
+    pub lemma_index: FieldIndex,
+    /// Form value -> words (built by `compile_tree`)
+    pub form_index: FieldIndex,
+    /// Dependency relation -> words (built by `compile_tree`)
+    pub deprel_index: FieldIndex,
 }
 
 impl Tree {
@@ -126,7 +207,17 @@ impl Tree {
             root_id: None,
             sentence_text: None,
             metadata: HashMap::new(),
+            comment_order: Vec::new(),
+            raw_comments: Vec::new(),
+            is_new_doc: false,
+            is_new_par: false,
+            doc_id: None,
+            multiword_tokens: Vec::new(),
             string_pool: string_pool.clone(),
+            upos_index: FieldIndex::new(),
+            lemma_index: FieldIndex::new(),
+            form_index: FieldIndex::new(),
+            deprel_index: FieldIndex::new(),
         }
     }
 
@@ -140,10 +231,21 @@ impl Tree {
             root_id: None,
             sentence_text,
             metadata,
+            comment_order: Vec::new(),
+            raw_comments: Vec::new(),
+            is_new_doc: false,
+            is_new_par: false,
+            doc_id: None,
+            multiword_tokens: Vec::new(),
             string_pool: string_pool.clone(),
+            upos_index: FieldIndex::new(),
+            lemma_index: FieldIndex::new(),
+            form_index: FieldIndex::new(),
+            deprel_index: FieldIndex::new(),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_minimal_word(
         &mut self,
         id: WordId,
@@ -192,15 +294,89 @@ impl Tree {
         self.words.push(word);
     }
 
-    /// Fill in children
+    /// Fill in children, depths and the per-field indices
     pub fn compile_tree(&mut self) {
+        let mut queue = VecDeque::new();
         for word_id in 0..self.words.len() {
             if let Some(head) = self.words[word_id].head {
                 self.words[head].children.push(word_id);
             } else {
                 self.root_id = Some(word_id);
+                self.words[word_id].depth = 0;
+                queue.push_back(word_id);
+            }
+            let word = &self.words[word_id];
+            let (upos, lemma, form, deprel) = (word.upos, word.lemma, word.form, word.deprel);
+            self.upos_index.entry(upos).or_default().push(word_id);
+            self.lemma_index.entry(lemma).or_default().push(word_id);
+            self.form_index.entry(form).or_default().push(word_id);
+            self.deprel_index.entry(deprel).or_default().push(word_id);
+        }
+
+        // BFS from the root(s); words on a head cycle are never reached
+        while let Some(word_id) = queue.pop_front() {
+            let depth = self.words[word_id].depth.saturating_add(1);
+            for i in 0..self.words[word_id].children.len() {
+                let child = self.words[word_id].children[i];
+                if self.words[child].depth == u16::MAX {
+                    self.words[child].depth = depth;
+                    queue.push_back(child);
+                }
+            }
+        }
+    }
+
+    /// Check that word ids match positions and every head refers to a word in the tree
+    ///
+    /// [`Tree::recompile`] relies on both, so call this first on trees whose words
+    /// were added, removed or reattached.
+    pub fn check_heads(&self) -> Result<(), String> {
+        let n_words = self.words.len();
+        for (position, word) in self.words.iter().enumerate() {
+            if word.id != position {
+                return Err(format!("word {} has id {}", position, word.id));
+            }
+            if let Some(head) = word.head.filter(|&head| head >= n_words) {
+                return Err(format!(
+                    "word {} has head {}, but the tree has {} words",
+                    position, head, n_words
+                ));
             }
         }
+        Ok(())
+    }
+
+    /// Rebuild the children, depths and indexes after words have been edited
+    ///
+    /// Heads must still refer to words in the tree (see [`Tree::check_heads`]).
+    pub fn recompile(&mut self) {
+        for word in &mut self.words {
+            word.children.clear();
+            word.depth = u16::MAX;
+        }
+        self.root_id = None;
+        self.upos_index.clear();
+        self.lemma_index.clear();
+        self.form_index.clear();
+        self.deprel_index.clear();
+        self.compile_tree();
+    }
+
+    /// Depth of a word, found by walking the head chain
+    ///
+    /// Returns None if the id is out of range or the chain never reaches a root.
+    /// Compiled trees store the same value in `Word::depth`.
+    pub fn word_depth(&self, word_id: WordId) -> Option<usize> {
+        let mut current = self.words.get(word_id)?;
+        let mut depth = 0;
+        while let Some(head) = current.head {
+            depth += 1;
+            if depth > self.words.len() {
+                return None;
+            }
+            current = self.words.get(head)?;
+        }
+        Some(depth)
     }
 
     pub fn word(&self, id: WordId) -> Result<&Word, String> {
@@ -235,6 +411,40 @@ impl Tree {
     }
     */
 
+    /// Check whether a word has feature `key=value` (false for unknown word ids)
+    pub fn has_feature(&self, word_id: WordId, key: &str, value: &str) -> bool {
+        self.words.get(word_id).is_some_and(|word| {
+            word.feats.iter().any(|(k, v)| {
+                self.string_pool
+                    .compare_kv(*k, *v, key.as_bytes(), value.as_bytes())
+            })
+        })
+    }
+
+    /// Value of feature `key` on a word, if present
+    ///
+    /// Returns an `Arc` rather than a slice since values live in the shared string pool.
+    pub fn feature_value(&self, word_id: WordId, key: &str) -> Option<Arc<[u8]>> {
+        let word = self.words.get(word_id)?;
+        word.feats
+            .iter()
+            .find(|(k, _)| self.string_pool.compare_bytes(*k, key.as_bytes()))
+            .map(|(_, v)| self.string_pool.resolve(*v))
+    }
+
+    /// The root word, if the tree has one
+    pub fn root(&self) -> Option<&Word> {
+        self.words.get(self.root_id?)
+    }
+
+    /// All words without a head (more than one only in malformed trees)
+    pub fn roots(&self) -> Vec<&Word> {
+        self.words
+            .iter()
+            .filter(|word| word.head.is_none())
+            .collect()
+    }
+
     pub fn head_id(&self, word_id: WordId) -> Result<Option<WordId>, String> {
         Ok(self.word(word_id)?.head)
     }
@@ -247,6 +457,38 @@ impl Tree {
         self.words[from_id].children.contains(&to_id)
     }
 
+    /// Check whether two words have the same parent (roots are nobody's siblings)
+    pub fn are_siblings(&self, a: WordId, b: WordId) -> bool {
+        a != b && self.words[a].head.is_some() && self.words[a].head == self.words[b].head
+    }
+
+    /// The sibling right after `word_id` in word order, skipping the words between them
+    pub fn next_sibling(&self, word_id: WordId) -> Option<WordId> {
+        let siblings = &self.words[self.words[word_id].head?].children;
+        let position = siblings.iter().position(|&sibling| sibling == word_id)?;
+        siblings.get(position + 1).copied()
+    }
+
+    /// Check whether `descendant_id` is a proper descendant of `ancestor_id`
+    pub fn dominates(&self, ancestor_id: WordId, descendant_id: WordId) -> bool {
+        self.ancestor_distance(ancestor_id, descendant_id).is_some()
+    }
+
+    /// Number of edges from `ancestor_id` down to `descendant_id`, or None if it
+    /// isn't an ancestor (a word is not its own ancestor)
+    pub fn ancestor_distance(&self, ancestor_id: WordId, descendant_id: WordId) -> Option<usize> {
+        let mut current = descendant_id;
+        // Bounded walk up the head chain, so malformed (cyclic) trees terminate
+        for distance in 1..=self.words.len() {
+            let head = self.words[current].head?;
+            if head == ancestor_id {
+                return Some(distance);
+            }
+            current = head;
+        }
+        None
+    }
+
     /// Find dependency path from ancestor X to descendant Y.
     /// Returns None if X and Y are the same node or if no path exists.
     /// Returns Some(vec![X, ..., Y]) if Y is a descendant of X.
@@ -305,6 +547,242 @@ impl Tree {
         None
     }
 
+    /// Whether the arc from `head_id` to `dep_id` is non-projective: some word between
+    /// them in linear order is not a descendant of `head_id`
+    ///
+    /// Returns false if `dep_id` is not a child of `head_id`.
+    pub fn has_non_projective_arc(&self, head_id: WordId, dep_id: WordId) -> bool {
+        if !self.check_rel(head_id, dep_id) {
+            return false;
+        }
+        let (start, end) = (head_id.min(dep_id), head_id.max(dep_id));
+        (start + 1..end).any(|w| !self.dominates(head_id, w))
+    }
+
+    /// Non-projective arcs as `(head, dependent)` pairs, in dependent order
+    pub fn non_projective_arcs(&self) -> Vec<(WordId, WordId)> {
+        self.words
+            .iter()
+            .filter_map(|word| word.head.map(|head| (head, word.id)))
+            .filter(|&(head, dep)| self.has_non_projective_arc(head, dep))
+            .collect()
+    }
+
+    /// True if no arc crosses another (every word between a head and its
+    /// dependent is a descendant of the head)
+    pub fn is_projective(&self) -> bool {
+        self.words.iter().all(|word| {
+            word.head
+                .is_none_or(|head| !self.has_non_projective_arc(head, word.id))
+        })
+    }
+
+    /// The word followed by its ancestors, ending at the root
+    ///
+    /// Empty if the id is out of range. On a head cycle the path stops before
+    /// revisiting a word.
+    pub fn path_to_root(&self, word_id: WordId) -> Vec<WordId> {
+        let mut path = Vec::new();
+        let mut current = Some(word_id);
+        while let Some(id) = current {
+            let Some(word) = self.words.get(id) else {
+                break;
+            };
+            if path.contains(&id) {
+                break;
+            }
+            path.push(id);
+            current = word.head;
+        }
+        path
+    }
+
+    /// Lowest common ancestor of two words (a word counts as its own ancestor)
+    ///
+    /// Returns None if either id is out of range or the words have no common ancestor.
+    pub fn lca(&self, a: WordId, b: WordId) -> Option<WordId> {
+        let ancestors: HashSet<WordId> = self.path_to_root(a).into_iter().collect();
+        self.path_to_root(b)
+            .into_iter()
+            .find(|id| ancestors.contains(id))
+    }
+
+    /// Ids of the words in the subtree rooted at `root_id`, in linear order
+    ///
+    /// Panics if `root_id` is out of range.
+    pub fn subtree_yield(&self, root_id: WordId) -> Vec<WordId> {
+        // The visited flags guard against head cycles
+        let mut in_subtree = vec![false; self.words.len()];
+        in_subtree[root_id] = true;
+        let mut stack = vec![root_id];
+        while let Some(word_id) = stack.pop() {
+            for &child in &self.words[word_id].children {
+                if !in_subtree[child] {
+                    in_subtree[child] = true;
+                    stack.push(child);
+                }
+            }
+        }
+        (0..self.words.len()).filter(|&id| in_subtree[id]).collect()
+    }
+
+    /// Number of words in the subtree rooted at `word_id`, including itself
+    ///
+    /// Panics if `word_id` is out of range.
+    pub fn subtree_size(&self, word_id: WordId) -> usize {
+        self.subtree_yield(word_id).len()
+    }
+
+    /// Leftmost and rightmost word ids of the subtree rooted at `root_id`
+    ///
+    /// The subtree may have gaps if the tree is non-projective. Panics if `root_id`
+    /// is out of range.
+    pub fn subtree_span(&self, root_id: WordId) -> (WordId, WordId) {
+        let ids = self.subtree_yield(root_id);
+        (ids[0], ids[ids.len() - 1])
+    }
+
+    /// Surface text of the subtree rooted at `root_id`: its forms in linear order,
+    /// separated by spaces except after words with `SpaceAfter=No`
+    ///
+    /// Panics if `root_id` is out of range.
+    pub fn subtree_text(&self, root_id: WordId) -> String {
+        self.words_text(&self.subtree_yield(root_id))
+    }
+
+    /// Forms of the given words joined as text, spaced according to `SpaceAfter=No`
+    fn words_text(&self, word_ids: &[WordId]) -> String {
+        let mut text = Vec::new();
+        for (i, &word_id) in word_ids.iter().enumerate() {
+            let word = &self.words[word_id];
+            text.extend_from_slice(&self.string_pool.resolve(word.form));
+            let space_after = !word.misc.iter().any(|&(key, value)| {
+                self.string_pool.compare_bytes(key, b"SpaceAfter")
+                    && self.string_pool.compare_bytes(value, b"No")
+            });
+            if space_after && i + 1 < word_ids.len() {
+                text.push(b' ');
+            }
+        }
+        String::from_utf8_lossy(&text).into_owned()
+    }
+
+    /// Greatest depth of any word (0 for a single word or an empty tree)
+    ///
+    /// Uses the depths filled in by `compile_tree`; words on a head cycle are ignored.
+    pub fn height(&self) -> usize {
+        self.words
+            .iter()
+            .filter(|word| word.depth != u16::MAX)
+            .map(|word| word.depth as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Mean number of children over the words that have any (0.0 if none do)
+    pub fn mean_branching_factor(&self) -> f64 {
+        let (heads, children) = self
+            .words
+            .iter()
+            .filter(|word| !word.children.is_empty())
+            .fold((0, 0), |(heads, children), word| {
+                (heads + 1, children + word.children.len())
+            });
+        if heads == 0 {
+            0.0
+        } else {
+            children as f64 / heads as f64
+        }
+    }
+
+    /// Copy the subtree rooted at `root_id` into a standalone tree.
+    ///
+    /// Words keep their linear order and are renumbered from 0 (token ids from 1);
+    /// heads are remapped and `root_id` becomes the root. Enhanced dependencies are
+    /// remapped the same way (the one on `root_id`'s basic head becomes a root
+    /// relation) and those with heads outside the subtree are dropped; multiword
+    /// tokens are kept if all their words are in the subtree. The string pool is
+    /// shared with this tree. If this tree has a sentence text, the new one gets the
+    /// forms of the subtree's words, spaced according to `SpaceAfter=No`.
+    ///
+    /// Panics if `root_id` is out of range.
+    pub fn extract_subtree(&self, root_id: WordId) -> Tree {
+        let old_ids = self.subtree_yield(root_id);
+        let mut new_ids = vec![None; self.words.len()];
+        for (new_id, &old_id) in old_ids.iter().enumerate() {
+            new_ids[old_id] = Some(new_id);
+        }
+
+        let sentence_text = self
+            .sentence_text
+            .as_ref()
+            .map(|_| self.words_text(&old_ids));
+
+        let mut subtree =
+            Tree::with_metadata(&self.string_pool, sentence_text, self.metadata.clone());
+        subtree.comment_order = self.comment_order.clone();
+        subtree.raw_comments = self.raw_comments.clone();
+        subtree.is_new_doc = self.is_new_doc;
+        subtree.is_new_par = self.is_new_par;
+        subtree.doc_id = self.doc_id.clone();
+        subtree.multiword_tokens = self
+            .multiword_tokens
+            .iter()
+            .filter_map(|token| {
+                let token_ids: Vec<TokenId> = self
+                    .words
+                    .iter()
+                    .filter(|word| (token.start..=token.end).contains(&word.token_id))
+                    .map(|word| new_ids[word.id].map(|new_id| new_id + 1))
+                    .collect::<Option<_>>()?;
+                Some(MultiwordToken {
+                    start: *token_ids.first()?,
+                    end: *token_ids.last()?,
+                    form: token.form,
+                    misc: token.misc.clone(),
+                })
+            })
+            .collect();
+        for (new_id, &old_id) in old_ids.iter().enumerate() {
+            let word = &self.words[old_id];
+            let head = if old_id == root_id {
+                None
+            } else {
+                word.head.and_then(|head| new_ids[head])
+            };
+            let mut new_word = Word::new(
+                new_id,
+                new_id + 1,
+                word.form,
+                word.lemma,
+                word.upos,
+                word.xpos,
+                word.feats.clone(),
+                head,
+                word.deprel,
+                word.misc.clone(),
+            );
+            new_word.enhanced_deps = word
+                .enhanced_deps
+                .iter()
+                .filter_map(|dep| {
+                    let head = match dep.head {
+                        Some(_) if old_id == root_id && dep.head == word.head => None,
+                        Some(head) => Some(new_ids[head]?),
+                        None => None,
+                    };
+                    Some(Dep {
+                        head,
+                        deprel: dep.deprel,
+                    })
+                })
+                .collect();
+            subtree.words.push(new_word);
+        }
+        subtree.compile_tree();
+        subtree
+    }
+
     pub fn len(&self) -> usize {
         self.words.len()
     }
@@ -312,6 +790,531 @@ impl Tree {
     pub fn is_empty(&self) -> bool {
         self.words.is_empty()
     }
+
+    /// Serialize the tree as JSON, with every interned field resolved to a string:
+    ///
+    /// `{"sentence_text": ..., "metadata": {...}, "comments": [...], "multiword_tokens":
+    /// [{"start": 1, "end": 2, "form": ..., "misc": {...}}, ...], "words": [{"id": 0,
+    /// "token_id": 1, "form": ..., "lemma": ..., "upos": ..., "xpos": ..., "feats": {...},
+    /// "head": null, "deprel": ..., "deps": [{"head": 0, "deprel": ...}, ...],
+    /// "misc": {...}}, ...]}`
+    ///
+    /// Metadata keys are sorted; comments are the raw lines as read; feats and misc
+    /// keep their CoNLL-U order. Heads in `deps` are word ids, like `head`.
+    pub fn to_json(&self) -> String {
+        let resolve =
+            |sym: Sym| String::from_utf8_lossy(&self.string_pool.resolve(sym)).into_owned();
+        let features = |out: &mut String, features: &Features| {
+            out.push('{');
+            for (i, &(key, value)) in features.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                push_json_string(out, &resolve(key));
+                out.push_str(": ");
+                push_json_string(out, &resolve(value));
+            }
+            out.push('}');
+        };
+
+        let mut out = String::from("{\"sentence_text\": ");
+        match &self.sentence_text {
+            Some(text) => push_json_string(&mut out, text),
+            None => out.push_str("null"),
+        }
+
+        out.push_str(", \"metadata\": {");
+        let mut metadata: Vec<_> = self.metadata.iter().collect();
+        metadata.sort();
+        for (i, (key, value)) in metadata.into_iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            push_json_string(&mut out, key);
+            out.push_str(": ");
+            push_json_string(&mut out, value);
+        }
+
+        out.push_str("}, \"comments\": [");
+        for (i, comment) in self.raw_comments.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            push_json_string(&mut out, comment);
+        }
+
+        out.push_str("], \"multiword_tokens\": [");
+        for (i, token) in self.multiword_tokens.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            out.push_str(&format!(
+                "{{\"start\": {}, \"end\": {}, \"form\": ",
+                token.start, token.end
+            ));
+            push_json_string(&mut out, &resolve(token.form));
+            out.push_str(", \"misc\": ");
+            features(&mut out, &token.misc);
+            out.push('}');
+        }
+
+        out.push_str("], \"words\": [");
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            out.push_str(&format!(
+                "{{\"id\": {}, \"token_id\": {}",
+                word.id, word.token_id
+            ));
+            for (name, sym) in [
+                ("form", word.form),
+                ("lemma", word.lemma),
+                ("upos", word.upos),
+                ("xpos", word.xpos),
+            ] {
+                out.push_str(&format!(", \"{}\": ", name));
+                push_json_string(&mut out, &resolve(sym));
+            }
+            out.push_str(", \"feats\": ");
+            features(&mut out, &word.feats);
+            match word.head {
+                Some(head) => out.push_str(&format!(", \"head\": {}", head)),
+                None => out.push_str(", \"head\": null"),
+            }
+            out.push_str(", \"deprel\": ");
+            push_json_string(&mut out, &resolve(word.deprel));
+            out.push_str(", \"deps\": [");
+            for (i, dep) in word.enhanced_deps.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                match dep.head {
+                    Some(head) => out.push_str(&format!("{{\"head\": {}", head)),
+                    None => out.push_str("{\"head\": null"),
+                }
+                out.push_str(", \"deprel\": ");
+                push_json_string(&mut out, &resolve(dep.deprel));
+                out.push('}');
+            }
+            out.push_str("], \"misc\": ");
+            features(&mut out, &word.misc);
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Rebuild a tree serialized by [`Tree::to_json`], interning its fields in `string_pool`
+    ///
+    /// The comments are read again as CoNLL-U comment lines, which restores the
+    /// document and paragraph flags and the comment order; `sentence_text` and
+    /// `metadata` then come from their own keys. Word ids must match positions and
+    /// heads (basic and enhanced) must refer to words in the tree.
+    pub fn from_json(json: &str, string_pool: &BytestringPool) -> Result<Tree, String> {
+        fn field<'a, T>(
+            value: &'a JsonValue,
+            key: &str,
+            cast: impl Fn(&'a JsonValue) -> Option<T>,
+        ) -> Result<T, String> {
+            value
+                .get(key)
+                .and_then(cast)
+                .ok_or_else(|| format!("missing or invalid \"{}\"", key))
+        }
+        fn string<'a>(value: &'a JsonValue, key: &str) -> Result<&'a str, String> {
+            field(value, key, JsonValue::as_str)
+        }
+        let optional_usize = |value: &JsonValue| match value {
+            JsonValue::Null => Some(None),
+            value => value.as_usize().map(Some),
+        };
+
+        let root = parse_json(json).ok_or("malformed JSON")?;
+        let mut tree = Tree::new(string_pool);
+        let features = |pool: &mut BytestringPool, value: &JsonValue, key: &str| {
+            field(value, key, JsonValue::as_object)?
+                .iter()
+                .map(|(name, value)| {
+                    let value = value.as_str().ok_or_else(|| {
+                        format!("\"{}\" value of \"{}\" is not a string", key, name)
+                    })?;
+                    Ok((
+                        pool.get_or_intern(name.as_bytes()),
+                        pool.get_or_intern(value.as_bytes()),
+                    ))
+                })
+                .collect::<Result<Features, String>>()
+        };
+
+        for comment in field(&root, "comments", JsonValue::as_array)? {
+            let comment = comment.as_str().ok_or("comment is not a string")?;
+            parse_comment(comment.as_bytes(), &mut tree);
+        }
+        tree.sentence_text = field(&root, "sentence_text", |value| match value {
+            JsonValue::Null => Some(None),
+            value => value.as_str().map(|text| Some(text.to_string())),
+        })?;
+        tree.metadata = field(&root, "metadata", JsonValue::as_object)?
+            .iter()
+            .map(|(key, value)| match value.as_str() {
+                Some(value) => Ok((key.clone(), value.to_string())),
+                None => Err(format!("metadata value of \"{}\" is not a string", key)),
+            })
+            .collect::<Result<_, String>>()?;
+
+        for token in field(&root, "multiword_tokens", JsonValue::as_array)? {
+            let form = tree
+                .string_pool
+                .get_or_intern(string(token, "form")?.as_bytes());
+            tree.multiword_tokens.push(MultiwordToken {
+                start: field(token, "start", JsonValue::as_usize)?,
+                end: field(token, "end", JsonValue::as_usize)?,
+                form,
+                misc: features(&mut tree.string_pool, token, "misc")?,
+            });
+        }
+
+        let words = field(&root, "words", JsonValue::as_array)?;
+        for word in words {
+            let feats = features(&mut tree.string_pool, word, "feats")?;
+            let misc = features(&mut tree.string_pool, word, "misc")?;
+            tree.add_word(
+                field(word, "id", JsonValue::as_usize)?,
+                field(word, "token_id", JsonValue::as_usize)?,
+                string(word, "form")?.as_bytes(),
+                string(word, "lemma")?.as_bytes(),
+                string(word, "upos")?.as_bytes(),
+                string(word, "xpos")?.as_bytes(),
+                feats,
+                field(word, "head", optional_usize)?,
+                string(word, "deprel")?.as_bytes(),
+                misc,
+            );
+            for dep in field(word, "deps", JsonValue::as_array)? {
+                let head = field(dep, "head", optional_usize)?;
+                if let Some(head) = head.filter(|&head| head >= words.len()) {
+                    return Err(format!(
+                        "enhanced head {} of word {} is not in the tree",
+                        head,
+                        tree.words.len() - 1
+                    ));
+                }
+                let deprel = tree
+                    .string_pool
+                    .get_or_intern(string(dep, "deprel")?.as_bytes());
+                let word = tree.words.last_mut().expect("word was just added");
+                word.enhanced_deps.push(Dep { head, deprel });
+            }
+        }
+        tree.check_heads()?;
+        tree.compile_tree();
+        Ok(tree)
+    }
+
+    /// Serialize the tree as a CoNLL-U sentence, ending with the blank separator line
+    ///
+    /// Comments come first. The `raw_comments` lines are written as read, except that
+    /// a `key = value` line whose value has since changed is rewritten, and one whose
+    /// key was removed is dropped. Metadata without a raw line follows in
+    /// `comment_order`, then `sent_id`, then `text`, then the rest sorted by key.
+    /// Multiword tokens are written before their first word. Empty fields are
+    /// written as `_`, and features keep their original order.
+    pub fn to_conllu(&self) -> String {
+        let mut out = String::new();
+        let push_field = |out: &mut String, bytes: &[u8]| {
+            if bytes.is_empty() {
+                out.push('_');
+            } else {
+                out.push_str(&String::from_utf8_lossy(bytes));
+            }
+        };
+        let push_features = |out: &mut String, features: &Features| {
+            if features.is_empty() {
+                out.push('_');
+            }
+            for (i, &(key, value)) in features.iter().enumerate() {
+                if i > 0 {
+                    out.push('|');
+                }
+                out.push_str(&String::from_utf8_lossy(&self.string_pool.resolve(key)));
+                out.push('=');
+                out.push_str(&String::from_utf8_lossy(&self.string_pool.resolve(value)));
+            }
+        };
+
+        let current_value = |key: &str| match key {
+            "text" => self.sentence_text.as_ref(),
+            _ => self.metadata.get(key),
+        };
+        let mut keys: Vec<&str> = Vec::new();
+        for line in &self.raw_comments {
+            let Some((key, value)) = split_comment(line) else {
+                out.push_str(line);
+                out.push('\n');
+                continue;
+            };
+            if keys.contains(&key) {
+                continue;
+            }
+            keys.push(key);
+            match current_value(key) {
+                Some(current) if current == value => {
+                    out.push_str(line);
+                    out.push('\n');
+                }
+                Some(current) => out.push_str(&format!("# {} = {}\n", key, current)),
+                None => {}
+            }
+        }
+        let written = keys.len();
+        for key in self
+            .comment_order
+            .iter()
+            .map(String::as_str)
+            .chain(["sent_id", "text"])
+        {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let mut rest: Vec<&str> = self
+            .metadata
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !keys.contains(key))
+            .collect();
+        rest.sort();
+        keys.extend(rest);
+        for key in keys.drain(written..) {
+            if let Some(value) = current_value(key) {
+                out.push_str(&format!("# {} = {}\n", key, value));
+            }
+        }
+
+        let mut multiword_tokens = self.multiword_tokens.iter().peekable();
+        for word in &self.words {
+            while let Some(mwt) = multiword_tokens.next_if(|mwt| mwt.start <= word.token_id) {
+                out.push_str(&format!("{}-{}\t", mwt.start, mwt.end));
+                push_field(&mut out, &self.string_pool.resolve(mwt.form));
+                out.push_str("\t_\t_\t_\t_\t_\t_\t_\t");
+                push_features(&mut out, &mwt.misc);
+                out.push('\n');
+            }
+
+            out.push_str(&word.token_id.to_string());
+            for sym in [word.form, word.lemma, word.upos, word.xpos] {
+                out.push('\t');
+                push_field(&mut out, &self.string_pool.resolve(sym));
+            }
+            out.push('\t');
+            push_features(&mut out, &word.feats);
+            // Heads are stored as 0-based word ids; CoNLL-U uses 1-based ids with 0 for the root
+            out.push_str(&format!("\t{}\t", word.head.map_or(0, |head| head + 1)));
+            push_field(&mut out, &self.string_pool.resolve(word.deprel));
+            out.push('\t');
+            if word.enhanced_deps.is_empty() {
+                out.push('_');
+            }
+            for (i, dep) in word.enhanced_deps.iter().enumerate() {
+                if i > 0 {
+                    out.push('|');
+                }
+                out.push_str(&format!("{}:", dep.head.map_or(0, |head| head + 1)));
+                out.push_str(&String::from_utf8_lossy(
+                    &self.string_pool.resolve(dep.deprel),
+                ));
+            }
+            out.push('\t');
+            push_features(&mut out, &word.misc);
+            out.push('\n');
+        }
+        out.push('\n');
+        out
+    }
+
+    /// Render the tree as a GraphViz DOT graph with the default style
+    pub fn to_dot(&self) -> String {
+        self.to_dot_with_style(&DotStyle::default())
+    }
+
+    /// Render the tree as a GraphViz DOT graph
+    pub fn to_dot_with_style(&self, style: &DotStyle) -> String {
+        let mut out = Vec::new();
+        self.write_dot(&mut out, style)
+            .expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("DOT output is UTF-8")
+    }
+
+    /// Write the tree as a GraphViz DOT graph
+    ///
+    /// Each word is a node labelled with its form and UPOS (plus lemma and XPOS if
+    /// the style asks for them), and each arc goes from head to dependent, labelled
+    /// with the relation. Roots get a double border.
+    pub fn write_dot(
+        &self,
+        writer: &mut dyn std::io::Write,
+        style: &DotStyle,
+    ) -> std::io::Result<()> {
+        let resolve =
+            |sym: Sym| String::from_utf8_lossy(&self.string_pool.resolve(sym)).into_owned();
+        let rankdir = match style.direction {
+            LayoutDirection::TopToBottom => "TB",
+            LayoutDirection::LeftToRight => "LR",
+        };
+        writeln!(writer, "digraph tree {{")?;
+        writeln!(writer, "  rankdir={};", rankdir)?;
+        writeln!(writer, "  node [shape=box];")?;
+        for word in &self.words {
+            let mut lines = vec![resolve(word.form)];
+            if style.show_lemma {
+                lines.push(resolve(word.lemma));
+            }
+            lines.push(resolve(word.upos));
+            if style.show_xpos {
+                lines.push(resolve(word.xpos));
+            }
+            let label: Vec<String> = lines.iter().map(|line| dot_escape(line)).collect();
+            let root = if word.head.is_none() {
+                ", peripheries=2"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "  n{} [label=\"{}\"{}];",
+                word.id,
+                label.join("\\n"),
+                root
+            )?;
+        }
+        for word in &self.words {
+            if let Some(head) = word.head {
+                writeln!(
+                    writer,
+                    "  n{} -> n{} [label=\"{}\"];",
+                    head,
+                    word.id,
+                    dot_escape(&resolve(word.deprel))
+                )?;
+            }
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// Draw the tree as text, one word per line with its dependents indented below it
+    ///
+    /// Each word is shown as `form/UPOS/deprel`, followed by its features in
+    /// brackets if `show_features` is set. Dependents are in linear order:
+    ///
+    /// ```text
+    /// runs/VERB/root
+    /// ├── dog/NOUN/nsubj
+    /// └── quickly/ADV/advmod
+    /// ```
+    pub fn to_ascii(&self, show_features: bool) -> String {
+        let mut out = String::new();
+        for root in self.roots() {
+            self.push_ascii_subtree(&mut out, root.id, "", "", show_features);
+        }
+        out
+    }
+
+    /// Append the lines for the subtree of `word_id`: the word's own line starts
+    /// with `branch`, and the lines below it with `indent`
+    fn push_ascii_subtree(
+        &self,
+        out: &mut String,
+        word_id: WordId,
+        branch: &str,
+        indent: &str,
+        show_features: bool,
+    ) {
+        let resolve =
+            |sym: Sym| String::from_utf8_lossy(&self.string_pool.resolve(sym)).into_owned();
+        let word = &self.words[word_id];
+        out.push_str(&format!(
+            "{}{}/{}/{}",
+            branch,
+            resolve(word.form),
+            resolve(word.upos),
+            resolve(word.deprel)
+        ));
+        if show_features && !word.feats.is_empty() {
+            let feats: Vec<String> = word
+                .feats
+                .iter()
+                .map(|&(key, value)| format!("{}={}", resolve(key), resolve(value)))
+                .collect();
+            out.push_str(&format!(" [{}]", feats.join("|")));
+        }
+        out.push('\n');
+        for (i, &child) in word.children.iter().enumerate() {
+            let (child_branch, child_indent) = if i + 1 == word.children.len() {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            self.push_ascii_subtree(
+                out,
+                child,
+                &format!("{}{}", indent, child_branch),
+                &format!("{}{}", indent, child_indent),
+                show_features,
+            );
+        }
+    }
+}
+
+/// Layout direction of a DOT graph (GraphViz `rankdir`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LayoutDirection {
+    /// Heads above their dependents
+    #[default]
+    TopToBottom,
+    /// Heads to the left of their dependents
+    LeftToRight,
+}
+
+/// What `Tree::write_dot` puts in the graph
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DotStyle {
+    /// Add the lemma to each node label
+    pub show_lemma: bool,
+    /// Add the XPOS tag to each node label
+    pub show_xpos: bool,
+    pub direction: LayoutDirection,
+}
+
+/// Escape a string for use inside a quoted DOT label
+fn dot_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Append `s` to `out` as a quoted JSON string
+pub(crate) fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
 }
 
 impl Default for Tree {
@@ -337,6 +1340,34 @@ mod tests {
         assert_eq!(tree.children_ids(0).unwrap().len(), 1);
     }
 
+    #[test]
+    fn test_word_depth() {
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"The", b"the", b"DET", b"_", Some(1), b"det");
+        tree.add_minimal_word(1, b"dog", b"dog", b"NOUN", b"_", Some(2), b"nsubj");
+        tree.add_minimal_word(2, b"runs", b"run", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(3, b"fast", b"fast", b"ADV", b"_", Some(2), b"advmod");
+        assert_eq!(tree.words[0].depth, u16::MAX);
+        tree.compile_tree();
+
+        let depths: Vec<u16> = tree.words.iter().map(|w| w.depth).collect();
+        assert_eq!(depths, vec![2, 1, 0, 1]);
+        for word in &tree.words {
+            assert_eq!(tree.word_depth(word.id), Some(word.depth as usize));
+        }
+        assert_eq!(tree.word_depth(4), None);
+    }
+
+    #[test]
+    fn test_word_depth_cycle() {
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"a", b"a", b"X", b"_", Some(1), b"dep");
+        tree.add_minimal_word(1, b"b", b"b", b"X", b"_", Some(0), b"dep");
+        tree.compile_tree();
+        assert_eq!(tree.words[0].depth, u16::MAX);
+        assert_eq!(tree.word_depth(0), None);
+    }
+
     #[test]
     fn test_children_by_deprel() {
         // Test multiple matches
@@ -425,4 +1456,445 @@ mod tests {
         // Same node
         assert!(tree.find_path(&tree.words[0], &tree.words[0]).is_none());
     }
+
+    #[test]
+    fn test_root() {
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"The", b"the", b"DET", b"_", Some(1), b"det");
+        tree.add_minimal_word(1, b"dog", b"dog", b"NOUN", b"_", Some(2), b"nsubj");
+        tree.add_minimal_word(2, b"runs", b"run", b"VERB", b"_", None, b"root");
+        tree.compile_tree();
+
+        assert_eq!(tree.root().map(|w| w.id), Some(2));
+        let roots: Vec<_> = tree.roots().iter().map(|w| w.id).collect();
+        assert_eq!(roots, vec![2]);
+    }
+
+    #[test]
+    fn test_root_empty_tree() {
+        let tree = Tree::default();
+        assert!(tree.root().is_none());
+        assert!(tree.roots().is_empty());
+    }
+
+    #[test]
+    fn test_roots_multiple() {
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"Hello", b"hello", b"INTJ", b"_", None, b"root");
+        tree.add_minimal_word(1, b"world", b"world", b"NOUN", b"_", None, b"root");
+        tree.compile_tree();
+
+        let roots: Vec<_> = tree.roots().iter().map(|w| w.id).collect();
+        assert_eq!(roots, vec![0, 1]);
+        assert!(tree.root().is_some());
+    }
+
+    #[test]
+    fn test_siblings() {
+        // "and" heads three conjuncts: cats (1), dogs (2), birds (3)
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"and", b"and", b"CCONJ", b"_", None, b"root");
+        tree.add_minimal_word(1, b"cats", b"cat", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(2, b"dogs", b"dog", b"NOUN", b"_", Some(0), b"conj");
+        tree.add_minimal_word(3, b"birds", b"bird", b"NOUN", b"_", Some(0), b"conj");
+        tree.compile_tree();
+
+        let ids = |words: Vec<&Word>| words.iter().map(|w| w.id).collect::<Vec<_>>();
+        assert_eq!(ids(tree.words[2].siblings(&tree)), vec![1, 3]);
+        assert!(tree.words[1].left_siblings(&tree).is_empty());
+        assert_eq!(ids(tree.words[1].right_siblings(&tree)), vec![2, 3]);
+        assert_eq!(ids(tree.words[3].left_siblings(&tree)), vec![1, 2]);
+        assert!(tree.words[3].right_siblings(&tree).is_empty());
+        assert!(tree.words[0].siblings(&tree).is_empty());
+    }
+
+    #[test]
+    fn test_has_feature_and_feature_value() {
+        let mut tree = Tree::default();
+        let feats = vec![
+            (
+                tree.string_pool.get_or_intern(b"Number"),
+                tree.string_pool.get_or_intern(b"Sing"),
+            ),
+            (
+                tree.string_pool.get_or_intern(b"Tense"),
+                tree.string_pool.get_or_intern(b"Past"),
+            ),
+        ];
+        tree.add_word(
+            0,
+            1,
+            b"was",
+            b"be",
+            b"AUX",
+            b"_",
+            feats,
+            None,
+            b"root",
+            Features::new(),
+        );
+        tree.compile_tree();
+
+        assert!(tree.has_feature(0, "Tense", "Past"));
+        assert!(!tree.has_feature(0, "Tense", "Pres"));
+        assert!(!tree.has_feature(0, "Mood", "Ind"));
+        assert!(!tree.has_feature(5, "Tense", "Past"));
+
+        assert_eq!(
+            tree.feature_value(0, "Number").as_deref(),
+            Some(&b"Sing"[..])
+        );
+        assert_eq!(tree.feature_value(0, "Mood"), None);
+        assert_eq!(tree.feature_value(5, "Number"), None);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut tree = Tree::with_metadata(
+            &BytestringPool::new(),
+            Some("Say \"hi\"".to_string()),
+            HashMap::from([("sent_id".to_string(), "s1".to_string())]),
+        );
+        let feats = vec![(
+            tree.string_pool.get_or_intern(b"Mood"),
+            tree.string_pool.get_or_intern(b"Imp"),
+        )];
+        tree.add_word(
+            0,
+            1,
+            b"Say",
+            b"say",
+            b"VERB",
+            b"VB",
+            feats,
+            None,
+            b"root",
+            vec![],
+        );
+        tree.add_word(
+            1,
+            2,
+            b"\"hi\"",
+            b"hi",
+            b"INTJ",
+            b"UH",
+            vec![],
+            Some(0),
+            b"obj",
+            vec![],
+        );
+        tree.compile_tree();
+        tree.raw_comments = vec!["# sent_id = s1".to_string(), "# checked".to_string()];
+        tree.multiword_tokens.push(MultiwordToken {
+            start: 1,
+            end: 2,
+            form: tree.string_pool.get_or_intern(b"Sayhi"),
+            misc: vec![],
+        });
+        tree.words[1].enhanced_deps.push(Dep {
+            head: Some(0),
+            deprel: tree.string_pool.get_or_intern(b"obj"),
+        });
+
+        assert_eq!(
+            tree.to_json(),
+            concat!(
+                r#"{"sentence_text": "Say \"hi\"", "metadata": {"sent_id": "s1"}, "#,
+                r##""comments": ["# sent_id = s1", "# checked"], "##,
+                r#""multiword_tokens": [{"start": 1, "end": 2, "form": "Sayhi", "misc": {}}], "words": ["#,
+                r#"{"id": 0, "token_id": 1, "form": "Say", "lemma": "say", "upos": "VERB", "xpos": "VB", "#,
+                r#""feats": {"Mood": "Imp"}, "head": null, "deprel": "root", "deps": [], "misc": {}}, "#,
+                r#"{"id": 1, "token_id": 2, "form": "\"hi\"", "lemma": "hi", "upos": "INTJ", "xpos": "UH", "#,
+                r#""feats": {}, "head": 0, "deprel": "obj", "deps": [{"head": 0, "deprel": "obj"}], "misc": {}}]}"#
+            )
+        );
+
+        let mut out = String::new();
+        push_json_string(&mut out, "a\\b\n\u{1}");
+        assert_eq!(out, r#""a\\b\n\u0001""#);
+    }
+
+    #[test]
+    fn test_from_json_round_trip() {
+        let conllu = "# newdoc id = d1\n\
+                      # sent_id = s1\n\
+                      # text = Al mar \"x\"\n\
+                      # checked\n\
+                      1-2\tAl\t_\t_\t_\t_\t_\t_\t_\tSpaceAfter=No\n\
+                      1\tA\ta\tADP\t_\t_\t3\tcase\t3:case\t_\n\
+                      2\tl\tel\tDET\t_\tDefinite=Def|PronType=Art\t3\tdet\t3:det\t_\n\
+                      3\tmar\tmar\tNOUN\t_\t_\t0\troot\t0:root\tGloss=sea\n\
+                      4\t\"x\"\tx\tPUNCT\t_\t_\t3\tpunct\t3:punct|1:dep\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let copy = Tree::from_json(&tree.to_json(), &BytestringPool::new()).unwrap();
+        assert_eq!(copy.to_json(), tree.to_json());
+        assert_eq!(copy.to_conllu(), tree.to_conllu());
+        assert_eq!(copy.comment_order, tree.comment_order);
+        assert_eq!(copy.doc_id.as_deref(), Some("d1"));
+        assert_eq!(copy.root_id, Some(2));
+        assert_eq!(copy.words[2].children, vec![0, 1, 3]);
+
+        let pool = BytestringPool::new();
+        assert!(Tree::from_json("{\"words\": [", &pool).is_err());
+        assert!(Tree::from_json("{\"comments\": []}", &pool).is_err());
+        let bad_head = tree.to_json().replace("\"head\": 2,", "\"head\": 9,");
+        assert!(Tree::from_json(&bad_head, &pool).is_err());
+        let bad_dep = tree.to_json().replace(
+            "{\"head\": 0, \"deprel\": \"dep\"}",
+            "{\"head\": 9, \"deprel\": \"dep\"}",
+        );
+        assert_ne!(bad_dep, tree.to_json());
+        assert!(Tree::from_json(&bad_dep, &pool).is_err());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let conllu = "1\t\"Hi\"\thi\tINTJ\tUH\t_\t2\tdiscourse\t_\t_\n\
+                      2\truns\trun\tVERB\tVBZ\t_\t0\troot\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tree.to_dot(),
+            concat!(
+                "digraph tree {\n",
+                "  rankdir=TB;\n",
+                "  node [shape=box];\n",
+                "  n0 [label=\"\\\"Hi\\\"\\nINTJ\"];\n",
+                "  n1 [label=\"runs\\nVERB\", peripheries=2];\n",
+                "  n1 -> n0 [label=\"discourse\"];\n",
+                "}\n"
+            )
+        );
+
+        let style = DotStyle {
+            show_lemma: true,
+            show_xpos: true,
+            direction: LayoutDirection::LeftToRight,
+        };
+        let dot = tree.to_dot_with_style(&style);
+        assert!(dot.contains("  rankdir=LR;\n"));
+        assert!(dot.contains("  n1 [label=\"runs\\nrun\\nVERB\\nVBZ\", peripheries=2];\n"));
+        assert_eq!(dot_escape("a\\b\nc"), "a\\\\b\\nc");
+    }
+
+    #[test]
+    fn test_to_ascii() {
+        let conllu = "1\tThe\tthe\tDET\tDT\t_\t2\tdet\t_\t_\n\
+                      2\tdog\tdog\tNOUN\tNN\tNumber=Sing\t3\tnsubj\t_\t_\n\
+                      3\truns\trun\tVERB\tVBZ\tNumber=Sing|Tense=Pres\t0\troot\t_\t_\n\
+                      4\tquickly\tquickly\tADV\tRB\t_\t3\tadvmod\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tree.to_ascii(false),
+            concat!(
+                "runs/VERB/root\n",
+                "├── dog/NOUN/nsubj\n",
+                "│   └── The/DET/det\n",
+                "└── quickly/ADV/advmod\n"
+            )
+        );
+        assert!(tree.to_ascii(true).starts_with(
+            "runs/VERB/root [Number=Sing|Tense=Pres]\n├── dog/NOUN/nsubj [Number=Sing]\n"
+        ));
+        assert_eq!(Tree::default().to_ascii(false), "");
+    }
+
+    #[test]
+    fn test_extract_subtree() {
+        let conllu = "# text = The big dog barked.\n\
+                      1\tThe\tthe\tDET\tDT\t_\t3\tdet\t_\t_\n\
+                      2\tbig\tbig\tADJ\tJJ\tDegree=Pos\t3\tamod\t_\t_\n\
+                      3\tdog\tdog\tNOUN\tNN\tNumber=Sing\t4\tnsubj\t_\t_\n\
+                      4\tbarked\tbark\tVERB\tVBD\t_\t0\troot\t_\tSpaceAfter=No\n\
+                      5\t.\t.\tPUNCT\t.\t_\t4\tpunct\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let form = |tree: &Tree, id: WordId| {
+            String::from_utf8_lossy(&tree.string_pool.resolve(tree.words[id].form)).into_owned()
+        };
+
+        // NP subtree rooted at "dog"
+        let np = tree.extract_subtree(2);
+        assert_eq!(np.len(), 3);
+        assert_eq!(np.sentence_text.as_deref(), Some("The big dog"));
+        assert_eq!(np.root_id, Some(2));
+        assert_eq!(form(&np, 2), "dog");
+        assert_eq!(np.words[0].head, Some(2));
+        assert_eq!(np.words[1].head, Some(2));
+        assert_eq!(np.words[2].head, None);
+        assert_eq!(np.words[2].children, vec![0, 1]);
+        assert_eq!(np.words[1].token_id, 2);
+        assert!(np.has_feature(2, "Number", "Sing"));
+        assert_eq!(np.words[2].depth, 0);
+
+        // Whole tree: same words, SpaceAfter=No respected
+        let all = tree.extract_subtree(3);
+        assert_eq!(all.len(), 5);
+        assert_eq!(all.sentence_text.as_deref(), Some("The big dog barked."));
+
+        // A leaf gives a one-word tree
+        let leaf = tree.extract_subtree(0);
+        assert_eq!(leaf.len(), 1);
+        assert_eq!(leaf.root_id, Some(0));
+        assert_eq!(form(&leaf, 0), "The");
+    }
+
+    #[test]
+    fn test_extract_subtree_enhanced_and_multiword() {
+        let conllu = "1\tVa\tir\tVERB\t_\t_\t0\troot\t0:root\t_\n\
+                      2-3\tal\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                      2\ta\ta\tADP\t_\t_\t5\tcase\t5:case\t_\n\
+                      3\tel\tel\tDET\t_\t_\t5\tdet\t5:det\t_\n\
+                      4-5\tnuevomar\t_\t_\t_\t_\t_\t_\t_\t_\n\
+                      4\tnuevo\tnuevo\tADJ\t_\t_\t5\tamod\t5:amod|1:dep\t_\n\
+                      5\tmar\tmar\tNOUN\t_\t_\t1\tobl\t1:obl:a|4:dep\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        let deps = |tree: &Tree, id: WordId| -> Vec<(Option<WordId>, String)> {
+            tree.words[id]
+                .enhanced_deps
+                .iter()
+                .map(|dep| {
+                    let deprel = tree.string_pool.resolve(dep.deprel);
+                    (dep.head, String::from_utf8_lossy(&deprel).into_owned())
+                })
+                .collect()
+        };
+
+        // Subtree rooted at "mar": deps into the subtree are renumbered, the one to
+        // the old head becomes a root relation and the one to "Va" is dropped
+        let np = tree.extract_subtree(4);
+        assert_eq!(np.len(), 4);
+        assert_eq!(deps(&np, 0), vec![(Some(3), "case".to_string())]);
+        assert_eq!(deps(&np, 2), vec![(Some(3), "amod".to_string())]);
+        assert_eq!(
+            deps(&np, 3),
+            vec![(None, "obl:a".to_string()), (Some(2), "dep".to_string())]
+        );
+        let tokens: Vec<_> = np
+            .multiword_tokens
+            .iter()
+            .map(|token| (token.start, token.end))
+            .collect();
+        assert_eq!(tokens, vec![(1, 2), (3, 4)]);
+        assert!(np.to_conllu().contains("1-2\tal\t"));
+
+        // "nuevo" alone only covers half of "nuevomar"
+        let adj = tree.extract_subtree(3);
+        assert!(adj.multiword_tokens.is_empty());
+        assert_eq!(deps(&adj, 0), vec![(None, "amod".to_string())]);
+    }
+
+    #[test]
+    fn test_subtree_yield_and_text() {
+        let conllu = "1\tI\tI\tPRON\t_\t_\t2\tnsubj\t_\t_\n\
+                      2\tsaw\tsee\tVERB\t_\t_\t0\troot\t_\t_\n\
+                      3\tthe\tthe\tDET\t_\t_\t4\tdet\t_\t_\n\
+                      4\tdog\tdog\tNOUN\t_\t_\t2\tobj\t_\tSpaceAfter=No\n\
+                      5\t's\t's\tPART\t_\t_\t4\tcase\t_\t_\n\
+                      6\tball\tball\tNOUN\t_\t_\t2\tobl\t_\tSpaceAfter=No\n\
+                      7\t.\t.\tPUNCT\t_\t_\t2\tpunct\t_\t_\n\n";
+        let tree = crate::conllu::TreeIterator::from_string(conllu)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(tree.subtree_yield(3), vec![2, 3, 4]);
+        assert_eq!(tree.subtree_span(3), (2, 4));
+        assert_eq!(tree.subtree_text(3), "the dog's");
+        assert_eq!(tree.subtree_text(1), "I saw the dog's ball.");
+        assert_eq!(tree.subtree_span(5), (5, 5));
+    }
+
+    #[test]
+    fn test_lca() {
+        // helped(0) -> us(1), win(3); win -> to(2)
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"helped", b"help", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(1, b"us", b"we", b"PRON", b"_", Some(0), b"obj");
+        tree.add_minimal_word(2, b"to", b"to", b"PART", b"_", Some(3), b"mark");
+        tree.add_minimal_word(3, b"win", b"win", b"VERB", b"_", Some(0), b"xcomp");
+        tree.compile_tree();
+
+        assert_eq!(tree.path_to_root(2), vec![2, 3, 0]);
+        assert_eq!(tree.path_to_root(0), vec![0]);
+        assert!(tree.path_to_root(9).is_empty());
+
+        assert_eq!(tree.lca(1, 2), Some(0));
+        assert_eq!(tree.lca(2, 3), Some(3)); // ancestor of the other
+        assert_eq!(tree.lca(2, 2), Some(2));
+        assert_eq!(tree.lca(1, 9), None);
+    }
+
+    #[test]
+    fn test_path_to_root_cycle() {
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"a", b"a", b"X", b"_", Some(1), b"dep");
+        tree.add_minimal_word(1, b"b", b"b", b"X", b"_", Some(0), b"dep");
+        assert_eq!(tree.path_to_root(0), vec![0, 1]);
+        assert_eq!(tree.lca(0, 1), Some(1));
+    }
+
+    #[test]
+    fn test_structural_metrics() {
+        // helped(0) -> us(1), win(3); win -> to(2)
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"helped", b"help", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(1, b"us", b"we", b"PRON", b"_", Some(0), b"obj");
+        tree.add_minimal_word(2, b"to", b"to", b"PART", b"_", Some(3), b"mark");
+        tree.add_minimal_word(3, b"win", b"win", b"VERB", b"_", Some(0), b"xcomp");
+        tree.compile_tree();
+
+        assert_eq!(tree.height(), 2);
+        assert_eq!(tree.subtree_size(0), 4);
+        assert_eq!(tree.subtree_size(3), 2);
+        assert_eq!(tree.subtree_size(2), 1);
+        // helped has 2 children, win has 1
+        assert_eq!(tree.mean_branching_factor(), 1.5);
+
+        let empty = Tree::default();
+        assert_eq!(empty.height(), 0);
+        assert_eq!(empty.mean_branching_factor(), 0.0);
+    }
+
+    #[test]
+    fn test_projectivity() {
+        // helped(0) -> us(1), win(3); win -> to(2)
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"helped", b"help", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(1, b"us", b"we", b"PRON", b"_", Some(0), b"obj");
+        tree.add_minimal_word(2, b"to", b"to", b"PART", b"_", Some(3), b"mark");
+        tree.add_minimal_word(3, b"win", b"win", b"VERB", b"_", Some(0), b"xcomp");
+        tree.compile_tree();
+        assert!(tree.is_projective());
+        assert!(tree.non_projective_arcs().is_empty());
+
+        // A(0) hearing(1) is(2) scheduled(3) on(4) the(5) issue(6):
+        // "on the issue" modifies "hearing" across "is scheduled"
+        let mut tree = Tree::default();
+        tree.add_minimal_word(0, b"A", b"a", b"DET", b"_", Some(1), b"det");
+        tree.add_minimal_word(1, b"hearing", b"hearing", b"NOUN", b"_", Some(3), b"nsubj");
+        tree.add_minimal_word(2, b"is", b"be", b"AUX", b"_", Some(3), b"aux");
+        tree.add_minimal_word(3, b"scheduled", b"schedule", b"VERB", b"_", None, b"root");
+        tree.add_minimal_word(4, b"on", b"on", b"ADP", b"_", Some(6), b"case");
+        tree.add_minimal_word(5, b"the", b"the", b"DET", b"_", Some(6), b"det");
+        tree.add_minimal_word(6, b"issue", b"issue", b"NOUN", b"_", Some(1), b"nmod");
+        tree.compile_tree();
+        assert!(!tree.is_projective());
+        assert_eq!(tree.non_projective_arcs(), vec![(1, 6)]);
+        assert!(tree.has_non_projective_arc(1, 6));
+        assert!(!tree.has_non_projective_arc(3, 1));
+        assert!(!tree.has_non_projective_arc(6, 1)); // not an arc
+    }
 }
@@ -0,0 +1,298 @@
+//! Semantic validation of parsed trees
+//!
+//! Parsing only checks that each line is well formed. `validate_tree` checks the
+//! sentence as a whole against the UD guidelines: head references, the root,
+//! cycles, UPOS tags and feature order.
+
+use crate::conllu::{ParseError, TreeIterator};
+use crate::tree::{Tree, WordId};
+use std::io::BufRead;
+use thiserror::Error;
+
+/// Universal POS tags defined by UD
+pub const UPOS_TAGS: [&str; 17] = [
+    "ADJ", "ADP", "ADV", "AUX", "CCONJ", "DET", "INTJ", "NOUN", "NUM", "PART", "PRON", "PROPN",
+    "PUNCT", "SCONJ", "SYM", "VERB", "X",
+];
+
+/// Kind of problem found by `validate_tree`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationErrorKind {
+    /// The sentence could not be parsed (only produced by `TreeIterator::validated`)
+    Parse,
+    /// HEAD refers to a word outside the sentence
+    HeadOutOfBounds,
+    /// No word has an empty head
+    NoRoot,
+    /// More than one word has an empty head
+    MultipleRoots,
+    /// The head chain loops back on itself, so the words never reach the root
+    Cycle,
+    /// UPOS is not one of the UD tags
+    InvalidUpos,
+    /// FEATS pairs are not sorted alphabetically by key
+    UnsortedFeatures,
+}
+
+impl ValidationErrorKind {
+    /// Whether the problem breaks the tree structure (rather than just the annotation)
+    pub fn is_fatal(self) -> bool {
+        matches!(
+            self,
+            Self::Parse | Self::HeadOutOfBounds | Self::NoRoot | Self::Cycle
+        )
+    }
+}
+
+/// A problem found while validating a tree
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message}")]
+pub struct ValidationError {
+    pub kind: ValidationErrorKind,
+    pub word_id: Option<WordId>,
+    pub message: String,
+}
+
+/// Non-fatal validation problems, returned alongside the tree by `TreeIterator::validated`
+pub type ValidationWarning = ValidationError;
+
+impl ValidationError {
+    fn new(kind: ValidationErrorKind, word_id: Option<WordId>, message: String) -> Self {
+        Self {
+            kind,
+            word_id,
+            message,
+        }
+    }
+}
+
+impl From<ParseError> for ValidationError {
+    fn from(err: ParseError) -> Self {
+        Self::new(ValidationErrorKind::Parse, None, err.to_string())
+    }
+}
+
+/// Check a tree against the UD guidelines, returning every problem found
+///
+/// Only the words' heads and fields are used, so the check also works on trees
+/// that have not been compiled.
+pub fn validate_tree(tree: &Tree) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let num_words = tree.words.len();
+
+    let roots: Vec<WordId> = tree
+        .words
+        .iter()
+        .filter(|word| word.head.is_none())
+        .map(|word| word.id)
+        .collect();
+    match roots.as_slice() {
+        [] if num_words > 0 => errors.push(ValidationError::new(
+            ValidationErrorKind::NoRoot,
+            None,
+            "no word is attached to the root".to_string(),
+        )),
+        [_] | [] => {}
+        [_, rest @ ..] => {
+            for &word_id in rest {
+                errors.push(ValidationError::new(
+                    ValidationErrorKind::MultipleRoots,
+                    Some(word_id),
+                    format!("word {} is a second root", word_id),
+                ));
+            }
+        }
+    }
+
+    // Walk each head chain once: 1 = on the current path, 2 = already checked
+    let mut state = vec![0u8; num_words];
+    for start in 0..num_words {
+        let mut path = Vec::new();
+        let mut current = start;
+        while state[current] == 0 {
+            state[current] = 1;
+            path.push(current);
+            match tree.words[current].head {
+                None => break,
+                Some(head) if head >= num_words => {
+                    errors.push(ValidationError::new(
+                        ValidationErrorKind::HeadOutOfBounds,
+                        Some(current),
+                        format!(
+                            "word {} has head {}, but the sentence has {} words",
+                            current, head, num_words
+                        ),
+                    ));
+                    break;
+                }
+                Some(head) => current = head,
+            }
+        }
+        if state[current] == 1 && tree.words[current].head.is_some_and(|h| h < num_words) {
+            // Back on the current path: everything from `current` on is the cycle
+            let cycle = &path[path.iter().position(|&id| id == current).unwrap()..];
+            let first = *cycle.iter().min().unwrap();
+            errors.push(ValidationError::new(
+                ValidationErrorKind::Cycle,
+                Some(first),
+                format!("words {:?} form a head cycle", cycle),
+            ));
+        }
+        for id in path {
+            state[id] = 2;
+        }
+    }
+
+    for word in &tree.words {
+        let upos = tree.string_pool.resolve(word.upos);
+        if !UPOS_TAGS.iter().any(|tag| tag.as_bytes() == &*upos) {
+            errors.push(ValidationError::new(
+                ValidationErrorKind::InvalidUpos,
+                Some(word.id),
+                format!(
+                    "word {} has invalid UPOS {:?}",
+                    word.id,
+                    String::from_utf8_lossy(&upos)
+                ),
+            ));
+        }
+
+        let keys: Vec<Vec<u8>> = word
+            .feats
+            .iter()
+            .map(|&(key, _)| tree.string_pool.resolve(key).to_ascii_lowercase())
+            .collect();
+        if keys.windows(2).any(|pair| pair[0] > pair[1]) {
+            errors.push(ValidationError::new(
+                ValidationErrorKind::UnsortedFeatures,
+                Some(word.id),
+                format!("word {} has features out of alphabetical order", word.id),
+            ));
+        }
+    }
+
+    errors
+}
+
+impl<R: BufRead> TreeIterator<R> {
+    /// Validate each tree as it is parsed
+    ///
+    /// Yields the tree with its non-fatal warnings, or the first fatal problem
+    /// (including parse errors).
+    pub fn validated(
+        self,
+    ) -> impl Iterator<Item = Result<(Tree, Vec<ValidationWarning>), ValidationError>> {
+        self.map(|result| {
+            let tree = result?;
+            let (fatal, warnings): (Vec<_>, Vec<_>) = validate_tree(&tree)
+                .into_iter()
+                .partition(|error| error.kind.is_fatal());
+            match fatal.into_iter().next() {
+                Some(error) => Err(error),
+                None => Ok((tree, warnings)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytes::BytestringPool;
+    use std::collections::HashMap;
+
+    fn build_tree(words: &[(&str, Option<WordId>)]) -> Tree {
+        let mut tree = Tree::with_metadata(&BytestringPool::new(), None, HashMap::new());
+        for (i, &(upos, head)) in words.iter().enumerate() {
+            tree.add_word(
+                i,
+                i + 1,
+                b"w",
+                b"w",
+                upos.as_bytes(),
+                b"_",
+                vec![],
+                head,
+                b"dep",
+                vec![],
+            );
+        }
+        tree
+    }
+
+    fn kinds(tree: &Tree) -> Vec<ValidationErrorKind> {
+        validate_tree(tree).into_iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn test_valid_tree() {
+        let tree = build_tree(&[("DET", Some(1)), ("NOUN", Some(2)), ("VERB", None)]);
+        assert!(validate_tree(&tree).is_empty());
+    }
+
+    #[test]
+    fn test_structural_errors() {
+        use ValidationErrorKind::*;
+
+        let tree = build_tree(&[("NOUN", None), ("VERB", None)]);
+        assert_eq!(kinds(&tree), vec![MultipleRoots]);
+        assert_eq!(validate_tree(&tree)[0].word_id, Some(1));
+
+        let tree = build_tree(&[("NOUN", Some(5)), ("VERB", None)]);
+        assert_eq!(kinds(&tree), vec![HeadOutOfBounds]);
+
+        let tree = build_tree(&[("NOUN", Some(1)), ("VERB", Some(0))]);
+        assert_eq!(kinds(&tree), vec![NoRoot, Cycle]);
+
+        // A chain hanging off a cycle is reported once, at the cycle
+        let tree = build_tree(&[
+            ("VERB", None),
+            ("NOUN", Some(2)),
+            ("NOUN", Some(3)),
+            ("NOUN", Some(2)),
+        ]);
+        let errors = validate_tree(&tree);
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].kind, errors[0].word_id), (Cycle, Some(2)));
+
+        let tree = build_tree(&[("VERB", Some(0))]);
+        assert_eq!(kinds(&tree), vec![NoRoot, Cycle]);
+    }
+
+    #[test]
+    fn test_annotation_errors() {
+        use ValidationErrorKind::*;
+
+        let mut tree = build_tree(&[("NOUN", Some(1)), ("verb", None)]);
+        assert_eq!(kinds(&tree), vec![InvalidUpos]);
+
+        let pool = &mut tree.string_pool;
+        tree.words[0].feats = vec![
+            (pool.get_or_intern(b"Number"), pool.get_or_intern(b"Sing")),
+            (pool.get_or_intern(b"Gender"), pool.get_or_intern(b"Masc")),
+        ];
+        assert_eq!(kinds(&tree), vec![UnsortedFeatures, InvalidUpos]);
+        assert!(!UnsortedFeatures.is_fatal() && Cycle.is_fatal());
+    }
+
+    #[test]
+    fn test_validated_iterator() {
+        let conllu = "1\tword\tword\tNOUN\t_\tNumber=Sing|Case=Nom\t0\troot\t_\t_\n\n\
+                      1\ta\ta\tNOUN\t_\t_\t2\tdep\t_\t_\n\
+                      2\tb\tb\tNOUN\t_\t_\t1\tdep\t_\t_\n\n\
+                      1\tbad\n\n";
+        let mut trees = TreeIterator::from_string(conllu).validated();
+
+        let (tree, warnings) = trees.next().unwrap().unwrap();
+        assert_eq!(tree.words.len(), 1);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].kind, ValidationErrorKind::UnsortedFeatures);
+
+        let err = trees.next().unwrap().unwrap_err();
+        assert_eq!(err.kind, ValidationErrorKind::NoRoot);
+
+        let err = trees.next().unwrap().unwrap_err();
+        assert_eq!(err.kind, ValidationErrorKind::Parse);
+        assert!(trees.next().is_none());
+    }
+}
@@ -106,6 +106,25 @@ class TestPattern:
         assert pattern is not None
         assert "Pattern" in repr(pattern)
 
+    def test_order_by(self):
+        """Pattern.order_by exposes the ORDER BY clause."""
+        pattern = treesearch.compile_query(
+            "MATCH { V []; S []; V -> S; } ORDER BY V.lemma, S.form DESC"
+        )
+        assert pattern.order_by == [("V", "lemma", True), ("S", "form", False)]
+        assert treesearch.compile_query("MATCH { V []; }").order_by == []
+
+    def test_search_respects_order_by(self, sample_conllu):
+        """Matches within a tree come back in ORDER BY order."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        asc = [t.word(m["W"]).lemma for t, m in tb.search("MATCH { W []; } ORDER BY W.lemma")]
+        desc = [
+            t.word(m["W"]).lemma
+            for t, m in tb.search("MATCH { W []; } ORDER BY W.lemma DESC")
+        ]
+        assert asc == sorted(asc)
+        assert desc == asc[::-1]
+
     @pytest.mark.parametrize(
         "query",
         [
@@ -162,6 +181,47 @@ class TestTreeReading:
         assert hasattr(tree_iter, "__iter__")
         assert hasattr(tree_iter, "__next__")
 
+    def test_trees_with_index(self, multi_tree_conllu):
+        """.trees_with_index() yields (index, Tree) pairs."""
+        tb = treesearch.Treebank.from_string(multi_tree_conllu)
+        pairs = list(tb.trees_with_index())
+        assert [idx for idx, _ in pairs] == [0, 1]
+        assert all(isinstance(tree, treesearch.Tree) for _, tree in pairs)
+
+    def test_read_trees_from_file(self, temp_conllu_file):
+        """read_trees() reads a single file path."""
+        assert len(list(treesearch.read_trees(temp_conllu_file))) == 1
+
+    def test_read_trees_from_path_object(self, temp_conllu_file):
+        """read_trees() accepts a pathlib.Path."""
+        from pathlib import Path
+
+        assert len(list(treesearch.read_trees(Path(temp_conllu_file)))) == 1
+
+    def test_read_trees_from_glob(self, temp_multi_files):
+        """read_trees() expands glob patterns."""
+        tmpdir, _ = temp_multi_files
+        assert len(list(treesearch.read_trees(f"{tmpdir}/*.conllu"))) == 6
+
+    def test_read_trees_from_list(self, temp_multi_files):
+        """read_trees() reads a list of files."""
+        _, paths = temp_multi_files
+        assert len(list(treesearch.read_trees(paths[:2]))) == 4
+
+    def test_read_trees_from_string(self, sample_conllu, multi_tree_conllu):
+        """read_trees() parses CoNLL-U text starting with a comment or a digit."""
+        assert len(list(treesearch.read_trees(sample_conllu))) == 1
+        no_comments = "\n".join(
+            line for line in multi_tree_conllu.splitlines() if not line.startswith("#")
+        )
+        assert len(list(treesearch.read_trees(no_comments))) == 2
+
+    def test_read_trees_digit_filename(self, sample_conllu, tmp_path):
+        """A file name starting with a digit is still read as a file."""
+        path = tmp_path / "2024.conllu"
+        path.write_text(sample_conllu)
+        assert len(list(treesearch.read_trees(str(path)))) == 1
+
     def test_nonexistent_file_raises_oserror(self):
         """Reading nonexistent file raises OSError."""
         trees = treesearch.Treebank.from_file("/nonexistent/file.conllu").trees()
@@ -189,6 +249,25 @@ class TestTreeProperties:
         assert tree.metadata["sent_id"] == "1"
         assert tree.metadata["source"] == "test"
 
+    def test_document_boundaries(self):
+        """Tree.is_new_doc, is_new_par and doc_id come from newdoc/newpar comments."""
+        conllu = (
+            "# newdoc id = d1\n# newpar\n1\tHi\thi\tINTJ\t_\t_\t0\troot\t_\t_\n\n"
+            "1\tYo\tyo\tINTJ\t_\t_\t0\troot\t_\t_\n\n"
+        )
+        first, second = treesearch.Treebank.from_string(conllu).trees()
+        assert (first.is_new_doc, first.is_new_par, first.doc_id) == (True, True, "d1")
+        assert (second.is_new_doc, second.is_new_par, second.doc_id) == (False, False, None)
+
+    def test_raw_comments(self, complex_conllu):
+        """Tree.raw_comments keeps every comment line in order."""
+        tree = list(treesearch.Treebank.from_string(complex_conllu).trees())[0]
+        assert tree.raw_comments == [
+            "# sent_id = 1",
+            "# text = The big dog runs.",
+            "# source = test",
+        ]
+
     def test_len(self, sample_conllu):
         """len(tree) returns word count."""
         tree = list(treesearch.Treebank.from_string(sample_conllu).trees())[0]
@@ -199,6 +278,34 @@ class TestTreeProperties:
         tree = list(treesearch.Treebank.from_string(sample_conllu).trees())[0]
         assert "<Tree len=6" in repr(tree)
 
+    def test_has_feature(self, complex_conllu):
+        """tree.has_feature checks a key=value feature on a word."""
+        tree = list(treesearch.Treebank.from_string(complex_conllu).trees())[0]
+        assert tree.has_feature(3, "Tense", "Pres")
+        assert not tree.has_feature(3, "Tense", "Past")
+        with pytest.raises(IndexError):
+            tree.has_feature(99, "Tense", "Pres")
+
+    def test_feature_value(self, complex_conllu):
+        """tree.feature_value returns the value or None."""
+        tree = list(treesearch.Treebank.from_string(complex_conllu).trees())[0]
+        assert tree.feature_value(2, "Number") == "Sing"
+        assert tree.feature_value(2, "Tense") is None
+
+    def test_root(self, sample_conllu):
+        """tree.root returns the root Word."""
+        tree = list(treesearch.Treebank.from_string(sample_conllu).trees())[0]
+        assert tree.root is not None
+        assert tree.root.head is None
+        assert tree.root.deprel == "root"
+
+    def test_roots(self, sample_conllu):
+        """tree.roots lists every headless word."""
+        tree = list(treesearch.Treebank.from_string(sample_conllu).trees())[0]
+        roots = tree.roots
+        assert len(roots) == 1
+        assert roots[0].id == tree.root.id
+
     def test_getitem(self, sample_conllu):
         """tree[i] returns word by index."""
         tree = list(treesearch.Treebank.from_string(sample_conllu).trees())[0]
@@ -344,6 +451,30 @@ class TestWordNavigation:
         verb = tree.word(1)
         assert verb.children_by_deprel("nonexistent") == []
 
+    def test_left_siblings(self, tree):
+        """word.left_siblings lists earlier words with the same parent."""
+        assert tree.word(0).left_siblings == []  # "He" is the leftmost
+        forms = [w.form for w in tree.word(4).left_siblings]  # "win"
+        assert forms == ["He", "us"]
+
+    def test_right_siblings(self, tree):
+        """word.right_siblings lists later words with the same parent."""
+        assert tree.word(5).right_siblings == []  # "." is the rightmost
+        forms = [w.form for w in tree.word(0).right_siblings]  # "He"
+        assert forms == ["us", "win", "."]
+
+    def test_depth(self, tree):
+        """word.depth is the distance from the root."""
+        assert tree.word(1).depth == 0  # "helped"
+        assert tree.word(0).depth == 1  # "He"
+        assert tree.word(3).depth == 2  # "to"
+
+    def test_siblings_of_root(self, tree):
+        """The root has no siblings."""
+        root = tree.word(1)
+        assert root.left_siblings == []
+        assert root.right_siblings == []
+
 
 # ==============================================================================
 # Search Tests - API Surface
@@ -372,6 +503,83 @@ class TestSearch:
             assert isinstance(match, dict)
             break
 
+    def test_search_to_jsonl(self, sample_conllu, tmp_path):
+        """MatchIterator.to_jsonl writes one JSON object per match."""
+        import json
+
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        path = tmp_path / "matches.jsonl"
+        count = tb.search('MATCH { V [upos="VERB"]; }').to_jsonl(str(path))
+        rows = [json.loads(line) for line in path.read_text().splitlines()]
+        assert count == len(rows) == 2
+        assert rows[0] == {
+            "sent_id": None,
+            "sentence_text": "He helped us to win.",
+            "V": {"form": "helped", "lemma": "help", "upos": "VERB"},
+        }
+
+        query = 'MATCH { V [lemma="help"]; O []; V -[obj]-> O; }'
+        tb.search(query).to_jsonl(str(path), fields=["deprel", "token_id"])
+        row = json.loads(path.read_text())
+        assert row["O"] == {"deprel": "obj", "token_id": 3}
+        with pytest.raises(ValueError):
+            tb.search(query).to_jsonl(str(path), fields=["color"])
+
+    def test_search_to_csv(self, sample_conllu, tmp_path):
+        """MatchIterator.to_csv writes a header and one row per match."""
+        import csv
+
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        query = 'MATCH { V [upos="VERB"]; } OPTIONAL { O []; V -[obj]-> O; }'
+        columns = [("V.lemma", "V", "lemma"), ("O.form", "O", "form")]
+        path = tmp_path / "matches.csv"
+        assert tb.search(query).to_csv(str(path), columns) == 2
+        with open(path, newline="") as f:
+            rows = list(csv.reader(f))
+        assert rows == [["V.lemma", "O.form"], ["help", "us"], ["win", ""]]
+
+        path = tmp_path / "matches.tsv"
+        tb.search(query).to_csv(str(path), columns, sep="\t")
+        assert path.read_text().splitlines()[0] == "V.lemma\tO.form"
+        with pytest.raises(ValueError):
+            tb.search(query).to_csv(str(path), columns, sep="::")
+        with pytest.raises(ValueError):
+            tb.search(query).to_csv(str(path), [("x", "V", "color")])
+
+    def test_search_to_arrow(self, sample_conllu):
+        """MatchIterator.to_arrow builds a RecordBatch with one column per variable field."""
+        pytest.importorskip("pyarrow")
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        query = 'MATCH { V [upos="VERB"]; } OPTIONAL { O []; V -[obj]-> O; }'
+        batch = tb.search(query).to_arrow(["V.lemma", "O.form", "O.token_id"])
+        assert batch.column_names == ["V.lemma", "O.form", "O.token_id"]
+        assert batch.to_pydict() == {
+            "V.lemma": ["help", "win"],
+            "O.form": ["us", None],
+            "O.token_id": [3, None],
+        }
+
+        batch = tb.search(query).to_arrow()
+        assert batch.column_names == ["O.lemma", "O.upos", "V.lemma", "V.upos"]
+
+        matches = tb.search(query)
+        assert matches.to_arrow(batch_size=1).num_rows == 1
+        assert matches.to_arrow(batch_size=1).num_rows == 1
+        assert matches.to_arrow().num_rows == 0
+
+        # The default schema comes from the pattern, even when O is unbound in a batch
+        matches = tb.search(query, ordered=True)
+        schemas = [matches.to_arrow(batch_size=1).column_names for _ in range(2)]
+        assert schemas == [["O.lemma", "O.upos", "V.lemma", "V.upos"]] * 2
+        with pytest.raises(ValueError):
+            tb.search(query).to_arrow(["lemma"])
+
+        # A schema naming a variable the pattern lacks is an error, not a null column
+        matches = tb.search(query)
+        with pytest.raises(ValueError, match="X"):
+            matches.to_arrow(["V.lemma", "X.form"])
+        assert matches.to_arrow().num_rows == 2
+
     def test_search_accepts_string_query(self, sample_conllu):
         """Treebank.search accepts query string directly."""
         tb = treesearch.Treebank.from_string(sample_conllu)
@@ -393,6 +601,258 @@ class TestSearch:
         pattern_matches = [m for _, m in tb.search(treesearch.compile_query(query))]
         assert str_matches == pattern_matches
 
+    def test_tree_metrics(self, sample_conllu):
+        """height, subtree_size and mean_branching_factor."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree.height() == 2  # helped -> win -> to
+        assert tree.subtree_size(1) == 6
+        assert tree.subtree_size(4) == 2
+        assert tree.mean_branching_factor() == 2.5  # helped: 4, win: 1
+        with pytest.raises(IndexError):
+            tree.subtree_size(len(tree))
+
+    def test_subtree_text(self, sample_conllu):
+        """subtree_text and subtree_span give the surface extent of a subtree."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree.subtree_text(4) == "to win"
+        assert tree.subtree_span(4) == (3, 4)
+        assert tree.subtree_text(1) == "He helped us to win ."
+        with pytest.raises(IndexError):
+            tree.subtree_text(len(tree))
+
+    def test_tree_projectivity(self, sample_conllu):
+        """is_projective and non_projective_arcs."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree.is_projective()
+        assert tree.non_projective_arcs() == []
+
+        conllu = """1\tA\ta\tDET\tDT\t_\t2\tdet\t_\t_
+2\thearing\thearing\tNOUN\tNN\t_\t4\tnsubj\t_\t_
+3\tis\tbe\tAUX\tVBZ\t_\t4\taux\t_\t_
+4\tscheduled\tschedule\tVERB\tVBN\t_\t0\troot\t_\t_
+5\ton\ton\tADP\tIN\t_\t7\tcase\t_\t_
+6\tthe\tthe\tDET\tDT\t_\t7\tdet\t_\t_
+7\tissue\tissue\tNOUN\tNN\t_\t2\tnmod\t_\t_
+
+"""
+        tree = next(iter(treesearch.Treebank.from_string(conllu).trees()))
+        assert not tree.is_projective()
+        assert tree.non_projective_arcs() == [(1, 6)]
+
+    def test_tree_iter(self, sample_conllu):
+        """Iterating over a tree yields its words in order."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        words = list(tree)
+        assert len(words) == len(tree)
+        assert [w.id for w in words] == list(range(len(tree)))
+        assert [w.form for w in words] == [tree[i].form for i in range(len(tree))]
+        verbs = [w.lemma for w in tree if w.upos == "VERB"]
+        assert verbs == [tree[i].lemma for i in range(len(tree)) if tree[i].upos == "VERB"]
+        it = iter(tree)
+        assert iter(it) is it
+
+    def test_tree_to_conllu(self, sample_conllu):
+        """tree.to_conllu() round-trips through the parser."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        text = tree.to_conllu()
+        assert text.endswith("\n\n")
+        again = next(iter(treesearch.Treebank.from_string(text).trees()))
+        assert again.to_conllu() == text
+        assert again.sentence_text == tree.sentence_text
+        assert [again[i].form for i in range(len(again))] == [tree[i].form for i in range(len(tree))]
+
+    def test_tree_validate(self, sample_conllu):
+        """tree.validate() reports UD problems as messages."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree.validate() == []
+        conllu = "1\ta\ta\tnoun\t_\t_\t0\troot\t_\t_\n2\tb\tb\tNOUN\t_\t_\t0\troot\t_\t_\n\n"
+        tree = next(iter(treesearch.Treebank.from_string(conllu).trees()))
+        problems = tree.validate()
+        assert len(problems) == 2
+        assert any("second root" in p for p in problems)
+        assert any("UPOS" in p for p in problems)
+
+    def test_word_enhanced_deps(self):
+        """The DEPS column is parsed into word.enhanced_deps."""
+        conllu = """1\tit\tit\tPRON\t_\t_\t2\tnsubj\t2:nsubj|3:nsubj:xsubj\t_
+2\tstarts\tstart\tVERB\t_\t_\t0\troot\t0:root\t_
+3\training\train\tVERB\t_\t_\t2\txcomp\t2:xcomp\t_
+
+"""
+        tree = next(iter(treesearch.Treebank.from_string(conllu).trees()))
+        assert tree[0].enhanced_deps == [(1, "nsubj"), (2, "nsubj:xsubj")]
+        assert tree[1].enhanced_deps == [(None, "root")]
+        assert tree.to_conllu() == conllu
+
+    def test_tree_multiword_tokens(self, sample_conllu):
+        """Multiword token lines are kept on the tree."""
+        conllu = """1-2\tdel\t_\t_\t_\t_\t_\t_\t_\t_
+1\tde\tde\tADP\t_\t_\t3\tcase\t_\t_
+2\tel\tel\tDET\t_\t_\t3\tdet\t_\t_
+3\tmar\tmar\tNOUN\t_\t_\t0\troot\t_\t_
+
+"""
+        tree = next(iter(treesearch.Treebank.from_string(conllu).trees()))
+        assert tree.multiword_tokens == [{"start": 1, "end": 2, "form": "del", "misc": {}}]
+        assert len(tree) == 3
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree.multiword_tokens == []
+
+    def test_tree_lca(self, sample_conllu):
+        """tree.lca() finds the lowest common ancestor."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree.lca(0, 3) == 1  # He, to -> helped
+        assert tree.lca(3, 4) == 4  # to, win -> win
+        with pytest.raises(IndexError):
+            tree.lca(0, len(tree))
+
+    def test_word_equality_and_ordering(self, sample_conllu):
+        """Words compare by tree and id, hash consistently and sort by position."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree[3] == tree[3]
+        assert tree[3] != tree[2]
+        assert tree[0] < tree[1] and tree[2] >= tree[1]
+        assert len({tree[0], tree[0], tree[1]}) == 2
+        assert sorted([tree[2], tree[0], tree[1]]) == [tree[0], tree[1], tree[2]]
+        assert tree[0] in tree[1].children()
+        other = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert tree[0] != other[0]
+        assert tree[0] != "He"
+
+    def test_word_path_to_root(self, sample_conllu):
+        """word.path_to_root() follows heads from the word up to the root."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        path = tree[3].path_to_root()  # to -> win -> helped
+        assert [w.id for w in path] == [3, 4, 1]
+        assert [w.form for w in tree.root.path_to_root()] == [tree.root.form]
+
+    def test_tree_subtree(self, sample_conllu):
+        """tree.subtree() extracts the words below a node as a new tree."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        sub = tree.subtree(4)  # "to win"
+        assert [sub[i].form for i in range(len(sub))] == ["to", "win"]
+        assert sub.root.form == "win"
+        assert sub[0].head == 1
+        assert sub.sentence_text == "to win"
+        assert len(tree.subtree(0)) == 1  # leaf
+        with pytest.raises(IndexError):
+            tree.subtree(len(tree))
+
+    def test_tree_to_dot(self, sample_conllu, tmp_path):
+        """tree.to_dot() and save_dot() render DOT graphs."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        dot = tree.to_dot()
+        assert dot.startswith("digraph tree {\n  rankdir=TB;")
+        assert '  n1 [label="helped\\nVERB", peripheries=2];' in dot
+        assert '  n1 -> n0 [label="nsubj"];' in dot
+        assert dot.count(" -> ") == len(tree) - 1
+
+        dot = tree.to_dot(show_lemma=True, direction="LR")
+        assert "rankdir=LR;" in dot
+        assert 'label="helped\\nhelp\\nVERB"' in dot
+        with pytest.raises(ValueError):
+            tree.to_dot(direction="sideways")
+
+        path = tmp_path / "tree.dot"
+        tree.save_dot(str(path), show_xpos=True)
+        assert path.read_text() == tree.to_dot(show_xpos=True)
+
+    def test_tree_display(self, sample_conllu):
+        """tree.display() prints the tree drawn by to_ascii()."""
+        import contextlib
+        import io
+
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        lines = tree.to_ascii().splitlines()
+        assert lines[0] == "helped/VERB/root"
+        assert lines[1] == "├── He/PRON/nsubj"
+        assert len(lines) == len(tree)
+
+        out = io.StringIO()
+        with contextlib.redirect_stdout(out):
+            tree.display()
+        assert out.getvalue() == tree.to_ascii()
+
+    def test_tree_to_networkx(self, sample_conllu):
+        """tree.to_networkx() builds a DiGraph from heads to dependents."""
+        nx = pytest.importorskip("networkx")
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        graph = tree.to_networkx()
+        assert graph.number_of_nodes() == len(tree)
+        assert graph.number_of_edges() == len(tree) - 1
+        assert graph.nodes[1]["form"] == "helped"
+        assert graph.nodes[1]["upos"] == "VERB"
+        assert graph.edges[1, 0]["deprel"] == "nsubj"
+        assert nx.is_arborescence(graph)
+
+        undirected = tree.to_networkx_undirected()
+        assert not undirected.is_directed()
+        assert nx.shortest_path_length(undirected, 0, 2) == 2
+
+    def test_tree_to_json(self, sample_conllu):
+        """tree.to_json() serializes comments, multiword tokens and all word fields."""
+        import json
+
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        data = json.loads(tree.to_json())
+        assert data["sentence_text"] == tree.sentence_text
+        assert data["metadata"] == tree.metadata
+        assert len(data["words"]) == len(tree)
+        for word, entry in zip((tree[i] for i in range(len(tree))), data["words"]):
+            assert entry["form"] == word.form
+            assert entry["lemma"] == word.lemma
+            assert entry["head"] == word.head
+            assert entry["deprel"] == word.deprel
+            assert entry["feats"] == word.feats
+            assert entry["misc"] == word.misc
+            assert entry["deps"] == []
+        assert data["comments"] == ["# text = He helped us to win."]
+        assert data["multiword_tokens"] == []
+
+        conllu = """1-2\tdel\t_\t_\t_\t_\t_\t_\t_\t_
+1\tde\tde\tADP\t_\t_\t3\tcase\t3:case\t_
+2\tel\tel\tDET\t_\t_\t3\tdet\t3:det\t_
+3\tmar\tmar\tNOUN\t_\t_\t0\troot\t0:root\t_
+
+"""
+        tree = next(iter(treesearch.Treebank.from_string(conllu).trees()))
+        data = json.loads(tree.to_json())
+        assert data["multiword_tokens"] == tree.multiword_tokens
+        assert data["words"][0]["deps"] == [{"head": 2, "deprel": "case"}]
+        assert data["words"][2]["deps"] == [{"head": None, "deprel": "root"}]
+
+    def test_tree_from_json(self, sample_conllu):
+        """Tree.from_json() rebuilds the tree that Tree.to_json() wrote."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        copy = treesearch.Tree.from_json(tree.to_json())
+        assert copy.to_json() == tree.to_json()
+        assert copy.to_conllu() == tree.to_conllu()
+        assert [copy[i].head for i in range(len(copy))] == [tree[i].head for i in range(len(tree))]
+
+        with pytest.raises(ValueError):
+            treesearch.Tree.from_json('{"words": []')
+
+    def test_search_max_matches(self, sample_conllu, multi_tree_conllu):
+        """max_matches limits the matches returned from each tree."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        matches = list(tb.search('MATCH { V [upos="VERB"]; }', max_matches=1))
+        assert [m["V"] for _, m in matches] == [1]  # helped only
+        assert len(list(tb.search('MATCH { W []; }', max_matches=0))) == 0
+
+        # The limit is per tree, not global
+        tb = treesearch.Treebank.from_string(multi_tree_conllu)
+        matches = list(tb.search('MATCH { W []; }', max_matches=1))
+        assert [(tree.sentence_text, m["W"]) for tree, m in matches] == [
+            ("The dog runs.", 0),
+            ("Cats sleep.", 0),
+        ]
+
+    def test_format_match(self, sample_conllu):
+        """tree.format_match shows form and UPOS of bound words."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        tree, match = next(iter(tb.search('MATCH { V [lemma="help"]; S []; V -[nsubj]-> S; }')))
+        assert tree.format_match(match) == "{S=0 (He/PRON), V=1 (helped/VERB)}"
+
     def test_search_invalid_string_raises_valueerror(self, sample_conllu):
         """Invalid query string raises ValueError."""
         tb = treesearch.Treebank.from_string(sample_conllu)
@@ -412,6 +872,22 @@ class TestSearch:
         matches = list(treesearch.search_trees(trees, 'MATCH { V [upos="VERB"]; }'))
         assert len(matches) == 2  # One verb per tree
 
+    def test_search_trees_string_and_pattern_equivalent(self, tree):
+        """search_trees gives the same results for a string and a compiled Pattern."""
+        query = 'MATCH { V [upos="VERB"]; }'
+        str_matches = [m for _, m in treesearch.py_search_trees([tree], query)]
+        pattern_matches = [
+            m
+            for _, m in treesearch.py_search_trees([tree], treesearch.compile_query(query))
+        ]
+        assert len(str_matches) == 2
+        assert str_matches == pattern_matches
+
+    def test_search_trees_invalid_string_raises_valueerror(self, tree):
+        """search_trees raises ValueError for an invalid query string."""
+        with pytest.raises(ValueError, match="Query parse error"):
+            treesearch.search_trees(tree, "INVALID SYNTAX")
+
 
 # ==============================================================================
 # Filter Tests
@@ -446,12 +922,259 @@ class TestFilter:
         # filter() returns 1 tree
         assert len(list(tb.filter('MATCH { V [upos="VERB"]; }'))) == 1
 
+    def test_filter_predicate(self, multi_tree_conllu):
+        """filter(callable) returns a FilteredTreebank of the accepted trees."""
+        tb = treesearch.Treebank.from_string(multi_tree_conllu)
+        long = tb.filter(lambda t: len(t) > 2)
+        assert isinstance(long, treesearch.FilteredTreebank)
+        assert [t.sentence_text for t in long.trees()] == ["The dog runs."]
+        assert [t.sentence_text for t in long] == ["The dog runs."]
+        # Only the matches in accepted trees are returned
+        matches = list(long.search('MATCH { N [upos=/NOUNS?/]; }'))
+        assert [tree.word(m["N"]).form for tree, m in matches] == ["dog"]
+        # Filters chain
+        assert list(long.filter(lambda t: t.sentence_text.startswith("Cats"))) == []
+
+    def test_filter_predicate_called_once_per_tree(self):
+        """search() on a FilteredTreebank runs the predicate once per tree."""
+        conllu = "1\tsaw\tsee\tVERB\t_\t_\t0\troot\t_\t_\n2\trun\trun\tVERB\t_\t_\t1\txcomp\t_\t_\n\n"
+        calls = []
+
+        def predicate(tree):
+            calls.append(tree)
+            return True
+
+        tb = treesearch.Treebank.from_string(conllu).filter(predicate)
+        assert len(list(tb.search('MATCH { V [upos="VERB"]; }'))) == 2
+        assert len(calls) == 1
+
+    def test_filter_predicate_errors_propagate(self, sample_conllu):
+        """Exceptions raised by the predicate reach the caller."""
+        tb = treesearch.Treebank.from_string(sample_conllu).filter(lambda t: 1 / 0)
+        with pytest.raises(ZeroDivisionError):
+            list(tb.trees())
+        with pytest.raises(ZeroDivisionError):
+            list(tb.search('MATCH { V [upos="VERB"]; }'))
+
+    def test_filter_by_length_and_metadata(self, multi_tree_conllu):
+        """filter_by_length() and filter_by_metadata() cover the common cases."""
+        conllu = "# sent_id = a\n# genre = news\n" + multi_tree_conllu
+        tb = treesearch.Treebank.from_string(conllu)
+        assert [len(t) for t in tb.filter_by_length(max=2)] == [2]
+        assert [len(t) for t in tb.filter_by_length(3, 3)] == [3]
+        assert [t.metadata["sent_id"] for t in tb.filter_by_metadata("genre", "news")] == ["a"]
+        assert list(tb.filter_by_metadata("genre", "news").filter_by_length(max=2)) == []
+
     def test_filter_no_matches(self, sample_conllu):
         """filter() returns empty when no matches."""
         tb = treesearch.Treebank.from_string(sample_conllu)
         trees = list(tb.filter('MATCH { X [upos="NONEXISTENT"]; }'))
         assert len(trees) == 0
 
+    def test_write_conllu(self, sample_conllu, tmp_path):
+        """write_conllu() writes trees that read back the same, plain or gzipped."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        expected = [tree.to_conllu() for tree in tb.trees()]
+        for name in ["out.conllu", "out.conllu.gz"]:
+            path = tmp_path / name
+            assert tb.write_conllu(str(path)) == len(expected)
+            written = treesearch.Treebank.from_file(str(path))
+            assert [tree.to_conllu() for tree in written.trees()] == expected
+
+    def test_save_binary(self, sample_conllu, tmp_path):
+        """save_binary() writes a cache that from_binary() and load_or_build() read."""
+        conllu = tmp_path / "sample.conllu"
+        conllu.write_text(sample_conllu)
+        path = tmp_path / "sample.tsbt"
+        tb = treesearch.Treebank.from_file(str(conllu))
+        assert tb.save_binary(str(path)) == 1
+        loaded = treesearch.Treebank.from_binary(str(path))
+        assert [t.to_conllu() for t in loaded.trees()] == [t.to_conllu() for t in tb.trees()]
+        assert treesearch.Treebank.load_or_build(str(conllu), str(path)).count() == 1
+        with pytest.raises(OSError):
+            treesearch.Treebank.from_binary(str(conllu))
+
+    def test_multi_search(self, sample_conllu):
+        """multi_search() runs several patterns in one pass."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        results = tb.multi_search({
+            "verb": 'MATCH { V [upos="VERB"]; }',
+            "obj": treesearch.compile_query('MATCH { V []; O []; V -[obj]-> O; }'),
+            "adj": 'MATCH { A [upos="ADJ"]; }',
+        })
+        assert sorted(results) == ["adj", "obj", "verb"]
+        assert [tree.word(m["V"]).lemma for tree, m in results["verb"]] == ["help", "win"]
+        assert len(results["obj"]) == 1
+        assert results["adj"] == []
+        with pytest.raises(ValueError):
+            tb.multi_search({"bad": "MATCH {"})
+
+    def test_frequency_per_million(self, sample_conllu):
+        """frequency_per_million() normalizes the match count by word count."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        freq = tb.frequency_per_million('MATCH { V [upos="VERB"]; }')
+        assert abs(freq - 2 / 6 * 1_000_000) < 1e-6
+        assert treesearch.Treebank.from_string("").frequency_per_million("MATCH { V []; }") == 0.0
+
+    def test_kwic(self, sample_conllu):
+        """kwic() returns aligned concordance lines."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        query = 'MATCH { V [upos="VERB"]; }'
+        lines = tb.kwic(query, anchor="V", context=2)
+        assert lines == [
+            "   He [helped] us to",
+            "us to [win] .",
+        ]
+        assert len({line.index("[") for line in lines}) == 1
+        assert tb.kwic(query, anchor="V", context=1, sort="right") == [
+            "to [win] .",
+            "He [helped] us",
+        ]
+        with pytest.raises(ValueError):
+            tb.kwic(query, anchor="V", sort="middle")
+
+    def test_count_by(self, temp_multi_files):
+        """count_by() groups match counts by a field of one variable."""
+        _, files = temp_multi_files
+        tb = treesearch.Treebank.from_files(files)
+        by_upos = tb.count_by("MATCH { W []; }", group_by="W", field="upos")
+        assert sum(by_upos.values()) == sum(len(t) for t in tb.trees())
+        verbs = tb.count_by('MATCH { V [upos="VERB"]; }', "V")
+        assert sum(verbs.values()) == len(list(tb.search('MATCH { V [upos="VERB"]; }')))
+        with pytest.raises(ValueError):
+            tb.count_by("MATCH { W []; }", group_by="X")
+        with pytest.raises(ValueError):
+            tb.count_by("MATCH { W []; }", group_by="W", field="color")
+
+    def test_stats(self, temp_multi_files):
+        """stats() summarizes the whole treebank."""
+        _, files = temp_multi_files
+        tb = treesearch.Treebank.from_files(files)
+        stats = tb.stats()
+        assert stats["n_sentences"] == tb.count()
+        assert stats["n_words"] == sum(len(t) for t in tb.trees())
+        assert sum(stats["upos_frequencies"].values()) == stats["n_words"]
+        assert stats["deprel_frequencies"]["root"] == stats["n_sentences"]
+        assert stats["max_sentence_length"] >= stats["mean_sentence_length"] > 0
+        assert stats["n_nonprojective"] == 0
+
+    def test_sample(self, temp_multi_files):
+        """sample() draws a reproducible subset of trees."""
+        _, files = temp_multi_files
+        tb = treesearch.Treebank.from_files(files)
+        texts = lambda trees: [t.sentence_text for t in trees]
+        sample = tb.sample(3, seed=42)
+        assert isinstance(sample, treesearch.Treebank)
+        assert sample.count() == 3
+        assert texts(sample.trees()) == texts(tb.sample(3, seed=42).trees())
+        assert set(texts(sample.trees())) <= set(texts(tb.trees()))
+        assert tb.sample(100).count() == tb.count()
+        assert tb.sample(10**12).count() == tb.count()
+
+    def test_sample_matching(self, temp_multi_files):
+        """sample_matching() only draws trees that match."""
+        _, files = temp_multi_files
+        tb = treesearch.Treebank.from_files(files)
+        trees = tb.sample_matching('MATCH { D [upos="DET"]; }', 2, seed=1)
+        assert len(trees) == 2
+        assert all(t.sentence_text == "The dog runs." for t in trees)
+        assert tb.sample_matching('MATCH { X [upos="NONEXISTENT"]; }', 2) == []
+
+    def test_count(self, temp_multi_files):
+        """count() and count_matching_sentences() count trees, not matches."""
+        _, files = temp_multi_files
+        tb = treesearch.Treebank.from_files(files)
+        trees = list(tb.trees())
+        assert tb.count() == len(trees)
+        assert tb.count_matching_sentences("MATCH { W []; }") == len(trees)
+        query = 'MATCH { V [upos="VERB"]; }'
+        expected = sum(1 for t in trees if treesearch.compile_query(query).matches(t))
+        assert tb.count_matching_sentences(treesearch.compile_query(query)) == expected
+        assert tb.count_matching_sentences('MATCH { X [upos="NONEXISTENT"]; }') == 0
+
+    def test_any_match(self, sample_conllu):
+        """any_match() reports whether some tree matches."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        assert tb.any_match('MATCH { V [upos="VERB"]; }')
+        assert not tb.any_match('MATCH { X [upos="NONEXISTENT"]; }')
+        assert tb.any_match(treesearch.compile_query('MATCH { V [lemma="help"]; }'))
+
+    def test_pattern_pickle(self, sample_conllu):
+        """Patterns survive a pickle round trip with the same matches."""
+        import pickle
+
+        pattern = treesearch.compile_query('MATCH { V [upos="VERB"]; N []; V -> N; } ORDER BY N.form')
+        restored = pickle.loads(pickle.dumps(pattern))
+        assert isinstance(restored, treesearch.Pattern)
+        assert restored.order_by == pattern.order_by
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        assert [m for _, m in tb.search(restored)] == [m for _, m in tb.search(pattern)]
+
+    def test_pattern_complexity(self, sample_conllu):
+        """complexity() estimates the search size and warns on broad queries."""
+        pattern = treesearch.compile_query('MATCH { V [lemma="help"]; N [upos="NOUN"]; V -> N; }')
+        bound = pattern.complexity()
+        assert bound["n_vars"] == 2
+        assert bound["n_edge_checks"] == 1
+        assert bound["warning"] is None
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        assert pattern.complexity(tb)["domain_size_product"] >= 1.0
+        broad = treesearch.compile_query("MATCH { A []; B []; C []; D []; E []; }")
+        assert "5 variables" in broad.complexity()["warning"]
+
+    def test_pattern_json(self, sample_conllu):
+        """Patterns round-trip through JSON."""
+        import json
+
+        query = 'MATCH { V [upos="VERB"]; N []; V -> N; }'
+        text = treesearch.compile_query(query).to_json()
+        assert json.loads(text) == {"query": query}
+        restored = treesearch.Pattern.from_json(text)
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        assert [m for _, m in tb.search(restored)] == [m for _, m in tb.search(query)]
+        with pytest.raises(ValueError):
+            treesearch.Pattern.from_json('{"name": "x"}')
+        with pytest.raises(ValueError):
+            treesearch.Pattern.from_json(json.dumps({"query": "MATCH {"}))
+
+    def test_pattern_matches_tree(self, sample_conllu):
+        """pattern.matches(tree) checks a single tree."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        assert treesearch.compile_query('MATCH { V [upos="VERB"]; }').matches(tree)
+        assert not treesearch.compile_query('MATCH { X [upos="NONEXISTENT"]; }').matches(tree)
+
+    def test_pattern_search_order(self, sample_conllu):
+        """pattern.search(tree, order=...) sorts the matches of one tree."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        pattern = treesearch.compile_query("MATCH { H []; D []; H -> D; }")
+        leftmost = pattern.search(tree, order="leftmost")
+        assert len(leftmost) == 5
+        assert [min(m.values()) for m in leftmost] == sorted(min(m.values()) for m in leftmost)
+        rightmost = pattern.search(tree, order="rightmost")
+        assert max(rightmost[0].values()) == 5
+        by_dep = pattern.search(tree, order="D")
+        assert [m["D"] for m in by_dep] == [0, 2, 3, 4, 5]
+        with pytest.raises(ValueError):
+            pattern.search(tree, order="X")
+
+    def test_sentence_block(self, complex_conllu):
+        """A SENTENCE block filters trees by metadata and text."""
+        tb = treesearch.Treebank.from_string(complex_conllu)
+        query = 'SENTENCE {{ {} }} MATCH {{ V [upos="VERB"]; }}'
+        assert tb.count_matching_sentences(query.format('metadata.source = "test"')) == 1
+        assert tb.count_matching_sentences(query.format('metadata.source = "web"')) == 0
+        assert tb.count_matching_sentences(query.format('text CONTAINS "dog"')) == 1
+
+    def test_pattern_explain(self, sample_conllu):
+        """pattern.explain(tree) shows the search plan."""
+        tree = next(iter(treesearch.Treebank.from_string(sample_conllu).trees()))
+        pattern = treesearch.compile_query('MATCH { V [lemma="help"]; N []; V -> N; }')
+        plan = pattern.explain(tree).splitlines()
+        assert plan[0] == "MATCH: 2 variables, 1 edge constraint"
+        assert plan[1] == '  1. V [lemma="help"]: 1 candidate, 1 after arc consistency'
+        assert plan[2].startswith("  2. N []: 6 candidates")
+        assert plan[3] == "  Edge V -> N: checked when N is assigned (step 2)"
+
 
 # ==============================================================================
 # Multi-file Tests
@@ -483,6 +1206,54 @@ class TestMultiFile:
         for t1, t2 in zip(ordered1, ordered2):
             assert t1.sentence_text == t2.sentence_text
 
+    def test_to_memory(self, temp_multi_files):
+        """to_memory() gives the same trees on repeated iteration."""
+        tmpdir, _ = temp_multi_files
+        tb = treesearch.load(f"{tmpdir}/*.conllu").to_memory()
+        first = [t.sentence_text for t in tb.trees()]
+        second = [t.sentence_text for t in tb.trees()]
+        assert len(first) == 6
+        assert first == second
+
+    def test_from_glob(self, temp_multi_files):
+        """Treebank.from_glob() reads the matching files in sorted order."""
+        tmp_path, files = temp_multi_files
+        tb = treesearch.Treebank.from_glob(str(tmp_path / "*.conllu"))
+        expected = treesearch.Treebank.from_files(sorted(files))
+        assert [t.sentence_text for t in tb.trees()] == [t.sentence_text for t in expected.trees()]
+        with pytest.raises(ValueError, match="Invalid glob pattern"):
+            treesearch.Treebank.from_glob(str(tmp_path / "[*.conllu"))
+        with pytest.warns(UserWarning, match="no files match"):
+            tb = treesearch.Treebank.from_glob(str(tmp_path / "*.missing"))
+        assert list(tb.trees()) == []
+
+    def test_from_directory(self, temp_multi_files, sample_conllu):
+        """Treebank.from_directory() globs inside a directory, optionally recursively."""
+        tmp_path, files = temp_multi_files
+        (tmp_path / "sub").mkdir()
+        (tmp_path / "sub" / "nested.conllu").write_text(sample_conllu)
+        flat = treesearch.Treebank.from_directory(str(tmp_path))
+        deep = treesearch.Treebank.from_directory(str(tmp_path), recursive=True)
+        assert len(list(deep.trees())) == len(list(flat.trees())) + 1
+        with pytest.warns(UserWarning):
+            treesearch.Treebank.from_directory(str(tmp_path), pattern="*.txt")
+
+    def test_to_memory_missing_file(self):
+        """to_memory() raises OSError for unreadable files."""
+        tb = treesearch.Treebank.from_file("/nonexistent/file.conllu")
+        with pytest.raises(OSError, match="Failed to open file"):
+            tb.to_memory()
+
+    def test_with_buffer_size(self, temp_multi_files):
+        """with_buffer_size() changes batching without changing results."""
+        tmpdir, _ = temp_multi_files
+        tb = treesearch.load(f"{tmpdir}/*.conllu")
+        query = 'MATCH { V [upos="VERB"]; }'
+        expected = [t.sentence_text for t, _ in tb.search(query)]
+        tuned = tb.with_buffer_size(match_batch=1, channel_batches=1)
+        assert [t.sentence_text for t, _ in tuned.search(query)] == expected
+        assert len(list(tb.with_buffer_size().search(query, ordered=False))) == 6
+
     def test_search_glob(self, temp_multi_files):
         """search() works with glob pattern."""
         tmpdir, _ = temp_multi_files
@@ -553,6 +1324,12 @@ class TestConstraintTypes:
         matches = list(tb.search('MATCH { V [upos="VERB" & lemma="help"]; }'))
         assert len(matches) == 1
 
+    def test_or_constraint(self, sample_conllu):
+        """| matches either alternative."""
+        tb = treesearch.Treebank.from_string(sample_conllu)
+        matches = list(tb.search('MATCH { W [upos="PRON" | upos="PART"]; }'))
+        assert len(matches) == 3  # He, us, to
+
     def test_negated_constraint(self, sample_conllu):
         """!= negates a constraint."""
         tb = treesearch.Treebank.from_string(sample_conllu)